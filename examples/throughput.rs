@@ -0,0 +1,72 @@
+//! Expressions per second: compiled, and evaluated with `eval`, which
+//! finds them in its cache after the first time, once compiled with
+//! `compile` or as a `Program`, then a batch of distinct lines like a
+//! spreadsheet column, which all miss the cache:
+//!
+//!   cargo run --release --example throughput
+
+use std::collections::HashMap;
+use std::time::Instant;
+use calculator::Calculator;
+
+/// What a spreadsheet or config file might hold, from a number to a
+/// formula with calls and nesting.
+const EXPRS: [&str; 5] = [
+    "42",
+    "price * (1 + vat) - discount",
+    "200000 * rate / (1 - (1 + rate)^-n)",
+    "sqrt(x^2 + y^2) + atan2(y, x) * 180 / pi",
+    "max(1, 2, 3, 4, 5) + min(6, 7, 8) * abs(-9) / clamp(x, 1, 10)",
+];
+
+const RUNS: usize = 200_000;
+
+const LINES: usize = 100_000;
+
+fn main() {
+    let mut calc = Calculator::new();
+    let vars = HashMap::from([
+        ("price", 100.), ("vat", 0.2), ("discount", 5.), ("rate", 0.05 / 12.), ("n", 360.), ("x", 3.), ("y", 4.),
+    ]);
+    for (name, value) in vars {
+        calc.set_var(name, value).unwrap();
+    }
+
+    println!("{:<66} {:>12} {:>12} {:>12} {:>12}", "expression", "compile/s", "eval/s", "evaluate/s", "program/s");
+    for expr in EXPRS {
+        let per_second = |start: Instant| RUNS as f64 / start.elapsed().as_secs_f64();
+        let start = Instant::now();
+        for _ in 0..RUNS {
+            calc.compile(expr).unwrap();
+        }
+        let compiled = per_second(start);
+
+        let start = Instant::now();
+        for _ in 0..RUNS {
+            calc.eval(expr.to_string()).unwrap();
+        }
+        let parsed = per_second(start);
+
+        let expression = calc.compile(expr).unwrap();
+        let start = Instant::now();
+        for _ in 0..RUNS {
+            calc.evaluate(&expression).unwrap();
+        }
+        let evaluated = per_second(start);
+
+        let program = expression.to_program().unwrap();
+        let start = Instant::now();
+        for _ in 0..RUNS {
+            calc.run_program(&program).unwrap();
+        }
+        println!("{:<66} {:>12.0} {:>12.0} {:>12.0} {:>12.0}", expr, compiled, parsed, evaluated, per_second(start));
+    }
+
+    let lines: Vec<String> = (0..LINES).map(|i| format!("price * (1 + vat) * {} - discount / {}", i, i % 7 + 1)).collect();
+    let start = Instant::now();
+    for line in &lines {
+        calc.eval(line.clone()).unwrap();
+    }
+    let elapsed = start.elapsed();
+    println!("{} distinct lines in {:?}, {:.0} lines/s", LINES, elapsed, LINES as f64 / elapsed.as_secs_f64());
+}
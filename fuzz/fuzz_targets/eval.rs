@@ -0,0 +1,26 @@
+#![no_main]
+
+use calculator::{Arithmetic, Calculator, Limits};
+use libfuzzer_sys::fuzz_target;
+
+// Evaluates each line of the input in every arithmetic, with the limits of
+// the server, so an error is fine but a panic or a hang is a bug.
+fuzz_target!(|data: &[u8]| {
+    let Ok(source) = std::str::from_utf8(data) else { return };
+    for arithmetic in [Arithmetic::Float, Arithmetic::Integer, Arithmetic::Rational, Arithmetic::Decimal { places: 28 }] {
+        let mut calc = Calculator::new();
+        calc.set_arithmetic(arithmetic);
+        calc.set_limits(Limits {
+            max_length: Some(10_000),
+            max_tokens: Some(5_000),
+            max_depth: Some(100),
+            max_arguments: Some(1_000),
+            max_call_depth: None,
+            max_steps: Some(1_000_000),
+            timeout: Some(std::time::Duration::from_secs(1)),
+        });
+        for line in source.lines() {
+            let _ = calc.eval(line.to_string());
+        }
+    }
+});
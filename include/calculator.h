@@ -0,0 +1,47 @@
+/* The C interface of the calculator library, built with
+ * `cargo build --lib --release --no-default-features --features ffi`
+ * into target/release/libcalculator.so (calculator.dll, libcalculator.dylib).
+ */
+#ifndef CALCULATOR_H
+#define CALCULATOR_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define CALC_OK 0
+/* A null handle, expression or result pointer, or an expression that isn't UTF-8. */
+#define CALC_ERR_ARGUMENT (-1)
+#define CALC_ERR_SYNTAX 1
+#define CALC_ERR_UNKNOWN_FUNCTION 2
+#define CALC_ERR_UNKNOWN_VARIABLE 3
+#define CALC_ERR_ASSIGNMENT 4
+#define CALC_ERR_ARITY 5
+#define CALC_ERR_DOMAIN 6
+/* Also a result that isn't a number, like a list or a boolean. */
+#define CALC_ERR_TYPE 7
+#define CALC_ERR_ENVIRONMENT 8
+#define CALC_ERR_INTERRUPTED 9
+#define CALC_ERR_LIMIT 10
+#define CALC_ERR_DIVISION_BY_ZERO 11
+
+typedef struct CalcHandle CalcHandle;
+
+/* A calculator, freed by calc_free. Variables and functions defined in
+ * one calc_eval are kept for the next. */
+CalcHandle *calc_new(void);
+void calc_free(CalcHandle *handle);
+
+/* Evaluates the expression and writes the result to out. Returns CALC_OK
+ * or an error code, with the message kept for calc_last_error_message. */
+int calc_eval(CalcHandle *handle, const char *expr, double *out);
+
+/* The message of the last failed calc_eval, empty after one that
+ * succeeded. Valid until the next calc_eval or calc_free. */
+const char *calc_last_error_message(const CalcHandle *handle);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
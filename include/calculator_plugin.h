@@ -0,0 +1,47 @@
+/* The interface of a function pack, a shared library the calculator loads
+ * from ~/.config/calculator/plugins when the REPL starts:
+ *
+ *     static int fv(const double *args, double *out) {
+ *         double rate = args[0], n = args[1], pmt = args[2];
+ *         *out = pmt * (pow(1 + rate, n) - 1) / rate;
+ *         return 0;
+ *     }
+ *
+ *     static const CalcPluginFunction FUNCTIONS[] = {
+ *         { "fv(rate, n, pmt)", fv },
+ *     };
+ *
+ *     const CalcPluginFunction *calc_plugin_functions(size_t *count) {
+ *         *count = sizeof FUNCTIONS / sizeof FUNCTIONS[0];
+ *         return FUNCTIONS;
+ *     }
+ *
+ * built with `cc -shared -fPIC -o finance.so finance.c`.
+ */
+#ifndef CALCULATOR_PLUGIN_H
+#define CALCULATOR_PLUGIN_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct {
+    /* Like "fv(rate, n, pmt)", the function takes as many arguments as the
+     * signature has parameters. */
+    const char *signature;
+    /* Writes the result to out and returns 0, or returns another number
+     * for an error. */
+    int (*call)(const double *args, double *out);
+} CalcPluginFunction;
+
+/* The functions of the plugin, an array of *count that stays valid while
+ * the plugin is loaded. */
+const CalcPluginFunction *calc_plugin_functions(size_t *count);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
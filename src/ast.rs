@@ -0,0 +1,286 @@
+//! The parsed form of an `Expression`, for tools that analyze, transform or
+//! translate expressions rather than evaluate them.
+//!
+//! ```
+//! use calculator::Calculator;
+//! use calculator::ast::{walk, Expr, Fold, Visitor};
+//!
+//! let calc = Calculator::new();
+//! let ast = calc.compile("2*x + sin(x)").unwrap().ast();
+//!
+//! // The functions it calls.
+//! struct Calls(Vec<String>);
+//! impl Visitor for Calls {
+//!     fn visit(&mut self, expr: &Expr) {
+//!         if let Expr::Call { name, .. } = expr {
+//!             self.0.push(name.clone());
+//!         }
+//!         walk(self, expr);
+//!     }
+//! }
+//! let mut calls = Calls(vec![]);
+//! calls.visit(&ast);
+//! assert_eq!(calls.0, ["sin"]);
+//!
+//! // `x` renamed to `t`, printed back as source.
+//! struct Rename;
+//! impl Fold for Rename {
+//!     fn fold(&mut self, expr: Expr) -> Expr {
+//!         match expr {
+//!             Expr::Name(name) if name == "x" => Expr::Name("t".to_string()),
+//!             expr => calculator::ast::fold_children(self, expr),
+//!         }
+//!     }
+//! }
+//! assert_eq!(Rename.fold(ast).to_string(), "(2 * t) + sin(t)");
+//! ```
+
+use core::fmt;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::expression::Node;
+use crate::operator::Fixity;
+
+/// A node of a parsed expression. Implicit multiplication is already an
+/// explicit `*`, `2x` is `BinaryOp { symbol: "*", .. }`.
+///
+/// It prints back as source, with parentheses around every operand that
+/// isn't a number, name, call, list or index, so it compiles to the same
+/// expression with the operators it was parsed with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    /// A parameter, constant or variable.
+    Name(String),
+    /// `$NAME`, an environment variable.
+    Env(String),
+    UnaryOp {
+        symbol: String,
+        /// `Prefix` or `Postfix`.
+        fixity: Fixity,
+        operand: Box<Expr>,
+    },
+    /// An infix operator, a comparison or a logical operator.
+    BinaryOp {
+        symbol: String,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    List(Vec<Expr>),
+    /// `list[index]`, counting from 0.
+    Index {
+        list: Box<Expr>,
+        index: Box<Expr>,
+    },
+    Call {
+        name: String,
+        arguments: Vec<Expr>,
+    },
+    Assign {
+        name: String,
+        value: Box<Expr>,
+    },
+    /// `f(x, y) = body`, or `area := w * h` without parameters.
+    Define {
+        name: String,
+        params: Vec<String>,
+        body: Box<Expr>,
+    },
+    /// `(x, y) -> body`.
+    Lambda {
+        params: Vec<String>,
+        body: Box<Expr>,
+    },
+    /// `a = 2; b = 3; a*b`, only ever the whole expression.
+    Sequence(Vec<Expr>),
+}
+
+impl Expr {
+    /// The nodes directly inside this one, in source order.
+    pub fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Number(_) | Expr::Name(_) | Expr::Env(_) => vec![],
+            Expr::UnaryOp { operand, .. } => vec![operand],
+            Expr::BinaryOp { lhs, rhs, .. } => vec![lhs, rhs],
+            Expr::List(items) | Expr::Call { arguments: items, .. } | Expr::Sequence(items) => items.iter().collect(),
+            Expr::Index { list, index } => vec![list, index],
+            Expr::Assign { value: body, .. } | Expr::Define { body, .. } | Expr::Lambda { body, .. } => vec![body],
+        }
+    }
+
+    /// The node without its children, as `to_tree` draws it: `+`, `-x` for
+    /// a prefix operator, `sin()` for a call or `f(x) =` for a definition.
+    pub fn label(&self) -> String {
+        match self {
+            Expr::Number(x) => x.to_string(),
+            Expr::Name(name) => name.clone(),
+            Expr::Env(name) => format!("${}", name),
+            Expr::UnaryOp { symbol, fixity: Fixity::Postfix, .. } => format!("x{}", symbol),
+            Expr::UnaryOp { symbol, .. } => format!("{}x", symbol),
+            Expr::BinaryOp { symbol, .. } => symbol.clone(),
+            Expr::List(_) => "[]".to_string(),
+            Expr::Index { .. } => "index".to_string(),
+            Expr::Call { name, .. } => format!("{}()", name),
+            Expr::Assign { name, .. } => format!("{} =", name),
+            Expr::Define { name, params, .. } if params.is_empty() => format!("{} :=", name),
+            Expr::Define { name, params, .. } => format!("{}({}) =", name, params.join(", ")),
+            Expr::Lambda { params, .. } => format!("({}) ->", params.join(", ")),
+            Expr::Sequence(_) => ";".to_string(),
+        }
+    }
+
+    /// The tree of the expression drawn with box-drawing lines, each node
+    /// on a line above its children, which shows how `1 - 2 - 3` grouped:
+    ///
+    ///   -
+    ///   ├── -
+    ///   │   ├── 1
+    ///   │   └── 2
+    ///   └── 3
+    pub fn to_tree(&self) -> String {
+        let mut lines = vec![self.label()];
+        self.branches("", &mut lines);
+        lines.join("\n")
+    }
+
+    /// The tree as a Graphviz graph, each node labeled as in `to_tree` with
+    /// edges to its children in order, for `dot -Tsvg`.
+    pub fn to_dot(&self) -> String {
+        let mut lines = vec!["digraph {".to_string(), "    node [shape=box];".to_string()];
+        self.nodes(&mut 0, &mut lines);
+        lines.push("}".to_string());
+        lines.join("\n") + "\n"
+    }
+
+    /// The lines of this node and those under it, numbered from `next`.
+    /// Returns the number of this one.
+    fn nodes(&self, next: &mut usize, lines: &mut Vec<String>) -> usize {
+        let id = *next;
+        *next += 1;
+        let label = self.label().replace('\\', "\\\\").replace('"', "\\\"");
+        lines.push(format!("    n{} [label=\"{}\"];", id, label));
+        for child in self.children() {
+            let child = child.nodes(next, lines);
+            lines.push(format!("    n{} -> n{};", id, child));
+        }
+        id
+    }
+
+    /// The lines of the children under a node whose lines start with
+    /// `indent`.
+    fn branches(&self, indent: &str, lines: &mut Vec<String>) {
+        let children = self.children();
+        for (i, child) in children.iter().enumerate() {
+            let last = i == children.len() - 1;
+            lines.push(format!("{}{}{}", indent, if last { "└── " } else { "├── " }, child.label()));
+            child.branches(&format!("{}{}", indent, if last { "    " } else { "│   " }), lines);
+        }
+    }
+}
+
+impl From<&Node> for Expr {
+    fn from(node: &Node) -> Self {
+        let boxed = |node: &Node| Box::new(Expr::from(node));
+        let all = |nodes: &[Node]| nodes.iter().map(Expr::from).collect();
+        match node {
+            &Node::Number(x) => Expr::Number(x),
+            Node::Name(name, _) => Expr::Name(name.clone()),
+            Node::Env(name, _) => Expr::Env(name.clone()),
+            Node::Unary { symbol, operator, operand, .. } => Expr::UnaryOp { symbol: symbol.clone(), fixity: operator.fixity(), operand: boxed(operand) },
+            Node::Binary { symbol, lhs, rhs, .. } => Expr::BinaryOp { symbol: symbol.clone(), lhs: boxed(lhs), rhs: boxed(rhs) },
+            Node::List(items, _) => Expr::List(all(items)),
+            Node::Index { list, index, .. } => Expr::Index { list: boxed(list), index: boxed(index) },
+            Node::Call { name, arguments, .. } => Expr::Call { name: name.clone(), arguments: all(arguments) },
+            Node::Assign { name, value, .. } => Expr::Assign { name: name.clone(), value: boxed(value) },
+            Node::Define { name, params, body, .. } => Expr::Define { name: name.clone(), params: params.clone(), body: boxed(body) },
+            Node::Lambda { params, body } => Expr::Lambda { params: params.clone(), body: boxed(body) },
+            Node::Sequence(statements) => Expr::Sequence(all(statements)),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |items: &[Expr]| items.iter().map(Expr::to_string).collect::<Vec<_>>().join(", ");
+        match self {
+            Expr::Number(x) => write!(f, "{}", x),
+            Expr::Name(name) => write!(f, "{}", name),
+            Expr::Env(name) => write!(f, "${}", name),
+            Expr::UnaryOp { symbol, fixity: Fixity::Postfix, operand } => write!(f, "{}{}", Operand(operand), symbol),
+            // A word like `not` needs a space before its operand.
+            Expr::UnaryOp { symbol, operand, .. } if symbol.chars().all(|c| c.is_ascii_alphabetic()) => write!(f, "{} {}", symbol, Operand(operand)),
+            Expr::UnaryOp { symbol, operand, .. } => write!(f, "{}{}", symbol, Operand(operand)),
+            Expr::BinaryOp { symbol, lhs, rhs } => write!(f, "{} {} {}", Operand(lhs), symbol, Operand(rhs)),
+            Expr::List(items) => write!(f, "[{}]", join(items)),
+            Expr::Index { list, index } => write!(f, "{}[{}]", Operand(list), index),
+            Expr::Call { name, arguments } => write!(f, "{}({})", name, join(arguments)),
+            Expr::Assign { name, value } => write!(f, "{} = {}", name, value),
+            Expr::Define { name, params, body } if params.is_empty() => write!(f, "{} := {}", name, body),
+            Expr::Define { name, params, body } => write!(f, "{}({}) = {}", name, params.join(", "), body),
+            Expr::Lambda { params, body } => write!(f, "({}) -> {}", params.join(", "), body),
+            Expr::Sequence(statements) => {
+                let statements: Vec<String> = statements.iter().map(Expr::to_string).collect();
+                write!(f, "{}", statements.join("; "))
+            }
+        }
+    }
+}
+
+/// An operand, in parentheses unless it is atomic.
+struct Operand<'a>(&'a Expr);
+
+impl fmt::Display for Operand<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            &Expr::Number(x) if x >= 0. => write!(f, "{}", x),
+            Expr::Name(_) | Expr::Env(_) | Expr::List(_) | Expr::Index { .. } | Expr::Call { .. } => write!(f, "{}", self.0),
+            expr => write!(f, "({})", expr),
+        }
+    }
+}
+
+/// Goes through an `Expr` top down. An implementation overrides `visit`
+/// and calls `walk` from it to go on into the children.
+pub trait Visitor {
+    fn visit(&mut self, expr: &Expr) {
+        walk(self, expr);
+    }
+}
+
+/// Visits the children of `expr`.
+pub fn walk<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    for child in expr.children() {
+        visitor.visit(child);
+    }
+}
+
+/// Rebuilds an `Expr`. An implementation overrides `fold` to replace the
+/// nodes it is interested in and calls `fold_children` for the others.
+pub trait Fold {
+    fn fold(&mut self, expr: Expr) -> Expr {
+        fold_children(self, expr)
+    }
+}
+
+/// `expr` with each of its children folded.
+pub fn fold_children<F: Fold + ?Sized>(folder: &mut F, expr: Expr) -> Expr {
+    let mut boxed = |expr: Box<Expr>| Box::new(folder.fold(*expr));
+    match expr {
+        Expr::Number(_) | Expr::Name(_) | Expr::Env(_) => expr,
+        Expr::UnaryOp { symbol, fixity, operand } => Expr::UnaryOp { symbol, fixity, operand: boxed(operand) },
+        Expr::BinaryOp { symbol, lhs, rhs } => {
+            let lhs = boxed(lhs);
+            Expr::BinaryOp { symbol, lhs, rhs: boxed(rhs) }
+        }
+        Expr::Index { list, index } => {
+            let list = boxed(list);
+            Expr::Index { list, index: boxed(index) }
+        }
+        Expr::Assign { name, value } => Expr::Assign { name, value: boxed(value) },
+        Expr::Define { name, params, body } => Expr::Define { name, params, body: boxed(body) },
+        Expr::Lambda { params, body } => Expr::Lambda { params, body: boxed(body) },
+        Expr::List(items) => Expr::List(items.into_iter().map(|item| folder.fold(item)).collect()),
+        Expr::Call { name, arguments } => Expr::Call { name, arguments: arguments.into_iter().map(|argument| folder.fold(argument)).collect() },
+        Expr::Sequence(statements) => Expr::Sequence(statements.into_iter().map(|statement| folder.fold(statement)).collect()),
+    }
+}
@@ -0,0 +1,236 @@
+use core::cmp::Ordering;
+use core::fmt;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::exact::Exact;
+use crate::value::Value;
+
+const BASE: u64 = 1_000_000_000;
+
+/// Results with more digits are floats instead.
+const MAX_DIGITS: usize = 100_000;
+
+/// The largest `n!` worked out exactly, it has 35660 digits.
+const MAX_FACTORIAL: u32 = 10_000;
+
+/// An integer of any size, so `2^200` and `100!` are exact.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    /// Base 10^9 digits, least significant first, without leading zeros.
+    /// Zero has none and isn't negative.
+    limbs: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn from_i64(n: i64) -> Self {
+        Self { negative: n < 0, ..Self::from_u64(n.unsigned_abs()) }
+    }
+
+    pub fn from_u64(n: u64) -> Self {
+        let mut limbs = vec![];
+        let mut rest = n;
+        while rest > 0 {
+            limbs.push((rest % BASE) as u32);
+            rest /= BASE;
+        }
+        Self { negative: false, limbs }
+    }
+
+    /// None if it is negative or doesn't fit.
+    pub fn to_u64(&self) -> Option<u64> {
+        match self.negative {
+            true => None,
+            false => self.limbs.iter().rev().try_fold(0u64, |n, &limb| n.checked_mul(BASE)?.checked_add(limb as u64)),
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap()
+    }
+
+    fn new(negative: bool, mut limbs: Vec<u32>) -> Option<Self> {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        if limbs.len() * 9 > MAX_DIGITS {
+            return None;
+        }
+        Some(Self { negative: negative && !limbs.is_empty(), limbs })
+    }
+
+    /// `|self| * factor`.
+    fn mul_small(&self, factor: u32) -> Option<Self> {
+        let mut carry = 0;
+        let mut limbs: Vec<u32> = self.limbs.iter().map(|&limb| {
+            let n = limb as u64 * factor as u64 + carry;
+            carry = n / BASE;
+            (n % BASE) as u32
+        }).collect();
+        if carry > 0 {
+            limbs.push(carry as u32);
+        }
+        Self::new(self.negative, limbs)
+    }
+
+    /// The quotient and remainder of `|self| / divisor`.
+    fn div_small(&self, divisor: u32) -> (Vec<u32>, u32) {
+        let mut rest = 0;
+        let mut limbs = self.limbs.clone();
+        for limb in limbs.iter_mut().rev() {
+            let n = rest * BASE + *limb as u64;
+            *limb = (n / divisor as u64) as u32;
+            rest = n % divisor as u64;
+        }
+        (limbs, rest as u32)
+    }
+
+    /// Only integers that fit in a limb.
+    fn small(&self) -> Option<u32> {
+        match self.limbs.as_slice() {
+            [] => Some(0),
+            &[limb] => Some(limb),
+            _ => None,
+        }
+    }
+}
+
+fn cmp_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut ret = vec![];
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let n = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        ret.push((n % BASE) as u32);
+        carry = n / BASE;
+    }
+    if carry > 0 {
+        ret.push(carry as u32);
+    }
+    ret
+}
+
+/// `a - b` for `a >= b`.
+fn sub_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut ret = vec![];
+    let mut borrow = 0;
+    for (i, &limb) in a.iter().enumerate() {
+        let mut n = limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = (n < 0) as i64;
+        if n < 0 {
+            n += BASE as i64;
+        }
+        ret.push(n as u32);
+    }
+    ret
+}
+
+impl Exact for BigInt {
+    /// Only integers up to 2^53, past which floats skip some.
+    fn from_f64(x: f64) -> Option<Self> {
+        (x.fract() == 0. && x.abs() <= 2f64.powi(53)).then(|| Self::from_i64(x as i64))
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Integer(n) => Some(n.clone()),
+            &Value::Number(x) => Self::from_f64(x),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Integer(self)
+    }
+
+    fn add(&self, other: &Self) -> Option<Self> {
+        if self.negative == other.negative {
+            return Self::new(self.negative, add_magnitudes(&self.limbs, &other.limbs));
+        }
+        match cmp_magnitudes(&self.limbs, &other.limbs) {
+            Ordering::Less => Self::new(other.negative, sub_magnitudes(&other.limbs, &self.limbs)),
+            _ => Self::new(self.negative, sub_magnitudes(&self.limbs, &other.limbs)),
+        }
+    }
+
+    fn sub(&self, other: &Self) -> Option<Self> {
+        self.add(&other.neg()?)
+    }
+
+    fn mul(&self, other: &Self) -> Option<Self> {
+        if (self.limbs.len() + other.limbs.len()) * 9 > MAX_DIGITS + 18 {
+            return None;
+        }
+        let mut ret = vec![0u64; self.limbs.len() + other.limbs.len() + 1];
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let mut carry = 0;
+            for (j, &b) in other.limbs.iter().enumerate() {
+                let n = ret[i + j] + a as u64 * b as u64 + carry;
+                ret[i + j] = n % BASE;
+                carry = n / BASE;
+            }
+            ret[i + other.limbs.len()] += carry;
+        }
+        Self::new(self.negative != other.negative, ret.into_iter().map(|limb| limb as u32).collect())
+    }
+
+    /// Only divisions without a remainder by a divisor below 10^9 are
+    /// exact.
+    fn div(&self, other: &Self) -> Option<Self> {
+        let divisor = other.small().filter(|&divisor| divisor != 0)?;
+        match self.div_small(divisor) {
+            (limbs, 0) => Self::new(self.negative != other.negative, limbs),
+            _ => None,
+        }
+    }
+
+    fn neg(&self) -> Option<Self> {
+        Self::new(!self.negative, self.limbs.clone())
+    }
+
+    fn pow(&self, exponent: &Self) -> Option<Self> {
+        if exponent.negative {
+            return None;
+        }
+        let mut n = exponent.small()?;
+        let (mut base, mut ret) = (self.clone(), Self::from_i64(1));
+        while n > 0 {
+            if n & 1 == 1 {
+                ret = ret.mul(&base)?;
+            }
+            n >>= 1;
+            if n > 0 {
+                base = base.mul(&base)?;
+            }
+        }
+        Some(ret)
+    }
+
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_magnitudes(&self.limbs, &other.limbs),
+            (true, true) => cmp_magnitudes(&other.limbs, &self.limbs),
+        }
+    }
+
+    fn factorial(&self) -> Option<Self> {
+        if self.negative {
+            return None;
+        }
+        let n = self.small().filter(|&n| n <= MAX_FACTORIAL)?;
+        (2..=n).try_fold(Self::from_i64(1), |ret, i| ret.mul_small(i))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some((last, rest)) = self.limbs.split_last() else { return write!(f, "0") };
+        write!(f, "{}{}", if self.negative { "-" } else { "" }, last)?;
+        rest.iter().rev().try_for_each(|limb| write!(f, "{:09}", limb))
+    }
+}
@@ -0,0 +1,72 @@
+use alloc::collections::BTreeMap;
+use alloc::rc::Rc;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::HashMap;
+use crate::expression::Expression;
+
+/// The expressions `Calculator::eval` compiled last by their source, so
+/// evaluating the same text again skips parsing it. When it is full the
+/// least recently used one makes room.
+#[derive(Clone)]
+pub(crate) struct Cache {
+    capacity: usize,
+    /// Each expression with when it was last used.
+    entries: HashMap<Rc<str>, (Rc<Expression>, u64)>,
+    /// The sources by when they were last used.
+    order: BTreeMap<u64, Rc<str>>,
+    clock: u64,
+}
+
+impl Cache {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, entries: HashMap::new(), order: BTreeMap::new(), clock: 0 }
+    }
+
+    pub fn get(&mut self, source: &str) -> Option<Rc<Expression>> {
+        let (expr, used) = self.entries.get_mut(source)?;
+        let source = self.order.remove(used).unwrap();
+        self.clock += 1;
+        *used = self.clock;
+        self.order.insert(self.clock, source);
+        Some(expr.clone())
+    }
+
+    pub fn insert(&mut self, source: String, expr: Rc<Expression>) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.shrink(self.capacity - 1);
+        let source: Rc<str> = source.into();
+        self.clock += 1;
+        self.order.insert(self.clock, source.clone());
+        self.entries.insert(source, (expr, self.clock));
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.shrink(capacity);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    /// The sources, the most recently used first.
+    pub fn sources(&self) -> Vec<&str> {
+        self.order.values().rev().map(|source| &**source).collect()
+    }
+
+    /// Drops the least recently used expressions until at most `len` are left.
+    fn shrink(&mut self, len: usize) {
+        while self.entries.len() > len {
+            let (_, oldest) = self.order.pop_first().unwrap();
+            self.entries.remove(&oldest);
+        }
+    }
+}
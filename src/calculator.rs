@@ -1,260 +1,2936 @@
-use std::collections::HashMap;
+use core::ops::Range;
+use core::cell::Cell;
+use alloc::rc::Rc;
+use core::sync::atomic::{AtomicBool, Ordering};
+use alloc::sync::Arc;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::{HashMap, HashSet, Instant};
 use crate::calculator::Function::*;
+use crate::ast::Expr;
+use crate::bigint::BigInt;
+use crate::cache::Cache;
+use crate::calculus;
+use crate::date::{self, Duration};
+#[cfg(feature = "decimal")]
+use crate::decimal::Decimal;
+use crate::diagnostic::{suggest, Diagnostic, ErrorKind};
+use crate::encoding;
+use crate::exact;
+use crate::expression::{variables, Compiler, Expression, Node, OPERAND};
+use crate::format::{self, Formatter, Rounding};
+use crate::functions;
+use crate::interval::{self, MONOTONIC, PLUS_MINUS};
+use crate::limits::Limits;
+use crate::numeric::Numeric;
+#[cfg(feature = "matrix")]
+use crate::matrix::{self, Matrix};
+use crate::operator::{Fixity, Operator, OperatorTable, IN};
+use crate::lexer::TokenKind;
 use crate::parser::Parser;
+use crate::plot::{self, Plot};
+use crate::program::{Op, Program};
+use crate::rational::Rational;
+use crate::resolver::Resolver;
+use crate::stats::Stats;
+use crate::symbolic::{is_constant, mentions, rewrite, Algebra, Derivative, Formula, Lambda};
+#[cfg(feature = "units")]
+use crate::units::{self, Quantity, Unit};
+use crate::value::Value;
 
-pub(crate) enum Function {
+/// A function that can be called from expressions, see
+/// `Calculator::register_function`.
+#[allow(clippy::enum_variant_names)]
+#[derive(Clone)]
+pub enum Function {
     OneArg(fn(f64) -> f64),
     TwoArg(fn(f64, f64) -> f64),
     ThreeArg(fn(f64, f64, f64) -> f64),
+    /// Takes the given number of arguments and can reject them.
+    Fallible(usize, fn(&[f64]) -> Result<f64, String>),
+    /// Takes at least the given number of arguments, written as `...` at
+    /// the end of the signature: `max(x, ...)`.
+    Variadic(usize, fn(&[f64]) -> f64),
+    /// Like `Fallible`, but a closure that can capture state, see
+    /// `Function::closure`.
+    Closure(usize, SharedFunction),
+    /// Like `Closure`, but takes at least the given number of arguments, see
+    /// `Function::variadic_closure`.
+    VariadicClosure(usize, SharedFunction),
+    /// Takes and returns any `Value`, lists and matrices as they are,
+    /// `det(A)`.
+    Values(usize, fn(&[Value]) -> Result<Value, String>),
+    /// Like `Fallible`, but answers true or false, `isprime(7)`.
+    Predicate(usize, fn(&[f64]) -> Result<bool, String>),
+}
+
+type SharedFunction = Rc<dyn Fn(&[f64]) -> Result<f64, String>>;
+
+/// Variables set for one evaluation, see `Calculator::eval_with`.
+pub type Bindings = HashMap<String, f64>;
+
+/// The unit the trigonometric functions take and return angles in.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AngleUnit {
+    #[default]
+    Radians,
+    Degrees,
+}
+
+/// The numbers plain arithmetic like `0.1 + 0.2` is evaluated with.
+/// Expressions that use anything else, like functions or constants, are
+/// evaluated with floats whatever the setting.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Arithmetic {
+    #[default]
+    Float,
+    /// `Decimal`s rounded to `places` digits after the point, with the
+    /// `decimal` feature.
+    #[cfg(feature = "decimal")]
+    Decimal { places: u32 },
+    /// `Rational`s, results are fractions like `1/2`.
+    Rational,
+    /// `BigInt`s, for expressions of integers whose results don't fit in a
+    /// float, `2^200` or `100!`.
+    Integer,
+}
+
+/// The builtin functions taking an angle, and those returning one.
+const TRIG: [&str; 3] = ["sin", "cos", "tan"];
+const INVERSE_TRIG: [&str; 4] = ["asin", "acos", "atan", "atan2"];
+
+/// A function defined in an expression with `f(x, y) = body`.
+#[derive(Clone)]
+struct UserFunction {
+    params: Vec<String>,
+    body: Arc<Node>,
+}
+
+/// How deep user functions can call each other, so recursion fails with an
+/// error instead of overflowing the stack.
+const MAX_CALL_DEPTH: usize = 100;
+
+/// The most work `preview` does before giving up, as it runs on every key
+/// press.
+const PREVIEW_STEPS: usize = 10_000;
+const PREVIEW_TIMEOUT: core::time::Duration = core::time::Duration::from_millis(5);
+
+/// How many compiled expressions `eval` keeps unless told otherwise.
+const CACHE_SIZE: usize = 128;
+
+/// `if(cond, then, else)`, which only evaluates the branch it takes so it
+/// can't be an ordinary `Function`.
+pub(crate) const CONDITIONAL: &str = "if";
+
+/// `diff(expr, x)`, which differentiates `expr` rather than its value.
+const DERIVATIVE: &str = "diff";
+
+/// `integrate(expr, x, a, b)`, which evaluates `expr` for many `x`.
+const INTEGRAL: &str = "integrate";
+
+/// How close `integrate` gets to the integral unless it is told.
+const TOLERANCE: f64 = 1e-10;
+
+/// `solve(expr, x, guess)`, which evaluates `expr` for many `x`. Its first
+/// argument can be an equation, `solve(x^2 = 2, x)`.
+pub(crate) const SOLVE: &str = "solve";
+
+/// `sum(i, from, to, expr)` and `prod(i, from, to, expr)`, which evaluate
+/// `expr` for each `i`. `sum` of other arguments adds them up.
+pub(crate) const SUM: &str = "sum";
+const PRODUCT: &str = "prod";
+
+/// How many terms a sum or product can have.
+const MAX_TERMS: f64 = 1e8;
+
+/// `simplify(expr)`, which rewrites `expr` rather than evaluating it.
+const SIMPLIFY: &str = "simplify";
+
+/// `plot(expr, x, a, b)`, which evaluates `expr` for many `x`.
+const PLOT: &str = "plot";
+
+/// `map(f, xs)`, `filter(f, xs)` and `reduce(f, xs, init)`, which call the
+/// lambda `f`.
+const MAP: &str = "map";
+const FILTER: &str = "filter";
+const REDUCE: &str = "reduce";
+
+/// `odesolve(f, t0, y0, t1, steps)`, which calls the lambda `f` for the slope
+/// `y'` at each `t` and `y`.
+const ODESOLVE: &str = "odesolve";
+
+/// How many steps `odesolve` can take.
+const MAX_STEPS: f64 = 1e7;
+
+/// `montecarlo(f, n)` and `montecarlo(f, n, a, b)`, which call the lambda `f`
+/// with random numbers.
+const MONTE_CARLO: &str = "montecarlo";
+
+/// How many samples `montecarlo` can take.
+const MAX_SAMPLES: f64 = 1e7;
+
+/// The namespace of the statistics builtins, `stats::median` is `median`.
+const STATISTICS: &str = "stats";
+
+/// The functions that take their arguments unevaluated or call them, with
+/// their signatures.
+const SPECIAL_FORMS: [(&str, &str); 12] = [
+    (CONDITIONAL, "if(cond, then, else)"),
+    (DERIVATIVE, "diff(expr, x, at)"),
+    (SIMPLIFY, "simplify(expr)"),
+    (INTEGRAL, "integrate(expr, x, a, b, tolerance)"),
+    (SOLVE, "solve(expr, x, guess)"),
+    (PRODUCT, "prod(i, from, to, expr)"),
+    (PLOT, "plot(expr, x, a, b)"),
+    (MAP, "map(f, xs)"),
+    (FILTER, "filter(f, xs)"),
+    (REDUCE, "reduce(f, xs, init)"),
+    (ODESOLVE, "odesolve(f, t0, y0, t1, steps)"),
+    (MONTE_CARLO, "montecarlo(f, n, a, b)"),
+];
+
+pub(crate) fn is_special(name: &str) -> bool {
+    SPECIAL_FORMS.iter().any(|&(special, _)| special == name)
+}
+
+impl Function {
+    pub fn arity(&self) -> usize {
+        match self {
+            OneArg(_) => 1,
+            TwoArg(_) => 2,
+            ThreeArg(_) => 3,
+            Fallible(arity, _) | Variadic(arity, _) | Closure(arity, _) | VariadicClosure(arity, _) | Values(arity, _) | Predicate(arity, _) => *arity,
+        }
+    }
+
+    pub fn closure(arity: usize, f: impl Fn(&[f64]) -> Result<f64, String> + 'static) -> Self {
+        Closure(arity, Rc::new(f))
+    }
+
+    pub fn variadic_closure(min: usize, f: impl Fn(&[f64]) -> Result<f64, String> + 'static) -> Self {
+        VariadicClosure(min, Rc::new(f))
+    }
+
+    /// Whether more than `arity` arguments are accepted.
+    pub fn is_variadic(&self) -> bool {
+        matches!(self, Variadic(..) | VariadicClosure(..))
+    }
 }
 
 /// Grammar
-///   expression: addition
-///   addition: multiplication ('+' | '-' multiplication)*
-///   multiplication: unary ('*' | '/' | '%' | '^' unary)*
-///   unary: '-'? parentheses
-///   parentheses: '(' expression ')' | atom
-///   atom: number | call
-///   number: [0-9]+ ('.' [0-9]+)?
-///   call: identifier ('(' arguments ')')?
-///   identifier: [a-zA-Z][a-zA-Z0-9]*
+///   statements: statement (';' statement)* ';'?
+///   statement: definition | (identifier '=')? expression
+///   definition: identifier '(' identifier (',' identifier)* ')' '=' expression
+///   expression: lambda | operand (infix operand)*
+///   lambda: ('(' identifier (',' identifier)* ')' | identifier) '->' expression
+///   operand: prefix operand | parentheses postfix*
+///   parentheses: ('(' expression ')' | list | atom) ('[' expression ']')*
+///   list: '[' arguments? ']'
+///   atom: number | env | call
+///   number: digits ('.' digits)? | '0x' hex | '0o' octal | '0b' binary
+///   digits: [0-9]+ ('_' [0-9]+)*
+///   call: identifier ('(' arguments? ')')?
+///   identifier: [a-zA-Z][a-zA-Z0-9_]* (('.' | '::') [a-zA-Z][a-zA-Z0-9_]*)*
+///   env: '$' [a-zA-Z_][a-zA-Z0-9_]* | '$' [0-9]+
 ///   arguments: expression (',' expression)*
+///
+/// The operators and how they group come from an `OperatorTable`. A name or
+/// '(' right after a number or ')' multiplies like `*`, `2pi` is `2*pi`.
+/// The first argument of `solve` can be an equation, `lhs = rhs` is read as
+/// `lhs - rhs`.
+///
+/// `$2` is short for `out(2)`, which the REPL defines as its second result.
+///
+/// A clone starts with the same definitions and shares the interrupt flag
+/// and the resolver.
+#[derive(Clone)]
 pub struct Calculator {
-    parser: Parser,
-    functions: HashMap<String, Function>,
+    operators: Arc<OperatorTable>,
+    /// The overloads of each name, by number of parameters.
+    functions: HashMap<String, Vec<Function>>,
+    /// Names of the functions `new` registers, which only
+    /// `override_function` replaces.
+    builtins: HashSet<String>,
+    /// Parameter names of the functions, e.g. `clamp(x, min, max)`, one per
+    /// overload.
+    signatures: HashMap<String, Vec<String>>,
     constants: HashMap<String, f64>,
+    /// Read-only names given a value from outside of expressions, like the
+    /// cells of a `Sheet`.
+    values: HashMap<String, Value>,
+    /// Assigned with `name = expression`, kept across evaluations.
+    variables: HashMap<String, Value>,
+    /// Defined with `name := expression`, evaluated whenever the name is
+    /// read. A name is either a variable or a formula.
+    formulas: HashMap<String, UserFunction>,
+    user_functions: HashMap<String, UserFunction>,
+    /// Given for a single evaluation by `evaluate_with`.
+    bindings: HashMap<String, Value>,
+    /// Asked for the names that are none of the above.
+    resolver: Option<Rc<dyn Resolver>>,
+    /// Exchange rates by currency code, see `set_rates`.
+    rates: HashMap<String, f64>,
+    /// Arguments of the user functions being called, innermost last.
+    scopes: Vec<HashMap<String, Value>>,
+    /// Whether `$NAME` reads the environment variable `NAME`.
+    env_vars: bool,
+    /// Whether to normalize pasted numbers like `$ 1,234.50`.
+    sanitize: bool,
+    /// Whether `4.7k` is 4700.
+    si_prefixes: bool,
+    /// Whether `3,14` is a number, see `set_decimal_separator`.
+    decimal_comma: bool,
+    rounding: Rounding,
+    /// Whether to collect warnings about easily misread expressions.
+    warn: bool,
+    /// Whether infinite and NaN results are errors, see `set_strict`.
+    strict: bool,
+    warnings: Vec<Diagnostic>,
+    /// The operations performed so far, only recorded by `explain`.
+    steps: Option<Vec<String>>,
+    stats: Stats,
+    /// The expressions `eval` compiled, cleared by the settings that change
+    /// how expressions compile.
+    cache: Cache,
+    limits: Limits,
+    /// Steps of the evaluation so far, for `Limits::max_steps`.
+    taken: usize,
+    /// When the evaluation runs out of `Limits::timeout`.
+    deadline: Option<Instant>,
+    formatter: Formatter,
+    angle_unit: AngleUnit,
+    arithmetic: Arithmetic,
+    /// State of the random number generator behind `rand`, shared with
+    /// the functions.
+    rng: Rc<Cell<u64>>,
+    /// Source of the expression being evaluated, for warnings.
+    source: String,
+    /// Set from another thread or a signal handler to cancel `eval`.
+    interrupted: Arc<AtomicBool>,
+}
+
+impl Default for Calculator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Calculator {
     pub fn new() -> Self {
-        Self {
-            parser: Parser::new("".to_string()),
-            functions: HashMap::from([
-                // @formatter:off
-                ("abs".to_string(), OneArg(|a| a.abs())),
-                ("ceil".to_string(), OneArg(|a| a.ceil())),
-                ("floor".to_string(), OneArg(|a| a.floor())),
-                ("round".to_string(), OneArg(|a| a.round())),
-                ("sign".to_string(), OneArg(|a| a.signum())),
-
-                ("sin".to_string(), OneArg(|a| a.sin())),
-                ("cos".to_string(), OneArg(|a| a.cos())),
-                ("tan".to_string(), OneArg(|a| a.tan())),
-                ("asin".to_string(), OneArg(|a| a.asin())),
-                ("acos".to_string(), OneArg(|a| a.acos())),
-                ("atan".to_string(), OneArg(|a| a.atan())),
-
-                ("ln".to_string(), OneArg(|a| a.ln())),
-                ("log".to_string(), TwoArg(|a,b| b.log(a))),
-                ("sqrt".to_string(), OneArg(|a| a.sqrt())),
-
-                ("max".to_string(), TwoArg(|a, b| a.max(b))),
-                ("min".to_string(), TwoArg(|a, b| a.min(b))),
-
-                ("clamp".to_string(), ThreeArg(|a, b, c| a.clamp(b, c))),
-                ("clamp01".to_string(), OneArg(|a| a.clamp(0., 1.))),
-                // @formatter:on
-            ]),
+        let mut calc = Self {
+            operators: Arc::new(OperatorTable::default()),
+            functions: HashMap::new(),
+            builtins: HashSet::new(),
+            signatures: HashMap::new(),
             constants: HashMap::from([
-                ("pi".to_string(), std::f64::consts::PI),
-                ("e".to_string(), std::f64::consts::E),
-            ])
+                ("pi".to_string(), core::f64::consts::PI),
+                ("e".to_string(), core::f64::consts::E),
+            ]).into_iter().chain(functions::PHYSICAL.map(|(name, value)| (name.to_string(), value))).collect(),
+            values: HashMap::new(),
+            variables: HashMap::new(),
+            formulas: HashMap::new(),
+            user_functions: HashMap::new(),
+            bindings: HashMap::new(),
+            resolver: None,
+            rates: HashMap::new(),
+            scopes: vec![],
+            env_vars: false,
+            sanitize: false,
+            si_prefixes: false,
+            decimal_comma: false,
+            rounding: Rounding::HalfUp,
+            warn: false,
+            strict: false,
+            warnings: vec![],
+            steps: None,
+            stats: Stats::default(),
+            cache: Cache::new(CACHE_SIZE),
+            limits: Limits::default(),
+            taken: 0,
+            deadline: None,
+            formatter: Formatter::default(),
+            angle_unit: AngleUnit::default(),
+            arithmetic: Arithmetic::default(),
+            rng: Rc::new(Cell::new(functions::seed())),
+            source: String::new(),
+            interrupted: Arc::new(AtomicBool::new(false)),
+        };
+
+        let builtins = [
+            // @formatter:off
+            ("abs(x)", OneArg(|a| a.abs())),
+            ("ceil(x)", OneArg(|a| a.ceil())),
+            ("floor(x)", OneArg(|a| a.floor())),
+            // Both follow the rounding mode, see `call_builtin`.
+            ("round(x)", OneArg(|a| Rounding::HalfUp.round(a, 0))),
+            ("round(x, digits)", TwoArg(|a, b| Rounding::HalfUp.round(a, b as i32))),
+            ("trunc(x)", OneArg(|a| a.trunc())),
+            ("fract(x)", OneArg(|a| a.fract())),
+            ("frac(x)", Values(1, |args| {
+                let x = args[0].number().ok_or(format!("Expected a number but got {}", args[0]))?;
+                Rational::approximate(x).map(Value::Fraction).ok_or(format!("{} has no simple fraction", x))
+            })),
+            ("cf(x, n)", Values(2, |args| {
+                let n = args[1].number().ok_or(format!("Expected a number but got {}", args[1])).and_then(natural)?;
+                let terms = match &args[0] {
+                    Value::Fraction(r) => r.continued_fraction(),
+                    value => {
+                        let x = value.number().ok_or(format!("Expected a number but got {}", value))?;
+                        Rational::continued_fraction_of(x).ok_or(format!("{:e} has too many digits for a continued fraction", x))?
+                    }
+                };
+                Ok(Value::List(terms.into_iter().take(n as usize).map(|term| term as f64).collect()))
+            })),
+            ("ratapprox(x, maxden)", Values(2, |args| {
+                let max = args[1].number().ok_or(format!("Expected a number but got {}", args[1])).and_then(natural)?;
+                if max == 0 {
+                    return Err("Expected a denominator of at least 1".to_string());
+                }
+                let r = match &args[0] {
+                    Value::Fraction(r) => *r,
+                    value => {
+                        let x = value.number().ok_or(format!("Expected a number but got {}", value))?;
+                        match <Rational as exact::Exact>::from_f64(x) {
+                            Some(r) => r,
+                            // Too small for its digits to fit, and closer to 0 than to 1/maxden.
+                            None if x.abs() < 0.5 / max as f64 => Rational::new(0, 1).unwrap(),
+                            None => return Err(format!("{:e} has too many digits for a fraction", x)),
+                        }
+                    }
+                };
+                Ok(Value::Fraction(r.best_approximation(i128::from(max))))
+            })),
+            ("fromcf(terms)", Values(1, |args| {
+                let terms = args[0].list().ok_or(format!("Expected a list of terms but got {}", args[0]))?;
+                let terms = terms.iter().map(|&term| integer(term).map(i128::from)).collect::<Result<Vec<_>, _>>()?;
+                match terms.iter().skip(1).find(|&&term| term <= 0) {
+                    _ if terms.is_empty() => Err("Expected at least 1 term".to_string()),
+                    Some(term) => Err(format!("Expected positive terms after the first but got {}", term)),
+                    None => Rational::from_continued_fraction(&terms).map(Value::Fraction).ok_or("Expected terms whose fraction fits in 128 bits".to_string()),
+                }
+            })),
+            ("sign(x)", OneArg(|a| a.signum())),
+
+            ("sin(x)", OneArg(|a| a.sin())),
+            ("cos(x)", OneArg(|a| a.cos())),
+            ("tan(x)", OneArg(|a| a.tan())),
+            ("asin(x)", OneArg(|a| a.asin())),
+            ("acos(x)", OneArg(|a| a.acos())),
+            ("atan(x)", OneArg(|a| a.atan())),
+            ("atan2(y, x)", TwoArg(|a, b| a.atan2(b))),
+
+            ("sinh(x)", OneArg(|a| a.sinh())),
+            ("cosh(x)", OneArg(|a| a.cosh())),
+            ("tanh(x)", OneArg(|a| a.tanh())),
+            ("asinh(x)", OneArg(|a| a.asinh())),
+            ("acosh(x)", OneArg(|a| a.acosh())),
+            ("atanh(x)", OneArg(|a| a.atanh())),
+
+            ("deg(x)", OneArg(|a| a.to_degrees())),
+            ("rad(x)", OneArg(|a| a.to_radians())),
+
+            ("ln(x)", OneArg(|a| a.ln())),
+            ("log(x)", OneArg(|a| a.log10())),
+            ("log(base, x)", TwoArg(|a,b| b.log(a))),
+            ("log2(x)", OneArg(|a| a.log2())),
+            ("log10(x)", OneArg(|a| a.log10())),
+            ("exp(x)", OneArg(|a| a.exp())),
+            ("exp2(x)", OneArg(|a| a.exp2())),
+            ("sqrt(x)", OneArg(|a| a.sqrt())),
+            ("cbrt(x)", OneArg(|a| a.cbrt())),
+            ("hypot(x, y)", TwoArg(|a, b| a.hypot(b))),
+
+            ("max(x, ...)", Variadic(1, |args| args.iter().copied().fold(f64::NEG_INFINITY, f64::max))),
+            ("min(x, ...)", Variadic(1, |args| args.iter().copied().fold(f64::INFINITY, f64::min))),
+
+            ("clamp(x, min, max)", Fallible(3, |args| match (args[1], args[2]) {
+                (min, max) if min <= max => Ok(args[0].clamp(min, max)),
+                (min, max) => Err(format!("Expected min <= max but got {} and {}", min, max)),
+            })),
+            ("clamp01(x)", OneArg(|a| a.clamp(0., 1.))),
+            ("lerp(a, b, t)", ThreeArg(|a, b, t| a + (b - a) * t)),
+            ("invlerp(a, b, x)", Fallible(3, |args| Ok((args[2] - args[0]) / range_length(args[0], args[1])?))),
+            ("remap(x, a, b, c, d)", Fallible(5, |args| {
+                let t = (args[0] - args[1]) / range_length(args[1], args[2])?;
+                Ok(args[3] + (args[4] - args[3]) * t)
+            })),
+            ("smoothstep(edge0, edge1, x)", Fallible(3, |args| {
+                let t = ((args[2] - args[0]) / range_length(args[0], args[1])?).clamp(0., 1.);
+                Ok(t * t * (3. - 2. * t))
+            })),
+            ("step(edge, x)", TwoArg(|a, b| if b < a { 0. } else { 1. })),
+
+            ("assert(x)", Fallible(1, |args| match args[0] {
+                x if x != 0. && !x.is_nan() => Ok(x),
+                x => Err(format!("Assertion failed: {} is not true", x)),
+            })),
+            ("asserteq(a, b)", Fallible(2, |args| match (args[0], args[1]) {
+                (a, b) if a == b => Ok(a),
+                (a, b) => Err(format!("Assertion failed: {} != {}", a, b)),
+            })),
+            ("assertnear(a, b, tol)", Fallible(3, |args| match (args[0], args[1], args[2]) {
+                (a, b, tol) if (a - b).abs() <= tol => Ok(a),
+                (a, b, tol) => Err(format!("Assertion failed: {} and {} differ by more than {}", a, b, tol)),
+            })),
+
+            ("zscore(x, mu, sigma)", Fallible(3, |args| match args[2] {
+                sigma if sigma > 0. => Ok((args[0] - args[1]) / sigma),
+                sigma => Err(format!("Expected a positive standard deviation but got {}", sigma)),
+            })),
+
+            ("popcount(x)", Fallible(1, |args| Ok(twos_complement(args[0])?.count_ones() as f64))),
+            ("bitand(a, b)", Fallible(2, |args| Ok((integer(args[0])? & integer(args[1])?) as f64))),
+            ("bitor(a, b)", Fallible(2, |args| Ok((integer(args[0])? | integer(args[1])?) as f64))),
+            ("bitxor(a, b)", Fallible(2, |args| Ok((integer(args[0])? ^ integer(args[1])?) as f64))),
+            ("setbit(x, n)", Fallible(2, |args| Ok((integer(args[0])? | 1 << bit_index(args[1])?) as f64))),
+            ("getbit(x, n)", Fallible(2, |args| Ok((integer(args[0])? >> bit_index(args[1])? & 1) as f64))),
+            ("rotl(x, n, width)", Fallible(3, |args| rotate(args, true))),
+            ("rotr(x, n, width)", Fallible(3, |args| rotate(args, false))),
+
+            ("tobase(x, b)", Values(2, |args| {
+                let [x, b] = [&args[0], &args[1]].map(|arg| arg.number().ok_or(format!("Expected a number but got {}", arg)).and_then(integer));
+                to_base(x?, b?)
+            })),
+
+            ("ncr(n, k)", Fallible(2, |args| Ok(choose(natural(args[0])?, natural(args[1])?, true)))),
+            ("npr(n, k)", Fallible(2, |args| Ok(choose(natural(args[0])?, natural(args[1])?, false)))),
+
+            ("modinv(a, m)", Fallible(2, |args| modinv(integer(args[0])?, integer(args[1])?).map(|x| x as f64))),
+            ("crt(rs, ms)", Values(2, |args| match (args[0].list(), args[1].list()) {
+                (Some(rs), Some(ms)) if rs.len() == ms.len() && !rs.is_empty() => crt(rs, ms).map(|x| Value::Number(x as f64)),
+                (Some(rs), Some(ms)) => Err(format!("Expected as many remainders as moduli, at least one, but got {} and {}", rs.len(), ms.len())),
+                _ => Err("Expected a list of remainders and a list of moduli".to_string()),
+            })),
+
+            ("exponent(x)", OneArg(|a| exponent(a) as f64)),
+            ("mantissa(x)", OneArg(|a| a.abs() / 2f64.powi(exponent(a)))),
+            ("frexp(x)", Values(1, |args| {
+                let (m, e) = frexp(args[0].number().ok_or(format!("Expected a number but got {}", args[0]))?);
+                Ok(Value::List(vec![m, e as f64]))
+            })),
+            ("ldexp(m, e)", Fallible(2, |args| Ok(ldexp(args[0], integer(args[1])?)))),
+            ("ulp(x)", OneArg(ulp)),
+            // The IEEE-754 pattern as an integer, exact where a float isn't
+            // past 2^53, and back, NaN payloads and `-0` included.
+            ("bits(x)", Values(1, |args| match args[0].number() {
+                Some(x) => Ok(Value::Integer(BigInt::from_u64(x.to_bits()))),
+                None => Err(format!("Expected a number but got {}", args[0])),
+            })),
+            ("frombits(n)", Values(1, |args| <BigInt as exact::Exact>::from_value(&args[0]).and_then(|n| n.to_u64())
+                .map(|bits| Value::Number(f64::from_bits(bits)))
+                .ok_or(format!("Expected a bit pattern from 0 to 2^64 - 1 like bits(x) gives but got {}", args[0])))),
+            ("nextafter(x, y)", TwoArg(nextafter)),
+
+            ("polyroots(coefficients)", Values(1, |args| match args[0].list() {
+                Some(coefficients) => calculus::polyroots(coefficients).map(Value::List),
+                None => Err(format!("Expected a list of coefficients but got {}", args[0])),
+            })),
+            // @formatter:on
+        ];
+        let random = functions::random(&calc.rng);
+        let mut builtins: Vec<_> = builtins.into_iter().chain(functions::statistics()).chain(functions::sequences()).chain(functions::number_theory()).chain(random).collect();
+        #[cfg(feature = "special-functions")]
+        builtins.extend(crate::special::functions());
+        #[cfg(feature = "matrix")]
+        builtins.extend(matrix::functions());
+        builtins.extend(interval::functions().into_iter().chain(date::functions()));
+        for (signature, function) in builtins {
+            calc.register_function(signature, function).unwrap();
+        }
+        // Also in a namespace, `stats::median`, for `use_namespace`.
+        for (signature, function) in functions::statistics() {
+            calc.register_function(&format!("{}::{}", STATISTICS, signature), function).unwrap();
         }
+        calc.builtins = calc.functions.keys().cloned().collect();
+        for (name, signature) in SPECIAL_FORMS {
+            calc.signatures.insert(name.to_string(), vec![signature.to_string()]);
+        }
+
+        calc
+    }
+
+    /// Adds a function or replaces one added before with the same name and
+    /// number of parameters, the builtin functions are only replaced by
+    /// `override_function`. A name can have overloads taking different
+    /// numbers of arguments, like `log(x)` and `log(base, x)`. The
+    /// signature names the parameters, which is what the REPL shows while
+    /// typing a call:
+    ///
+    ///   calc.register_function("mix(a, b, t)", Function::ThreeArg(|a, b, t| a + (b - a) * t))?;
+    ///
+    /// A `Function::closure` can capture state:
+    ///
+    ///   let rates = HashMap::from([(1, 0.92), (2, 0.79)]);
+    ///   calc.register_function("rate(id)", Function::closure(1, move |args| {
+    ///       rates.get(&(args[0] as i32)).copied().ok_or(format!("No rate for {}", args[0]))
+    ///   }))?;
+    pub fn register_function(&mut self, signature: &str, function: Function) -> Result<(), String> {
+        let name = signature.split('(').next().unwrap_or_default().trim();
+        if self.builtins.contains(name) {
+            return Err(format!("'{}' is a builtin function, use override_function to replace it", name));
+        }
+        self.override_function(signature, function)
+    }
+
+    /// Like `register_function`, but also replaces builtin functions.
+    pub fn override_function(&mut self, signature: &str, function: Function) -> Result<(), String> {
+        let (name, params) = signature.strip_suffix(')')
+            .and_then(|signature| signature.split_once('('))
+            .ok_or(format!("Expected a signature like 'f(x, y)' but got '{}'", signature))?;
+        let name = name.trim();
+        validate_name(name)?;
+
+        let mut params: Vec<&str> = params.split(',').map(str::trim).filter(|param| !param.is_empty()).collect();
+        if function.is_variadic() != (params.last() == Some(&"...")) {
+            return Err(format!("The signature '{}' has to end with '...' exactly if the function is variadic", signature));
+        }
+        params.retain(|&param| param != "...");
+        let params = params.len();
+        if params != function.arity() {
+            return Err(format!("The signature '{}' has {} parameters but the function takes {}", signature, params, function.arity()));
+        }
+
+        let overloads = self.functions.entry(name.to_string()).or_default();
+        let signatures = self.signatures.entry(name.to_string()).or_default();
+        let same = overloads.iter().position(|other| match (other.is_variadic(), function.is_variadic()) {
+            (false, false) => other.arity() == function.arity(),
+            (variadic, other_variadic) => variadic && other_variadic,
+        });
+        match same {
+            Some(i) => {
+                overloads[i] = function;
+                signatures[i] = signature.to_string();
+            }
+            None => {
+                let i = overloads.partition_point(|other| other.arity() <= function.arity());
+                overloads.insert(i, function);
+                signatures.insert(i, signature.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes the functions of `namespace`, like `stats::median` or a
+    /// `game::lerp` defined in an expression, callable without it as
+    /// `median` and `lerp`. A name that is already a function keeps that
+    /// one, which is still called with the namespace. Returns the names
+    /// imported and those kept, in order.
+    pub fn use_namespace(&mut self, namespace: &str) -> Result<(Vec<String>, Vec<String>), String> {
+        let prefix = format!("{}::", namespace);
+        let mut names: Vec<String> = self.functions.keys().chain(self.user_functions.keys())
+            .filter_map(|name| name.strip_prefix(&prefix))
+            .filter(|name| !name.contains("::"))
+            .map(str::to_string)
+            .collect();
+        if names.is_empty() {
+            return Err(format!("There are no functions in '{}'", namespace));
+        }
+        names.sort();
+        names.dedup();
+        let (mut imported, mut kept) = (vec![], vec![]);
+        for name in names {
+            let qualified = format!("{}{}", prefix, name);
+            // Builtins like `stats::median` are the same as those without.
+            if self.builtins.contains(&name) && self.builtins.contains(&qualified) {
+                imported.push(name);
+                continue;
+            }
+            if self.functions.contains_key(&name) || self.user_functions.contains_key(&name) {
+                kept.push(name);
+                continue;
+            }
+            if let Some(overloads) = self.functions.get(&qualified).cloned() {
+                let signatures = self.signatures[&qualified].iter().map(|signature| signature.replacen(&prefix, "", 1)).collect();
+                self.functions.insert(name.clone(), overloads);
+                self.signatures.insert(name.clone(), signatures);
+            } else if let Some(function) = self.user_functions.get(&qualified).cloned() {
+                self.user_functions.insert(name.clone(), function);
+            }
+            imported.push(name);
+        }
+        Ok((imported, kept))
     }
 
-    pub fn eval(&mut self, expr: String) -> Result<f64, String> {
-        self.parser = Parser::new(expr);
-        self.expression()
+    /// Adds a constant or replaces the one with the same name.
+    pub fn register_constant(&mut self, name: &str, value: f64) -> Result<(), String> {
+        validate_name(name)?;
+        self.constants.insert(name.to_string(), value);
+        Ok(())
     }
 
-    fn expression(&mut self) -> Result<f64, String> {
-        self.addition()
+    /// Adds an operator or replaces the one with the same symbol and fixity,
+    /// see `OperatorTable` for how precedence works.
+    ///
+    ///   calc.register_operator("<>", 5, Operator::Infix(Assoc::Left, |a, b| ((a - b).abs() < 1e-9) as i32 as f64))?;
+    ///   calc.register_operator("°", 40, Operator::Postfix(|a| a.to_radians()))?;
+    pub fn register_operator(&mut self, symbol: &str, precedence: u32, operator: Operator) -> Result<(), String> {
+        self.cache.clear();
+        Arc::make_mut(&mut self.operators).add(symbol, precedence, operator)
     }
 
-    fn addition(&mut self) -> Result<f64, String> {
-        let mut ret = self.multiplication()?;
+    pub fn operators(&self) -> &OperatorTable {
+        &self.operators
+    }
 
-        while let token = self.parser.peek() {
-            match token {
-                '+' => {
-                    self.parser.advance();
-                    ret += self.multiplication()?;
-                }
-                '-' => {
-                    self.parser.advance();
-                    ret -= self.multiplication()?;
-                }
-                _ => break
+    /// Swaps in another operator table, e.g. `OperatorTable::preset("math")`.
+    pub fn set_operators(&mut self, operators: OperatorTable) {
+        self.operators = Arc::new(operators);
+        self.cache.clear();
+    }
+
+    /// Function names with their signatures like `clamp(x, min, max)`.
+    pub fn signatures(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.signatures.iter().flat_map(|(name, signatures)| signatures.iter().map(move |signature| (name.as_str(), signature.as_str())))
+    }
+
+    pub fn constants(&self) -> impl Iterator<Item = (&str, f64)> {
+        self.constants.iter().map(|(name, &value)| (name.as_str(), value))
+    }
+
+    /// Functions defined in expressions, with their signatures like
+    /// `sq(x)`.
+    pub fn definitions(&self) -> impl Iterator<Item = (&str, String)> {
+        self.user_functions.iter().map(|(name, function)| (name.as_str(), format!("{}({})", name, function.params.join(", "))))
+    }
+
+    /// The function `name` defined in an expression, written back as source
+    /// like `sq(x) = x ^ 2`.
+    pub fn definition(&self, name: &str) -> Option<String> {
+        let function = self.user_functions.get(name)?;
+        Some(format!("{}({}) = {}", name, function.params.join(", "), Expr::from(&*function.body)))
+    }
+
+    /// Forgets the functions defined in expressions.
+    pub fn clear_definitions(&mut self) {
+        self.user_functions.clear();
+    }
+
+    /// Makes `name` evaluate to `value`, the builtin constants take
+    /// precedence.
+    pub fn define(&mut self, name: &str, value: impl Into<Value>) {
+        self.values.insert(name.to_string(), value.into());
+    }
+
+    pub fn undefine(&mut self, name: &str) {
+        self.values.remove(name);
+    }
+
+    /// Sets a variable like `name = value` in an expression would, so
+    /// expressions evaluated later can use it.
+    pub fn set_var(&mut self, name: &str, value: impl Into<Value>) -> Result<(), String> {
+        validate_name(name)?;
+        self.assignable(name)?;
+        self.formulas.remove(name);
+        self.variables.insert(name.to_string(), value.into());
+        Ok(())
+    }
+
+    /// A variable set with `set_var` or assigned in an expression.
+    pub fn get_var(&self, name: &str) -> Option<Value> {
+        self.variables.get(name).cloned()
+    }
+
+    /// Every variable with its value, in no particular order.
+    pub fn vars(&self) -> impl Iterator<Item = (&str, Value)> {
+        self.variables.iter().map(|(name, value)| (name.as_str(), value.clone()))
+    }
+
+    /// Formulas defined with `name := expression`, with the expression
+    /// written back as source like `w * h`.
+    pub fn formulas(&self) -> impl Iterator<Item = (&str, String)> {
+        self.formulas.iter().map(|(name, formula)| (name.as_str(), Expr::from(&*formula.body).to_string()))
+    }
+
+    /// Forgets the variables and formulas.
+    pub fn clear_vars(&mut self) {
+        self.variables.clear();
+        self.formulas.clear();
+    }
+
+    /// Sets what unknown names are looked up with, variables and constants
+    /// still take precedence:
+    ///
+    ///   calc.set_resolver(|name: &str| std::env::var(name).ok()?.parse().ok());
+    pub fn set_resolver(&mut self, resolver: impl Resolver + 'static) {
+        self.resolver = Some(Rc::new(resolver));
+    }
+
+    pub fn clear_resolver(&mut self) {
+        self.resolver = None;
+    }
+
+    /// Sets the currencies, with how many of each one unit of a common base
+    /// currency buys, so `{"USD": 1, "EUR": 0.92}` makes `100 USD in EUR`
+    /// 92 EUR.
+    pub fn set_rates(&mut self, rates: HashMap<String, f64>) {
+        self.rates = rates;
+    }
+
+    pub fn rates(&self) -> impl Iterator<Item = (&str, f64)> {
+        self.rates.iter().map(|(code, &rate)| (code.as_str(), rate))
+    }
+
+    /// Why `name` can't be assigned to, if it can't.
+    fn assignable(&self, name: &str) -> Result<(), String> {
+        let reason = if self.constants.contains_key(name) {
+            "a constant"
+        } else if self.functions.contains_key(name) || is_special(name) {
+            "a function"
+        } else if self.values.contains_key(name) {
+            "read-only"
+        } else {
+            return Ok(());
+        };
+        Err(format!("Can't assign to '{}', it is {}", name, reason))
+    }
+
+    /// Whether `name` is a builtin function or constant.
+    pub fn is_builtin(&self, name: &str) -> bool {
+        self.functions.contains_key(name) || self.constants.contains_key(name) || is_special(name)
+    }
+
+    pub fn set_env_vars(&mut self, enabled: bool) {
+        self.env_vars = enabled;
+        self.cache.clear();
+    }
+
+    /// Accept numbers as they are copied from documents, see
+    /// `lexer::sanitize`.
+    pub fn set_sanitize(&mut self, enabled: bool) {
+        self.sanitize = enabled;
+        self.cache.clear();
+    }
+
+    pub fn sanitize(&self) -> bool {
+        self.sanitize
+    }
+
+    /// Read an SI prefix right after a number as a power of ten, so `4.7k`
+    /// is 4700 and `47u` is 0.000047. Off by default, where `2n` is `2*n`.
+    pub fn set_si_prefixes(&mut self, enabled: bool) {
+        self.si_prefixes = enabled;
+        self.cache.clear();
+    }
+
+    /// Sets the decimal point to `.` or `,`. With `,` numbers are read and
+    /// written like `3,14`, and as in spreadsheets arguments and list
+    /// items are separated by `;` rather than a comma, `max(1,5; 2)`. A `;`
+    /// outside of parentheses and brackets still separates statements.
+    pub fn set_decimal_separator(&mut self, separator: char) -> Result<(), String> {
+        self.decimal_comma = match separator {
+            '.' => false,
+            ',' => true,
+            _ => return Err(format!("Expected '.' or ',' as the decimal separator but got '{}'", separator)),
+        };
+        self.formatter.decimal_comma = self.decimal_comma;
+        self.cache.clear();
+        Ok(())
+    }
+
+    pub fn decimal_separator(&self) -> char {
+        if self.decimal_comma { ',' } else { '.' }
+    }
+
+    pub fn set_warnings(&mut self, enabled: bool) {
+        self.warn = enabled;
+        self.cache.clear();
+    }
+
+    /// Makes operators and functions that give infinity or NaN from finite
+    /// numbers fail with a `Domain` error, like `sqrt(-1)` that is undefined,
+    /// or for `1/0` a `DivisionByZero` one. Off by default, following IEEE 754.
+    pub fn set_strict(&mut self, enabled: bool) {
+        self.strict = enabled;
+    }
+
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Warnings collected by the last `eval`, empty unless enabled.
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        self.cache.clear();
+    }
+
+    /// With `Degrees` the builtin `sin`, `cos` and `tan` take degrees and
+    /// `asin`, `acos` and `atan` return them.
+    pub fn set_angle_unit(&mut self, unit: AngleUnit) {
+        self.angle_unit = unit;
+    }
+
+    pub fn angle_unit(&self) -> AngleUnit {
+        self.angle_unit
+    }
+
+    /// Sets how `round` rounds, and the results the formatter cuts to a
+    /// precision, `Rounding::HalfEven` for financial calculations.
+    pub fn set_rounding(&mut self, rounding: Rounding) {
+        self.rounding = rounding;
+        self.formatter.rounding = rounding;
+    }
+
+    pub fn rounding(&self) -> Rounding {
+        self.rounding
+    }
+
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.arithmetic = arithmetic;
+    }
+
+    pub fn arithmetic(&self) -> Arithmetic {
+        self.arithmetic
+    }
+
+    /// Restarts the random numbers of `rand`, `randint` and `montecarlo` from
+    /// `seed`, so the same seed gives the same numbers. They start from the
+    /// time otherwise.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng.set(seed);
+    }
+
+    /// Sets how `format` writes numbers, e.g. with 4 significant digits.
+    pub fn set_formatter(&mut self, formatter: Formatter) {
+        self.formatter = formatter;
+    }
+
+    pub fn formatter(&self) -> Formatter {
+        self.formatter
+    }
+
+    /// `value` as the set `Formatter` writes it.
+    pub fn format(&self, value: Value) -> String {
+        let separator = if self.formatter.decimal_comma { "; " } else { ", " };
+        match value {
+            Value::Number(x) => self.formatter.format(x),
+            Value::Bool(b) => b.to_string(),
+            Value::List(xs) => {
+                let xs: Vec<String> = xs.into_iter().map(|x| self.formatter.format(x)).collect();
+                format!("[{}]", xs.join(separator))
+            }
+            #[cfg(feature = "decimal")]
+            Value::Decimal(d) => d.to_string(),
+            Value::Fraction(r) => r.to_string(),
+            Value::Integer(n) => n.to_string(),
+            Value::Radix(n, base) => format::in_base(n, base),
+            Value::Interval(i) => i.to_string(),
+            #[cfg(feature = "units")]
+            Value::Quantity(q) => format!("{} {}", self.formatter.format(q.value()), q.unit()),
+            Value::Date(d) => d.to_string(),
+            Value::Duration(d) => d.to_string(),
+            Value::Formula(f) => f.to_string(),
+            Value::Lambda(f) => f.to_string(),
+            Value::Plot(p) => p.to_string(),
+            Value::Histogram(h) => h.to_string(),
+            #[cfg(feature = "matrix")]
+            Value::Matrix(m) => {
+                let rows: Vec<String> = (0..m.rows()).map(|row| self.format(Value::List(m.row(row).to_vec()))).collect();
+                format!("[{}]", rows.join(separator))
             }
         }
+    }
 
-        Ok(ret)
+    pub fn stats(&self) -> &Stats {
+        &self.stats
     }
 
-    fn multiplication(&mut self) -> Result<f64, String> {
-        let mut ret = self.unary()?;
+    pub fn reset_stats(&mut self) {
+        self.stats = Stats::default();
+    }
 
-        while let token = self.parser.peek() {
-            match token {
-                '*' => {
-                    self.parser.advance();
-                    ret *= self.unary()?;
-                }
-                '/' => {
-                    self.parser.advance();
-                    ret /= self.unary()?;
-                }
-                '%' => {
-                    self.parser.advance();
-                    ret %= self.unary()?;
+    /// Storing `true` in the flag makes the running `eval` fail with an
+    /// `Interrupted` error, it is cleared when the next `eval` starts.
+    pub fn interrupt_flag(&self) -> Arc<AtomicBool> {
+        self.interrupted.clone()
+    }
+
+    /// Whether `expr` defines a function, or its last statement does, `eval`
+    /// returns NaN for those.
+    pub fn is_definition(&self, expr: &str) -> bool {
+        let Ok(expr) = self.compile(expr) else { return false };
+        let last = match &expr.node {
+            Node::Sequence(statements) => statements.last(),
+            node => Some(node),
+        };
+        matches!(last, Some(Node::Define { .. }))
+    }
+
+    /// Parses `expr` once for evaluating it any number of times with
+    /// `evaluate`.
+    pub fn compile(&self, expr: &str) -> Result<Expression, Diagnostic> {
+        let parser = Parser::with_options(expr, &self.operators, self.sanitize, self.si_prefixes, self.decimal_comma);
+        Compiler::new(parser, &self.operators, &self.interrupted)
+            .with_options(self.limits, self.env_vars, self.warn)
+            .compile(expr.to_string())
+    }
+
+    /// The expression of `Expression::to_bytes`, as `compile` parsed it
+    /// wherever that was, with the operators of this calculator for its
+    /// symbols. Fails for bytes of a later version of the library or that
+    /// are corrupt, rather than misreading them.
+    pub fn load_expression(&self, bytes: &[u8]) -> Result<Expression, Diagnostic> {
+        encoding::decode(bytes, &self.operators)
+    }
+
+    /// Every syntax error of `expr`, where `compile` stops at the first, for
+    /// editors and `calculator run --check`. Empty if it compiles.
+    ///
+    /// After each error the parser recovers at the next operator, comma,
+    /// bracket or `;`: the tokens up to it are left out and the rest is
+    /// parsed again, so `1 + * 2 3 4` has two errors rather than one for
+    /// each token after the first. Checking stops at an error that isn't a
+    /// syntax error, which isn't reported. With warnings enabled, an
+    /// expression without errors gives those about its grouping, like for
+    /// `2^3^2`.
+    pub fn check(&self, expr: &str) -> Vec<Diagnostic> {
+        let mut chars: Vec<char> = expr.chars().collect();
+        let mut errors = vec![];
+        // Leaving out a `)` that closed a `(` makes the rest incomplete.
+        let mut closed = false;
+        loop {
+            let source: String = chars.iter().collect();
+            let err = match self.compile(&source) {
+                Ok(compiled) => {
+                    if errors.is_empty() {
+                        errors = compiled.warnings;
+                    }
+                    break;
                 }
-                '^' => {
-                    self.parser.advance();
-                    ret = ret.powf(self.unary()?);
+                Err(err) => err,
+            };
+            let (at, expected) = match err.kind {
+                ErrorKind::Syntax { pos, expected } if pos < chars.len() => (pos, expected),
+                ErrorKind::Syntax { .. } | ErrorKind::Incomplete if !closed => {
+                    errors.push(err);
+                    break;
                 }
-                _ => break
+                _ => break,
+            };
+            let parser = Parser::with_options(&source, &self.operators, self.sanitize, self.si_prefixes, self.decimal_comma);
+            let sync = |kind: &TokenKind| matches!(kind, TokenKind::Symbol(_) | TokenKind::Comma | TokenKind::Semicolon | TokenKind::Close | TokenKind::CloseBracket
+                | TokenKind::Equals | TokenKind::Arrow | TokenKind::ColonEquals | TokenKind::End);
+            let tokens = parser.tokens();
+            let Some(i) = tokens.iter().position(|token| token.span.end > at) else { break };
+            // Where an operand was expected the token is left out, otherwise
+            // everything up to the next place to recover at.
+            let end = match sync(&tokens[i].kind) || expected == Some(OPERAND) {
+                true => tokens[i].span.end,
+                false => tokens[i + 1..].iter().find(|token| sync(&token.kind)).map_or(chars.len(), |token| token.span.start),
+            };
+            closed |= matches!(tokens[i].kind, TokenKind::Close | TokenKind::CloseBracket);
+            let skipped = tokens[i].span.start..end.min(chars.len());
+            errors.push(err);
+            if chars[skipped.clone()].iter().all(|c| c.is_whitespace()) {
+                break;
             }
+            chars[skipped].fill(' ');
+        }
+        errors
+    }
+
+    /// `expr` compiled, from the cache if it was compiled before.
+    fn cached(&mut self, expr: String) -> Result<Rc<Expression>, Diagnostic> {
+        if let Some(compiled) = self.cache.get(&expr) {
+            self.stats.cache_hits += 1;
+            return Ok(compiled);
         }
+        let compiled = Rc::new(self.compile(&expr)?);
+        self.cache.insert(expr, compiled.clone());
+        Ok(compiled)
+    }
+
+    /// How many compiled expressions `eval` keeps to skip parsing the same
+    /// text again, the least recently used go first. 128 by default, 0
+    /// turns the cache off.
+    pub fn set_cache_size(&mut self, size: usize) {
+        self.cache.set_capacity(size);
+    }
+
+    pub fn cache_size(&self) -> usize {
+        self.cache.capacity()
+    }
+
+    /// The expressions in the cache, the most recently used first.
+    pub fn cached_expressions(&self) -> Vec<&str> {
+        self.cache.sources()
+    }
 
+    /// Checks that `expr` parses and only calls functions that exist, with
+    /// as many arguments as they take, without evaluating it. The names it
+    /// reads don't have to be defined yet, see `Expression::variables`.
+    pub fn validate(&self, expr: &str) -> Result<(), Diagnostic> {
+        let expr = self.compile(expr)?;
+        self.check_calls(&expr.node, &mut HashMap::new())
+    }
+
+    pub fn evaluate(&mut self, expr: &Expression) -> Result<Value, Diagnostic> {
+        let start = now();
+        self.interrupted.store(false, Ordering::Relaxed);
+        let ret = self.run(expr);
+        self.record(start, ret.as_ref().err());
+        ret
+    }
+
+    /// `compile` and `evaluate` in one go.
+    pub fn eval(&mut self, expr: String) -> Result<Value, Diagnostic> {
+        let start = now();
+        self.interrupted.store(false, Ordering::Relaxed);
+        let ret = self.cached(expr).and_then(|expr| self.run(&expr));
+        self.record(start, ret.as_ref().err());
+        ret
+    }
+
+    /// `eval` with the arithmetic of `N`, giving the result as an `N`, see
+    /// `Numeric`. The arithmetic is put back afterwards.
+    pub fn eval_as<N: Numeric>(&mut self, expr: &str) -> Result<N, Diagnostic> {
+        let arithmetic = core::mem::replace(&mut self.arithmetic, N::ARITHMETIC);
+        let ret = self.eval(expr.to_string());
+        self.arithmetic = arithmetic;
+        let value = ret?;
+        N::from_value(&value).ok_or_else(|| mismatch(N::EXPECTED, value, 0..expr.chars().count()))
+    }
+
+    /// `eval` with the value of every statement of `a = 2; b = 3; a*b`
+    /// rather than just the last.
+    pub fn eval_each(&mut self, expr: String) -> Result<Vec<Value>, Diagnostic> {
+        let start = now();
+        self.interrupted.store(false, Ordering::Relaxed);
+        let ret = self.cached(expr).and_then(|expr| self.run_each(&expr));
+        self.record(start, ret.as_ref().err());
+        ret
+    }
+
+    /// Evaluates `expr` with the variables in `bindings`, which are only set
+    /// for this evaluation and hide variables of the same name:
+    ///
+    ///   let record = HashMap::from([("rate".to_string(), 40.), ("hours".to_string(), 6.)]);
+    ///   calc.eval_with("rate * hours", &record)?;
+    pub fn eval_with(&mut self, expr: &str, bindings: &Bindings) -> Result<Value, Diagnostic> {
+        self.bind(bindings);
+        let ret = self.eval(expr.to_string());
+        self.bindings.clear();
+        ret
+    }
+
+    /// `evaluate` with `bindings` like `eval_with`.
+    pub fn evaluate_with(&mut self, expr: &Expression, bindings: &Bindings) -> Result<Value, Diagnostic> {
+        self.bind(bindings);
+        let ret = self.evaluate(expr);
+        self.bindings.clear();
+        ret
+    }
+
+    /// `eval` of each of `exprs` in order, one result for each.
+    pub fn eval_many(&mut self, exprs: &[&str]) -> Vec<Result<Value, Diagnostic>> {
+        exprs.iter().map(|expr| self.eval(expr.to_string())).collect()
+    }
+
+    /// The other formulas each of `formulas` reads by name, in the order
+    /// they first appear, `tax` reads `total` in
+    /// `{"total": "price * qty", "tax": "total * 0.19"}`.
+    pub fn dependencies(&self, formulas: &HashMap<String, String>) -> Result<HashMap<String, Vec<String>>, Diagnostic> {
+        let mut names: Vec<&String> = formulas.keys().collect();
+        names.sort();
+        let mut ret = HashMap::new();
+        for name in names {
+            if let Err(msg) = validate_name(name).and_then(|_| self.assignable(name)) {
+                return Err(Diagnostic::new(msg).with_kind(ErrorKind::Assignment));
+            }
+            let expr = self.compile(&formulas[name]).map_err(|err| in_formula(name, err))?;
+            let reads = expr.variables().into_iter().filter(|var| formulas.contains_key(var)).collect();
+            ret.insert(name.clone(), reads);
+        }
         Ok(ret)
     }
 
-    fn unary(&mut self) -> Result<f64, String> {
-        match self.parser.peek() {
-            '-' => {
-                self.parser.advance();
-                Ok(-self.parentheses()?)
+    /// The names of `formulas` in an order to evaluate them in, each after
+    /// the ones it reads and otherwise by name. Fails on a cycle like
+    /// `Circular reference a -> b -> a`.
+    pub fn formula_order(&self, formulas: &HashMap<String, String>) -> Result<Vec<String>, Diagnostic> {
+        let dependencies = self.dependencies(formulas)?;
+        let mut names: Vec<&str> = dependencies.keys().map(String::as_str).collect();
+        names.sort();
+        // False while a name is on the stack, true once it is in the order.
+        let mut done: HashMap<&str, bool> = HashMap::new();
+        let mut order = vec![];
+        for root in names {
+            if done.contains_key(root) {
+                continue;
+            }
+            // Without recursion, so a long chain can't overflow the stack.
+            done.insert(root, false);
+            let mut stack = vec![(root, 0)];
+            while let Some(&mut (name, ref mut next)) = stack.last_mut() {
+                let Some(read) = dependencies[name].get(*next) else {
+                    done.insert(name, true);
+                    order.push(name.to_string());
+                    stack.pop();
+                    continue;
+                };
+                *next += 1;
+                match done.get(read.as_str()) {
+                    Some(true) => {}
+                    Some(false) => {
+                        let start = stack.iter().position(|&(visiting, _)| visiting == read).unwrap_or(0);
+                        let cycle: Vec<&str> = stack[start..].iter().map(|&(visiting, _)| visiting).chain([read.as_str()]).collect();
+                        return Err(Diagnostic::new(format!("Circular reference {}", cycle.join(" -> "))).with_kind(ErrorKind::Assignment));
+                    }
+                    None => {
+                        done.insert(read, false);
+                        stack.push((read, 0));
+                    }
+                }
             }
-            _ => self.parentheses()
         }
+        Ok(order)
     }
 
-    fn parentheses(&mut self) -> Result<f64, String> {
-        match self.parser.peek() {
-            '(' => {
-                self.parser.advance();
-                let ret = self.expression();
-                self.parser.consume(')', "Expected ')'")?;
-                ret
+    /// Evaluates `formulas` that read each other by name, each after the
+    /// ones it reads like the cells of a spreadsheet:
+    ///
+    ///   let formulas = HashMap::from([
+    ///       ("total".to_string(), "price * qty".to_string()),
+    ///       ("tax".to_string(), "total * 0.19".to_string()),
+    ///   ]);
+    ///   calc.eval_formulas(&formulas)?["tax"];
+    ///
+    /// The names only hold the values while the formulas are evaluated. The
+    /// first error fails the whole set, with the name of its formula.
+    pub fn eval_formulas(&mut self, formulas: &HashMap<String, String>) -> Result<HashMap<String, Value>, Diagnostic> {
+        let order = self.formula_order(formulas)?;
+        let mut ret = HashMap::new();
+        let mut result = Ok(());
+        for name in &order {
+            match self.eval(formulas[name].clone()) {
+                Ok(value) => {
+                    self.define(name, value.clone());
+                    ret.insert(name.clone(), value);
+                }
+                Err(err) => {
+                    result = Err(in_formula(name, err));
+                    break;
+                }
             }
-            _ => self.atom()
         }
+        for name in ret.keys() {
+            self.undefine(name);
+        }
+        result.map(|_| ret)
     }
 
-    fn atom(&mut self) -> Result<f64, String> {
-        match self.parser.peek() {
-            '0'..='9' => self.number(),
-            _ => self.call()
+    /// Evaluates the definitions of `src`, one per line like `sq(x) = x^2`,
+    /// `rate = 0.07` or `total := price * (1 + rate)`, and returns their
+    /// names in order. Blank lines and those starting with `#` are skipped.
+    ///
+    /// It defines all of them or none: the lines are evaluated in a clone,
+    /// which replaces the calculator once every line succeeded. The first
+    /// line that fails or isn't a definition is the error, with its number.
+    pub fn load_definitions(&mut self, src: &str) -> Result<Vec<String>, Diagnostic> {
+        let mut trial = self.clone();
+        let mut names = vec![];
+        for (i, line) in src.lines().enumerate().map(|(i, line)| (i + 1, line.trim())) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let in_line = |err: Diagnostic| Diagnostic { message: format!("Line {}: {}", i, err.message), ..err };
+            let expr = trial.compile(line).map_err(in_line)?;
+            match &expr.node {
+                Node::Define { name, .. } | Node::Assign { name, .. } => names.push(name.clone()),
+                _ => return Err(in_line(Diagnostic::new("Expected a definition like 'f(x) = ...', 'x = ...' or 'x := ...'")
+                    .with_expected("a definition")
+                    .with_label(0..line.len(), "not a definition"))),
+            }
+            trial.evaluate(&expr).map_err(in_line)?;
         }
+        *self = trial;
+        Ok(names)
     }
 
-    fn number(&mut self) -> Result<f64, String> {
-        let mut num = 0.;
+    /// Forgets the function, variable or formula `name` that expressions
+    /// defined, returns whether there was one.
+    pub fn forget(&mut self, name: &str) -> bool {
+        let function = self.user_functions.remove(name).is_some();
+        let formula = self.formulas.remove(name).is_some();
+        self.variables.remove(name).is_some() || function || formula
+    }
+
+    /// `evaluate_with` for a row of `Expression::eval_batch`, running the
+    /// program of the expression if it has one.
+    pub(crate) fn evaluate_row(&mut self, expr: &Expression, program: Option<&Program>, bindings: &Bindings) -> Result<Value, Diagnostic> {
+        self.bind(bindings);
+        let ret = match program {
+            Some(program) => self.run_program(program),
+            None => self.evaluate(expr),
+        };
+        self.bindings.clear();
+        ret
+    }
+
+    /// Runs a program from `Expression::to_program`, with the same result,
+    /// errors and stats as `evaluate` of its expression.
+    pub fn run_program(&mut self, program: &Program) -> Result<Value, Diagnostic> {
+        let start = now();
+        self.interrupted.store(false, Ordering::Relaxed);
+        let ret = match self.link(program) {
+            Some((values, functions)) => self.execute(program, &values, &functions),
+            None => self.run(&program.expression),
+        };
+        self.record(start, ret.as_ref().err());
+        ret
+    }
+
+    /// The values of the names a program reads and the builtins it calls,
+    /// `None` for `if`. None if its expression has to be evaluated instead.
+    fn link(&self, program: &Program) -> Option<(Vec<f64>, Vec<Option<Function>>)> {
+        if self.strict || self.warn || self.steps.is_some() || self.arithmetic != Arithmetic::Float || self.angle_unit == AngleUnit::Degrees || self.rounding != Rounding::HalfUp {
+            return None;
+        }
+        let values = program.names.iter()
+            .map(|(name, span)| match self.name(name, span.clone()) {
+                Ok(Value::Number(x)) => Some(x),
+                _ => None,
+            })
+            .collect::<Option<Vec<f64>>>()?;
+        let functions = program.calls.iter()
+            .map(|(name, arguments)| match self.overload(name, *arguments) {
+                _ if name == CONDITIONAL => Some(None),
+                Some((_, Values(..) | Predicate(..))) | None => None,
+                Some((_, func)) if !func.is_variadic() || func.arity() <= *arguments => Some(Some(func.clone())),
+                Some(_) => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some((values, functions))
+    }
 
-        while let token = self.parser.peek() {
-            match token {
-                '0'..='9' => {
-                    self.parser.advance();
-                    num = num * 10. + token.to_digit(10).unwrap() as f64;
+    fn execute(&mut self, program: &Program, values: &[f64], functions: &[Option<Function>]) -> Result<Value, Diagnostic> {
+        self.warnings = program.expression.warnings.clone();
+        self.scopes.clear();
+        self.taken = 0;
+        self.deadline = self.limits.timeout.and_then(|timeout| Some(now()? + timeout));
+        let mut counts = vec![0; program.calls.len()];
+        let ret = self.instructions(program, values, functions, &mut counts);
+        for ((name, _), count) in program.calls.iter().zip(counts).filter(|&(_, count)| count > 0) {
+            match self.stats.calls.get_mut(name) {
+                Some(calls) => *calls += count,
+                None => {
+                    self.stats.calls.insert(name.clone(), count);
                 }
-                _ => break
             }
         }
+        let x = ret?;
+        Ok(if program.boolean { Value::Bool(x != 0.) } else { Value::Number(x) })
+    }
 
-        if self.parser.peek() == '.' {
-            self.parser.advance();
-            // fraction part
-            let mut frac = 0.;
-            let mut weight = 0.1;
-            while let token = self.parser.peek() {
-                match token {
-                    '0'..='9' => {
-                        self.parser.advance();
-                        frac += weight * token.to_digit(10).unwrap() as f64;
-                        weight /= 10.;
-                    }
-                    _ => break
+    fn instructions(&mut self, program: &Program, values: &[f64], functions: &[Option<Function>], counts: &mut [usize]) -> Result<f64, Diagnostic> {
+        let domain = |msg: String, span: usize| Diagnostic::new(msg).with_kind(ErrorKind::Domain).with_label(program.spans[span].clone(), "");
+        let truth = |b: bool| if b { 1. } else { 0. };
+        let mut stack: Vec<f64> = Vec::with_capacity(program.depth);
+        let pop = |stack: &mut Vec<f64>| stack.pop().unwrap();
+        let mut pc = 0;
+        while let Some(&op) = program.code.get(pc) {
+            pc += 1;
+            match op {
+                Op::Push(x) => stack.push(x),
+                Op::Load(name) => stack.push(values[name]),
+                Op::Step(span) => self.step_limits(&program.spans[span])?,
+                Op::Unary(f) => {
+                    let x = pop(&mut stack);
+                    stack.push(f(x));
                 }
-            }
-            num += frac;
-        }
-
-        Ok(num)
-    }
-
-    fn call(&mut self) -> Result<f64, String> {
-        match self.parser.peek() {
-            'a'..='z' | 'A'..='Z' => {
-                let identifier = self.identifier();
-
-                if self.parser.peek() == '(' {
-                    self.parser.consume('(', "Expected '('")?;
-                    let arguments = self.arguments()?;
-                    self.parser.consume(')', "Expected ')'")?;
-
-                    if let Some(func) = self.functions.get(&identifier) {
-                        match func {
-                            OneArg(f) => {
-                                if arguments.len() != 1 {
-                                    return Err(format!("Expected 1 argument for function '{}'", identifier));
-                                }
-                                Ok(f(arguments[0]))
-                            },
-                            TwoArg(f, ) => {
-                                if arguments.len() != 2 {
-                                    return Err(format!("Expected 2 arguments for function '{}'", identifier));
-                                }
-                                Ok(f(arguments[0], arguments[1]))
-                            },
-                            ThreeArg(f) => {
-                                if arguments.len() != 3 {
-                                    return Err(format!("Expected 3 arguments for function '{}'", identifier));
-                                }
-                                Ok(f(arguments[0], arguments[1], arguments[2]))
-                            },
-                        }
-                    } else {
-                        Err(format!("Unknown function '{}'", identifier))
+                Op::FallibleUnary(f, span) => {
+                    let x = pop(&mut stack);
+                    stack.push(f(x).map_err(|msg| domain(msg, span))?);
+                }
+                Op::Binary(f) => {
+                    let (y, x) = (pop(&mut stack), pop(&mut stack));
+                    stack.push(f(x, y));
+                }
+                Op::Compare(f) => {
+                    let (y, x) = (pop(&mut stack), pop(&mut stack));
+                    stack.push(truth(f(x, y)));
+                }
+                Op::ShortCircuit(f, end) => {
+                    let x = *stack.last().unwrap() != 0.;
+                    if f(x, true) == f(x, false) {
+                        *stack.last_mut().unwrap() = truth(f(x, true));
+                        pc = end;
                     }
-                } else {
-                    if let Some(&value) = self.constants.get(&identifier) {
-                        Ok(value)
-                    } else {
-                        Err(format!("Unknown constant '{}'", identifier))
+                }
+                Op::Logical(f) => {
+                    let (y, x) = (pop(&mut stack) != 0., pop(&mut stack) != 0.);
+                    stack.push(truth(f(x, y)));
+                }
+                Op::LogicalUnary(f) => {
+                    let x = pop(&mut stack) != 0.;
+                    stack.push(truth(f(x)));
+                }
+                Op::Call(call, span) => {
+                    counts[call] += 1;
+                    let start = stack.len() - program.calls[call].1;
+                    let arguments = &stack[start..];
+                    let ret = match &functions[call] {
+                        Some(OneArg(f)) => Ok(f(arguments[0])),
+                        Some(TwoArg(f)) => Ok(f(arguments[0], arguments[1])),
+                        Some(ThreeArg(f)) => Ok(f(arguments[0], arguments[1], arguments[2])),
+                        Some(Variadic(_, f)) => Ok(f(arguments)),
+                        Some(Fallible(_, f)) => f(arguments),
+                        Some(Closure(_, f) | VariadicClosure(_, f)) => f(arguments),
+                        _ => unreachable!(),
+                    };
+                    stack.truncate(start);
+                    stack.push(ret.map_err(|msg| domain(msg, span))?);
+                }
+                Op::Count(call) => counts[call] += 1,
+                Op::JumpUnless(target) => {
+                    if pop(&mut stack) == 0. {
+                        pc = target;
                     }
                 }
-            }
-            _ => {
-                Err(format!("Expected a identifier but got {}", self.parser.peek()))
+                Op::Jump(target) => pc = target,
             }
         }
+        Ok(pop(&mut stack))
     }
 
-    fn identifier(&mut self) -> String {
-        let mut ret = String::new();
-        while let token = self.parser.peek() {
-            match token {
-                'a'..='z' | 'A'..='Z' | '0'..='9' => {
-                    ret.push(token);
-                    self.parser.advance();
-                }
-                _ => break
-            }
-        }
-        ret
+    fn bind(&mut self, bindings: &Bindings) {
+        self.bindings = bindings.iter().map(|(name, &value)| (name.clone(), Value::Number(value))).collect();
     }
 
-    fn arguments(&mut self) -> Result<Vec<f64>, String> {
-        let mut ret = vec![self.expression()?];
-        while let token = self.parser.peek() {
-            match token {
-                ',' => {
-                    self.parser.advance();
-                    ret.push(self.expression()?);
-                }
-                _ => break,
-            }
+    /// The value of `expr` if it evaluates, without keeping anything it
+    /// changes: assignments are undone, random numbers are drawn again and
+    /// nothing is counted in the stats.
+    /// Definitions aren't evaluated, those followed by other statements are
+    /// undone. It gives up after a few thousand steps or milliseconds, within
+    /// the limits set.
+    pub fn preview(&mut self, expr: &str) -> Option<Value> {
+        if self.is_definition(expr) {
+            return None;
         }
-        Ok(ret)
+        let expr = self.compile(expr).ok()?;
+        let (variables, formulas) = (self.variables.clone(), self.formulas.clone());
+        let user_functions = matches!(expr.node, Node::Sequence(_)).then(|| self.user_functions.clone());
+        let rng = self.rng.get();
+        let warnings = core::mem::take(&mut self.warnings);
+        let limits = self.limits;
+        self.limits.max_steps = Some(limits.max_steps.map_or(PREVIEW_STEPS, |max| max.min(PREVIEW_STEPS)));
+        self.limits.timeout = Some(limits.timeout.map_or(PREVIEW_TIMEOUT, |max| max.min(PREVIEW_TIMEOUT)));
+        let ret = self.run(&expr);
+        self.limits = limits;
+        self.variables = variables;
+        self.formulas = formulas;
+        if let Some(user_functions) = user_functions {
+            self.user_functions = user_functions;
+        }
+        self.warnings = warnings;
+        self.rng.set(rng);
+        ret.ok()
+    }
+
+    /// Evaluates `expr` like `eval`, along with every operation in the order
+    /// it was performed: `2+3*4^2` is `4^2 = 16`, `3*16 = 48`, `2+48 = 50`.
+    pub fn explain(&mut self, expr: String) -> Result<(Value, Vec<String>), Diagnostic> {
+        self.steps = Some(vec![]);
+        let ret = self.eval(expr);
+        let steps = self.steps.take().unwrap_or_default();
+        ret.map(|value| (value, steps))
+    }
+
+    fn run(&mut self, expr: &Expression) -> Result<Value, Diagnostic> {
+        self.run_each(expr).map(|mut values| values.pop().unwrap())
+    }
+
+    /// The value of each statement of `expr`.
+    fn run_each(&mut self, expr: &Expression) -> Result<Vec<Value>, Diagnostic> {
+        self.warnings = expr.warnings.clone();
+        self.scopes.clear();
+        self.source = expr.source().to_string();
+        self.taken = 0;
+        self.deadline = self.limits.timeout.and_then(|timeout| Some(now()? + timeout));
+        let statements = match &expr.node {
+            Node::Sequence(statements) => statements.as_slice(),
+            node => core::slice::from_ref(node),
+        };
+        let mut values = vec![];
+        for statement in statements {
+            values.push(match self.exact(statement) {
+                Some(ret) => ret,
+                None => self.node(statement)?,
+            });
+        }
+        Ok(values)
+    }
+
+    /// `node` evaluated with the `Arithmetic` if it isn't `Float` and the
+    /// node only does what it can do exactly.
+    fn exact(&mut self, node: &Node) -> Option<Value> {
+        let (name, node) = match node {
+            Node::Assign { name, value, .. } => (Some(name), value.as_ref()),
+            node => (None, node),
+        };
+        // Errors and `explain` steps come from the float evaluation.
+        if name.is_some_and(|name| self.assignable(name).is_err()) || self.steps.is_some() {
+            return None;
+        }
+        let lookup = |name: &str| self.bindings.get(name).or(self.values.get(name)).or(self.variables.get(name)).cloned();
+        let ret = match self.arithmetic {
+            #[cfg(feature = "decimal")]
+            Arithmetic::Decimal { places } => match exact::evaluate::<Decimal>(node, &lookup)? {
+                Value::Decimal(d) => Value::Decimal(d.round(places)),
+                value => value,
+            },
+            Arithmetic::Rational => exact::evaluate::<Rational>(node, &lookup)?,
+            Arithmetic::Integer => exact::evaluate::<BigInt>(node, &lookup)?,
+            _ => return None,
+        };
+        if let Some(name) = name {
+            self.formulas.remove(name);
+            self.variables.insert(name.clone(), ret.clone());
+        }
+        Some(ret)
+    }
+
+    fn record(&mut self, start: Option<Instant>, err: Option<&Diagnostic>) {
+        self.stats.evaluations += 1;
+        if let Some(start) = start {
+            self.stats.time += start.elapsed();
+        }
+        if let Some(err) = err {
+            *self.stats.errors.entry(err.kind.name()).or_default() += 1;
+        }
+    }
+
+    fn step(&mut self, step: impl FnOnce() -> String, value: Value) {
+        if let Some(steps) = &mut self.steps {
+            steps.push(format!("{} = {}", step(), value));
+        }
+    }
+
+    fn check_interrupt(&self) -> Result<(), Diagnostic> {
+        if self.interrupted.load(Ordering::Relaxed) {
+            Err(Diagnostic::new("Interrupted").with_kind(ErrorKind::Interrupted))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Counts a step of the evaluation at `span` against the `Limits`.
+    fn step_limits(&mut self, span: &Range<usize>) -> Result<(), Diagnostic> {
+        self.check_interrupt()?;
+        self.taken += 1;
+        let limit = |msg: String| Err(Diagnostic::new(msg).with_kind(ErrorKind::Limit).with_label(span.clone(), "past the limit"));
+        if let Some(max) = self.limits.max_steps.filter(|&max| self.taken > max) {
+            return limit(format!("Evaluation takes more than {} steps", max));
+        }
+        match (self.deadline, self.limits.timeout) {
+            (Some(deadline), Some(timeout)) if Instant::now() > deadline => limit(format!("Evaluation takes longer than {:?}", timeout)),
+            _ => Ok(()),
+        }
+    }
+
+    /// How deep calls of defined functions and lambdas can nest.
+    fn max_call_depth(&self) -> usize {
+        self.limits.max_call_depth.map_or(MAX_CALL_DEPTH, |max| max.min(MAX_CALL_DEPTH))
+    }
+
+    fn node(&mut self, node: &Node) -> Result<Value, Diagnostic> {
+        match node {
+            Node::Number(value) => Ok(Value::Number(*value)),
+            Node::Name(name, span) if self.is_formula(name) => self.call_body(name, self.formulas[name].clone(), &[], span.clone()),
+            Node::Name(name, span) => self.name(name, span.clone()),
+            Node::Env(name, span) => env(name, span.clone()),
+            Node::Unary { symbol, operator, operand, span } => self.unary(symbol, operator, operand, span),
+            Node::Binary { symbol, operator, lhs, rhs, span } => {
+                self.step_limits(span)?;
+                let ret = match operator {
+                    Operator::Infix(_, f) => {
+                        let percent = matches!(&**rhs, Node::Unary { symbol, operator: Operator::Postfix(_), .. } if symbol == "%");
+                        let (lhs, rhs) = match (float(self.node(lhs)?), float(self.node(rhs)?)) {
+                            // `200 + 10%` adds 10% of 200.
+                            (Value::Number(lhs), Value::Number(rhs)) if percent && (symbol == "+" || symbol == "-") => (lhs, lhs * rhs),
+                            (Value::Number(lhs), Value::Number(rhs)) if symbol != PLUS_MINUS && symbol != IN => (lhs, rhs),
+                            (lhs, rhs) => {
+                                let ret = elementwise(symbol, &lhs, &rhs, *f, span.clone())?;
+                                self.step(|| format!("{}{}{}", lhs, symbol, rhs), ret.clone());
+                                return Ok(ret);
+                            }
+                        };
+                        let ret = f(lhs, rhs);
+                        if self.strict {
+                            strict_binary(symbol, lhs, rhs, ret, span)?;
+                        }
+                        self.step(|| format!("{}{}{}", lhs, symbol, rhs), ret.into());
+                        if self.warn {
+                            self.warn_stability(span.clone(), symbol == "+" || symbol == "-", &[lhs, rhs], ret);
+                        }
+                        return Ok(ret.into());
+                    }
+                    Operator::Comparison(f) => {
+                        let (lhs, rhs) = (self.number(lhs, span)?, self.number(rhs, span)?);
+                        let ret = f(lhs, rhs);
+                        self.step(|| format!("{}{}{}", lhs, symbol, rhs), ret.into());
+                        ret
+                    }
+                    Operator::Logical(f) => {
+                        let lhs = self.bool(lhs, span)?;
+                        if f(lhs, true) == f(lhs, false) {
+                            f(lhs, true)
+                        } else {
+                            let rhs = self.bool(rhs, span)?;
+                            self.step(|| format!("{}{}{}", lhs, symbol, rhs), f(lhs, rhs).into());
+                            f(lhs, rhs)
+                        }
+                    }
+                    _ => unreachable!(),
+                };
+                Ok(Value::Bool(ret))
+            }
+            Node::List(elements, span) => {
+                let elements = elements.iter().map(|element| self.node(element)).collect::<Result<Vec<Value>, _>>()?;
+                // A list of lists is a matrix, `[[1, 2], [3, 4]]`.
+                #[cfg(feature = "matrix")]
+                if !elements.is_empty() && elements.iter().all(|element| element.list().is_some()) {
+                    let rows = elements.into_iter().map(|row| row.list().unwrap().to_vec()).collect();
+                    return Matrix::from_rows(rows).map(Value::Matrix).map_err(|msg| {
+                        Diagnostic::new(msg).with_kind(ErrorKind::Domain).with_label(span.clone(), "")
+                    });
+                }
+                let xs = elements.into_iter()
+                    .map(|element| element.number().ok_or_else(|| mismatch("a number", element, span.clone())))
+                    .collect::<Result<Vec<f64>, _>>()?;
+                Ok(Value::List(xs))
+            }
+            Node::Index { list, index, span } => {
+                let list = self.node(list)?;
+                let len = match &list {
+                    Value::List(xs) => xs.len(),
+                    #[cfg(feature = "matrix")]
+                    Value::Matrix(m) => m.rows(),
+                    _ => return Err(mismatch("a list", list, span.clone())),
+                };
+                let index = self.number(index, span)?;
+                let Some(i) = integer(index).ok().and_then(|i| usize::try_from(i).ok()).filter(|&i| i < len) else {
+                    return Err(Diagnostic::new(format!("Index {} is out of range for a list of {} numbers", index, len))
+                        .with_kind(ErrorKind::Domain)
+                        .with_label(span.clone(), ""));
+                };
+                // A matrix is indexed by row, `m[1][0]`.
+                let ret = match &list {
+                    #[cfg(feature = "matrix")]
+                    Value::Matrix(m) => Value::List(m.row(i).to_vec()),
+                    _ => Value::Number(list.list().unwrap()[i]),
+                };
+                self.step(|| format!("{}[{}]", list, index), ret.clone());
+                Ok(ret)
+            }
+            Node::Call { name, arguments, name_span, span } => self.call(name, arguments, name_span.clone(), span.clone()),
+            Node::Assign { name, value, name_span } => {
+                if let Err(msg) = self.assignable(name) {
+                    return Err(Diagnostic::new(msg)
+                        .with_kind(ErrorKind::Assignment)
+                        .with_label(name_span.clone(), ""));
+                }
+
+                let value = self.node(value)?;
+                self.formulas.remove(name);
+                self.variables.insert(name.clone(), value.clone());
+                Ok(value)
+            }
+            Node::Define { name, params, body, name_span } if params.is_empty() => {
+                if let Err(msg) = self.assignable(name) {
+                    return Err(Diagnostic::new(msg)
+                        .with_kind(ErrorKind::Assignment)
+                        .with_label(name_span.clone(), ""));
+                }
+                if let Some(cycle) = self.cycle(&mut vec![name.clone()], body) {
+                    return Err(Diagnostic::new(format!("Circular reference {}", cycle.join(" -> ")))
+                        .with_kind(ErrorKind::Assignment)
+                        .with_label(name_span.clone(), ""));
+                }
+                self.variables.remove(name);
+                self.formulas.insert(name.clone(), UserFunction { params: vec![], body: body.clone() });
+                Ok(Value::Number(f64::NAN))
+            }
+            Node::Define { name, params, body, name_span } => {
+                if self.functions.contains_key(name) || is_special(name) {
+                    return Err(Diagnostic::new(format!("Can't define '{}', it is a builtin function", name))
+                        .with_kind(ErrorKind::Assignment)
+                        .with_label(name_span.clone(), ""));
+                }
+                self.user_functions.insert(name.clone(), UserFunction { params: params.clone(), body: body.clone() });
+                Ok(Value::Number(f64::NAN))
+            }
+            Node::Lambda { .. } => Ok(Value::Lambda(self.lambda(node).unwrap())),
+            Node::Sequence(statements) => self.sequence(statements),
+        }
+    }
+
+    fn sequence(&mut self, statements: &[Node]) -> Result<Value, Diagnostic> {
+        let mut ret = Value::Number(f64::NAN);
+        for statement in statements {
+            ret = self.node(statement)?;
+        }
+        Ok(ret)
+    }
+
+    fn unary(&mut self, symbol: &str, operator: &Operator, operand: &Node, span: &Range<usize>) -> Result<Value, Diagnostic> {
+        self.step_limits(span)?;
+        if let Operator::LogicalPrefix(f) = operator {
+            let x = self.bool(operand, span)?;
+            self.step(|| format!("{} {}", symbol, x), f(x).into());
+            return Ok(Value::Bool(f(x)));
+        }
+        let apply = |x: f64| match operator {
+            Operator::Prefix(f) | Operator::Postfix(f) => Ok(f(x)),
+            Operator::FalliblePostfix(f) => f(x),
+            _ => unreachable!(),
+        };
+        let domain = |msg| Diagnostic::new(msg).with_kind(ErrorKind::Domain).with_label(span.clone(), "");
+        let operand = self.node(operand)?;
+        let ret: Value = match &operand {
+            &Value::Number(x) => apply(x).map_err(domain)?.into(),
+            #[cfg(feature = "decimal")]
+            Value::Decimal(d) => apply(d.to_f64()).map_err(domain)?.into(),
+            Value::Fraction(r) => apply(r.to_f64()).map_err(domain)?.into(),
+            Value::Integer(n) => apply(n.to_f64()).map_err(domain)?.into(),
+            &Value::Radix(n, _) => apply(n as f64).map_err(domain)?.into(),
+            Value::List(xs) => xs.iter().map(|&x| apply(x)).collect::<Result<Vec<f64>, _>>().map_err(domain)?.into(),
+            #[cfg(feature = "matrix")]
+            Value::Matrix(m) => Value::Matrix(m.map(apply).map_err(domain)?),
+            Value::Interval(i) => match operator {
+                Operator::Prefix(f) if symbol == "-" || symbol == "√" => Value::Interval(i.map(symbol, f).map_err(domain)?),
+                _ => return Err(mismatch("a number", operand, span.clone())),
+            },
+            #[cfg(feature = "units")]
+            Value::Quantity(q) => match operator {
+                Operator::Prefix(f) if symbol == "-" => Value::Quantity(q.map(f)),
+                _ => return Err(mismatch("a number", operand, span.clone())),
+            },
+            Value::Duration(d) if symbol == "-" => Value::Duration(Duration::from_seconds(-d.seconds())),
+            Value::Bool(_) | Value::Date(_) | Value::Duration(_) | Value::Formula(_) | Value::Lambda(_) | Value::Plot(_) | Value::Histogram(_) => return Err(mismatch("a number", operand, span.clone())),
+        };
+        let postfix = operator.fixity() == Fixity::Postfix;
+        if let (true, Some(x), Value::Number(y)) = (self.strict, operand.number(), &ret) {
+            let expr = if postfix { format!("{}{}", x, symbol) } else { format!("{}{}", symbol, x) };
+            finite(expr, &[x], *y, span)?;
+        }
+        self.step(|| if postfix { format!("{}{}", operand, symbol) } else { format!("{}{}", symbol, operand) }, ret.clone());
+        Ok(ret)
+    }
+
+    /// Evaluates an operand of the operator at `span` that has to be a number.
+    fn number(&mut self, node: &Node, span: &Range<usize>) -> Result<f64, Diagnostic> {
+        let value = self.node(node)?;
+        value.number().ok_or_else(|| mismatch("a number", value, span.clone()))
+    }
+
+    fn bool(&mut self, node: &Node, span: &Range<usize>) -> Result<bool, Diagnostic> {
+        match self.node(node)? {
+            Value::Bool(b) => Ok(b),
+            value => Err(mismatch("a boolean", value, span.clone())),
+        }
+    }
+
+    /// Warns that `span` computed `value` from `inputs` losing most of the
+    /// precision, by adding or subtracting nearly equal values, or that it
+    /// overflowed.
+    fn warn_stability(&mut self, span: Range<usize>, additive: bool, inputs: &[f64], value: f64) {
+        if inputs.iter().any(|input| !input.is_finite()) {
+            return;
+        }
+        let expr: String = self.source.chars().skip(span.start).take(span.len()).collect();
+        let magnitude = inputs.iter().fold(0., |max: f64, input| max.max(input.abs()));
+
+        let warning = if value.is_infinite() {
+            Diagnostic::new(format!("'{}' overflows to {}", expr.trim(), value))
+                .with_hint("the exact result is beyond the largest float, about 1.8e308")
+        } else if additive && (value != 0. && magnitude / value.abs() >= 1e8 || value == 0. && magnitude >= 2f64.powi(53)) {
+            // A 0 is only suspect where floats are further apart than 1, so
+            // whole numbers go missing like the 1 of `(1e16 + 1) - 1e16`.
+            let lost = match value {
+                0. => 16,
+                _ => ((magnitude / value.abs()).log10() as u32).min(16),
+            };
+            Diagnostic::new(format!("'{}' cancels about {} of 16 significant digits", expr.trim(), lost))
+                .with_hint("the operands are nearly equal, so the result may be mostly rounding error")
+        } else {
+            return;
+        };
+        self.warnings.push(warning.with_kind(ErrorKind::Warning).with_label(span, ""));
+    }
+
+    /// `name` as one of a unit or a currency, with an error for currencies
+    /// without an exchange rate.
+    #[cfg(feature = "units")]
+    fn unit(&self, name: &str, span: Range<usize>) -> Option<Result<Value, Diagnostic>> {
+        if let Some(unit) = Unit::lookup(name) {
+            return Some(Ok(Quantity::of(1., unit)));
+        }
+        if let Some(&rate) = self.rates.get(name).filter(|_| units::is_currency(name)) {
+            return Some(Ok(Quantity::of(1., Unit::currency(name, rate))));
+        }
+        if !units::is_currency(name) {
+            return None;
+        }
+        let err = Diagnostic::new(format!("Unknown currency '{}'", name))
+            .with_kind(ErrorKind::UnknownVariable)
+            .with_label(span, "no exchange rate");
+        Some(Err(match suggest(name, self.rates.keys()) {
+            _ if self.rates.is_empty() => err.with_hint("no exchange rates are loaded"),
+            Some(candidate) => err.with_hint(format!("did you mean '{}'?", candidate)),
+            None => err,
+        }))
+    }
+
+    fn name(&self, name: &str, span: Range<usize>) -> Result<Value, Diagnostic> {
+        if let Some(value) = self.scopes.last().and_then(|scope| scope.get(name)) {
+            return Ok(value.clone());
+        }
+        if let Some(&value) = self.constants.get(name) {
+            return Ok(Value::Number(value));
+        }
+        if let Some(value) = self.bindings.get(name).or(self.values.get(name)).or(self.variables.get(name)) {
+            return Ok(float(value.clone()));
+        }
+        if let Some(value) = self.resolver.as_ref().and_then(|resolver| resolver.resolve(name)) {
+            return Ok(Value::Number(value));
+        }
+        #[cfg(feature = "units")]
+        if let Some(ret) = self.unit(name, span.clone()) {
+            return ret;
+        }
+
+        let err = Diagnostic::new(format!("Unknown variable '{}'", name))
+            .with_kind(ErrorKind::UnknownVariable)
+            .with_label(span, "not defined");
+        let names = self.constants.keys().chain(self.bindings.keys()).chain(self.values.keys()).chain(self.variables.keys());
+        Err(if self.functions.contains_key(name) || self.user_functions.contains_key(name) {
+            err.with_hint(format!("'{}' is a function, call it like {}(x)", name, name))
+        } else if let Some(candidate) = suggest(name, names) {
+            err.with_hint(format!("did you mean '{}'?", candidate))
+        } else {
+            err
+        })
+    }
+
+    fn call(&mut self, name: &str, arguments: &[Node], name_span: Range<usize>, span: Range<usize>) -> Result<Value, Diagnostic> {
+        self.step_limits(&span)?;
+        if name == CONDITIONAL {
+            return self.conditional(arguments, span);
+        }
+        if name == DERIVATIVE {
+            return self.derivative(arguments, span);
+        }
+        if name == SIMPLIFY {
+            return self.simplify(arguments, span);
+        }
+        if name == INTEGRAL {
+            return self.integral(arguments, span);
+        }
+        if name == SOLVE {
+            return self.solve(arguments, span);
+        }
+        if name == PRODUCT || name == SUM && is_series(arguments) {
+            return self.series(name, arguments, span);
+        }
+        if name == PLOT {
+            return self.plot(arguments, span);
+        }
+        if name == MAP || name == FILTER || name == REDUCE {
+            return self.higher_order(name, arguments, span);
+        }
+        if name == ODESOLVE {
+            return self.odesolve(arguments, span);
+        }
+        if name == MONTE_CARLO {
+            return self.montecarlo(arguments, span);
+        }
+        // A variable or parameter holding a lambda, like `f` after `f = x -> x^2`.
+        if !self.functions.contains_key(name) && !self.user_functions.contains_key(name) {
+            if let Some(lambda) = self.lambda(&Node::Name(name.to_string(), name_span.clone())) {
+                return self.call_lambda(&lambda, arguments, span);
+            }
+        }
+        let (arity, variadic, overload) = match (self.functions.contains_key(name), self.user_functions.get(name)) {
+            (true, _) => match self.overload(name, arguments.len()) {
+                Some((overload, func)) => (func.arity(), func.is_variadic(), overload),
+                None => return Err(arity_error(&self.arities(name), name, arguments.len(), span)),
+            },
+            (false, Some(func)) => (func.params.len(), false, 0),
+            (false, None) => return Err(self.unknown_function(name, name_span)),
+        };
+        if arguments.len() != arity && !variadic {
+            return Err(arity_error(&arity.to_string(), name, arguments.len(), span));
+        }
+
+        let mut arguments = arguments.iter().map(|argument| self.node(argument)).collect::<Result<Vec<Value>, _>>()?;
+        // A list passed to a builtin that takes any number of arguments
+        // stands for its numbers, `mean(xs)`.
+        if variadic {
+            arguments = arguments.into_iter().flat_map(|argument| match argument {
+                Value::List(xs) => xs.into_iter().map(Value::Number).collect(),
+                argument => vec![argument],
+            }).collect();
+        }
+        if arguments.len() < arity {
+            return Err(arity_error(&format!("at least {}", arity), name, arguments.len(), span));
+        }
+        *self.stats.calls.entry(name.to_string()).or_default() += 1;
+
+        let ret = match self.functions.contains_key(name) {
+            true => self.call_builtin(name, overload, &arguments, span.clone())?,
+            false => self.call_user(name, &arguments, span.clone())?,
+        };
+        self.step(|| {
+            let arguments: Vec<String> = arguments.iter().map(Value::to_string).collect();
+            format!("{}({})", name, arguments.join(", "))
+        }, ret.clone());
+        if self.warn {
+            let numbers: Option<Vec<f64>> = arguments.iter().map(|argument| argument.number()).collect();
+            if let (Some(numbers), Some(value)) = (numbers, ret.number()) {
+                self.warn_stability(span, false, &numbers, value);
+            }
+        }
+        Ok(ret)
+    }
+
+    /// The calls of `node` for `validate`. `local` has the functions defined
+    /// in the expression by their arity, and the parameters and variables it
+    /// assigns, which could hold lambdas, without one.
+    fn check_calls(&self, node: &Node, local: &mut HashMap<String, Option<usize>>) -> Result<(), Diagnostic> {
+        rewrite(node, &mut |node| {
+            let (params, body) = match node {
+                Node::Define { params, body, .. } if params.is_empty() => (params, body),
+                Node::Define { name, params, body, .. } => {
+                    local.insert(name.clone(), Some(params.len()));
+                    (params, body)
+                }
+                Node::Lambda { params, body } => (params, body),
+                Node::Assign { name, .. } => {
+                    local.insert(name.clone(), None);
+                    return None;
+                }
+                // A function can call one defined by a later statement.
+                Node::Sequence(statements) => {
+                    for statement in statements {
+                        match statement {
+                            Node::Define { name, params, .. } if !params.is_empty() => {
+                                local.insert(name.clone(), Some(params.len()));
+                            }
+                            _ => {}
+                        }
+                    }
+                    return None;
+                }
+                Node::Call { name, arguments, name_span, span } => {
+                    let (arity, variadic) = match (local.get(name), self.functions.contains_key(name), self.user_functions.get(name)) {
+                        _ if is_special(name) || name == SUM => return None,
+                        (Some(None), _, _) => return None,
+                        (Some(&Some(arity)), _, _) => (arity, false),
+                        (None, true, _) => match self.overload(name, arguments.len()) {
+                            Some(_) => return None,
+                            None => return Some(Err(arity_error(&self.arities(name), name, arguments.len(), span.clone()))),
+                        },
+                        (None, false, Some(func)) => (func.params.len(), false),
+                        _ if self.variables.contains_key(name) || self.values.contains_key(name) => return None,
+                        _ => return Some(Err(self.unknown_function(name, name_span.clone()))),
+                    };
+                    if arguments.len() != arity && !variadic {
+                        return Some(Err(arity_error(&arity.to_string(), name, arguments.len(), span.clone())));
+                    }
+                    return None;
+                }
+                _ => return None,
+            };
+            local.extend(params.iter().map(|param| (param.clone(), None)));
+            Some(self.check_calls(body, local).map(|()| node.clone()))
+        }).map(|_| ())
+    }
+
+    /// The overload of the builtin `name` taking `count` arguments, with its
+    /// index. One with exactly as many parameters comes before a variadic
+    /// one.
+    fn overload(&self, name: &str, count: usize) -> Option<(usize, &Function)> {
+        let overloads = self.functions.get(name)?;
+        overloads.iter().position(|func| !func.is_variadic() && func.arity() == count)
+            .or_else(|| overloads.iter().position(Function::is_variadic))
+            .map(|i| (i, &overloads[i]))
+    }
+
+    /// The numbers of arguments the overloads of `name` take, like `1 or 2`.
+    fn arities(&self, name: &str) -> String {
+        let mut arities: Vec<String> = self.functions[name].iter()
+            .map(|func| match func.is_variadic() {
+                true => format!("at least {}", func.arity()),
+                false => func.arity().to_string(),
+            })
+            .collect();
+        let last = arities.pop().unwrap_or_default();
+        match arities.is_empty() {
+            true => last,
+            false => format!("{} or {}", arities.join(", "), last),
+        }
+    }
+
+    fn unknown_function(&self, name: &str, name_span: Range<usize>) -> Diagnostic {
+        let err = Diagnostic::new(format!("Unknown function '{}'", name))
+            .with_kind(ErrorKind::UnknownFunction)
+            .with_label(name_span, "not a function");
+        if self.constants.contains_key(name) {
+            err.with_hint(format!("'{}' is a constant, use it without parentheses", name))
+        } else if self.variables.contains_key(name) {
+            err.with_hint(format!("'{}' is a variable, use it without parentheses", name))
+        } else if let Some(candidate) = suggest(name, self.functions.keys().chain(self.user_functions.keys())) {
+            err.with_hint(format!("did you mean '{}'?", candidate))
+        } else {
+            err
+        }
+    }
+
+    /// Evaluates only the branch of `if(cond, then, else)` that `cond` picks.
+    fn conditional(&mut self, arguments: &[Node], span: Range<usize>) -> Result<Value, Diagnostic> {
+        if arguments.len() != 3 {
+            return Err(arity_error("3", CONDITIONAL, arguments.len(), span));
+        }
+        *self.stats.calls.entry(CONDITIONAL.to_string()).or_default() += 1;
+        let branch = if self.bool(&arguments[0], &span)? { &arguments[1] } else { &arguments[2] };
+        self.node(branch)
+    }
+
+    /// `diff(expr, x)`, the derivative of `expr` by `x` as a `Formula`, or
+    /// with a third argument its value there.
+    fn derivative(&mut self, arguments: &[Node], span: Range<usize>) -> Result<Value, Diagnostic> {
+        if !(2..=3).contains(&arguments.len()) {
+            return Err(arity_error("2 or 3", DERIVATIVE, arguments.len(), span));
+        }
+        let var = variable(&arguments[1], "to differentiate by", "second", &span)?;
+        *self.stats.calls.entry(DERIVATIVE.to_string()).or_default() += 1;
+        let domain = |msg| Diagnostic::new(msg).with_kind(ErrorKind::Domain).with_label(span.clone(), "");
+        let expr = self.expand(&arguments[0], 0).map_err(domain)?;
+        let degrees = self.angle_unit == AngleUnit::Degrees;
+        let derivative = Derivative::new(&self.operators, var, span.clone(), degrees).of(&expr).map_err(domain)?;
+        let Some(at) = arguments.get(2) else {
+            return Ok(Value::Formula(Formula::new(derivative, &self.operators)));
+        };
+
+        let at = self.node(at)?;
+        // Spans of expanded functions don't point into the expression.
+        self.bound(var, at, &derivative).map_err(|err| relabel(err, span))
+    }
+
+    /// `simplify(expr)`, `expr` as a `Formula` with the calls of builtins
+    /// that come out whole evaluated, the numbers folded and like terms
+    /// added up.
+    fn simplify(&mut self, arguments: &[Node], span: Range<usize>) -> Result<Value, Diagnostic> {
+        if arguments.len() != 1 {
+            return Err(arity_error("1", SIMPLIFY, arguments.len(), span));
+        }
+        *self.stats.calls.entry(SIMPLIFY.to_string()).or_default() += 1;
+        let domain = |msg| Diagnostic::new(msg).with_kind(ErrorKind::Domain).with_label(span.clone(), "");
+        let expr = self.expand(&arguments[0], 0).map_err(domain)?;
+        // `sqrt(4)` is 2 but `sqrt(2)` stays as it is.
+        let expr = rewrite(&expr, &mut |node| match node {
+            Node::Call { .. } if is_constant(node) => match self.node(node).map(|value| value.number()) {
+                Ok(Some(x)) if x.fract() == 0. => Some(Ok::<_, ()>(Node::Number(x))),
+                _ => None,
+            },
+            _ => None,
+        }).unwrap();
+        let simplified = Algebra::new(&self.operators, span.clone()).simplify(&expr).map_err(domain)?;
+        Ok(Value::Formula(Formula::new(simplified, &self.operators)))
+    }
+
+    /// `integrate(expr, x, a, b)`, the integral of `expr` over `x` from `a`
+    /// to `b`, to within a tolerance that a fifth argument can give.
+    /// `integrate(f, a, b)` integrates the lambda `f`.
+    fn integral(&mut self, arguments: &[Node], span: Range<usize>) -> Result<Value, Diagnostic> {
+        let lambda = arguments.first().and_then(|f| self.lambda(f));
+        let (expected, range) = if lambda.is_some() { ("3 or 4", 3..=4) } else { ("4 or 5", 4..=5) };
+        if !range.contains(&arguments.len()) {
+            return Err(arity_error(expected, INTEGRAL, arguments.len(), span));
+        }
+        let (var, expr, arguments) = match &lambda {
+            Some(lambda) => (parameter(lambda, &span)?, lambda.body(), &arguments[1..]),
+            None => (variable(&arguments[1], "to integrate over", "second", &span)?, &arguments[0], &arguments[2..]),
+        };
+        *self.stats.calls.entry(INTEGRAL.to_string()).or_default() += 1;
+        let (a, b) = (self.number(&arguments[0], &span)?, self.number(&arguments[1], &span)?);
+        let tolerance = match arguments.get(2) {
+            Some(tolerance) => self.number(tolerance, &span)?,
+            None => TOLERANCE,
+        };
+        if tolerance.is_nan() || tolerance <= 0. {
+            return Err(Diagnostic::new(format!("Expected a positive tolerance but got {}", tolerance))
+                .with_kind(ErrorKind::Domain)
+                .with_label(span, ""));
+        }
+        self.within(lambda.as_ref(), span.clone(), |calc| {
+            let mut f = |x: f64| {
+                let value = calc.bound(var, Value::Number(x), expr)?;
+                value.number().ok_or_else(|| mismatch("a number", value, span.clone()))
+            };
+            let fail = |msg| Diagnostic::new(msg).with_kind(ErrorKind::Domain).with_label(span.clone(), "");
+            calculus::integrate(&mut f, fail, a, b, tolerance).map(Value::Number)
+        })
+    }
+
+    /// `solve(expr, x, guess)`, an `x` where `expr` is 0, by Newton's method
+    /// from `guess` or 1. `solve(expr, x, a, b)` bisects between `a` and
+    /// `b` instead. `solve(f, guess)` solves the lambda `f`.
+    fn solve(&mut self, arguments: &[Node], span: Range<usize>) -> Result<Value, Diagnostic> {
+        let lambda = arguments.first().and_then(|f| self.lambda(f));
+        let (expected, range) = if lambda.is_some() { ("1 to 3", 1..=3) } else { ("2 to 4", 2..=4) };
+        if !range.contains(&arguments.len()) {
+            return Err(arity_error(expected, SOLVE, arguments.len(), span));
+        }
+        let (var, expr, arguments) = match &lambda {
+            Some(lambda) => (parameter(lambda, &span)?, lambda.body(), &arguments[1..]),
+            None => (variable(&arguments[1], "to solve for", "second", &span)?, &arguments[0], &arguments[2..]),
+        };
+        *self.stats.calls.entry(SOLVE.to_string()).or_default() += 1;
+        let bounds = arguments.iter().map(|argument| self.number(argument, &span)).collect::<Result<Vec<f64>, _>>()?;
+        self.within(lambda.as_ref(), span.clone(), |calc| calc.find_root(var, expr, &bounds, span))
+    }
+
+    fn find_root(&mut self, var: &str, expr: &Node, bounds: &[f64], span: Range<usize>) -> Result<Value, Diagnostic> {
+        // The slope from the derivative where it has one.
+        let degrees = self.angle_unit == AngleUnit::Degrees;
+        let slope = self.expand(expr, 0).ok().and_then(|expr| Derivative::new(&self.operators, var, span.clone(), degrees).of(&expr).ok());
+        let mut at = |node: &Node, x: f64| {
+            let value = self.bound(var, Value::Number(x), node)?;
+            value.number().ok_or_else(|| mismatch("a number", value, span.clone()))
+        };
+        let fail = |hint: &'static str| {
+            let span = span.clone();
+            move |msg| Diagnostic::new(msg).with_kind(ErrorKind::Domain).with_label(span.clone(), "").with_hint(hint)
+        };
+
+        if let [a, b] = bounds[..] {
+            let root = calculus::bisect(&mut |x| at(expr, x), fail("the function has to be above 0 at one bound and below it at the other"), a, b)?;
+            return Ok(Value::Number(root));
+        }
+        let mut f = |x: f64| {
+            let y = at(expr, x)?;
+            let slope = match &slope {
+                Some(slope) => at(slope, x)?,
+                None => {
+                    let h = 1e-7 * x.abs().max(1.);
+                    (at(expr, x + h)? - at(expr, x - h)?) / (2. * h)
+                }
+            };
+            Ok((y, slope))
+        };
+        let guess = bounds.first().copied().unwrap_or(1.);
+        let root = calculus::newton(&mut f, fail("try another guess, or solve(expr, x, a, b) between bounds where expr changes sign"), guess)?;
+        Ok(Value::Number(root))
+    }
+
+    /// `plot(expr, x, a, b)`, `expr` sampled at evenly spaced `x` from `a`
+    /// to `b`, or `plot(f, a, b)` for the lambda `f`. Where `expr` has no
+    /// value the graph has a gap.
+    fn plot(&mut self, arguments: &[Node], span: Range<usize>) -> Result<Value, Diagnostic> {
+        let lambda = arguments.first().and_then(|f| self.lambda(f));
+        let expected = if lambda.is_some() { 3 } else { 4 };
+        if arguments.len() != expected {
+            return Err(arity_error(&expected.to_string(), PLOT, arguments.len(), span));
+        }
+        let (var, expr, arguments) = match &lambda {
+            Some(lambda) => (parameter(lambda, &span)?, lambda.body(), &arguments[1..]),
+            None => (variable(&arguments[1], "to plot over", "second", &span)?, &arguments[0], &arguments[2..]),
+        };
+        *self.stats.calls.entry(PLOT.to_string()).or_default() += 1;
+        let (a, b) = (self.number(&arguments[0], &span)?, self.number(&arguments[1], &span)?);
+        if !(a.is_finite() && b.is_finite() && a < b) {
+            return Err(Diagnostic::new(format!("Expected finite bounds from low to high but got {} and {}", a, b))
+                .with_kind(ErrorKind::Domain)
+                .with_label(span, ""));
+        }
+
+        self.within(lambda.as_ref(), span.clone(), |calc| {
+            let mut points = vec![];
+            for i in 0..plot::SAMPLES {
+                let x = a + (b - a) * i as f64 / (plot::SAMPLES - 1) as f64;
+                let y = match calc.bound(var, Value::Number(x), expr) {
+                    Ok(value) => value.number().ok_or_else(|| mismatch("a number", value, span.clone()))?,
+                    Err(err) if matches!(err.kind, ErrorKind::Domain | ErrorKind::DivisionByZero) => f64::NAN,
+                    Err(err) => return Err(err),
+                };
+                points.push((x, y));
+            }
+            Ok(Value::Plot(Plot::new(points)))
+        })
+    }
+
+    /// `sum(i, from, to, expr)` or `prod(i, from, to, expr)`, `expr` added up
+    /// or multiplied for each whole `i` from `from` to `to`.
+    fn series(&mut self, name: &str, arguments: &[Node], span: Range<usize>) -> Result<Value, Diagnostic> {
+        if arguments.len() != 4 {
+            return Err(arity_error("4", name, arguments.len(), span));
+        }
+        let var = variable(&arguments[0], "to count with", "first", &span)?;
+        *self.stats.calls.entry(name.to_string()).or_default() += 1;
+        let (from, to) = (self.number(&arguments[1], &span)?, self.number(&arguments[2], &span)?);
+        let domain = |msg: String| Diagnostic::new(msg).with_kind(ErrorKind::Domain).with_label(span.clone(), "");
+        if from.fract() != 0. || to.fract() != 0. {
+            return Err(domain(format!("Expected whole bounds but got {} and {}", from, to)));
+        }
+        if to - from >= MAX_TERMS {
+            return Err(domain(format!("Can't go through more than {} terms", MAX_TERMS)).with_kind(ErrorKind::Limit));
+        }
+
+        let product = name == PRODUCT;
+        let mut ret = if product { 1. } else { 0. };
+        // Counted rather than stepping `i`, which stops changing past 2^53.
+        let terms = if to < from { 0 } else { (to - from) as u64 + 1 };
+        for k in 0..terms {
+            self.step_limits(&span)?;
+            let value = self.bound(var, Value::Number(from + k as f64), &arguments[3])?;
+            let x = value.number().ok_or_else(|| mismatch("a number", value, span.clone()))?;
+            if product { ret *= x } else { ret += x }
+        }
+        Ok(Value::Number(ret))
+    }
+
+    /// `map(f, xs)`, `filter(f, xs)` or `reduce(f, xs, init)`, calling the
+    /// lambda `f` with the numbers of `xs`. `reduce` without `init` starts
+    /// from the first number.
+    fn higher_order(&mut self, name: &str, arguments: &[Node], span: Range<usize>) -> Result<Value, Diagnostic> {
+        let (expected, range) = if name == REDUCE { ("2 or 3", 2..=3) } else { ("2", 2..=2) };
+        if !range.contains(&arguments.len()) {
+            return Err(arity_error(expected, name, arguments.len(), span));
+        }
+        *self.stats.calls.entry(name.to_string()).or_default() += 1;
+        let f = match self.node(&arguments[0])? {
+            Value::Lambda(f) => f,
+            value => return Err(mismatch("a lambda", value, span)),
+        };
+        let xs = match self.node(&arguments[1])? {
+            Value::List(xs) => xs,
+            value => return Err(mismatch("a list", value, span)),
+        };
+
+        match name {
+            MAP => xs.into_iter().map(|x| {
+                let value = self.apply(&f, vec![Value::Number(x)], span.clone())?;
+                value.number().ok_or_else(|| mismatch("a number", value, span.clone()))
+            }).collect::<Result<Vec<f64>, _>>().map(Value::List),
+            FILTER => {
+                let mut ret = vec![];
+                for x in xs {
+                    match self.apply(&f, vec![Value::Number(x)], span.clone())? {
+                        Value::Bool(keep) => if keep { ret.push(x) },
+                        value => return Err(mismatch("a boolean", value, span)),
+                    }
+                }
+                Ok(Value::List(ret))
+            }
+            _ => {
+                let mut xs = xs.into_iter();
+                let init = match arguments.get(2) {
+                    Some(init) => self.node(init)?,
+                    None => xs.next().map(Value::Number).ok_or_else(|| {
+                        Diagnostic::new("Can't reduce an empty list without an initial value")
+                            .with_kind(ErrorKind::Domain)
+                            .with_label(span.clone(), "")
+                    })?,
+                };
+                xs.try_fold(init, |acc, x| self.apply(&f, vec![acc, Value::Number(x)], span.clone()))
+            }
+        }
+    }
+
+    /// `odesolve(f, t0, y0, t1, steps)`, `y` at `t1` for `y' = f(t, y)` and
+    /// `y(t0) = y0` by `steps` steps of Runge-Kutta. With a list of times for
+    /// `t1` it is the list of `y` at each, taking `steps` steps from one to
+    /// the next.
+    fn odesolve(&mut self, arguments: &[Node], span: Range<usize>) -> Result<Value, Diagnostic> {
+        if arguments.len() != 5 {
+            return Err(arity_error("5", ODESOLVE, arguments.len(), span));
+        }
+        *self.stats.calls.entry(ODESOLVE.to_string()).or_default() += 1;
+        let f = match self.node(&arguments[0])? {
+            Value::Lambda(f) if f.params().len() == 2 => f,
+            value => return Err(mismatch("a lambda of t and y", value, span)),
+        };
+        let (mut t, mut y) = (self.number(&arguments[1], &span)?, self.number(&arguments[2], &span)?);
+        let (times, sampled) = match self.node(&arguments[3])? {
+            Value::List(times) => (times, true),
+            value => (vec![value.number().ok_or_else(|| mismatch("a number or a list of times", value, span.clone()))?], false),
+        };
+        let steps = self.number(&arguments[4], &span)?;
+        let domain = |msg: String| Diagnostic::new(msg).with_kind(ErrorKind::Domain).with_label(span.clone(), "");
+        if steps.fract() != 0. || steps < 1. {
+            return Err(domain(format!("Expected a whole number of steps but got {}", steps)));
+        }
+        if steps * times.len() as f64 > MAX_STEPS {
+            return Err(domain(format!("Can't take more than {} steps", MAX_STEPS)).with_kind(ErrorKind::Limit));
+        }
+
+        let slope = |calc: &mut Self, t: f64, y: f64| {
+            let value = calc.apply(&f, vec![Value::Number(t), Value::Number(y)], span.clone())?;
+            value.number().ok_or_else(|| mismatch("a number", value, span.clone()))
+        };
+        let mut ret = vec![];
+        for end in times {
+            if !end.is_finite() {
+                return Err(domain(format!("Expected finite times but got {}", end)));
+            }
+            let (start, h) = (t, (end - t) / steps);
+            for i in 0..steps as u64 {
+                let k1 = slope(self, t, y)?;
+                let k2 = slope(self, t + h / 2., y + h / 2. * k1)?;
+                let k3 = slope(self, t + h / 2., y + h / 2. * k2)?;
+                let k4 = slope(self, t + h, y + h * k3)?;
+                y += h / 6. * (k1 + 2. * k2 + 2. * k3 + k4);
+                // From the start rather than adding up `h`, so the last step ends on `end`.
+                t = start + h * (i + 1) as f64;
+            }
+            t = end;
+            ret.push(y);
+        }
+        Ok(if sampled { Value::List(ret) } else { Value::Number(y) })
+    }
+
+    /// `montecarlo(f, n)` or `montecarlo(f, n, a, b)`, the mean of `f` over
+    /// `n` calls with each parameter a random number from 0 to 1, or from `a`
+    /// to `b`. A boolean `f` counts as 1 when true, so the mean is how often.
+    /// The numbers come from the same generator as `rand`, see `seed`.
+    fn montecarlo(&mut self, arguments: &[Node], span: Range<usize>) -> Result<Value, Diagnostic> {
+        if arguments.len() != 2 && arguments.len() != 4 {
+            return Err(arity_error("2 or 4", MONTE_CARLO, arguments.len(), span));
+        }
+        *self.stats.calls.entry(MONTE_CARLO.to_string()).or_default() += 1;
+        let f = match self.node(&arguments[0])? {
+            Value::Lambda(f) if !f.params().is_empty() => f,
+            value => return Err(mismatch("a lambda", value, span)),
+        };
+        let n = self.number(&arguments[1], &span)?;
+        let (a, b) = match arguments.get(2..) {
+            Some([a, b]) => (self.number(a, &span)?, self.number(b, &span)?),
+            _ => (0., 1.),
+        };
+        let domain = |msg: String| Diagnostic::new(msg).with_kind(ErrorKind::Domain).with_label(span.clone(), "");
+        if n.fract() != 0. || n < 1. {
+            return Err(domain(format!("Expected a whole number of samples but got {}", n)));
+        }
+        if n > MAX_SAMPLES {
+            return Err(domain(format!("Can't take more than {} samples", MAX_SAMPLES)).with_kind(ErrorKind::Limit));
+        }
+        if !(a.is_finite() && b.is_finite() && a < b) {
+            return Err(domain(format!("Expected finite bounds from low to high but got {} and {}", a, b)));
+        }
+
+        let mut sum = 0.;
+        for _ in 0..n as u64 {
+            let sample = f.params().iter().map(|_| Value::Number(a + (b - a) * functions::next(&self.rng))).collect();
+            sum += match self.apply(&f, sample, span.clone())? {
+                Value::Bool(hit) => if hit { 1. } else { 0. },
+                value => value.number().ok_or_else(|| mismatch("a number or a boolean", value, span.clone()))?,
+            };
+        }
+        Ok(Value::Number(sum / n))
+    }
+
+    /// The lambda `node` is, written out or held by a name.
+    fn lambda(&self, node: &Node) -> Option<Lambda> {
+        match node {
+            Node::Lambda { params, body, .. } => {
+                let scope = self.scopes.last().cloned().unwrap_or_default();
+                Some(Lambda::new(params.clone(), body.clone(), scope, &self.operators))
+            }
+            Node::Name(name, span) => match self.name(name, span.clone()) {
+                Ok(Value::Lambda(lambda)) => Some(lambda),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn call_lambda(&mut self, lambda: &Lambda, arguments: &[Node], span: Range<usize>) -> Result<Value, Diagnostic> {
+        let arguments = arguments.iter().map(|argument| self.node(argument)).collect::<Result<Vec<Value>, _>>()?;
+        self.apply(lambda, arguments, span)
+    }
+
+    /// Evaluates the body of `lambda` with its parameters bound to
+    /// `arguments`, errors in the body are reported at the call.
+    fn apply(&mut self, lambda: &Lambda, arguments: Vec<Value>, span: Range<usize>) -> Result<Value, Diagnostic> {
+        if arguments.len() != lambda.params().len() {
+            return Err(arity_error(&lambda.params().len().to_string(), &lambda.to_string(), arguments.len(), span));
+        }
+        self.step_limits(&span)?;
+        if self.scopes.len() >= self.max_call_depth() {
+            return Err(Diagnostic::new(format!("Function calls are nested more than {} deep", self.max_call_depth()))
+                .with_kind(ErrorKind::Limit)
+                .with_label(span, "in this call"));
+        }
+
+        let warn = core::mem::replace(&mut self.warn, false);
+        self.scopes.push(lambda.scope(arguments));
+        let ret = self.node(lambda.body());
+        self.scopes.pop();
+        self.warn = warn;
+        ret.map_err(|err| relabel(err, span))
+    }
+
+    /// Runs `f` in the scope `lambda` was written in, if there is one, for
+    /// the special forms that take a lambda in place of an expression and
+    /// its variable.
+    fn within(&mut self, lambda: Option<&Lambda>, span: Range<usize>, f: impl FnOnce(&mut Self) -> Result<Value, Diagnostic>) -> Result<Value, Diagnostic> {
+        let Some(lambda) = lambda else { return f(self) };
+        self.scopes.push(lambda.scope(vec![]));
+        let ret = f(self);
+        self.scopes.pop();
+        ret.map_err(|err| relabel(err, span))
+    }
+
+    /// `node` evaluated with `var` bound to `value`, without warnings.
+    fn bound(&mut self, var: &str, value: Value, node: &Node) -> Result<Value, Diagnostic> {
+        let mut scope = self.scopes.last().cloned().unwrap_or_default();
+        scope.insert(var.to_string(), value);
+        let warn = core::mem::replace(&mut self.warn, false);
+        self.scopes.push(scope);
+        let ret = self.node(node);
+        self.scopes.pop();
+        self.warn = warn;
+        ret
+    }
+
+    /// `node` with the calls of user functions replaced by their bodies and
+    /// the names holding a `Formula` by the formula, so they can be
+    /// differentiated.
+    fn expand(&self, node: &Node, depth: usize) -> Result<Node, String> {
+        rewrite(node, &mut |node| match node {
+            Node::Name(name, span) => match self.name(name, span.clone()) {
+                Ok(Value::Formula(_)) if depth >= MAX_CALL_DEPTH => Some(Err(format!("Can't expand '{}', it refers to itself", name))),
+                Ok(Value::Formula(formula)) => Some(self.expand(formula.node(), depth + 1)),
+                _ => None,
+            },
+            Node::Call { name, arguments, .. } if !self.functions.contains_key(name) => {
+                let function = self.user_functions.get(name).filter(|function| function.params.len() == arguments.len())?;
+                if depth >= MAX_CALL_DEPTH {
+                    return Some(Err(format!("Can't expand '{}', it calls itself", name)));
+                }
+                let body = rewrite(&function.body, &mut |node| match node {
+                    Node::Name(name, _) => function.params.iter().position(|param| param == name).map(|i| Ok(arguments[i].clone())),
+                    _ => None,
+                });
+                Some(body.and_then(|body| self.expand(&body, depth + 1)))
+            }
+            _ => None,
+        })
+    }
+
+    fn call_builtin(&self, name: &str, overload: usize, arguments: &[Value], span: Range<usize>) -> Result<Value, Diagnostic> {
+        let function = &self.functions[name][overload];
+        if let Values(_, f) = function {
+            return f(arguments).map_err(|msg| Diagnostic::new(msg).with_kind(ErrorKind::Domain).with_label(span, ""));
+        }
+        if let (Some(Value::Interval(i)), OneArg(f)) = (arguments.first(), function) {
+            if MONOTONIC.contains(&name) && self.builtins.contains(name) {
+                return i.map(name, f).map(Value::Interval).map_err(|msg| {
+                    Diagnostic::new(msg).with_kind(ErrorKind::Domain).with_label(span, "")
+                });
+            }
+        }
+        let mut arguments = arguments.iter()
+            .map(|argument| argument.number().ok_or_else(|| mismatch("a number", argument.clone(), span.clone())))
+            .collect::<Result<Vec<f64>, _>>()?;
+        let degrees = self.angle_unit == AngleUnit::Degrees && self.builtins.contains(name);
+        // Multiples of 90 degrees give exact zeros, `sin(180)` is 0 rather
+        // than the rounding error of `sin(pi)`.
+        let quadrant = degrees && TRIG.contains(&name) && arguments[0] % 90. == 0.;
+        if degrees && TRIG.contains(&name) {
+            arguments[0] = arguments[0].to_radians();
+        }
+        let mut ret = match function {
+            _ if name == "round" && self.builtins.contains(name) => {
+                Ok(self.rounding.round(arguments[0], arguments.get(1).map_or(0, |&digits| digits as i32)))
+            }
+            OneArg(f) => Ok(f(arguments[0])),
+            TwoArg(f) => Ok(f(arguments[0], arguments[1])),
+            ThreeArg(f) => Ok(f(arguments[0], arguments[1], arguments[2])),
+            Variadic(_, f) => Ok(f(&arguments)),
+            Fallible(_, f) => f(&arguments),
+            Closure(_, f) | VariadicClosure(_, f) => f(&arguments),
+            Values(..) => unreachable!(),
+            Predicate(_, f) => {
+                return f(&arguments).map(Value::Bool).map_err(|msg| {
+                    Diagnostic::new(msg).with_kind(ErrorKind::Domain).with_label(span, "")
+                });
+            }
+        };
+        if degrees && INVERSE_TRIG.contains(&name) {
+            ret = ret.map(f64::to_degrees);
+        }
+        if quadrant {
+            ret = ret.map(|x| if x.abs() < 1e-12 { 0. } else { x });
+        }
+        if let (true, Ok(x)) = (self.strict, &ret) {
+            let expr = format!("{}({})", name, arguments.iter().map(f64::to_string).collect::<Vec<_>>().join(", "));
+            finite(expr, &arguments, *x, &span)?;
+        }
+        ret.map(Value::Number).map_err(|msg| {
+            Diagnostic::new(msg).with_kind(ErrorKind::Domain).with_label(span, "")
+        })
+    }
+
+    /// Whether `name` reads a formula rather than a parameter or binding.
+    fn is_formula(&self, name: &str) -> bool {
+        self.formulas.contains_key(name)
+            && !self.scopes.last().is_some_and(|scope| scope.contains_key(name))
+            && !self.bindings.contains_key(name)
+            && !self.values.contains_key(name)
+    }
+
+    /// The names from `path[0]` through formulas to itself if `body`, the
+    /// formula of the last name of `path`, reads the first.
+    fn cycle(&self, path: &mut Vec<String>, body: &Node) -> Option<Vec<String>> {
+        let mut names = vec![];
+        variables(body, &mut vec![], &mut names);
+        for name in names {
+            if name == path[0] {
+                path.push(name);
+                return Some(path.clone());
+            }
+            if let Some(formula) = self.formulas.get(&name).filter(|_| !path.contains(&name)) {
+                path.push(name);
+                if let Some(cycle) = self.cycle(path, &formula.body) {
+                    return Some(cycle);
+                }
+                path.pop();
+            }
+        }
+        None
+    }
+
+    fn call_user(&mut self, name: &str, arguments: &[Value], span: Range<usize>) -> Result<Value, Diagnostic> {
+        self.call_body(name, self.user_functions[name].clone(), arguments, span)
+    }
+
+    /// Evaluates the body of a user function or formula with its parameters
+    /// bound to `arguments`, errors in the body are reported at the call.
+    fn call_body(&mut self, name: &str, function: UserFunction, arguments: &[Value], span: Range<usize>) -> Result<Value, Diagnostic> {
+        self.step_limits(&span)?;
+        if self.scopes.len() >= self.max_call_depth() {
+            return Err(Diagnostic::new(format!("Function calls are nested more than {} deep", self.max_call_depth()))
+                .with_kind(ErrorKind::Limit)
+                .with_label(span, "in this call"));
+        }
+
+        // Spans in the body don't point into the expression being evaluated.
+        let warn = core::mem::replace(&mut self.warn, false);
+        self.scopes.push(function.params.into_iter().zip(arguments.iter().cloned()).collect());
+        let ret = self.node(&function.body);
+        self.scopes.pop();
+        self.warn = warn;
+
+        ret.map_err(|err| {
+            let ret = Diagnostic::new(format!("In '{}': {}", name, err.message))
+                .with_kind(err.kind)
+                .with_label(span, "");
+            match err.hint {
+                Some(hint) => ret.with_hint(hint),
+                None => ret,
+            }
+        })
+    }
+}
+
+/// The name a special form like `diff(expr, x)` binds, `purpose` like "to
+/// differentiate by" and `position` like "second".
+fn variable<'a>(node: &'a Node, purpose: &str, position: &str, span: &Range<usize>) -> Result<&'a str, Diagnostic> {
+    match node {
+        Node::Name(name, _) => Ok(name),
+        _ => Err(Diagnostic::new(format!("Expected a variable {}", purpose))
+            .with_kind(ErrorKind::Type)
+            .with_label(span.clone(), format!("the {} argument is not a name", position))),
+    }
+}
+
+/// The parameter of a lambda passed to a special form like `integrate(f, a,
+/// b)`, which has to take one.
+fn parameter<'a>(lambda: &'a Lambda, span: &Range<usize>) -> Result<&'a str, Diagnostic> {
+    match lambda.params() {
+        [param] => Ok(param),
+        params => Err(Diagnostic::new(format!("Expected a lambda of one parameter but got {}", lambda))
+            .with_kind(ErrorKind::Type)
+            .with_label(span.clone(), format!("the first argument takes {}", params.len()))),
+    }
+}
+
+/// `err` reported at `span`, for errors in a body whose spans don't point
+/// into the expression being evaluated.
+fn relabel(err: Diagnostic, span: Range<usize>) -> Diagnostic {
+    let ret = Diagnostic::new(err.message).with_kind(err.kind).with_label(span, "");
+    match err.hint {
+        Some(hint) => ret.with_hint(hint),
+        None => ret,
+    }
+}
+
+/// Whether `sum(...)` is the series `sum(i, from, to, expr)` rather than
+/// the sum of its arguments: there are four and the last mentions the first,
+/// which is a name.
+pub(crate) fn is_series(arguments: &[Node]) -> bool {
+    match arguments {
+        [Node::Name(var, _), _, _, expr] => mentions(expr, var),
+        _ => false,
+    }
+}
+
+/// The variable a special form like `diff(x^2, x)` or `sum(i, 1, 10, i^2)`
+/// binds in its other arguments.
+pub(crate) fn bound_variable<'a>(name: &str, arguments: &'a [Node]) -> Option<&'a str> {
+    let position = match name {
+        DERIVATIVE | INTEGRAL | SOLVE | PLOT => 1,
+        SUM | PRODUCT if is_series(arguments) => 0,
+        _ => return None,
+    };
+    match arguments.get(position) {
+        Some(Node::Name(var, _)) => Some(var),
+        _ => None,
+    }
+}
+
+/// `err` of the formula `name` of `Calculator::eval_formulas`.
+fn in_formula(name: &str, err: Diagnostic) -> Diagnostic {
+    Diagnostic { message: format!("In '{}': {}", name, err.message), ..err }
+}
+
+/// An `Arity` error, `expected` is a count like `2` or `at least 1`.
+fn arity_error(expected: &str, name: &str, got: usize, span: Range<usize>) -> Diagnostic {
+    let plural = if expected.ends_with(" 1") || expected == "1" { "" } else { "s" };
+    // Past the counts of a range or `at least` the closest is one of them.
+    let closest = expected.split(|c: char| !c.is_ascii_digit())
+        .filter_map(|count| count.parse::<usize>().ok())
+        .min_by_key(|count| count.abs_diff(got))
+        .unwrap_or_default();
+    Diagnostic::new(format!("Expected {} argument{} for function '{}'", expected, plural, name))
+        .with_kind(ErrorKind::Arity { expected: closest, got })
+        .with_label(span, format!("called with {}", got))
+}
+
+/// When an evaluation started, for the stats.
+fn now() -> Option<Instant> {
+    crate::CLOCK.then(Instant::now)
+}
+
+/// With `set_strict`, fails if `lhs symbol rhs` isn't finite, see `finite`.
+fn strict_binary(symbol: &str, lhs: f64, rhs: f64, value: f64, span: &Range<usize>) -> Result<(), Diagnostic> {
+    if symbol == "/" && rhs == 0. && lhs.is_finite() {
+        return Err(Diagnostic::new("Division by zero").with_kind(ErrorKind::DivisionByZero).with_label(span.clone(), "divides by zero"));
+    }
+    finite(format!("{}{}{}", lhs, symbol, rhs), &[lhs, rhs], value, span)
+}
+
+/// A `Domain` error for `expr` if it computed an infinity or NaN `value`
+/// from finite `inputs`.
+fn finite(expr: String, inputs: &[f64], value: f64, span: &Range<usize>) -> Result<(), Diagnostic> {
+    if value.is_finite() || inputs.iter().any(|input| !input.is_finite()) {
+        return Ok(());
+    }
+    let msg = match value.is_nan() {
+        true => format!("'{}' is undefined", expr),
+        false => format!("'{}' is {}", expr, value),
+    };
+    Err(Diagnostic::new(msg).with_kind(ErrorKind::Domain).with_label(span.clone(), ""))
+}
+
+/// A `Type` error for `value` used where `expected` is.
+fn mismatch(expected: &str, value: Value, span: Range<usize>) -> Diagnostic {
+    Diagnostic::new(format!("Expected {} but got {}", expected, value))
+        .with_kind(ErrorKind::Type)
+        .with_label(span, "")
+}
+
+/// Exact numbers and those in a base as floats, for evaluating with floats.
+fn float(value: Value) -> Value {
+    match value {
+        #[cfg(feature = "decimal")]
+        Value::Decimal(d) => Value::Number(d.to_f64()),
+        Value::Fraction(r) => Value::Number(r.to_f64()),
+        Value::Integer(n) => Value::Number(n.to_f64()),
+        Value::Radix(n, _) => Value::Number(n as f64),
+        value => value,
+    }
+}
+
+/// `f` applied to the numbers of two lists one by one, or to each number of
+/// a list and a number. Matrices follow `matrix::infix`, intervals
+/// `interval::infix`, dates `date::infix` and quantities `units::infix`.
+fn elementwise(symbol: &str, lhs: &Value, rhs: &Value, f: fn(f64, f64) -> f64, span: Range<usize>) -> Result<Value, Diagnostic> {
+    #[cfg(feature = "matrix")]
+    let ret = matrix::infix(symbol, lhs, rhs, f);
+    #[cfg(not(feature = "matrix"))]
+    let ret = None;
+    let ret = ret.or_else(|| interval::infix(symbol, lhs, rhs)).or_else(|| date::infix(symbol, lhs, rhs));
+    #[cfg(feature = "units")]
+    let ret = ret.or_else(|| units::infix(symbol, lhs, rhs));
+    if let Some(ret) = ret {
+        return ret.map_err(|msg| Diagnostic::new(msg).with_kind(ErrorKind::Domain).with_label(span, ""));
+    }
+    let ret = match (lhs, rhs) {
+        (Value::List(xs), Value::List(ys)) if xs.len() != ys.len() => {
+            return Err(Diagnostic::new(format!("Expected lists of the same length but got {} and {} numbers", xs.len(), ys.len()))
+                .with_kind(ErrorKind::Domain)
+                .with_label(span, ""));
+        }
+        (Value::List(xs), Value::List(ys)) => xs.iter().zip(ys).map(|(&x, &y)| f(x, y)).collect(),
+        (Value::List(xs), &Value::Number(y)) => xs.iter().map(|&x| f(x, y)).collect(),
+        (&Value::Number(x), Value::List(ys)) => ys.iter().map(|&y| f(x, y)).collect(),
+        (&Value::Number(x), &Value::Number(y)) => return Ok(f(x, y).into()),
+        (Value::Bool(_) | Value::Formula(_) | Value::Lambda(_) | Value::Plot(_) | Value::Histogram(_), _) => return Err(mismatch("a number", lhs.clone(), span)),
+        _ => return Err(mismatch("a number", rhs.clone(), span)),
+    };
+    Ok(Value::List(ret))
+}
+
+#[cfg(feature = "std")]
+fn env(name: &str, span: Range<usize>) -> Result<Value, Diagnostic> {
+    match std::env::var(name) {
+        Ok(value) => value.trim().parse().map(Value::Number).map_err(|_| {
+            Diagnostic::new(format!("Environment variable '{}' is not a number: '{}'", name, value))
+                .with_kind(ErrorKind::Environment)
+                .with_label(span, "")
+        }),
+        Err(_) => Err(Diagnostic::new(format!("Environment variable '{}' is not set", name))
+            .with_kind(ErrorKind::Environment)
+            .with_label(span, "")),
+    }
+}
+
+#[cfg(not(feature = "std"))]
+fn env(name: &str, span: Range<usize>) -> Result<Value, Diagnostic> {
+    Err(Diagnostic::new(format!("Can't read the environment variable '{}', the calculator is built without std", name))
+        .with_kind(ErrorKind::Environment)
+        .with_label(span, ""))
+}
+
+/// Names follow the grammar's `identifier`.
+fn validate_name(name: &str) -> Result<(), String> {
+    // Namespaced names like `phys.c` or `stats::median` are valid names
+    // joined by dots or `::`.
+    let valid = name.split("::").flat_map(|part| part.split('.')).all(|part| {
+        let mut chars = part.chars();
+        chars.next().is_some_and(|c| c.is_ascii_alphabetic()) && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    });
+    if let Some((_, signature)) = SPECIAL_FORMS.iter().find(|&&(special, _)| special == name) {
+        Err(format!("'{}' is reserved for {}", name, signature))
+    } else if valid {
+        Ok(())
+    } else {
+        Err(format!("'{}' is not a valid name, expected a letter followed by letters, digits and '_'", name))
+    }
+}
+
+pub(crate) fn integer(x: f64) -> Result<i64, String> {
+    if x.fract() == 0. && x.abs() < i64::MAX as f64 {
+        Ok(x as i64)
+    } else {
+        Err(format!("Expected an integer but got {}", x))
+    }
+}
+
+fn natural(x: f64) -> Result<u64, String> {
+    if x.fract() == 0. && (0. ..u64::MAX as f64).contains(&x) {
+        Ok(x as u64)
+    } else {
+        Err(format!("Expected a non-negative integer but got {}", x))
+    }
+}
+
+/// `n!`, which overflows to inf past 170.
+pub(crate) fn factorial(n: f64) -> Result<f64, String> {
+    let n = natural(n)?;
+    Ok(if n > 170 { f64::INFINITY } else { (2..=n).map(|i| i as f64).product() })
+}
+
+/// The ways to pick `k` of `n` things, as combinations if `unordered`
+/// and as permutations otherwise.
+fn choose(n: u64, k: u64, unordered: bool) -> f64 {
+    if k > n {
+        return 0.;
+    }
+    if unordered {
+        // Each partial product is itself a binomial coefficient, so it stays
+        // an integer as long as it is exact.
+        let k = k.min(n - k);
+        let mut acc = 1f64;
+        for i in 1..=k {
+            acc = (acc * (n - k + i) as f64 / i as f64).round();
+            // It stays infinite, rather than going on through a huge `k`.
+            if acc.is_infinite() {
+                break;
+            }
+        }
+        acc
+    } else {
+        let mut acc = 1f64;
+        for i in n - k + 1..=n {
+            acc *= i as f64;
+            if acc.is_infinite() {
+                break;
+            }
+        }
+        acc
+    }
+}
+
+/// `x` written in base `b`, like `0b1010` for `tobase(10, 2)`.
+fn to_base(x: i64, b: i64) -> Result<Value, String> {
+    if !(2..=36).contains(&b) {
+        return Err(format!("Base {} is out of range 2..36", b));
+    }
+    Ok(Value::Radix(x, b as u32))
+}
+
+/// The 64-bit two's complement pattern of an integer.
+fn twos_complement(x: f64) -> Result<u64, String> {
+    integer(x).map(|x| x as u64)
+}
+
+/// `b - a` for interpolating from `a` to `b`, which have to differ.
+fn range_length(a: f64, b: f64) -> Result<f64, String> {
+    if a == b {
+        return Err(format!("Expected a range with different ends but got {} to {}", a, b));
+    }
+    Ok(b - a)
+}
+
+fn bit_index(n: f64) -> Result<u32, String> {
+    match integer(n)? {
+        n @ 0..=63 => Ok(n as u32),
+        n => Err(format!("Bit index {} is out of range 0..63", n)),
+    }
+}
+
+/// Rotates the low `width` bits of `x` by `n` positions.
+fn rotate(args: &[f64], left: bool) -> Result<f64, String> {
+    let width = match integer(args[2])? {
+        width @ 1..=64 => width as u32,
+        width => return Err(format!("Width {} is out of range 1..64", width)),
+    };
+    let mask = if width == 64 { u64::MAX } else { (1 << width) - 1 };
+
+    let x = twos_complement(args[0])?;
+    if x & !mask != 0 {
+        return Err(format!("{} doesn't fit in {} bits", args[0], width));
+    }
+
+    let n = integer(args[1])?.rem_euclid(width as i64) as u32;
+    let n = if left { n } else { (width - n) % width };
+    let ret = if n == 0 { x } else { (x << n | x >> (width - n)) & mask };
+    Ok(ret as f64)
+}
+
+/// The `x` in `0..m` with `a * x = 1 (mod m)`, through the extended
+/// Euclidean algorithm.
+fn modinv(a: i64, m: i64) -> Result<i64, String> {
+    if m <= 0 {
+        return Err(format!("Expected a positive modulus but got {}", m));
+    }
+
+    let (mut r0, mut r1) = (m as i128, (a as i128).rem_euclid(m as i128));
+    let (mut t0, mut t1) = (0i128, 1i128);
+    while r1 != 0 {
+        let q = r0 / r1;
+        (r0, r1) = (r1, r0 - q * r1);
+        (t0, t1) = (t1, t0 - q * t1);
+    }
+
+    if r0 != 1 {
+        return Err(format!("{} has no inverse modulo {}, gcd is {}", a, m, r0));
+    }
+    Ok(t0.rem_euclid(m as i128) as i64)
+}
+
+/// The smallest `x` from 0 with `x = r (mod m)` for each remainder and
+/// modulus, by the Chinese remainder theorem. The moduli needn't be coprime
+/// as long as the remainders agree where they share factors.
+fn crt(rs: &[f64], ms: &[f64]) -> Result<i64, String> {
+    let (mut x, mut m) = (0i128, 1i128);
+    for (&r, &n) in rs.iter().zip(ms) {
+        let (r, n) = (integer(r)? as i128, integer(n)? as i128);
+        if n <= 0 {
+            return Err(format!("Expected a positive modulus but got {}", n));
+        }
+        // x + m*k = r (mod n), solved for k with m/g inverted modulo n/g.
+        let g = functions::gcd(m as i64, n as i64) as i128;
+        if (r - x).rem_euclid(g) != 0 {
+            return Err(format!("There is no number with remainder {} modulo {} and {} modulo {}", x, m, r.rem_euclid(n), n));
+        }
+        let inverse = modinv((m / g) as i64, (n / g) as i64)? as i128;
+        let k = ((r - x) / g).rem_euclid(n / g) * inverse % (n / g);
+        x += m * k;
+        m = m / g * n;
+        if m > 1 << 53 {
+            return Err(format!("The combined modulus {} is too large", m));
+        }
+        x = x.rem_euclid(m);
+    }
+    Ok(x as i64)
+}
+
+/// The unbiased exponent of the IEEE-754 representation, subnormals report
+/// the minimum exponent -1022.
+pub fn exponent(x: f64) -> i32 {
+    match ((x.to_bits() >> 52) & 0x7ff) as i32 {
+        0 => -1022,
+        biased => biased - 1023,
+    }
+}
+
+/// Splits `x` into a fraction in `[0.5, 1)` and a power of two, like C's
+/// `frexp`. The exponent is `exponent(x) + 1` for normal numbers.
+fn frexp(x: f64) -> (f64, i32) {
+    if x == 0. || !x.is_finite() {
+        return (x, 0);
+    }
+    if x.abs() < f64::MIN_POSITIVE {
+        let (m, e) = frexp(x * 2f64.powi(54));
+        return (m, e - 54);
+    }
+
+    let bits = x.to_bits();
+    let m = f64::from_bits(bits & !(0x7ff << 52) | (1022 << 52));
+    (m, ((bits >> 52) & 0x7ff) as i32 - 1022)
+}
+
+/// `m * 2^e` without overflowing in `2^e` when the result is representable.
+fn ldexp(mut m: f64, mut e: i64) -> f64 {
+    while e > 1000 && m.is_finite() && m != 0. {
+        m *= 2f64.powi(1000);
+        e -= 1000;
+    }
+    while e < -1000 && m.is_finite() && m != 0. {
+        m *= 2f64.powi(-1000);
+        e += 1000;
+    }
+    m * 2f64.powi(e.clamp(-1100, 1100) as i32)
+}
+
+/// The gap between `|x|` and the next float of larger magnitude.
+fn ulp(x: f64) -> f64 {
+    let x = x.abs();
+    if x == f64::MAX {
+        x - x.next_down()
+    } else {
+        x.next_up() - x
+    }
+}
+
+/// The next float after `x` in the direction of `y`.
+fn nextafter(x: f64, y: f64) -> f64 {
+    if x.is_nan() || y.is_nan() {
+        f64::NAN
+    } else if x == y {
+        y
+    } else if x < y {
+        x.next_up()
+    } else {
+        x.next_down()
     }
 }
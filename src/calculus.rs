@@ -0,0 +1,240 @@
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+
+/// How many times `integrate` evaluates the function before giving up.
+const MAX_EVALUATIONS: usize = 100_000;
+
+/// How often `integrate` halves an interval.
+const MAX_DEPTH: u32 = 50;
+
+/// The integral of `f` from `a` to `b` by adaptive Simpson quadrature,
+/// halving the intervals where the estimate is off by more than
+/// `tolerance`. `fail` makes the error for an integral that doesn't
+/// converge.
+pub(crate) fn integrate<E>(f: &mut dyn FnMut(f64) -> Result<f64, E>, fail: impl Fn(String) -> E, a: f64, b: f64, tolerance: f64) -> Result<f64, E> {
+    if !a.is_finite() || !b.is_finite() {
+        return Err(fail(format!("Expected finite bounds but got {} and {}", a, b)));
+    }
+    let mut evaluations = 0;
+    let mut sample = |x: f64| {
+        evaluations += 1;
+        if evaluations > MAX_EVALUATIONS {
+            return Err(fail(format!("The integral doesn't converge within {} evaluations", MAX_EVALUATIONS)));
+        }
+        match f(x)? {
+            y if y.is_finite() => Ok(y),
+            y => Err(fail(format!("The function is {} at {}", y, x))),
+        }
+    };
+
+    let m = (a + b) / 2.;
+    let (fa, fm, fb) = (sample(a)?, sample(m)?, sample(b)?);
+    // Intervals still to estimate, with the Simpson estimate of the whole
+    // of each and its function values at both ends and the middle.
+    let mut pending = vec![(a, b, tolerance, (b - a) / 6. * (fa + 4. * fm + fb), [fa, fm, fb], 0)];
+    let mut sum = 0.;
+    while let Some((a, b, tolerance, whole, [fa, fm, fb], depth)) = pending.pop() {
+        let m = (a + b) / 2.;
+        let (flm, frm) = (sample((a + m) / 2.)?, sample((m + b) / 2.)?);
+        let left = (m - a) / 6. * (fa + 4. * flm + fm);
+        let right = (b - m) / 6. * (fm + 4. * frm + fb);
+        let delta = left + right - whole;
+        if delta.abs() <= 15. * tolerance {
+            sum += left + right + delta / 15.;
+        } else if depth >= MAX_DEPTH {
+            return Err(fail(format!("The integral doesn't converge near {}", m)));
+        } else {
+            pending.push((a, m, tolerance / 2., left, [fa, flm, fm], depth + 1));
+            pending.push((m, b, tolerance / 2., right, [fm, frm, fb], depth + 1));
+        }
+    }
+    Ok(sum)
+}
+
+/// How many steps `newton` takes before giving up.
+const MAX_ITERATIONS: usize = 100;
+
+/// A root of `f` by Newton's method from `guess`, `f` giving the value and
+/// the slope at a point. `fail` makes the error for a guess it can't get
+/// to a root from.
+pub(crate) fn newton<E>(f: &mut dyn FnMut(f64) -> Result<(f64, f64), E>, fail: impl Fn(String) -> E, guess: f64) -> Result<f64, E> {
+    let mut x = guess;
+    for _ in 0..MAX_ITERATIONS {
+        let (y, slope) = f(x)?;
+        if y == 0. {
+            return Ok(x);
+        }
+        if !y.is_finite() {
+            return Err(fail(format!("The function is {} at {}", y, x)));
+        }
+        if slope == 0. || !slope.is_finite() {
+            return Err(fail(format!("The slope is {} at {}", slope, x)));
+        }
+        let next = x - y / slope;
+        if (next - x).abs() <= 1e-12 * x.abs().max(1.) {
+            return Ok(next);
+        }
+        x = next;
+    }
+    Err(fail(format!("Newton's method doesn't converge from {}, it got to {} after {} steps", guess, x, MAX_ITERATIONS)))
+}
+
+/// A root of `f` between `a` and `b` by bisection, `f` has to change sign
+/// between them.
+pub(crate) fn bisect<E>(f: &mut dyn FnMut(f64) -> Result<f64, E>, fail: impl Fn(String) -> E, a: f64, b: f64) -> Result<f64, E> {
+    let (mut a, mut b) = (a, b);
+    let (mut fa, fb) = (f(a)?, f(b)?);
+    if fa == 0. || fb == 0. {
+        return Ok(if fa == 0. { a } else { b });
+    }
+    if fa.is_nan() || fb.is_nan() || fa.signum() == fb.signum() {
+        return Err(fail(format!("The function doesn't change sign between {} and {}", a, b)));
+    }
+    // Halving any interval of floats gets to neighbors in fewer steps.
+    for _ in 0..2100 {
+        let m = (a + b) / 2.;
+        if m == a || m == b {
+            break;
+        }
+        match f(m)? {
+            0. => return Ok(m),
+            fm if fm.is_nan() => return Err(fail(format!("The function is NaN at {}", m))),
+            fm if fm.signum() == fa.signum() => (a, fa) = (m, fm),
+            _ => b = m,
+        }
+    }
+    // Where `1/x` changes sign there is no root.
+    let root = (a + b) / 2.;
+    match f(root)? {
+        y if y.is_finite() => Ok(root),
+        _ => Err(fail(format!("The function has a pole rather than a root at {}", root))),
+    }
+}
+
+/// How many rounds `polyroots` refines its guesses for.
+const MAX_ROUNDS: usize = 1000;
+
+/// The real roots of the polynomial with `coefficients` from the highest
+/// power down, each as often as it repeats, in increasing order. All roots
+/// are found together by the Durand–Kerner method, those whose imaginary
+/// part is lost in rounding are the real ones.
+pub(crate) fn polyroots(coefficients: &[f64]) -> Result<Vec<f64>, String> {
+    if let Some(c) = coefficients.iter().find(|c| !c.is_finite()) {
+        return Err(format!("Expected finite coefficients but got {}", c));
+    }
+    let Some(first) = coefficients.iter().position(|&c| c != 0.) else {
+        return Err("Every number is a root of the zero polynomial".to_string());
+    };
+    // Trailing zeros are roots at 0 of their own.
+    let last = coefficients.iter().rposition(|&c| c != 0.).unwrap();
+    let mut roots = vec![0.; coefficients.len() - 1 - last];
+    let monic: Vec<f64> = coefficients[first..=last].iter().map(|c| c / coefficients[first]).collect();
+    let degree = monic.len() - 1;
+
+    let value = |z: (f64, f64)| monic.iter().fold((0., 0.), |acc, &c| add(mul(acc, z), (c, 0.)));
+    // Guesses spread around a circle holding every root.
+    let radius = 1. + monic[1..].iter().map(|c| c.abs()).fold(0., f64::max);
+    let mut z: Vec<(f64, f64)> = (0..degree)
+        .map(|k| {
+            let angle = core::f64::consts::TAU * k as f64 / degree as f64 + 0.4;
+            (radius * angle.cos(), radius * angle.sin())
+        })
+        .collect();
+    for _ in 0..MAX_ROUNDS {
+        let mut change: f64 = 0.;
+        for i in 0..degree {
+            let denominator = (0..degree).filter(|&j| j != i).fold((1., 0.), |acc, j| mul(acc, sub(z[i], z[j])));
+            let step = div(value(z[i]), denominator);
+            if !(step.0.is_finite() && step.1.is_finite()) {
+                continue;
+            }
+            z[i] = sub(z[i], step);
+            change = change.max(step.0.hypot(step.1) / z[i].0.hypot(z[i].1).max(1.));
+        }
+        if change <= 1e-15 {
+            break;
+        }
+    }
+
+    // A root repeated m times only settles to within about the m-th root
+    // of the rounding error, spread evenly around it, so the guesses near
+    // each other stand for one root at their center.
+    // A root repeated m times only settles to within about the m-th root
+    // of the rounding error, so guesses near each other stand for one root.
+    // That is a simple root of the (m-1)th derivative, which Newton's method
+    // finds from the middle of them.
+    let mut clusters: Vec<Vec<(f64, f64)>> = vec![];
+    for root in z {
+        let near = |other: &(f64, f64)| sub(root, *other).0.hypot(sub(root, *other).1) <= 1e-3 * root.0.hypot(root.1).max(1.);
+        match clusters.iter_mut().find(|cluster| cluster.iter().any(near)) {
+            Some(cluster) => cluster.push(root),
+            None => clusters.push(vec![root]),
+        }
+    }
+    let scale = |x: f64| monic.iter().fold(0., |acc: f64, &c| acc * x.abs() + c.abs());
+    let is_root = |x: f64| horner(&monic, x).0.abs() <= 1e-9 * scale(x);
+    for cluster in clusters {
+        let m = cluster.len();
+        let (re, im) = cluster.iter().fold((0., 0.), |acc, &root| add(acc, root));
+        let (re, im) = (re / m as f64, im / m as f64);
+        if m > 1 && im.abs() <= 1e-3 * re.abs().max(1.) {
+            let derivative = (1..m).fold(monic.clone(), |p, _| differentiate(&p));
+            let x = polish(&derivative, re);
+            if is_root(x) {
+                roots.extend(core::iter::repeat_n(x, m));
+                continue;
+            }
+        }
+        for (re, im) in cluster {
+            let x = polish(&monic, re);
+            if im.abs() <= 1e-6 * re.abs().max(1.) && is_root(x) {
+                roots.push(x);
+            }
+        }
+    }
+    roots.sort_by(f64::total_cmp);
+    Ok(roots)
+}
+
+/// The value and slope of the polynomial `p` at `x`.
+fn horner(p: &[f64], x: f64) -> (f64, f64) {
+    p.iter().fold((0., 0.), |(y, slope), &c| (y * x + c, slope * x + y))
+}
+
+fn differentiate(p: &[f64]) -> Vec<f64> {
+    let degree = p.len() - 1;
+    p[..degree].iter().enumerate().map(|(i, c)| c * (degree - i) as f64).collect()
+}
+
+/// A few Newton steps on `p` from `x` while they get closer to 0, then
+/// the number with 12 significant digits if it is as good, so a root at
+/// 2 isn't 1.9999999999999998.
+fn polish(p: &[f64], mut x: f64) -> f64 {
+    for _ in 0..10 {
+        let (y, slope) = horner(p, x);
+        let next = x - y / slope;
+        if next.is_nan() || horner(p, next).0.abs() >= y.abs() {
+            break;
+        }
+        x = next;
+    }
+    let short: f64 = format!("{:.11e}", x).parse().unwrap();
+    if horner(p, short).0.abs() <= horner(p, x).0.abs() { short } else { x }
+}
+
+fn add(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    (a.0 + b.0, a.1 + b.1)
+}
+
+fn sub(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+fn mul(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+}
+
+fn div(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    let norm = b.0 * b.0 + b.1 * b.1;
+    ((a.0 * b.0 + a.1 * b.1) / norm, (a.1 * b.0 - a.0 * b.1) / norm)
+}
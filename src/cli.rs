@@ -0,0 +1,190 @@
+use std::path::PathBuf;
+use rustyline::EditMode;
+use crate::config;
+
+pub(crate) const USAGE: &str = "\
+Usage: calculator [options]
+       calculator [options] <expression>...
+       calculator test [paths...]
+       calculator grid <file>
+       calculator run [--quiet] [--check] <file>
+       calculator serve [--port <port>] [--allow-origin <origin>]
+
+Expressions given as arguments, or piped to stdin one per line, are evaluated
+and printed without starting the REPL. An argument starting with a single `-`
+like `-2^2` is an expression, and every argument after `--` is one. Errors go to stderr and make the exit
+code nonzero.
+
+Commands:
+  test         Run the .calc files under the given paths (default: tests)
+  grid         Evaluate a sheet of cells like `A3 = A1 + A2` and print it as a table
+  run          Execute a script of statements separated by newlines or `;`
+  serve        Evaluate the lines of `POST /eval` requests on localhost, see --json
+
+Options:
+  --porcelain  Print only result values, one per line, without prompts
+  --json       Print each result or error as a JSON object, one per line
+  --env        Allow `$NAME` to read numbers from environment variables
+  --sanitize   Accept numbers pasted from documents, like `$ 1,234.50` or `−3.2`
+  --si         Read SI prefixes on numbers, `4.7k` is 4700 and `100m` is 0.1
+  --warn       Warn about easily misread groupings and lost precision
+  --strict     Fail on division by zero and other results that aren't finite
+  --no-history Don't read or write the history file
+  --no-color   Print the REPL without colors, as does setting NO_COLOR
+  --edit-mode  The key bindings of the REPL, emacs or vi (default: the config file's)
+  --history-dups
+               Record a line in the history again when it repeats the previous one
+  --no-auto-history
+               Don't add the lines entered to the history
+  --copy       Put the last result on the clipboard too
+  --no-plugins Don't load the plugins in ~/.config/calculator/plugins
+  --no-init    Don't execute ~/.config/calculator/init.calc when the REPL starts
+  --session    Restore the REPL from a named session and save it there on exit
+  --quiet      Print only the result of the last statement of a script
+  --check      Report every syntax error of a script without executing it
+  --port       The port to serve on (default: 8080)
+  --allow-origin
+               Let pages of this origin call serve, like http://localhost:3000
+  -h, --help   Print this help";
+
+/// Command line options.
+#[derive(Default)]
+pub(crate) struct Options {
+    /// Machine-readable mode for driving the calculator from other programs.
+    pub porcelain: bool,
+    /// Print results as JSON objects, see `json::run`.
+    pub json: bool,
+    /// Expand `$NAME` from the environment.
+    pub env: bool,
+    /// Normalize currency symbols, separators and Unicode minus signs.
+    pub sanitize: bool,
+    /// Read `4.7k` as 4700.
+    pub si: bool,
+    /// Report ambiguous operator groupings.
+    pub warn: bool,
+    /// Fail rather than give inf or NaN.
+    pub strict: bool,
+    /// Keep the history of this session to itself.
+    pub no_history: bool,
+    pub no_color: bool,
+    /// Override the `Config` of the line editor.
+    pub edit_mode: Option<EditMode>,
+    pub history_dups: bool,
+    pub no_auto_history: bool,
+    /// Copy the last result of `Command::Eval` or stdin.
+    pub copy: bool,
+    /// Skip `plugin::load_all`.
+    pub no_plugins: bool,
+    /// Skip the `script::init` file.
+    pub no_init: bool,
+    /// The session the REPL restores and saves, `default` with the
+    /// `auto_session` setting.
+    pub session: Option<String>,
+    /// Only print the last result of `run`.
+    pub quiet: bool,
+    /// Only report the syntax errors of `run`.
+    pub check: bool,
+    /// The port of `serve`.
+    pub port: Option<u16>,
+    /// The origin whose pages `serve` allows, with CORS.
+    pub allow_origin: Option<String>,
+    pub help: bool,
+    pub command: Option<Command>,
+}
+
+pub(crate) enum Command {
+    /// Run `.calc` test files, from the given files and directories.
+    Test(Vec<PathBuf>),
+    /// Evaluate a `grid::Sheet`, the path is empty until it is given.
+    Grid(PathBuf),
+    /// Execute a `script`, the path is empty until it is given.
+    Run(PathBuf),
+    /// Evaluate the expressions and exit.
+    Eval(Vec<String>),
+    /// Run a `serve::Server`.
+    Serve,
+}
+
+impl Options {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        // After `--` every argument is an expression, even `test` or `--x`.
+        let mut terminated = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                _ if terminated => options.argument(arg, false)?,
+                "--" => terminated = true,
+                "--porcelain" => options.porcelain = true,
+                "--json" => options.json = true,
+                "--env" => options.env = true,
+                "--sanitize" => options.sanitize = true,
+                "--si" => options.si = true,
+                "--warn" => options.warn = true,
+                "--strict" => options.strict = true,
+                "--no-history" => options.no_history = true,
+                "--no-color" => options.no_color = true,
+                "--edit-mode" => {
+                    let mode = args.next().and_then(|mode| config::edit_mode(&mode));
+                    options.edit_mode = Some(mode.ok_or(format!("Expected emacs or vi after --edit-mode\n\n{}", USAGE))?);
+                }
+                "--history-dups" => options.history_dups = true,
+                "--no-auto-history" => options.no_auto_history = true,
+                "--copy" => options.copy = true,
+                "--no-plugins" => options.no_plugins = true,
+                "--no-init" => options.no_init = true,
+                "--session" => {
+                    let name = args.next().filter(|name| !name.starts_with('-'));
+                    options.session = Some(name.ok_or(format!("Expected a session name after --session\n\n{}", USAGE))?);
+                }
+                "--quiet" => options.quiet = true,
+                "--check" => options.check = true,
+                "--port" => {
+                    let port = args.next().and_then(|port| port.parse().ok());
+                    options.port = Some(port.ok_or(format!("Expected a port number after --port\n\n{}", USAGE))?);
+                }
+                "--allow-origin" => {
+                    let origin = args.next().filter(|origin| !origin.starts_with('-'));
+                    options.allow_origin = Some(origin.ok_or(format!("Expected an origin after --allow-origin\n\n{}", USAGE))?);
+                }
+                "-h" | "--help" => options.help = true,
+                // `-2^2` is an expression, `--typo` an option that doesn't exist.
+                _ if arg.starts_with("--") => return Err(format!("Unknown option '{}'\n\n{}", arg, USAGE)),
+                _ => options.argument(arg, true)?,
+            }
+        }
+
+        match &mut options.command {
+            Some(Command::Test(paths)) if paths.is_empty() => paths.push(PathBuf::from("tests")),
+            Some(Command::Grid(path)) if path.as_os_str().is_empty() => {
+                return Err(format!("Missing the sheet to evaluate\n\n{}", USAGE));
+            }
+            Some(Command::Run(path)) if path.as_os_str().is_empty() => {
+                return Err(format!("Missing the script to run\n\n{}", USAGE));
+            }
+            Some(Command::Serve) => {}
+            _ if options.port.is_some() => return Err(format!("--port is an option of serve\n\n{}", USAGE)),
+            _ if options.allow_origin.is_some() => return Err(format!("--allow-origin is an option of serve\n\n{}", USAGE)),
+            _ => {}
+        }
+
+        Ok(options)
+    }
+
+    /// An argument that isn't an option: the command, with `commands` if it
+    /// names one, or else what the command takes.
+    fn argument(&mut self, arg: String, commands: bool) -> Result<(), String> {
+        match self.command {
+            Some(Command::Test(ref mut paths)) => paths.push(PathBuf::from(arg)),
+            Some(Command::Eval(ref mut exprs)) => exprs.push(arg),
+            Some(Command::Grid(ref mut path) | Command::Run(ref mut path)) if path.as_os_str().is_empty() => *path = PathBuf::from(arg),
+            None if commands && arg == "test" => self.command = Some(Command::Test(vec![])),
+            None if commands && arg == "grid" => self.command = Some(Command::Grid(PathBuf::new())),
+            None if commands && arg == "run" => self.command = Some(Command::Run(PathBuf::new())),
+            None if commands && arg == "serve" => self.command = Some(Command::Serve),
+            Some(Command::Grid(_) | Command::Run(_) | Command::Serve) => return Err(format!("Unexpected argument '{}'\n\n{}", arg, USAGE)),
+            None => self.command = Some(Command::Eval(vec![arg])),
+        }
+        Ok(())
+    }
+}
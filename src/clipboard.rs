@@ -0,0 +1,50 @@
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+/// The programs `copy` tries in order, with their arguments.
+const PROGRAMS: &[(&str, &[&str])] = &[
+    ("pbcopy", &[]),
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+    ("clip.exe", &[]),
+];
+
+/// Puts `text` on the system clipboard through the first of `PROGRAMS` that
+/// runs, or else through the terminal with the OSC 52 escape, which works
+/// over ssh too. Returns what it was copied with.
+pub(crate) fn copy(text: &str) -> Result<&'static str, String> {
+    for &(program, args) in PROGRAMS {
+        let child = Command::new(program).args(args)
+            .stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null())
+            .spawn();
+        let Ok(mut child) = child else { continue };
+        let written = child.stdin.take().unwrap().write_all(text.as_bytes());
+        if child.wait().is_ok_and(|status| status.success()) && written.is_ok() {
+            return Ok(program);
+        }
+    }
+    let mut stdout = std::io::stdout();
+    if !stdout.is_terminal() {
+        return Err("Failed to copy, expected one of pbcopy, wl-copy, xclip, xsel or clip.exe".to_string());
+    }
+    write!(stdout, "{}", osc52(text)).and_then(|_| stdout.flush())
+        .map_err(|err| format!("Failed to copy: {}", err))?;
+    Ok("the terminal")
+}
+
+/// The escape asking the terminal to set its clipboard to `text`.
+pub(crate) fn osc52(text: &str) -> String {
+    const DIGITS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in text.as_bytes().chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| bits | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            encoded.push(match i <= chunk.len() {
+                true => DIGITS[(bits >> (18 - 6 * i) & 63) as usize] as char,
+                false => '=',
+            });
+        }
+    }
+    format!("\x1b]52;c;{}\x07", encoded)
+}
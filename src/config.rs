@@ -0,0 +1,173 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use calculator::PRESETS;
+use rustyline::EditMode;
+
+/// Settings read from `~/.config/calculator/config`.
+///
+/// The file contains one `key = value` pair per line, blank lines and lines
+/// starting with `#` are ignored.
+///
+///   edit_mode = emacs
+///   history_auto_add = true
+///   history_ignore_dups = true
+///   history_ignore_space = false
+///   history_search = true
+///   history_size = 1000
+///   operators = math
+///   auto_session = false
+///   import_path = ~/formulas:/usr/share/formulas
+pub(crate) struct Config {
+    /// The key bindings of the line editor, Emacs or Vi.
+    pub edit_mode: EditMode,
+    /// Add the lines entered to the history.
+    pub history_auto_add: bool,
+    /// Don't add a line to the history if it's the same as the previous one.
+    pub history_ignore_dups: bool,
+    /// Don't add lines starting with a space to the history.
+    pub history_ignore_space: bool,
+    /// Bind Ctrl-R / Ctrl-S to reverse / forward incremental history search.
+    pub history_search: bool,
+    /// How many lines of history are kept, in memory and in the history
+    /// file.
+    pub history_size: usize,
+    /// The operator precedence preset, one of `calculator::PRESETS`.
+    pub operators: String,
+    /// Restore the variables and functions of the last REPL when it starts
+    /// and save them when it exits, as the session `default`.
+    pub auto_session: bool,
+    /// The directories `import "finance.calc"` looks in after the one of the
+    /// importing script, before `config_dir()/lib`.
+    pub import_path: Vec<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            edit_mode: EditMode::Emacs,
+            history_auto_add: true,
+            history_ignore_dups: true,
+            history_ignore_space: false,
+            history_search: true,
+            history_size: 1000,
+            operators: "math".to_string(),
+            auto_session: false,
+            import_path: vec![],
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file, a missing file yields the default settings.
+    pub fn load() -> Result<Self, String> {
+        match config_dir().map(|dir| dir.join("config")) {
+            Some(path) if path.exists() => {
+                let src = fs::read_to_string(&path)
+                    .map_err(|err| format!("Failed to read '{}': {}", path.display(), err))?;
+                Self::parse(&src).map_err(|msg| format!("{}: {}", path.display(), msg))
+            }
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+
+        for (i, line) in src.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = line.split_once('=')
+                .ok_or(format!("line {}: Expected 'key = value'", i + 1))?;
+            let (key, value) = (key.trim(), value.trim());
+
+            if key == "operators" {
+                if !PRESETS.contains(&value) {
+                    return Err(format!("line {}: Expected one of {} for 'operators'", i + 1, PRESETS.join(", ")));
+                }
+                config.operators = value.to_string();
+                continue;
+            }
+            if key == "edit_mode" {
+                config.edit_mode = edit_mode(value)
+                    .ok_or(format!("line {}: Expected 'emacs' or 'vi' for 'edit_mode'", i + 1))?;
+                continue;
+            }
+            if key == "import_path" {
+                config.import_path = std::env::split_paths(value).filter(|dir| !dir.as_os_str().is_empty()).map(|dir| match dir.strip_prefix("~") {
+                    Ok(rest) => home::home_dir().map_or(dir.clone(), |home| home.join(rest)),
+                    Err(_) => dir,
+                }).collect();
+                continue;
+            }
+            if key == "history_size" {
+                config.history_size = value.parse()
+                    .map_err(|_| format!("line {}: Expected a number of lines for 'history_size'", i + 1))?;
+                continue;
+            }
+
+            let flag = match key {
+                "history_auto_add" => &mut config.history_auto_add,
+                "history_ignore_dups" => &mut config.history_ignore_dups,
+                "history_ignore_space" => &mut config.history_ignore_space,
+                "history_search" => &mut config.history_search,
+                "auto_session" => &mut config.auto_session,
+                _ => return Err(format!("line {}: Unknown setting '{}'", i + 1, key))
+            };
+            *flag = parse_bool(value)
+                .ok_or(format!("line {}: Expected 'true' or 'false' for '{}'", i + 1, key))?;
+        }
+
+        Ok(config)
+    }
+}
+
+/// `$XDG_CONFIG_HOME/calculator`, falling back to `~/.config/calculator`.
+pub(crate) fn config_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home::home_dir()?.join(".config"),
+    };
+    Some(base.join("calculator"))
+}
+
+/// `$XDG_DATA_HOME/calculator`, falling back to `~/.local/share/calculator`.
+pub(crate) fn data_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home::home_dir()?.join(".local/share"),
+    };
+    Some(base.join("calculator"))
+}
+
+/// Where the REPL saves named sessions, `data_dir()/sessions`.
+pub(crate) fn sessions_dir() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("sessions"))
+}
+
+/// The file of the session `name` in `dir`, which has to be a name of
+/// letters, digits, `-` and `_` so it can't point elsewhere.
+pub(crate) fn session_path(dir: &Path, name: &str) -> Result<PathBuf, String> {
+    match !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        true => Ok(dir.join(format!("{}.calc", name))),
+        false => Err(format!("'{}' is not a valid session name, expected letters, digits, '-' and '_'", name)),
+    }
+}
+
+pub(crate) fn edit_mode(value: &str) -> Option<EditMode> {
+    match value {
+        "emacs" => Some(EditMode::Emacs),
+        "vi" => Some(EditMode::Vi),
+        _ => None,
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" | "yes" | "on" => Some(true),
+        "false" | "no" | "off" => Some(false),
+        _ => None
+    }
+}
@@ -0,0 +1,235 @@
+use core::fmt;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::{SystemTime, UNIX_EPOCH};
+use crate::calculator::Function;
+#[cfg(feature = "units")]
+use crate::units::Unit;
+use crate::value::Value;
+
+const DAY: f64 = 86400.;
+
+/// A point in time in UTC, printed like `2024-05-01` or
+/// `2024-05-01T12:30:00Z`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Date {
+    /// Since 1970-01-01.
+    seconds: f64,
+}
+
+/// A length of time, printed like `P90D` or `PT1H30M`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Duration {
+    seconds: f64,
+}
+
+impl Date {
+    /// `None` for a day that doesn't exist, like February 30.
+    pub fn new(year: i64, month: u32, day: u32) -> Option<Self> {
+        if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+            return None;
+        }
+        Some(Self { seconds: days_from_civil(year, month, day) as f64 * DAY })
+    }
+
+    /// 1970-01-01 on a platform without a clock, see `now()`.
+    pub fn now() -> Self {
+        if !crate::CLOCK {
+            return Self { seconds: 0. };
+        }
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0., |time| time.as_secs() as f64);
+        Self { seconds }
+    }
+
+    /// Seconds since 1970-01-01.
+    pub fn timestamp(&self) -> f64 {
+        self.seconds
+    }
+
+    fn days(&self) -> i64 {
+        (self.seconds / DAY).floor() as i64
+    }
+
+    /// `date(year, month, day)`, plus the `seconds` past midnight if it has a
+    /// time, which evaluates back to the date.
+    pub(crate) fn to_source(self) -> String {
+        let (year, month, day) = civil_from_days(self.days());
+        let time = self.seconds - self.days() as f64 * DAY;
+        match time {
+            0. => format!("date({}, {}, {})", year, month, day),
+            _ => format!("date({}, {}, {}) + seconds({:?})", year, month, day, time),
+        }
+    }
+
+    /// 1 for Monday to 7 for Sunday.
+    pub fn weekday(&self) -> u32 {
+        // 1970-01-01 was a Thursday.
+        ((self.days() + 3).rem_euclid(7) + 1) as u32
+    }
+}
+
+impl Duration {
+    pub fn from_seconds(seconds: f64) -> Self {
+        Self { seconds }
+    }
+
+    pub fn seconds(&self) -> f64 {
+        self.seconds
+    }
+}
+
+fn leap(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if leap(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar, after
+/// Howard Hinnant's `days_from_civil`.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) as i64 + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// The inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    (yoe + era * 400 + (month <= 2) as i64, month, day)
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (year, month, day) = civil_from_days(self.days());
+        write!(f, "{:04}-{:02}-{:02}", year, month, day)?;
+        let time = (self.seconds - self.days() as f64 * DAY) as u32;
+        if time != 0 {
+            write!(f, "T{:02}:{:02}:{:02}Z", time / 3600, time / 60 % 60, time % 60)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.seconds < 0. {
+            write!(f, "-")?;
+        }
+        let rest = self.seconds.abs();
+        let (days, rest) = ((rest / DAY).floor(), rest % DAY);
+        let (hours, rest) = ((rest / 3600.).floor(), rest % 3600.);
+        let (minutes, seconds) = ((rest / 60.).floor(), rest % 60.);
+        write!(f, "P")?;
+        if days > 0. {
+            write!(f, "{}D", days)?;
+        }
+        if hours > 0. || minutes > 0. || seconds > 0. || days == 0. {
+            write!(f, "T")?;
+            if hours > 0. {
+                write!(f, "{}H", hours)?;
+            }
+            if minutes > 0. {
+                write!(f, "{}M", minutes)?;
+            }
+            if seconds > 0. || (hours == 0. && minutes == 0.) {
+                write!(f, "{}S", seconds)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A duration, or a quantity of time like `3 h`.
+fn duration(value: &Value) -> Option<Duration> {
+    match value {
+        Value::Duration(d) => Some(*d),
+        #[cfg(feature = "units")]
+        Value::Quantity(q) => q.to(&Unit::lookup("s")?).map(|q| Duration::from_seconds(q.value())),
+        _ => None,
+    }
+}
+
+/// An infix operator with a date or duration operand. `None` if it
+/// involves neither.
+pub(crate) fn infix(symbol: &str, lhs: &Value, rhs: &Value) -> Option<Result<Value, String>> {
+    let involved = |value: &Value| matches!(value, Value::Date(_) | Value::Duration(_));
+    if !involved(lhs) && !involved(rhs) {
+        return None;
+    }
+    let ret = match (symbol, lhs, rhs) {
+        ("+", Value::Date(date), other) | ("+", other, Value::Date(date)) if duration(other).is_some() => {
+            Ok(Value::Date(Date { seconds: date.seconds + duration(other).unwrap().seconds }))
+        }
+        ("-", Value::Date(a), Value::Date(b)) => Ok(Value::Duration(Duration::from_seconds(a.seconds - b.seconds))),
+        ("-", Value::Date(date), other) if duration(other).is_some() => {
+            Ok(Value::Date(Date { seconds: date.seconds - duration(other).unwrap().seconds }))
+        }
+        ("+" | "-" | "/", a, b) if duration(a).is_some() && duration(b).is_some() => {
+            let (a, b) = (duration(a).unwrap().seconds, duration(b).unwrap().seconds);
+            Ok(match symbol {
+                "+" => Value::Duration(Duration::from_seconds(a + b)),
+                "-" => Value::Duration(Duration::from_seconds(a - b)),
+                _ => Value::Number(a / b),
+            })
+        }
+        ("*", Value::Duration(d), n) | ("*", n, Value::Duration(d)) if n.number().is_some() => {
+            Ok(Value::Duration(Duration::from_seconds(d.seconds * n.number().unwrap())))
+        }
+        ("/", Value::Duration(d), n) if n.number().is_some() => Ok(Value::Duration(Duration::from_seconds(d.seconds / n.number().unwrap()))),
+        _ => Err(format!("Can't apply '{}' to {} and {}", symbol, lhs, rhs)),
+    };
+    Some(ret)
+}
+
+fn date(value: &Value) -> Result<Date, String> {
+    match value {
+        Value::Date(date) => Ok(*date),
+        value => Err(format!("Expected a date but got {}", value)),
+    }
+}
+
+fn number(value: &Value) -> Result<f64, String> {
+    value.number().ok_or(format!("Expected a number but got {}", value))
+}
+
+pub(crate) fn functions() -> Vec<(&'static str, Function)> {
+    vec![
+        ("date(year, month, day)", Function::Values(3, |args| {
+            let [year, month, day] = [number(&args[0])?, number(&args[1])?, number(&args[2])?];
+            if [year, month, day].iter().any(|x| x.fract() != 0.) || year.abs() > 1e6 {
+                return Err(format!("Expected a whole year, month and day but got {}, {} and {}", year, month, day));
+            }
+            Date::new(year as i64, month as u32, day as u32).map(Value::Date).ok_or(format!("There is no day {} in month {} of {}", day, month, year))
+        })),
+        ("now()", Function::Values(0, |_| match crate::CLOCK {
+            true => Ok(Value::Date(Date::now())),
+            false => Err("There is no clock on this platform".to_string()),
+        })),
+        ("weekday(date)", Function::Values(1, |args| Ok(Value::Number(date(&args[0])?.weekday() as f64)))),
+        ("days_between(a, b)", Function::Values(2, |args| {
+            Ok(Value::Number((date(&args[1])?.seconds - date(&args[0])?.seconds) / DAY))
+        })),
+        ("weeks(n)", Function::Values(1, |args| Ok(Value::Duration(Duration::from_seconds(number(&args[0])? * 7. * DAY))))),
+        ("days(n)", Function::Values(1, |args| Ok(Value::Duration(Duration::from_seconds(number(&args[0])? * DAY))))),
+        ("hours(n)", Function::Values(1, |args| Ok(Value::Duration(Duration::from_seconds(number(&args[0])? * 3600.))))),
+        ("minutes(n)", Function::Values(1, |args| Ok(Value::Duration(Duration::from_seconds(number(&args[0])? * 60.))))),
+        ("seconds(n)", Function::Values(1, |args| Ok(Value::Duration(Duration::from_seconds(number(&args[0])?))))),
+    ]
+}
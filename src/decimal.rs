@@ -0,0 +1,168 @@
+use core::cmp::Ordering;
+use core::fmt;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::exact::Exact;
+use crate::value::Value;
+
+/// The most digits after the point a `Decimal` keeps.
+pub const MAX_PLACES: u32 = 36;
+
+/// A decimal number `coefficient / 10^scale`, so `0.1 + 0.2` is exactly
+/// `0.3`. About 38 significant digits fit, past that results are floats.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Decimal {
+    coefficient: i128,
+    /// Digits after the point, without trailing zeros.
+    scale: u32,
+}
+
+impl Decimal {
+    fn new(mut coefficient: i128, mut scale: u32) -> Self {
+        while scale > 0 && coefficient % 10 == 0 {
+            coefficient /= 10;
+            scale -= 1;
+        }
+        Self { coefficient, scale }
+    }
+
+    /// Reads a number like `-12.375`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (int, frac) = text.split_once('.').unwrap_or((text, ""));
+        let scale = u32::try_from(frac.len()).ok().filter(|&scale| scale <= MAX_PLACES)?;
+        if !frac.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        Some(Self::new(format!("{}{}", int, frac).parse().ok()?, scale))
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.to_string().parse().unwrap()
+    }
+
+    /// Rounded half away from zero to at most `places` digits after the
+    /// point.
+    pub fn round(self, places: u32) -> Self {
+        let (mut coefficient, mut scale) = (self.coefficient, self.scale);
+        while scale > places {
+            let digit = coefficient % 10;
+            coefficient /= 10;
+            scale -= 1;
+            if scale == places && digit.abs() >= 5 {
+                coefficient += digit.signum();
+            }
+        }
+        Self::new(coefficient, scale)
+    }
+
+    /// The coefficient for `scale` digits after the point.
+    fn rescale(&self, scale: u32) -> Option<i128> {
+        self.coefficient.checked_mul(10i128.checked_pow(scale - self.scale)?)
+    }
+}
+
+impl Exact for Decimal {
+    fn from_f64(x: f64) -> Option<Self> {
+        // The shortest digits that read back as `x`, `0.1` rather than its
+        // binary value.
+        x.is_finite().then(|| Self::parse(&x.to_string()))?
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Decimal(d) => Some(*d),
+            &Value::Number(x) => Self::from_f64(x),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Decimal(self)
+    }
+
+    fn add(&self, other: &Self) -> Option<Self> {
+        let scale = self.scale.max(other.scale);
+        Some(Self::new(self.rescale(scale)?.checked_add(other.rescale(scale)?)?, scale))
+    }
+
+    fn sub(&self, other: &Self) -> Option<Self> {
+        self.add(&other.neg()?)
+    }
+
+    fn mul(&self, other: &Self) -> Option<Self> {
+        let ret = Self::new(self.coefficient.checked_mul(other.coefficient)?, self.scale + other.scale).round(MAX_PLACES);
+        // Too small to keep any digits.
+        (ret.coefficient != 0 || self.coefficient == 0 || other.coefficient == 0).then_some(ret)
+    }
+
+    fn div(&self, other: &Self) -> Option<Self> {
+        if other.coefficient == 0 {
+            return None;
+        }
+        let (num, den) = (self.coefficient.checked_abs()?, other.coefficient.checked_abs()?);
+        let mut scale = self.scale as i64 - other.scale as i64;
+        let (mut q, mut r) = (num / den, num % den);
+        // Long division, one digit after the point at a time.
+        while r != 0 && scale < MAX_PLACES as i64 {
+            let (Some(q10), Some(r10)) = (q.checked_mul(10), r.checked_mul(10)) else { break };
+            q = q10.checked_add(r10 / den)?;
+            r = r10 % den;
+            scale += 1;
+        }
+        if r != 0 && r >= den - r {
+            q = q.checked_add(1)?;
+        }
+        while scale < 0 {
+            q = q.checked_mul(10)?;
+            scale += 1;
+        }
+        if q == 0 && num != 0 {
+            return None;
+        }
+        let negative = (self.coefficient < 0) != (other.coefficient < 0);
+        Some(Self::new(if negative { -q } else { q }, scale as u32))
+    }
+
+    fn neg(&self) -> Option<Self> {
+        Some(Self { coefficient: self.coefficient.checked_neg()?, ..*self })
+    }
+
+    /// Only whole powers are exact.
+    fn pow(&self, exponent: &Self) -> Option<Self> {
+        if exponent.scale != 0 {
+            return None;
+        }
+        let n = exponent.coefficient;
+        let (mut base, mut ret) = (*self, Self::new(1, 0));
+        let mut k = n.unsigned_abs();
+        while k > 0 {
+            if k & 1 == 1 {
+                ret = ret.mul(&base)?;
+            }
+            k >>= 1;
+            if k > 0 {
+                base = base.mul(&base)?;
+            }
+        }
+        if n < 0 { Self::new(1, 0).div(&ret) } else { Some(ret) }
+    }
+
+    fn cmp(&self, other: &Self) -> Ordering {
+        match self.sub(other) {
+            Some(diff) => diff.coefficient.cmp(&0),
+            None => self.to_f64().total_cmp(&other.to_f64()),
+        }
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.coefficient < 0 { "-" } else { "" };
+        let digits = format!("{:0>width$}", self.coefficient.unsigned_abs(), width = self.scale as usize + 1);
+        let (int, frac) = digits.split_at(digits.len() - self.scale as usize);
+        match frac {
+            "" => write!(f, "{}{}", sign, int),
+            frac => write!(f, "{}{}.{}", sign, int, frac),
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use core::fmt;
+use core::ops::Range;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+
+/// An error message together with the parts of the expression it refers to.
+///
+/// Spans are char offsets into the original expression, so a diagnostic can
+/// be rendered with carets under the offending tokens:
+///
+///   Expected ')'
+///     (1 + 2
+///     ^ this '(' was never closed
+///           ^ expected ')' here
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub hint: Option<String>,
+}
+
+/// What went wrong, independent of the wording of the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// `pos` is the char offset the error was found at, where its first
+    /// label starts, and `expected` what would have been read there, like
+    /// `')'`.
+    Syntax { pos: usize, expected: Option<&'static str> },
+    /// A syntax error where the input ends, like `1 +` or `(1 + 2`, which
+    /// more input could complete.
+    Incomplete,
+    UnknownFunction,
+    UnknownVariable,
+    /// Assigning to a name that can't be assigned to.
+    Assignment,
+    /// A call with `got` arguments, `expected` is the accepted count
+    /// closest to it.
+    Arity { expected: usize, got: usize },
+    /// With `Calculator::set_strict`, `1/0`.
+    DivisionByZero,
+    /// A function rejected its arguments.
+    Domain,
+    /// A boolean where a number is expected or the other way around.
+    Type,
+    Environment,
+    /// The evaluation was cancelled through `Calculator::interrupt_flag`.
+    Interrupted,
+    /// The expression exceeds one of the `Limits`.
+    Limit,
+    /// Not an error, see `Calculator::warnings`.
+    Warning,
+}
+
+impl ErrorKind {
+    /// What went wrong without the details, the same for every syntax error.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::Syntax { .. } => "syntax error",
+            ErrorKind::Incomplete => "incomplete input",
+            ErrorKind::UnknownFunction => "unknown function",
+            ErrorKind::UnknownVariable => "unknown variable",
+            ErrorKind::Assignment => "invalid assignment",
+            ErrorKind::Arity { .. } => "wrong argument count",
+            ErrorKind::DivisionByZero => "division by zero",
+            ErrorKind::Domain => "invalid argument",
+            ErrorKind::Type => "type mismatch",
+            ErrorKind::Environment => "environment variable",
+            ErrorKind::Interrupted => "interrupted",
+            ErrorKind::Limit => "limit exceeded",
+            ErrorKind::Warning => "warning",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Range<usize>,
+    pub text: String,
+}
+
+impl Diagnostic {
+    /// A syntax error, see `with_kind` for the others.
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            kind: ErrorKind::Syntax { pos: 0, expected: None },
+            message: message.into(),
+            labels: vec![],
+            hint: None,
+        }
+    }
+
+    pub fn with_kind(mut self, kind: ErrorKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// What a syntax error expected, see `ErrorKind::Syntax`.
+    pub fn with_expected(mut self, what: &'static str) -> Self {
+        if let ErrorKind::Syntax { expected, .. } = &mut self.kind {
+            *expected = Some(what);
+        }
+        self
+    }
+
+    pub fn with_label(mut self, span: Range<usize>, text: impl Into<String>) -> Self {
+        if let ErrorKind::Syntax { pos, .. } = &mut self.kind {
+            if self.labels.is_empty() {
+                *pos = span.start;
+            }
+        }
+        self.labels.push(Label { span, text: text.into() });
+        self
+    }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    /// Renders the message, the source line with one caret line per label,
+    /// and the hint if there is one.
+    pub fn render(&self, source: &str) -> String {
+        self.styled(source, false)
+    }
+
+    /// Like `render`, with colors for a terminal: the message red, or yellow
+    /// for a warning, the carets cyan and the hint dimmed.
+    pub fn render_ansi(&self, source: &str) -> String {
+        self.styled(source, true)
+    }
+
+    fn styled(&self, source: &str, color: bool) -> String {
+        let paint = |style: &str, text: &str| match color {
+            true => format!("\x1b[{}m{}\x1b[0m", style, text),
+            false => text.to_string(),
+        };
+        let mut ret = paint(if self.kind == ErrorKind::Warning { "1;33" } else { "1;31" }, &self.message);
+
+        if !self.labels.is_empty() {
+            ret.push_str("\n  ");
+            ret.push_str(source);
+
+            let mut labels: Vec<&Label> = self.labels.iter().collect();
+            labels.sort_by_key(|label| label.span.start);
+            for label in labels {
+                let width = label.span.end.saturating_sub(label.span.start).max(1);
+                ret.push_str(&format!("\n  {}{}", " ".repeat(label.span.start), paint("1;36", &"^".repeat(width))));
+                if !label.text.is_empty() {
+                    ret.push(' ');
+                    ret.push_str(&label.text);
+                }
+            }
+        }
+
+        if let Some(hint) = &self.hint {
+            ret.push_str("\n  ");
+            ret.push_str(&paint("2", &format!("hint: {}", hint)));
+        }
+
+        ret
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// So a diagnostic can be returned with `?` from functions returning
+/// `Box<dyn Error>`, `render` is still needed to show where it points.
+impl core::error::Error for Diagnostic {}
+
+/// The candidate closest to `name`, if it is only a typo or two away.
+pub(crate) fn suggest<'a>(name: &str, candidates: impl Iterator<Item = &'a String>) -> Option<&'a String> {
+    candidates
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= (name.len() / 3).clamp(1, 2))
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = if ca == cb { prev } else { 1 + prev.min(cur).min(row[j]) };
+            prev = cur;
+        }
+    }
+
+    row[b.len()]
+}
@@ -0,0 +1,244 @@
+use core::ops::Range;
+use alloc::sync::Arc;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::diagnostic::{Diagnostic, ErrorKind};
+use crate::expression::{Expression, Node, AND};
+use crate::operator::{Fixity, OperatorTable};
+
+/// The bytes an encoded expression starts with, before the version.
+const MAGIC: &[u8] = b"CALC";
+/// The version `encode` writes, `decode` reads it and those before it.
+const VERSION: u8 = 1;
+/// How deep `decode` follows nodes nested inside each other, so bytes from
+/// elsewhere can't overflow the stack.
+const MAX_NESTING: usize = 10_000;
+
+/// The bytes of `Expression::to_bytes`: `MAGIC`, the version, the source and
+/// the nodes, each a tag followed by its fields. Counts, lengths and spans
+/// are little-endian `u32`s, strings UTF-8 after their length.
+pub(crate) fn encode(expression: &Expression) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    out.push(VERSION);
+    string(&mut out, &expression.source);
+    node(&mut out, &expression.node);
+    out
+}
+
+fn count(out: &mut Vec<u8>, n: usize) {
+    out.extend((n as u32).to_le_bytes());
+}
+
+fn string(out: &mut Vec<u8>, s: &str) {
+    count(out, s.len());
+    out.extend(s.as_bytes());
+}
+
+fn span(out: &mut Vec<u8>, span: &Range<usize>) {
+    count(out, span.start);
+    count(out, span.end);
+}
+
+fn node(out: &mut Vec<u8>, node: &Node) {
+    let nodes = |out: &mut Vec<u8>, nodes: &[Node]| {
+        count(out, nodes.len());
+        for item in nodes {
+            self::node(out, item);
+        }
+    };
+    let params = |out: &mut Vec<u8>, params: &[String]| {
+        count(out, params.len());
+        for param in params {
+            string(out, param);
+        }
+    };
+    match node {
+        Node::Number(x) => {
+            out.push(0);
+            out.extend(x.to_le_bytes());
+        }
+        Node::Name(name, name_span) => {
+            out.push(1);
+            string(out, name);
+            span(out, name_span);
+        }
+        Node::Env(name, name_span) => {
+            out.push(2);
+            string(out, name);
+            span(out, name_span);
+        }
+        // The operators are looked up again by symbol, a prefix and a
+        // postfix one can share it.
+        Node::Unary { symbol, operator, operand, span: unary_span } => {
+            out.push(3);
+            string(out, symbol);
+            out.push((operator.fixity() == Fixity::Postfix) as u8);
+            self::node(out, operand);
+            span(out, unary_span);
+        }
+        Node::Binary { symbol, lhs, rhs, span: binary_span, .. } => {
+            out.push(4);
+            string(out, symbol);
+            self::node(out, lhs);
+            self::node(out, rhs);
+            span(out, binary_span);
+        }
+        Node::List(items, list_span) => {
+            out.push(5);
+            nodes(out, items);
+            span(out, list_span);
+        }
+        Node::Index { list, index, span: index_span } => {
+            out.push(6);
+            self::node(out, list);
+            self::node(out, index);
+            span(out, index_span);
+        }
+        Node::Call { name, arguments, name_span, span: call_span } => {
+            out.push(7);
+            string(out, name);
+            nodes(out, arguments);
+            span(out, name_span);
+            span(out, call_span);
+        }
+        Node::Assign { name, value, name_span } => {
+            out.push(8);
+            string(out, name);
+            self::node(out, value);
+            span(out, name_span);
+        }
+        Node::Define { name, params: names, body, name_span } => {
+            out.push(9);
+            string(out, name);
+            params(out, names);
+            self::node(out, body);
+            span(out, name_span);
+        }
+        Node::Lambda { params: names, body } => {
+            out.push(10);
+            params(out, names);
+            self::node(out, body);
+        }
+        Node::Sequence(statements) => {
+            out.push(11);
+            nodes(out, statements);
+        }
+    }
+}
+
+/// The expression `encode` wrote into `bytes`, with the operators of
+/// `operators` for its symbols.
+pub(crate) fn decode(bytes: &[u8], operators: &Arc<OperatorTable>) -> Result<Expression, Diagnostic> {
+    let header = bytes.get(..MAGIC.len() + 1).filter(|header| header.starts_with(MAGIC))
+        .ok_or_else(|| Diagnostic::new("Expected an expression from Expression::to_bytes").with_kind(ErrorKind::Environment))?;
+    let version = header[MAGIC.len()];
+    if version > VERSION {
+        return Err(Diagnostic::new(format!("Expected an expression encoded by version {} or earlier but got version {}", VERSION, version))
+            .with_kind(ErrorKind::Environment));
+    }
+    let mut reader = Reader { bytes, pos: header.len(), chars: 0, depth: 0, operators };
+    let source = reader.string()?;
+    reader.chars = source.chars().count();
+    let node = reader.node()?;
+    if reader.pos != bytes.len() {
+        return Err(corrupt("there are bytes after its end"));
+    }
+    Ok(Expression { node, warnings: vec![], source, operators: operators.clone() })
+}
+
+fn corrupt(reason: &str) -> Diagnostic {
+    Diagnostic::new(format!("The encoded expression is corrupt, {}", reason)).with_kind(ErrorKind::Environment)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// The length of the source in chars, which the spans are in.
+    chars: usize,
+    /// The nodes being read.
+    depth: usize,
+    operators: &'a OperatorTable,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Diagnostic> {
+        let bytes = self.pos.checked_add(n).and_then(|end| self.bytes.get(self.pos..end)).ok_or_else(|| corrupt("it ends early"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, Diagnostic> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn count(&mut self) -> Result<usize, Diagnostic> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as usize)
+    }
+
+    fn string(&mut self) -> Result<String, Diagnostic> {
+        let length = self.count()?;
+        String::from_utf8(self.take(length)?.to_vec()).map_err(|_| corrupt("a name isn't UTF-8"))
+    }
+
+    /// A span, which has to be inside the source for errors to point into.
+    fn span(&mut self) -> Result<Range<usize>, Diagnostic> {
+        let span = self.count()?..self.count()?;
+        match span.start <= span.end && span.end <= self.chars {
+            true => Ok(span),
+            false => Err(corrupt("a span is outside of the source")),
+        }
+    }
+
+    fn nodes(&mut self) -> Result<Vec<Node>, Diagnostic> {
+        let count = self.count()?;
+        (0..count).map(|_| self.node()).collect()
+    }
+
+    fn params(&mut self) -> Result<Vec<String>, Diagnostic> {
+        let count = self.count()?;
+        (0..count).map(|_| self.string()).collect()
+    }
+
+    fn node(&mut self) -> Result<Node, Diagnostic> {
+        if self.depth == MAX_NESTING {
+            return Err(corrupt(&format!("it nests more than {} nodes", MAX_NESTING)));
+        }
+        self.depth += 1;
+        let unknown = |symbol: &str| Diagnostic::new(format!("The expression uses the operator '{}', which this calculator doesn't have", symbol))
+            .with_kind(ErrorKind::Environment);
+        let node = match self.byte()? {
+            0 => Node::Number(f64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            1 => Node::Name(self.string()?, self.span()?),
+            2 => Node::Env(self.string()?, self.span()?),
+            3 => {
+                let symbol = self.string()?;
+                let found = match self.byte()? {
+                    0 => self.operators.prefix(&symbol),
+                    _ => self.operators.postfix(&symbol),
+                };
+                let operator = found.ok_or_else(|| unknown(&symbol))?.operator;
+                Node::Unary { operator, operand: Box::new(self.node()?), span: self.span()?, symbol }
+            }
+            4 => {
+                let symbol = self.string()?;
+                // The `&&` joining a chain like `0 <= x < 10`.
+                let operator = match self.operators.infix(&symbol) {
+                    Some(found) => found.operator,
+                    None if symbol == "&&" => AND,
+                    None => return Err(unknown(&symbol)),
+                };
+                Node::Binary { operator, lhs: Box::new(self.node()?), rhs: Box::new(self.node()?), span: self.span()?, symbol }
+            }
+            5 => Node::List(self.nodes()?, self.span()?),
+            6 => Node::Index { list: Box::new(self.node()?), index: Box::new(self.node()?), span: self.span()? },
+            7 => Node::Call { name: self.string()?, arguments: self.nodes()?, name_span: self.span()?, span: self.span()? },
+            8 => Node::Assign { name: self.string()?, value: Box::new(self.node()?), name_span: self.span()? },
+            9 => Node::Define { name: self.string()?, params: self.params()?, body: Arc::new(self.node()?), name_span: self.span()? },
+            10 => Node::Lambda { params: self.params()?, body: Arc::new(self.node()?) },
+            11 => Node::Sequence(self.nodes()?),
+            tag => return Err(corrupt(&format!("it has a node of the unknown kind {}", tag))),
+        };
+        self.depth -= 1;
+        Ok(node)
+    }
+}
@@ -0,0 +1,73 @@
+use core::cmp::Ordering;
+use crate::expression::Node;
+use crate::operator::Operator;
+use crate::value::Value;
+
+/// A number type that plain arithmetic can be evaluated with instead of
+/// `f64`, see `Arithmetic`. Operations it can't do exactly give `None`, and
+/// the expression is evaluated with floats instead.
+pub(crate) trait Exact: Sized {
+    fn from_f64(x: f64) -> Option<Self>;
+    /// Its own kind of `Value`, numbers are read with `from_f64`.
+    fn from_value(value: &Value) -> Option<Self>;
+    fn into_value(self) -> Value;
+    fn add(&self, other: &Self) -> Option<Self>;
+    fn sub(&self, other: &Self) -> Option<Self>;
+    fn mul(&self, other: &Self) -> Option<Self>;
+    fn div(&self, other: &Self) -> Option<Self>;
+    fn neg(&self) -> Option<Self>;
+    fn pow(&self, exponent: &Self) -> Option<Self>;
+    fn cmp(&self, other: &Self) -> Ordering;
+    /// `n!`, only for integer types.
+    fn factorial(&self) -> Option<Self> {
+        None
+    }
+}
+
+/// `node` evaluated with `T`, reading names through `lookup`. Only numbers,
+/// names, `+ - * / ^ !`, negation and a comparison at the top are exact.
+pub(crate) fn evaluate<T: Exact>(node: &Node, lookup: &dyn Fn(&str) -> Option<Value>) -> Option<Value> {
+    match node {
+        Node::Binary { symbol, operator: Operator::Comparison(_), lhs, rhs, .. } => {
+            let ordering = number::<T>(lhs, lookup)?.cmp(&number::<T>(rhs, lookup)?);
+            let ret = match symbol.as_str() {
+                "<" => ordering.is_lt(),
+                "<=" => ordering.is_le(),
+                ">" => ordering.is_gt(),
+                ">=" => ordering.is_ge(),
+                "==" => ordering.is_eq(),
+                "!=" => ordering.is_ne(),
+                _ => return None,
+            };
+            Some(Value::Bool(ret))
+        }
+        node => number::<T>(node, lookup).map(T::into_value),
+    }
+}
+
+fn number<T: Exact>(node: &Node, lookup: &dyn Fn(&str) -> Option<Value>) -> Option<T> {
+    match node {
+        &Node::Number(x) => T::from_f64(x),
+        Node::Name(name, _) => lookup(name).and_then(|value| T::from_value(&value)),
+        Node::Unary { symbol, operator, operand, .. } => {
+            let operand = number::<T>(operand, lookup)?;
+            match (symbol.as_str(), operator) {
+                ("-", Operator::Prefix(_)) => operand.neg(),
+                ("!", Operator::FalliblePostfix(_)) => operand.factorial(),
+                _ => None,
+            }
+        }
+        Node::Binary { symbol, operator: Operator::Infix(..), lhs, rhs, .. } => {
+            let (lhs, rhs) = (number::<T>(lhs, lookup)?, number::<T>(rhs, lookup)?);
+            match symbol.as_str() {
+                "+" => lhs.add(&rhs),
+                "-" => lhs.sub(&rhs),
+                "*" => lhs.mul(&rhs),
+                "/" => lhs.div(&rhs),
+                "^" => lhs.pow(&rhs),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,660 @@
+use core::fmt;
+use core::ops::Range;
+use core::sync::atomic::{AtomicBool, Ordering};
+use alloc::sync::Arc;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::ast::Expr;
+use crate::calculator::{bound_variable, Bindings, Calculator, SOLVE};
+use crate::diagnostic::{Diagnostic, ErrorKind};
+use crate::encoding;
+use crate::lexer::TokenKind;
+use crate::limits::Limits;
+use crate::operator::{Assoc, Match, Operator, OperatorTable};
+use crate::parser::Parser;
+use crate::program::Program;
+use crate::value::Value;
+use crate::symbolic::{latex, print, rewrite};
+
+/// Joins the comparisons of a chain like `0 <= x < 10`.
+/// What an operand is expected to be, for the `expected` of syntax errors.
+pub(crate) const OPERAND: &str = "a number, a name or '('";
+
+pub(crate) const AND: Operator = Operator::Logical(|a, b| a && b);
+
+/// An expression parsed by `Calculator::compile`, which
+/// `Calculator::evaluate` can evaluate any number of times without parsing
+/// it again.
+///
+/// Names are looked up when the expression is evaluated, so it sees the
+/// variables and functions defined at that point. It prints with as few
+/// parentheses as the operators need, `((1 + 2))*x` as `(1 + 2)*x`.
+#[derive(Clone, Debug)]
+pub struct Expression {
+    pub(crate) node: Node,
+    /// Warnings about the grouping of operators, see `Calculator::warnings`.
+    pub(crate) warnings: Vec<Diagnostic>,
+    pub(crate) source: String,
+    pub(crate) operators: Arc<OperatorTable>,
+}
+
+impl Expression {
+    /// The text the expression was compiled from, which the spans of its
+    /// errors point into.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The expression in LaTeX, `1/2*sin(x)` as `\frac{1}{2}\sin\left(x\right)`.
+    pub fn to_latex(&self) -> String {
+        latex(&self.node, &self.operators)
+    }
+
+    /// The expression compiled for `Calculator::run_program`, or None if it
+    /// does more than compute numbers and booleans, see `Program`.
+    pub fn to_program(&self) -> Option<Program> {
+        Program::new(self)
+    }
+
+    /// The value of the expression with the variables of each row, in the
+    /// order of `rows`, like `Calculator::evaluate_with`. It is compiled to
+    /// a `Program` once for all of them if it can be.
+    pub fn eval_batch(&self, calc: &mut Calculator, rows: &[Bindings]) -> Vec<Result<Value, Diagnostic>> {
+        let program = self.to_program();
+        rows.iter().map(|row| calc.evaluate_row(self, program.as_ref(), row)).collect()
+    }
+
+    /// The expression as bytes that `Calculator::load_expression` turns back
+    /// into it without parsing, to cache it in a file or send it elsewhere.
+    /// They start with a version, which an older version of the library
+    /// refuses rather than misreads. The warnings of compiling it are left
+    /// out.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encoding::encode(self)
+    }
+
+    /// The parsed expression as an `ast::Expr`.
+    pub fn ast(&self) -> Expr {
+        Expr::from(&self.node)
+    }
+
+    /// The names the expression reads, in the order they first appear, like
+    /// `["rate", "n"]` for `200000 * rate / (1 - (1 + rate)^-n)`. Constants
+    /// like `pi` are names too. Parameters, the variables of `sum`, `diff`
+    /// and the like, and names an earlier statement assigns aren't.
+    pub fn variables(&self) -> Vec<String> {
+        let mut ret = vec![];
+        variables(&self.node, &mut vec![], &mut ret);
+        ret
+    }
+}
+
+/// Adds the names `node` reads that aren't `bound` to `names`.
+pub(crate) fn variables(node: &Node, bound: &mut Vec<String>, names: &mut Vec<String>) {
+    let _ = rewrite::<()>(node, &mut |node| {
+        match node {
+            Node::Name(name, _) if !bound.contains(name) && !names.contains(name) => names.push(name.clone()),
+            Node::Define { params, body, .. } | Node::Lambda { params, body } => {
+                let mut bound = bound.iter().chain(params).cloned().collect();
+                variables(body, &mut bound, names);
+            }
+            Node::Call { name, arguments, .. } => {
+                let var = bound_variable(name, arguments)?;
+                let mut bound = bound.iter().cloned().chain([var.to_string()]).collect();
+                for argument in arguments {
+                    variables(argument, &mut bound, names);
+                }
+            }
+            Node::Sequence(statements) => {
+                for statement in statements {
+                    variables(statement, bound, names);
+                    if let Node::Assign { name, .. } = statement {
+                        bound.push(name.clone());
+                    }
+                }
+            }
+            _ => return None,
+        }
+        // Whatever is rewritten is thrown away, only the children above were
+        // to be skipped.
+        Some(Ok(Node::Number(0.)))
+    });
+}
+
+/// Whether `node` applies the infix `symbol` and starts at `start`, where
+/// its operand began, rather than inside parentheses.
+fn unparenthesized(node: &Node, symbol: &str, start: usize) -> bool {
+    matches!(node, Node::Binary { symbol: s, span, .. } if s == symbol && span.start == start)
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", print(&self.node, &self.operators))
+    }
+}
+
+/// Spans are char columns of the source, like those of `Diagnostic`.
+#[derive(Clone, Debug)]
+pub(crate) enum Node {
+    Number(f64),
+    /// A parameter, constant or variable.
+    Name(String, Range<usize>),
+    /// `$NAME`, read from the environment when evaluated.
+    Env(String, Range<usize>),
+    /// A `Prefix`, `Postfix` or `FalliblePostfix` operator.
+    Unary {
+        symbol: String,
+        operator: Operator,
+        operand: Box<Node>,
+        span: Range<usize>,
+    },
+    /// An `Infix`, `Comparison` or `Logical` operator.
+    Binary {
+        symbol: String,
+        operator: Operator,
+        lhs: Box<Node>,
+        rhs: Box<Node>,
+        span: Range<usize>,
+    },
+    /// `[x, ...]`, with the span of the brackets.
+    List(Vec<Node>, Range<usize>),
+    /// `list[index]`, counting from 0.
+    Index {
+        list: Box<Node>,
+        index: Box<Node>,
+        span: Range<usize>,
+    },
+    Call {
+        name: String,
+        arguments: Vec<Node>,
+        name_span: Range<usize>,
+        span: Range<usize>,
+    },
+    Assign {
+        name: String,
+        value: Box<Node>,
+        name_span: Range<usize>,
+    },
+    /// `f(x, y) = body`, or without parameters `area := w * h`, a formula
+    /// that is evaluated wherever its name is read.
+    Define {
+        name: String,
+        params: Vec<String>,
+        body: Arc<Node>,
+        name_span: Range<usize>,
+    },
+    /// `(x, y) -> body`.
+    Lambda {
+        params: Vec<String>,
+        body: Arc<Node>,
+    },
+    /// `a = 2; b = 3; a*b`, evaluated in order to the value of the last.
+    /// Only ever the whole expression.
+    Sequence(Vec<Node>),
+}
+
+/// Parses an expression into `Node`s, see `Calculator` for the grammar.
+pub(crate) struct Compiler<'a> {
+    parser: Parser<'a>,
+    operators: &'a Arc<OperatorTable>,
+    limits: Limits,
+    env_vars: bool,
+    warn: bool,
+    warnings: Vec<Diagnostic>,
+    interrupted: &'a AtomicBool,
+    /// Tokens read so far, and whether each parenthesis open is a `[`.
+    tokens: usize,
+    brackets: Vec<bool>,
+}
+
+impl<'a> Compiler<'a> {
+    pub fn new(parser: Parser<'a>, operators: &'a Arc<OperatorTable>, interrupted: &'a AtomicBool) -> Self {
+        Self {
+            parser,
+            operators,
+            limits: Limits::default(),
+            env_vars: false,
+            warn: false,
+            warnings: vec![],
+            interrupted,
+            tokens: 0,
+            brackets: vec![],
+        }
+    }
+
+    pub fn with_options(mut self, limits: Limits, env_vars: bool, warn: bool) -> Self {
+        self.limits = limits;
+        self.env_vars = env_vars;
+        self.warn = warn;
+        self
+    }
+
+    pub fn compile(mut self, source: String) -> Result<Expression, Diagnostic> {
+        let length = source.chars().count();
+        if let Some(max) = self.limits.max_length.filter(|&max| length > max) {
+            return Err(Diagnostic::new(format!("Expression is longer than {} chars", max))
+                .with_kind(ErrorKind::Limit)
+                .with_label(max..length, "past the limit"));
+        }
+
+        let node = match self.statements() {
+            Err(err) if matches!(err.kind, ErrorKind::Syntax { .. }) && self.parser.peek() == &TokenKind::End => return Err(err.with_kind(ErrorKind::Incomplete)),
+            ret => ret?,
+        };
+        if self.parser.peek() != &TokenKind::End {
+            return Err(Diagnostic::new("Expected an operator or the end of the expression")
+                .with_expected("an operator or the end of the expression")
+                .with_label(self.parser.span(), self.parser.unexpected()));
+        }
+        Ok(Expression { node, warnings: self.warnings, source, operators: self.operators.clone() })
+    }
+
+    fn statements(&mut self) -> Result<Node, Diagnostic> {
+        let mut statements = vec![self.statement()?];
+        // A `;` can end the last statement too.
+        while self.parser.peek() == &TokenKind::Semicolon {
+            self.parser.advance();
+            if self.parser.peek() != &TokenKind::End {
+                statements.push(self.statement()?);
+            }
+        }
+        Ok(match statements.len() {
+            1 => statements.pop().unwrap(),
+            _ => Node::Sequence(statements),
+        })
+    }
+
+    fn statement(&mut self) -> Result<Node, Diagnostic> {
+        if self.parser.at_definition() {
+            return self.definition();
+        }
+        if self.parser.at_formula() {
+            return self.formula();
+        }
+        if !self.parser.at_assignment() {
+            return self.expression();
+        }
+
+        let start = self.parser.position();
+        let name = self.identifier();
+        let name_span = start..self.parser.end();
+        self.token()?;
+        self.parser.consume(TokenKind::Equals, "'='")?;
+
+        let value = Box::new(self.expression()?);
+        Ok(Node::Assign { name, value, name_span })
+    }
+
+    fn definition(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.parser.position();
+        let name = self.identifier();
+        let name_span = start..self.parser.end();
+        self.parser.consume(TokenKind::Open, "'('")?;
+        let params = self.params()?;
+        self.parser.consume(TokenKind::Equals, "'='")?;
+
+        let body = Arc::new(self.expression()?);
+        Ok(Node::Define { name, params, body, name_span })
+    }
+
+    /// `name := body`, a definition without parameters.
+    fn formula(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.parser.position();
+        let name = self.identifier();
+        let name_span = start..self.parser.end();
+        self.parser.consume(TokenKind::ColonEquals, "':='")?;
+
+        let body = Arc::new(self.expression()?);
+        Ok(Node::Define { name, params: vec![], body, name_span })
+    }
+
+    /// `(x) -> body`, or `x -> body` with one parameter.
+    fn lambda(&mut self) -> Result<Node, Diagnostic> {
+        let params = match self.parser.peek() {
+            TokenKind::Open => {
+                self.parser.advance();
+                self.params()?
+            }
+            _ => vec![self.identifier()],
+        };
+        self.parser.consume(TokenKind::Arrow, "'->'")?;
+
+        let body = Arc::new(self.expression()?);
+        Ok(Node::Lambda { params, body })
+    }
+
+    /// The names up to and including the ')' ending a parameter list.
+    fn params(&mut self) -> Result<Vec<String>, Diagnostic> {
+        let mut params: Vec<String> = vec![];
+        loop {
+            let start = self.parser.position();
+            let param = self.identifier();
+            if params.contains(&param) {
+                return Err(Diagnostic::new(format!("Parameter '{}' appears twice", param))
+                    .with_label(start..self.parser.end(), ""));
+            }
+            params.push(param);
+            if self.parser.peek() != &TokenKind::Comma {
+                break;
+            }
+            self.parser.advance();
+        }
+        self.parser.consume(TokenKind::Close, "')'")?;
+        Ok(params)
+    }
+
+    fn expression(&mut self) -> Result<Node, Diagnostic> {
+        self.check_interrupt()?;
+        self.binary(0)
+    }
+
+    fn check_interrupt(&self) -> Result<(), Diagnostic> {
+        if self.interrupted.load(Ordering::Relaxed) {
+            Err(Diagnostic::new("Interrupted").with_kind(ErrorKind::Interrupted))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Counts the current token against `max_tokens`.
+    fn token(&mut self) -> Result<(), Diagnostic> {
+        self.tokens += 1;
+        match self.limits.max_tokens {
+            Some(max) if self.tokens > max => {
+                Err(Diagnostic::new(format!("Expression has more than {} tokens", max))
+                    .with_kind(ErrorKind::Limit)
+                    .with_label(self.parser.span(), "past the limit"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The symbol of the current token if it is an operator.
+    fn symbol(&self) -> Option<String> {
+        match self.parser.peek() {
+            TokenKind::Symbol(symbol) => Some(symbol.clone()),
+            _ => None,
+        }
+    }
+
+    /// Reads the '(' or '[' at the current position, counting it against
+    /// `max_depth` until the matching `close`.
+    fn open(&mut self) -> Result<usize, Diagnostic> {
+        let open = self.parser.position();
+        self.token()?;
+        if let Some(max) = self.limits.max_depth.filter(|&max| self.brackets.len() >= max) {
+            return Err(Diagnostic::new(format!("Parentheses are nested more than {} deep", max))
+                .with_kind(ErrorKind::Limit)
+                .with_label(open..open + 1, "past the limit"));
+        }
+        // The kind of the token rather than the char at `open`, which
+        // `lexer::sanitize` can have added.
+        let bracket = self.parser.peek() == &TokenKind::OpenBracket;
+        match bracket {
+            true => self.parser.advance(),
+            false => self.parser.consume(TokenKind::Open, "'('")?,
+        }
+        self.brackets.push(bracket);
+        Ok(open)
+    }
+
+    /// Reads the ')' or ']' that matches the `open` one.
+    fn close(&mut self, open: usize) -> Result<(), Diagnostic> {
+        self.token()?;
+        let (kind, bracket, msg) = match self.brackets.last() {
+            Some(true) => (TokenKind::CloseBracket, '[', "']'"),
+            _ => (TokenKind::Close, '(', "')'"),
+        };
+        self.parser.consume(kind, msg).map_err(|err| {
+            err.with_label(open..open + 1, format!("this '{}' was never closed", bracket))
+        })?;
+        self.brackets.pop();
+        Ok(())
+    }
+
+    /// Parses operators binding at least as tight as `min_precedence` by
+    /// precedence climbing over the operator table.
+    fn binary(&mut self, min_precedence: u32) -> Result<Node, Diagnostic> {
+        // The body of a lambda reaches as far as an expression can.
+        if self.parser.at_lambda() {
+            return self.lambda();
+        }
+        let start = self.parser.position();
+        // Whether `ret` is more than a single operand, for `warn_grouping`.
+        let mut compound = false;
+        // The right operand of `ret` and where it starts, when `ret` ends in
+        // a comparison that the next one chains onto.
+        let mut chain: Option<(Node, usize)> = None;
+
+        let symbol = self.symbol().unwrap_or_default();
+        let mut ret = match self.operators.prefix(&symbol) {
+            Some(op) => {
+                self.token()?;
+                self.parser.advance();
+                compound = true;
+                let operand_start = self.parser.position();
+                let operand = Box::new(self.binary(op.precedence)?);
+                // `-2^2` is `-(2^2)` when `^` binds tighter.
+                if self.warn && unparenthesized(&operand, "^", operand_start) {
+                    self.warn_grouping(start, operand_start..self.parser.end(), &format!("'{}' applies to all of this", symbol));
+                }
+                Node::Unary { symbol, operator: op.operator, operand, span: start..self.parser.end() }
+            }
+            None => self.parentheses()?,
+        };
+
+        loop {
+            self.check_interrupt()?;
+
+            // A product without the '*' is read as if it were there.
+            let (symbol, implicit) = match self.symbol() {
+                Some(symbol) => (symbol, false),
+                None if self.parser.at_implicit_product() => ("*".to_string(), true),
+                None => break,
+            };
+            // An infix operator wins over a postfix one with the same symbol
+            // when an operand follows.
+            let op = match implicit {
+                // A unit after a number binds tighter than `/`, `10 m / 2 s`
+                // is 5 m/s.
+                true if self.parser.at_unit() => self.operators.infix(&symbol).map(|op| Match { precedence: op.precedence + 1, ..op }),
+                true => self.operators.infix(&symbol),
+                false if self.parser.operand_follows() => self.operators.infix(&symbol).or(self.operators.postfix(&symbol)),
+                false => self.operators.postfix(&symbol).or(self.operators.infix(&symbol)),
+            };
+            let Some(op) = op.filter(|op| op.precedence >= min_precedence) else { break };
+
+            self.token()?;
+            let lhs_end = self.parser.end();
+            let power = symbol == "^";
+            if !implicit {
+                self.parser.advance();
+            }
+
+            // `1/2x` is `(1/2)x`, the product doesn't bind tighter than `/`.
+            let product = implicit && unparenthesized(&ret, "/", start);
+            let lhs = Box::new(ret);
+            let rhs_start = self.parser.position();
+            let chained = chain.take();
+            // Only infix operators have an associativity.
+            ret = match (op.operator, op.operator.assoc()) {
+                (operator, None) => Node::Unary { symbol, operator, operand: lhs, span: start..self.parser.end() },
+                (operator, Some(assoc)) => {
+                    let rhs = Box::new(self.binary(match assoc {
+                        Assoc::Left => op.precedence + 1,
+                        Assoc::Right => op.precedence,
+                    })?);
+                    // `2^3^2` is `2^(3^2)` when `^` groups to the right.
+                    if self.warn && power && unparenthesized(&rhs, "^", rhs_start) {
+                        self.warn_grouping(start, rhs_start..self.parser.end(), "this is the exponent of '^'");
+                    }
+                    let comparison = matches!(operator, Operator::Comparison(_));
+                    if comparison {
+                        chain = Some(((*rhs).clone(), rhs_start));
+                    }
+                    match chained {
+                        // `0 <= x < 10` is `0 <= x && x < 10`.
+                        Some((middle, middle_start)) if comparison => {
+                            let next = Node::Binary { symbol, operator, lhs: Box::new(middle), rhs, span: middle_start..self.parser.end() };
+                            Node::Binary { symbol: "&&".to_string(), operator: AND, lhs, rhs: Box::new(next), span: start..self.parser.end() }
+                        }
+                        _ => Node::Binary { symbol, operator, lhs, rhs, span: start..self.parser.end() },
+                    }
+                }
+            };
+
+            if self.warn && power && compound {
+                self.warn_grouping(start, start..lhs_end, "this is the base of '^'");
+            }
+            if self.warn && product {
+                self.warn_grouping(start, start..lhs_end, "this is multiplied as a whole");
+            }
+            compound = true;
+        }
+
+        Ok(ret)
+    }
+
+    /// Warns that `grouped` is evaluated first in the expression from `start`
+    /// to the current position, e.g. `-2^2` is `(-2)^2` and `2*3^2` is
+    /// `(2*3)^2` with the `standard` operators.
+    fn warn_grouping(&mut self, start: usize, grouped: Range<usize>, label: &str) {
+        let end = self.parser.end();
+        let expr = self.parser.slice(start..end);
+        let rewrite = format!("{}({}){}", self.parser.slice(start..grouped.start).trim_start(), self.parser.slice(grouped.clone()).trim(),
+            self.parser.slice(grouped.end..end).trim_end());
+
+        self.warnings.push(Diagnostic::new(format!("'{}' is evaluated as {}", expr.trim(), rewrite))
+            .with_kind(ErrorKind::Warning)
+            .with_label(grouped, label)
+            .with_hint(format!("write {} to make this explicit", rewrite)));
+    }
+
+    fn parentheses(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.parser.position();
+        let mut ret = match self.parser.peek() {
+            TokenKind::Open => {
+                let open = self.open()?;
+                let ret = self.expression()?;
+                self.close(open)?;
+                ret
+            }
+            TokenKind::OpenBracket => self.list()?,
+            _ => self.atom()?,
+        };
+
+        while self.parser.peek() == &TokenKind::OpenBracket {
+            let open = self.open()?;
+            let index = Box::new(self.expression()?);
+            self.close(open)?;
+            ret = Node::Index { list: Box::new(ret), index, span: start..self.parser.end() };
+        }
+        Ok(ret)
+    }
+
+    /// `[x, ...]`, which can be empty.
+    fn list(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.parser.position();
+        let open = self.open()?;
+        let mut elements = vec![];
+        if self.parser.peek() != &TokenKind::CloseBracket {
+            elements.push(self.expression()?);
+            while self.parser.peek() == &TokenKind::Comma {
+                self.token()?;
+                self.parser.advance();
+                elements.push(self.expression()?);
+            }
+        }
+        self.close(open)?;
+        Ok(Node::List(elements, start..self.parser.end()))
+    }
+
+    fn atom(&mut self) -> Result<Node, Diagnostic> {
+        self.token()?;
+        match self.parser.peek().clone() {
+            TokenKind::Number(value) => {
+                self.parser.advance();
+                Ok(Node::Number(value))
+            }
+            // `$2` is short for `out(2)`.
+            TokenKind::Env(name) if name.starts_with(|c: char| c.is_ascii_digit()) => {
+                let span = self.parser.span();
+                self.parser.advance();
+                Ok(Node::Call { name: "out".to_string(), arguments: vec![Node::Number(name.parse().unwrap())], name_span: span.clone(), span })
+            }
+            TokenKind::Env(name) if self.env_vars => {
+                let span = self.parser.span();
+                if name.is_empty() {
+                    return Err(Diagnostic::new("Expected an environment variable name after '$'")
+                        .with_expected("an environment variable name")
+                        .with_label(span, ""));
+                }
+                self.parser.advance();
+                Ok(Node::Env(name, span))
+            }
+            TokenKind::Name(_) => self.call(),
+            TokenKind::Invalid(msg) => Err(Diagnostic::new(msg).with_label(self.parser.span(), "")),
+            TokenKind::End => Err(Diagnostic::new("Unexpected end of input")
+                .with_expected(OPERAND)
+                .with_label(self.parser.span(), "expected a number, a name or '('")),
+            _ => Err(Diagnostic::new(format!("Expected a number, a name or '(' but got '{}'", self.parser.slice(self.parser.span())))
+                .with_expected(OPERAND)
+                .with_label(self.parser.span(), self.parser.unexpected())),
+        }
+    }
+
+    fn call(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.parser.position();
+        let name = self.identifier();
+        let name_span = start..self.parser.end();
+
+        if self.parser.peek() == &TokenKind::Open {
+            let open = self.open()?;
+            let arguments = if self.parser.peek() == &TokenKind::Close { vec![] } else { self.arguments(name == SOLVE)? };
+            self.close(open)?;
+            Ok(Node::Call { name, arguments, name_span, span: start..self.parser.end() })
+        } else {
+            Ok(Node::Name(name, name_span))
+        }
+    }
+
+    /// Reads a name, a missing one is reported by whatever comes next.
+    fn identifier(&mut self) -> String {
+        match self.parser.peek().clone() {
+            TokenKind::Name(name) => {
+                self.parser.advance();
+                name
+            }
+            _ => String::new(),
+        }
+    }
+
+    /// With `equation` the first argument can be `lhs = rhs`, which is read
+    /// as `lhs - rhs`.
+    fn arguments(&mut self, equation: bool) -> Result<Vec<Node>, Diagnostic> {
+        let start = self.parser.position();
+        let mut ret = vec![self.expression()?];
+        if equation && self.parser.peek() == &TokenKind::Equals {
+            let Some(minus) = self.operators.infix("-") else {
+                return Err(Diagnostic::new("Can't read an equation without the '-' operator")
+                    .with_label(self.parser.span(), ""));
+            };
+            self.token()?;
+            self.parser.advance();
+            let lhs = Box::new(ret.pop().unwrap());
+            let rhs = Box::new(self.expression()?);
+            ret.push(Node::Binary { symbol: "-".to_string(), operator: minus.operator, lhs, rhs, span: start..self.parser.end() });
+        }
+        while self.parser.peek() == &TokenKind::Comma {
+            self.token()?;
+            self.parser.advance();
+            if let Some(max) = self.limits.max_arguments.filter(|&max| ret.len() >= max) {
+                return Err(Diagnostic::new(format!("Calls take at most {} arguments", max))
+                    .with_kind(ErrorKind::Limit)
+                    .with_label(self.parser.span(), "past the limit"));
+            }
+            ret.push(self.expression()?);
+        }
+        Ok(ret)
+    }
+}
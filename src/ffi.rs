@@ -0,0 +1,136 @@
+//! A C interface to the calculator, built into a `cdylib` with
+//! `cargo rustc --lib --release --no-default-features --features ffi --crate-type cdylib`.
+//! `include/calculator.h` declares it:
+//!
+//! ```c
+//! CalcHandle *calc = calc_new();
+//! double x;
+//! if (calc_eval(calc, "1/2*sin(pi/6)", &x) == CALC_OK) {
+//!     printf("%g\n", x);
+//! } else {
+//!     printf("%s\n", calc_last_error_message(calc));
+//! }
+//! calc_free(calc);
+//! ```
+//!
+//! ```
+//! use calculator::ffi::*;
+//!
+//! let calc = calc_new();
+//! let mut x = 0.;
+//! unsafe {
+//!     assert_eq!(calc_eval(calc, c"x = 3".as_ptr(), &mut x), CALC_OK);
+//!     assert_eq!(calc_eval(calc, c"x^2 + 1".as_ptr(), &mut x), CALC_OK);
+//!     assert_eq!(x, 10.);
+//!     assert_eq!(calc_eval(calc, c"sqrt(".as_ptr(), &mut x), CALC_ERR_SYNTAX);
+//!     let message = std::ffi::CStr::from_ptr(calc_last_error_message(calc));
+//!     assert_eq!(message.to_str(), Ok("Unexpected end of input"));
+//!     calc_free(calc);
+//! }
+//! ```
+
+use std::ffi::{c_char, c_double, c_int, CStr, CString};
+use crate::calculator::Calculator;
+use crate::diagnostic::ErrorKind;
+
+pub const CALC_OK: c_int = 0;
+/// A null handle, expression or result pointer, or an expression that
+/// isn't UTF-8.
+pub const CALC_ERR_ARGUMENT: c_int = -1;
+pub const CALC_ERR_SYNTAX: c_int = 1;
+pub const CALC_ERR_UNKNOWN_FUNCTION: c_int = 2;
+pub const CALC_ERR_UNKNOWN_VARIABLE: c_int = 3;
+pub const CALC_ERR_ASSIGNMENT: c_int = 4;
+pub const CALC_ERR_ARITY: c_int = 5;
+pub const CALC_ERR_DOMAIN: c_int = 6;
+/// Also a result that isn't a number, like a list or a boolean.
+pub const CALC_ERR_TYPE: c_int = 7;
+pub const CALC_ERR_ENVIRONMENT: c_int = 8;
+pub const CALC_ERR_INTERRUPTED: c_int = 9;
+pub const CALC_ERR_LIMIT: c_int = 10;
+pub const CALC_ERR_DIVISION_BY_ZERO: c_int = 11;
+
+/// A calculator with the message of its last error.
+pub struct CalcHandle {
+    calc: Calculator,
+    error: CString,
+}
+
+/// A calculator, freed by `calc_free`.
+#[no_mangle]
+pub extern "C" fn calc_new() -> *mut CalcHandle {
+    Box::into_raw(Box::new(CalcHandle { calc: Calculator::new(), error: CString::default() }))
+}
+
+/// # Safety
+///
+/// `handle` is null or from `calc_new`, and isn't used after.
+#[no_mangle]
+pub unsafe extern "C" fn calc_free(handle: *mut CalcHandle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Evaluates the expression `expr` and writes the result to `out`. Returns
+/// `CALC_OK`, or one of the error codes with the message kept for
+/// `calc_last_error_message`.
+///
+/// # Safety
+///
+/// `handle` is from `calc_new`, `expr` is a nul-terminated string and
+/// `out` points at a double.
+#[no_mangle]
+pub unsafe extern "C" fn calc_eval(handle: *mut CalcHandle, expr: *const c_char, out: *mut c_double) -> c_int {
+    let Some(handle) = handle.as_mut() else { return CALC_ERR_ARGUMENT };
+    let expr = match expr.is_null() || out.is_null() {
+        true => None,
+        false => CStr::from_ptr(expr).to_str().ok(),
+    };
+    let (code, message) = match expr {
+        None => (CALC_ERR_ARGUMENT, "Expected an expression in UTF-8 and a result pointer".to_string()),
+        Some(expr) => match handle.calc.eval(expr.to_string()) {
+            Ok(value) => match value.number() {
+                Some(x) => {
+                    *out = x;
+                    (CALC_OK, String::new())
+                }
+                None => (CALC_ERR_TYPE, format!("Expected a number but got {}", handle.calc.format(value))),
+            },
+            Err(err) => (code(err.kind), err.message),
+        },
+    };
+    // Messages have no nul bytes unless the expression does.
+    handle.error = CString::new(message.replace('\0', "")).unwrap();
+    code
+}
+
+/// The message of the last failed `calc_eval`, empty after one that
+/// succeeded. It stays valid until the next `calc_eval` or `calc_free`.
+///
+/// # Safety
+///
+/// `handle` is from `calc_new`.
+#[no_mangle]
+pub unsafe extern "C" fn calc_last_error_message(handle: *const CalcHandle) -> *const c_char {
+    match handle.as_ref() {
+        Some(handle) => handle.error.as_ptr(),
+        None => c"".as_ptr(),
+    }
+}
+
+fn code(kind: ErrorKind) -> c_int {
+    match kind {
+        ErrorKind::Syntax { .. } | ErrorKind::Incomplete => CALC_ERR_SYNTAX,
+        ErrorKind::UnknownFunction => CALC_ERR_UNKNOWN_FUNCTION,
+        ErrorKind::UnknownVariable => CALC_ERR_UNKNOWN_VARIABLE,
+        ErrorKind::Assignment => CALC_ERR_ASSIGNMENT,
+        ErrorKind::Arity { .. } => CALC_ERR_ARITY,
+        ErrorKind::Domain => CALC_ERR_DOMAIN,
+        ErrorKind::Type | ErrorKind::Warning => CALC_ERR_TYPE,
+        ErrorKind::Environment => CALC_ERR_ENVIRONMENT,
+        ErrorKind::Interrupted => CALC_ERR_INTERRUPTED,
+        ErrorKind::Limit => CALC_ERR_LIMIT,
+        ErrorKind::DivisionByZero => CALC_ERR_DIVISION_BY_ZERO,
+    }
+}
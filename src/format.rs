@@ -0,0 +1,513 @@
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+
+/// A printf-style output template such as `%.3f kWh` or `[%8.2e]`.
+///
+/// Every conversion is replaced by the value being formatted, anything else
+/// is copied as is. Supported conversions are `%f`, `%e`, `%E`, `%g`, `%G`
+/// and `%d`, optionally preceded by the flags `-`, `+`, ` `, `0`, a width and
+/// a precision. `%%` prints a literal `%`.
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+enum Part {
+    Text(String),
+    Value(Spec),
+}
+
+struct Spec {
+    left: bool,
+    zero: bool,
+    plus: bool,
+    space: bool,
+    width: usize,
+    precision: Option<usize>,
+    conversion: char,
+}
+
+impl Template {
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let chars: Vec<char> = src.chars().collect();
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            if chars[i] != '%' {
+                text.push(chars[i]);
+                i += 1;
+                continue;
+            }
+            i += 1;
+
+            if chars.get(i) == Some(&'%') {
+                text.push('%');
+                i += 1;
+                continue;
+            }
+
+            let mut spec = Spec {
+                left: false,
+                zero: false,
+                plus: false,
+                space: false,
+                width: 0,
+                precision: None,
+                conversion: 'f',
+            };
+
+            while let Some(&flag) = chars.get(i) {
+                match flag {
+                    '-' => spec.left = true,
+                    '0' => spec.zero = true,
+                    '+' => spec.plus = true,
+                    ' ' => spec.space = true,
+                    _ => break
+                }
+                i += 1;
+            }
+            spec.width = digits(&chars, &mut i).unwrap_or(0);
+            if chars.get(i) == Some(&'.') {
+                i += 1;
+                spec.precision = Some(digits(&chars, &mut i).unwrap_or(0));
+            }
+
+            match chars.get(i) {
+                Some(&c @ ('f' | 'e' | 'E' | 'g' | 'G' | 'd')) => spec.conversion = c,
+                Some(c) => return Err(format!("Unknown conversion '%{}' in format", c)),
+                None => return Err("Format ends in the middle of a conversion".to_string()),
+            }
+            i += 1;
+
+            if !text.is_empty() {
+                parts.push(Part::Text(core::mem::take(&mut text)));
+            }
+            parts.push(Part::Value(spec));
+        }
+
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        if !parts.iter().any(|part| matches!(part, Part::Value(_))) {
+            return Err("Format needs a conversion such as '%f'".to_string());
+        }
+
+        Ok(Self { parts })
+    }
+
+    pub fn format(&self, value: f64) -> String {
+        let mut ret = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => ret.push_str(text),
+                Part::Value(spec) => ret.push_str(&spec.format(value)),
+            }
+        }
+        ret
+    }
+}
+
+impl Spec {
+    fn format(&self, value: f64) -> String {
+        let digits = if !value.is_finite() {
+            if value.is_nan() { "NaN".to_string() } else { "inf".to_string() }
+        } else {
+            let abs = value.abs();
+            match self.conversion {
+                'f' => format!("{:.*}", self.precision.unwrap_or(6), abs),
+                'e' => exponential(abs, self.precision.unwrap_or(6)),
+                'E' => exponential(abs, self.precision.unwrap_or(6)).to_uppercase(),
+                'g' => general(abs, self.precision.unwrap_or(6)),
+                'G' => general(abs, self.precision.unwrap_or(6)).to_uppercase(),
+                'd' => format!("{:.0}", abs.trunc()),
+                _ => unreachable!()
+            }
+        };
+
+        let sign = if value.is_sign_negative() && !value.is_nan() {
+            "-"
+        } else if self.plus {
+            "+"
+        } else if self.space {
+            " "
+        } else {
+            ""
+        };
+
+        let len = sign.len() + digits.len();
+        if len >= self.width {
+            format!("{}{}", sign, digits)
+        } else if self.left {
+            format!("{}{}{}", sign, digits, " ".repeat(self.width - len))
+        } else if self.zero && value.is_finite() {
+            format!("{}{}{}", sign, "0".repeat(self.width - len), digits)
+        } else {
+            format!("{}{}{}", " ".repeat(self.width - len), sign, digits)
+        }
+    }
+}
+
+/// The notation `Formatter` writes numbers in.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Notation {
+    /// Fixed for moderate magnitudes, scientific for very large and very
+    /// small ones.
+    #[default]
+    Auto,
+    Fixed,
+    Scientific,
+    /// Exponents that are multiples of 3, written as SI prefixes from `f` to
+    /// `T`, `4.7k` and `12µ`, and as `1.5e+21` beyond them.
+    Engineering,
+}
+
+/// How `round` and `Formatter` round a number, see
+/// `Calculator::set_rounding`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Rounding {
+    /// Halves away from zero, `round(2.5)` is 3 and `round(-2.5)` is -3.
+    #[default]
+    HalfUp,
+    /// Halves to the even neighbour, `round(2.5)` is 2 and `round(3.5)` is
+    /// 4, as banks round.
+    HalfEven,
+    /// Toward negative infinity.
+    Floor,
+    /// Toward positive infinity.
+    Ceil,
+}
+
+impl Rounding {
+    /// `x` rounded to `digits` after the point, or before it when negative.
+    /// Halves are those of the shortest decimal digits that read back as
+    /// `x`, so `2.675` rounds like it is written rather than like the float
+    /// just below it.
+    pub fn round(self, x: f64, digits: i32) -> f64 {
+        if !x.is_finite() || x == 0. {
+            return x;
+        }
+        let repr = format!("{:e}", x.abs());
+        let (mantissa, exp) = repr.split_once('e').unwrap();
+        let exp: i32 = exp.parse().unwrap();
+        let all: Vec<u8> = mantissa.bytes().filter(u8::is_ascii_digit).map(|digit| digit - b'0').collect();
+        // How many of the digits are kept, the first one is of 10^exp.
+        let keep = exp + 1 + digits;
+        if keep >= all.len() as i32 {
+            return x;
+        }
+        let (kept, rest): (&[u8], Vec<u8>) = match keep {
+            keep if keep > 0 => (&all[..keep as usize], all[keep as usize..].to_vec()),
+            0 => (&[], all.clone()),
+            // Less than a tenth of the last digit kept.
+            _ => (&[], vec![0, 1]),
+        };
+        let n = kept.iter().fold(0u64, |n, &digit| n * 10 + digit as u64);
+        let tie = rest[0] == 5 && rest[1..].iter().all(|&digit| digit == 0);
+        let inexact = rest.iter().any(|&digit| digit != 0);
+        let negative = x < 0.;
+        let away = match self {
+            Rounding::HalfUp => rest[0] >= 5,
+            Rounding::HalfEven => rest[0] > 5 || rest[0] == 5 && (!tie || n % 2 == 1),
+            Rounding::Floor => negative && inexact,
+            Rounding::Ceil => !negative && inexact,
+        };
+        let magnitude: f64 = format!("{}e{}", n + away as u64, -digits).parse().unwrap();
+        if negative { -magnitude } else { magnitude }
+    }
+}
+
+/// How results are displayed, see `Calculator::format`.
+///
+/// Without a precision numbers are written with as many digits as it takes
+/// to read them back exactly, with one it is the number of significant
+/// digits for `Auto` and `Engineering` and of digits after the point
+/// otherwise, so `0.1 + 0.2` is `0.3` at a precision of 4.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Formatter {
+    pub notation: Notation,
+    pub precision: Option<usize>,
+    /// Separate thousands with commas, `1,234,567.5`.
+    pub grouping: bool,
+    /// Write a `,` for the decimal point and separate thousands with `.`,
+    /// `1.234.567,5`.
+    pub decimal_comma: bool,
+    /// How the digits cut off by the precision round.
+    pub rounding: Rounding,
+}
+
+impl Formatter {
+    pub fn format(&self, value: f64) -> String {
+        if !value.is_finite() {
+            return value.to_string();
+        }
+        let mut repr = self.notate(self.rounded(value));
+        if self.decimal_comma {
+            repr = repr.replace('.', ",");
+        }
+        if self.grouping { group(&repr, if self.decimal_comma { '.' } else { ',' }) } else { repr }
+    }
+
+    /// `value` rounded to the digits the precision keeps, which then format
+    /// without rounding again.
+    fn rounded(&self, value: f64) -> f64 {
+        let Some(precision) = self.precision else { return value };
+        if value == 0. || !value.is_finite() {
+            return value;
+        }
+        let exp = value.abs().log10().floor() as i32;
+        let digits = match self.notation {
+            Notation::Fixed => precision as i32,
+            Notation::Scientific => precision as i32 - exp,
+            Notation::Auto | Notation::Engineering => precision.max(1) as i32 - 1 - exp,
+        };
+        self.rounding.round(value, digits)
+    }
+
+    fn notate(&self, value: f64) -> String {
+        match (self.notation, self.precision) {
+            (Notation::Auto, None) if value != 0. && !(1e-7..1e21).contains(&value.abs()) => scientific(value),
+            (Notation::Auto | Notation::Fixed, None) => value.to_string(),
+            (Notation::Auto, Some(precision)) => {
+                let sign = if value.is_sign_negative() && value != 0. { "-" } else { "" };
+                format!("{}{}", sign, general(value.abs(), precision))
+            }
+            (Notation::Fixed, Some(precision)) => format!("{:.*}", precision, value),
+            (Notation::Scientific, None) => scientific(value),
+            (Notation::Scientific, Some(precision)) => {
+                let sign = if value.is_sign_negative() { "-" } else { "" };
+                format!("{}{}", sign, exponential(value.abs(), precision))
+            }
+            (Notation::Engineering, precision) => engineering(value, precision),
+        }
+    }
+}
+
+/// Puts `separator` between every three digits before the point.
+fn group(repr: &str, separator: char) -> String {
+    let (sign, rest) = repr.split_at(if repr.starts_with('-') { 1 } else { 0 });
+    let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    let (int, rest) = rest.split_at(end);
+
+    let mut ret = sign.to_string();
+    for (i, c) in int.chars().enumerate() {
+        if i > 0 && (int.len() - i) % 3 == 0 {
+            ret.push(separator);
+        }
+        ret.push(c);
+    }
+    ret + rest
+}
+
+/// The shortest `%e` that reads back as `value`, `1.5e+21`.
+fn scientific(value: f64) -> String {
+    let repr = format!("{:e}", value);
+    let (mantissa, exp) = repr.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    format!("{}e{}{:02}", mantissa, if exp < 0 { '-' } else { '+' }, exp.abs())
+}
+
+/// `value` with an exponent that is a multiple of 3, the shortest digits
+/// that read back as it or `precision` significant ones.
+fn engineering(value: f64, precision: Option<usize>) -> String {
+    if value == 0. {
+        return "0".to_string();
+    }
+    // The digits of the mantissa from `%e`, with the point moved to make the
+    // exponent a multiple of 3.
+    let repr = match precision {
+        Some(precision) => format!("{:.*e}", precision.max(1) - 1, value.abs()),
+        None => format!("{:e}", value.abs()),
+    };
+    let (mantissa, exp) = repr.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    let power = exp.div_euclid(3) * 3;
+    let mut digits = mantissa.replace('.', "");
+    let point = (exp - power) as usize + 1;
+    if digits.len() < point {
+        digits.push_str(&"0".repeat(point - digits.len()));
+    }
+    let number = match digits.split_at(point) {
+        (int, "") => int.to_string(),
+        (int, frac) => format!("{}.{}", int, frac),
+    };
+
+    let sign = if value < 0. { "-" } else { "" };
+    let prefix = match power {
+        -15 => "f",
+        -12 => "p",
+        -9 => "n",
+        -6 => "µ",
+        -3 => "m",
+        0 => "",
+        3 => "k",
+        6 => "M",
+        9 => "G",
+        12 => "T",
+        _ => return format!("{}{}e{}{:02}", sign, number, if power < 0 { '-' } else { '+' }, power.abs()),
+    };
+    format!("{}{}{}", sign, number, prefix)
+}
+
+fn digits(chars: &[char], i: &mut usize) -> Option<usize> {
+    let start = *i;
+    while chars.get(*i).is_some_and(|c| c.is_ascii_digit()) {
+        *i += 1;
+    }
+    chars[start..*i].iter().collect::<String>().parse().ok()
+}
+
+/// `%e` with C's exponent layout, `1.500000e+03` rather than Rust's `1.5e3`.
+fn exponential(value: f64, precision: usize) -> String {
+    let repr = format!("{:.*e}", precision, value);
+    let (mantissa, exp) = repr.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    format!("{}e{}{:02}", mantissa, if exp < 0 { '-' } else { '+' }, exp.abs())
+}
+
+/// `%g`, picks `%e` or `%f` depending on the exponent and drops trailing zeros.
+fn general(value: f64, precision: usize) -> String {
+    let precision = precision.max(1);
+    if value == 0. {
+        return "0".to_string();
+    }
+
+    let exp = format!("{:.*e}", precision - 1, value)
+        .split_once('e').unwrap().1
+        .parse::<i32>().unwrap();
+
+    if exp < -4 || exp >= precision as i32 {
+        let repr = exponential(value, precision - 1);
+        let (mantissa, exp) = repr.split_once('e').unwrap();
+        format!("{}e{}", trim_zeros(mantissa), exp)
+    } else {
+        trim_zeros(&format!("{:.*}", (precision as i32 - 1 - exp) as usize, value)).to_string()
+    }
+}
+
+fn trim_zeros(repr: &str) -> &str {
+    if repr.contains('.') {
+        repr.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        repr
+    }
+}
+
+/// The exact decimal value of a float, every digit of it.
+///
+/// A finite float is `m * 2^e`, for negative `e` that is `m * 5^-e / 10^-e`,
+/// so the digits come from multiplying out a big integer.
+pub fn exact(value: f64) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
+
+    let bits = value.to_bits();
+    let biased = ((bits >> 52) & 0x7ff) as i32;
+    let fraction = bits & ((1 << 52) - 1);
+    let (mantissa, exp) = match biased {
+        0 => (fraction, -1074),
+        _ => (fraction | 1 << 52, biased - 1075),
+    };
+
+    // Little endian base 10^9 limbs.
+    let mut limbs = vec![(mantissa % 1_000_000_000) as u32, (mantissa / 1_000_000_000) as u32];
+    let (factor, times) = if exp < 0 { (5, -exp) } else { (2, exp) };
+    for _ in 0..times {
+        let mut carry = 0;
+        for limb in limbs.iter_mut() {
+            let product = *limb as u64 * factor + carry;
+            *limb = (product % 1_000_000_000) as u32;
+            carry = product / 1_000_000_000;
+        }
+        if carry > 0 {
+            limbs.push(carry as u32);
+        }
+    }
+
+    let mut digits = limbs.iter().rev().map(|limb| format!("{:09}", limb)).collect::<String>();
+    let scale = (-exp).max(0) as usize;
+    if digits.len() <= scale {
+        digits.insert_str(0, &"0".repeat(scale + 1 - digits.len()));
+    }
+    let (int, frac) = digits.split_at(digits.len() - scale);
+    let int = match int.trim_start_matches('0') {
+        "" => "0",
+        int => int,
+    };
+    let frac = frac.trim_end_matches('0');
+
+    let sign = if value.is_sign_negative() { "-" } else { "" };
+    if frac.is_empty() {
+        format!("{}{}", sign, int)
+    } else {
+        format!("{}{}.{}", sign, int, frac)
+    }
+}
+
+/// A float in C's `%a` notation, like `0x1.999999999999ap-4` for 0.1.
+pub fn hex(value: f64) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
+
+    let bits = value.to_bits();
+    let sign = if value.is_sign_negative() { "-" } else { "" };
+    let biased = ((bits >> 52) & 0x7ff) as i32;
+    let fraction = bits & ((1 << 52) - 1);
+    let (lead, exp) = match (biased, fraction) {
+        (0, 0) => (0, 0),
+        (0, _) => (0, -1022),
+        _ => (1, biased - 1023),
+    };
+
+    let digits = format!("{:013x}", fraction);
+    let digits = digits.trim_end_matches('0');
+    if digits.is_empty() {
+        format!("{}0x{}p{:+}", sign, lead, exp)
+    } else {
+        format!("{}0x{}.{}p{:+}", sign, lead, digits, exp)
+    }
+}
+
+/// `n` in a base from 2 to 36, with the prefix of the literals in base 16, 8
+/// and 2 and the base as a subscript in the others: `0xff` and `zz₃₆`.
+pub fn in_base(n: i64, base: u32) -> String {
+    let mut digits = vec![];
+    let mut rest = n.unsigned_abs();
+    loop {
+        digits.push(char::from_digit((rest % base as u64) as u32, base).unwrap());
+        rest /= base as u64;
+        if rest == 0 {
+            break;
+        }
+    }
+    let sign = if n < 0 { "-" } else { "" };
+    let digits: String = digits.iter().rev().collect();
+    match base {
+        16 => format!("{}0x{}", sign, digits),
+        8 => format!("{}0o{}", sign, digits),
+        2 => format!("{}0b{}", sign, digits),
+        _ => {
+            let subscript: String = base.to_string().chars().map(|c| char::from_u32('₀' as u32 + c.to_digit(10).unwrap()).unwrap()).collect();
+            format!("{}{}{}", sign, digits, subscript)
+        }
+    }
+}
+
+/// An integer in base 16, 8 or 2 with its `0x`, `0o` or `0b` prefix like
+/// the literals, `-0x1f` for -31. None for other values.
+pub fn radix(value: f64, base: u32) -> Option<String> {
+    if value.fract() != 0. || value.abs() >= u64::MAX as f64 {
+        return None;
+    }
+    let n = value.abs() as u64;
+    let digits = match base {
+        16 => format!("0x{:x}", n),
+        8 => format!("0o{:o}", n),
+        2 => format!("0b{:b}", n),
+        _ => return None,
+    };
+    let sign = if value < 0. { "-" } else { "" };
+    Some(format!("{}{}", sign, digits))
+}
@@ -0,0 +1,326 @@
+//! Builtin functions grouped by topic, which `Calculator::new` registers
+//! along with the basic ones.
+use core::cell::Cell;
+use alloc::rc::Rc;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::{SystemTime, UNIX_EPOCH};
+use crate::calculator::{integer, Function};
+use crate::plot::{self, Histogram};
+use crate::value::Value;
+
+/// `rand()` in [0, 1), `rand(a, b)` in [a, b) and `randint(a, b)` from a to
+/// b inclusive, drawn with the generator state in `rng`.
+pub(crate) fn random(rng: &Rc<Cell<u64>>) -> Vec<(&'static str, Function)> {
+    let (uniform, int) = (rng.clone(), rng.clone());
+    vec![
+        ("rand(...)", Function::variadic_closure(0, move |args| match args {
+            [] => Ok(next(&uniform)),
+            &[a, b] => Ok(a + (b - a) * next(&uniform)),
+            _ => Err(format!("Expected no arguments or a range for function 'rand' but got {} arguments", args.len())),
+        })),
+        ("randint(a, b)", Function::closure(2, move |args| {
+            let (a, b) = (integer(args[0])?, integer(args[1])?);
+            if a > b {
+                return Err(format!("Expected a range from low to high but got {} to {}", a, b));
+            }
+            Ok((a as f64 + (next(&int) * ((b - a) as f64 + 1.)).floor()).min(b as f64))
+        })),
+    ]
+}
+
+/// A seed that differs between runs, where there is a clock.
+pub(crate) fn seed() -> u64 {
+    if !crate::CLOCK {
+        return 0x2545_F491_4F6C_DD1D;
+    }
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64)
+}
+
+/// The next number of the splitmix64 generator, scaled to [0, 1).
+pub(crate) fn next(rng: &Cell<u64>) -> f64 {
+    let state = rng.get().wrapping_add(0x9E3779B97F4A7C15);
+    rng.set(state);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// The most numbers a sequence function makes a list of.
+const MAX_SEQUENCE: usize = 1_000_000;
+
+/// Lists of evenly spaced numbers. `range(a, b, step)` goes from `a` by
+/// `step` as far as `b`, which it includes when a step lands on it, and
+/// `linspace(a, b, n)` has `n` numbers from `a` to `b`.
+pub(crate) fn sequences() -> Vec<(&'static str, Function)> {
+    vec![
+        ("range(a, b)", Function::Values(2, |args| range(number(&args[0])?, number(&args[1])?, 1.))),
+        ("range(a, b, step)", Function::Values(3, |args| range(number(&args[0])?, number(&args[1])?, number(&args[2])?))),
+        ("linspace(a, b, n)", Function::Values(3, |args| {
+            let (a, b, n) = (number(&args[0])?, number(&args[1])?, number(&args[2])?);
+            if !(a.is_finite() && b.is_finite()) {
+                return Err(format!("Expected finite ends but got {} and {}", a, b));
+            }
+            let n = match n {
+                n if n.fract() == 0. && (1. ..=MAX_SEQUENCE as f64).contains(&n) => n as usize,
+                n => return Err(format!("Expected a count from 1 to {} but got {}", MAX_SEQUENCE, n)),
+            };
+            let step = if n == 1 { 0. } else { (b - a) / (n - 1) as f64 };
+            // The last number is `b` itself rather than `a` plus the steps.
+            Ok(Value::List((0..n).map(|i| if i + 1 == n && n > 1 { b } else { a + step * i as f64 }).collect()))
+        })),
+    ]
+}
+
+fn range(a: f64, b: f64, step: f64) -> Result<Value, String> {
+    if !(a.is_finite() && b.is_finite() && step.is_finite()) {
+        return Err(format!("Expected finite numbers but got {}, {} and a step of {}", a, b, step));
+    }
+    if step == 0. || (b - a) * step < 0. {
+        return Err(format!("Expected a step towards {} from {} but got {}", b, a, step));
+    }
+    // A step that is off by a rounding error still reaches `b`.
+    let steps = ((b - a) / step * (1. + 1e-12)).floor();
+    if steps >= MAX_SEQUENCE as f64 {
+        return Err(format!("Expected at most {} numbers but the range has {}", MAX_SEQUENCE, steps + 1.));
+    }
+    // Spaced like `linspace` when a step lands on `b`, so that `range(0, 1,
+    // 0.1)` has 0.3 rather than 0.30000000000000004.
+    let lands = steps > 0. && (a + step * steps - b).abs() <= 1e-12 * (b - a).abs();
+    Ok(Value::List((0..=steps as usize).map(|i| match lands {
+        true => a + (b - a) * i as f64 / steps,
+        false => a + step * i as f64,
+    }).collect()))
+}
+
+fn number(value: &Value) -> Result<f64, String> {
+    value.number().ok_or(format!("Expected a number but got {}", value))
+}
+
+/// Summaries of a handful of measurements, `variance`, `stddev` and `cov`
+/// are those of a sample.
+pub(crate) fn statistics() -> Vec<(&'static str, Function)> {
+    vec![
+        ("sum(x, ...)", Function::Variadic(1, |args| args.iter().sum())),
+        ("avg(x, ...)", Function::Variadic(1, mean)),
+        ("mean(x, ...)", Function::Variadic(1, mean)),
+        ("median(x, ...)", Function::Variadic(1, median)),
+        ("variance(x, y, ...)", Function::Variadic(2, variance)),
+        ("stddev(x, y, ...)", Function::Variadic(2, |args| variance(args).sqrt())),
+        ("geomean(x, ...)", Function::variadic_closure(1, |args| match args.iter().find(|&&x| x <= 0.) {
+            Some(x) => Err(format!("Expected positive numbers for function 'geomean' but got {}", x)),
+            None => Ok((args.iter().map(|x| x.ln()).sum::<f64>() / args.len() as f64).exp()),
+        })),
+        ("standardize(xs)", Function::Values(1, |args| {
+            let xs = sample(&args[0])?;
+            let (mean, stddev) = (mean(xs), variance(xs).sqrt());
+            if stddev == 0. {
+                return Err("Expected numbers that aren't all equal".to_string());
+            }
+            Ok(Value::List(xs.iter().map(|x| (x - mean) / stddev).collect()))
+        })),
+        ("cov(xs, ys)", Function::Values(2, |args| {
+            let (xs, ys) = paired(&args[0], &args[1])?;
+            Ok(Value::Number(covariance(xs, ys)))
+        })),
+        ("corr(xs, ys)", Function::Values(2, |args| {
+            let (xs, ys) = paired(&args[0], &args[1])?;
+            let spread = (variance(xs) * variance(ys)).sqrt();
+            if spread == 0. {
+                return Err("Expected lists whose numbers aren't all equal".to_string());
+            }
+            Ok(Value::Number((covariance(xs, ys) / spread).clamp(-1., 1.)))
+        })),
+        ("wmean(xs, weights)", Function::Values(2, |args| {
+            let (xs, weights) = match (args[0].list(), args[1].list()) {
+                (Some(xs), Some(weights)) if xs.len() == weights.len() => (xs, weights),
+                (Some(xs), Some(weights)) => return Err(format!("Expected as many weights as numbers but got {} and {}", weights.len(), xs.len())),
+                _ => return Err("Expected a list of numbers and a list of weights".to_string()),
+            };
+            if let Some(w) = weights.iter().find(|&&w| w < 0.) {
+                return Err(format!("Expected weights that aren't negative but got {}", w));
+            }
+            let total: f64 = weights.iter().sum();
+            if total == 0. {
+                return Err("Expected weights that aren't all 0".to_string());
+            }
+            Ok(Value::Number(xs.iter().zip(weights).map(|(x, w)| x * w).sum::<f64>() / total))
+        })),
+        // Sturges' rule picks the bins without a count.
+        ("hist(xs)", Function::Values(1, |args| histogram(&args[0], (data(&args[0])?.len() as f64).log2().ceil() as usize + 1))),
+        ("hist(xs, bins)", Function::Values(2, |args| match number(&args[1])? {
+            bins if bins.fract() == 0. && (1. ..=plot::MAX_BINS as f64).contains(&bins) => histogram(&args[0], bins as usize),
+            bins => Err(format!("Expected 1 to {} bins but got {}", plot::MAX_BINS, bins)),
+        })),
+    ]
+}
+
+/// The finite numbers of a list, at least one.
+fn data(value: &Value) -> Result<&[f64], String> {
+    match value.list() {
+        Some([]) => Err("Expected at least 1 number but got an empty list".to_string()),
+        Some(xs) => match xs.iter().find(|x| !x.is_finite()) {
+            Some(x) => Err(format!("Expected finite numbers but got {}", x)),
+            None => Ok(xs),
+        },
+        None => Err(format!("Expected a list but got {}", value)),
+    }
+}
+
+fn histogram(value: &Value, bins: usize) -> Result<Value, String> {
+    Ok(Value::Histogram(Histogram::new(data(value)?, bins.min(plot::MAX_BINS))))
+}
+
+/// Two lists of as many numbers, at least two.
+fn paired<'a>(xs: &'a Value, ys: &'a Value) -> Result<(&'a [f64], &'a [f64]), String> {
+    let (xs, ys) = (sample(xs)?, sample(ys)?);
+    if xs.len() != ys.len() {
+        return Err(format!("Expected lists of the same length but got {} and {} numbers", xs.len(), ys.len()));
+    }
+    Ok((xs, ys))
+}
+
+/// The covariance of a sample of pairs.
+fn covariance(xs: &[f64], ys: &[f64]) -> f64 {
+    let (mx, my) = (mean(xs), mean(ys));
+    xs.iter().zip(ys).map(|(x, y)| (x - mx) * (y - my)).sum::<f64>() / (xs.len() - 1) as f64
+}
+
+/// The numbers of a list, at least two of them.
+fn sample(value: &Value) -> Result<&[f64], String> {
+    match value.list() {
+        Some(xs) if xs.len() >= 2 => Ok(xs),
+        Some(xs) => Err(format!("Expected at least 2 numbers but got {}", xs.len())),
+        None => Err(format!("Expected a list but got {}", value)),
+    }
+}
+
+fn mean(args: &[f64]) -> f64 {
+    args.iter().sum::<f64>() / args.len() as f64
+}
+
+fn median(args: &[f64]) -> f64 {
+    let mut sorted = args.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) { (sorted[mid - 1] + sorted[mid]) / 2. } else { sorted[mid] }
+}
+
+fn variance(args: &[f64]) -> f64 {
+    let mean = mean(args);
+    args.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (args.len() - 1) as f64
+}
+
+/// Functions of integers, and the modulo that follows the sign of the
+/// divisor.
+pub(crate) fn number_theory() -> Vec<(&'static str, Function)> {
+    vec![
+        ("gcd(a, b)", Function::Fallible(2, |args| Ok(gcd(integer(args[0])?, integer(args[1])?) as f64))),
+        ("lcm(a, b)", Function::Fallible(2, |args| Ok(lcm(integer(args[0])?, integer(args[1])?)))),
+        ("isprime(n)", Function::Predicate(1, |args| Ok(is_prime(integer(args[0])?)))),
+        ("nextprime(n)", Function::Fallible(1, |args| next_prime(integer(args[0])?))),
+        ("mod(a, b)", Function::TwoArg(modulo)),
+        ("div(a, b)", Function::TwoArg(|a, b| ((a - modulo(a, b)) / b).round())),
+    ]
+}
+
+pub(crate) fn gcd(a: i64, b: i64) -> u64 {
+    let (mut a, mut b) = (a.unsigned_abs(), b.unsigned_abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// As a float, since it can be far larger than the arguments.
+fn lcm(a: i64, b: i64) -> f64 {
+    match gcd(a, b) {
+        0 => 0.,
+        d => (a.unsigned_abs() / d) as f64 * b.unsigned_abs() as f64,
+    }
+}
+
+/// Miller-Rabin with the bases that decide every 64-bit number.
+fn is_prime(n: i64) -> bool {
+    let n = match u64::try_from(n) {
+        Ok(n) if n >= 2 => n,
+        _ => return false,
+    };
+    const BASES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+    if let Some(&base) = BASES.iter().find(|&&base| n % base == 0) {
+        return n == base;
+    }
+
+    let mul = |a: u64, b: u64| (a as u128 * b as u128 % n as u128) as u64;
+    let pow = |mut base: u64, mut exp: u64| {
+        let mut ret = 1;
+        while exp > 0 {
+            if exp & 1 == 1 {
+                ret = mul(ret, base);
+            }
+            base = mul(base, base);
+            exp >>= 1;
+        }
+        ret
+    };
+    let zeros = (n - 1).trailing_zeros();
+    let odd = (n - 1) >> zeros;
+    BASES.iter().all(|&base| {
+        let mut x = pow(base, odd);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        (1..zeros).any(|_| {
+            x = mul(x, x);
+            x == n - 1
+        })
+    })
+}
+
+/// The smallest prime larger than `n`.
+fn next_prime(n: i64) -> Result<f64, String> {
+    let mut candidate = n.max(1);
+    loop {
+        candidate = candidate.checked_add(1).ok_or(format!("There is no prime after {} in 64 bits", n))?;
+        if is_prime(candidate) {
+            return Ok(candidate as f64);
+        }
+    }
+}
+
+/// The remainder of flooring division, which has the sign of `b`:
+/// `mod(-7, 3)` is 2 where `-7 % 3` is -1.
+fn modulo(a: f64, b: f64) -> f64 {
+    let r = a % b;
+    if r != 0. && (r < 0.) != (b < 0.) { r + b } else { r }
+}
+
+/// CODATA 2018 values of physical constants in SI units, under `phys.`.
+pub(crate) const PHYSICAL: [(&str, f64); 22] = [
+    // @formatter:off
+    ("phys.c", 299_792_458.),          // speed of light, m/s
+    ("phys.h", 6.626_070_15e-34),      // Planck constant, J s
+    ("phys.hbar", 1.054_571_817e-34),  // reduced Planck constant, J s
+    ("phys.G", 6.674_30e-11),          // gravitational constant, m^3/(kg s^2)
+    ("phys.g", 9.806_65),              // standard gravity, m/s^2
+    ("phys.kB", 1.380_649e-23),        // Boltzmann constant, J/K
+    ("phys.Na", 6.022_140_76e23),      // Avogadro constant, 1/mol
+    ("phys.R", 8.314_462_618),         // gas constant, J/(mol K)
+    ("phys.e", 1.602_176_634e-19),     // elementary charge, C
+    ("phys.me", 9.109_383_701_5e-31),  // electron mass, kg
+    ("phys.mp", 1.672_621_923_69e-27), // proton mass, kg
+    ("phys.mn", 1.674_927_498_04e-27), // neutron mass, kg
+    ("phys.u", 1.660_539_066_60e-27),  // atomic mass constant, kg
+    ("phys.eps0", 8.854_187_812_8e-12), // vacuum permittivity, F/m
+    ("phys.mu0", 1.256_637_062_12e-6), // vacuum permeability, N/A^2
+    ("phys.sigma", 5.670_374_419e-8),  // Stefan-Boltzmann constant, W/(m^2 K^4)
+    ("phys.alpha", 7.297_352_569_3e-3), // fine-structure constant
+    ("phys.Rinf", 10_973_731.568_160), // Rydberg constant, 1/m
+    ("phys.a0", 5.291_772_109_03e-11), // Bohr radius, m
+    ("phys.F", 96_485.332_12),         // Faraday constant, C/mol
+    ("phys.atm", 101_325.),            // standard atmosphere, Pa
+    ("phys.ly", 9.460_730_472_580_8e15), // light year, m
+    // @formatter:on
+];
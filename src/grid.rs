@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use calculator::{Calculator, Value};
+
+/// A cell address from `A1` to `Z99`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct Cell {
+    /// 0 for `A`.
+    column: u8,
+    /// Starts from 1 like the written address.
+    row: u8,
+}
+
+impl Cell {
+    pub fn parse(name: &str) -> Option<Self> {
+        let mut chars = name.chars();
+        let column = chars.next().filter(char::is_ascii_uppercase)?;
+        let row = chars.as_str();
+        if row.is_empty() || row.len() > 2 || row.starts_with('0') || !row.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        Some(Self { column: column as u8 - b'A', row: row.parse().ok()? })
+    }
+}
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", (b'A' + self.column) as char, self.row)
+    }
+}
+
+/// Cells holding expressions that can refer to other cells by address.
+///
+/// A sheet is written one cell per line, blank lines and lines starting with
+/// `#` are ignored:
+///
+///   A1 = 120
+///   A2 = 80
+///   A3 = A1 + A2
+///   B3 = A3 * 1.2
+///
+/// Cells are evaluated after the cells they refer to, an empty cell counts
+/// as 0.
+pub(crate) struct Sheet {
+    /// In the order they were written.
+    cells: Vec<(Cell, String)>,
+}
+
+impl Sheet {
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let mut cells: Vec<(Cell, String)> = vec![];
+
+        for (i, line) in src.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, expr) = line.split_once('=')
+                .ok_or(format!("line {}: Expected 'cell = expression'", i + 1))?;
+            let (name, expr) = (name.trim(), expr.trim());
+            let cell = Cell::parse(name)
+                .ok_or(format!("line {}: '{}' is not a cell, expected A1 to Z99", i + 1, name))?;
+            if cells.iter().any(|&(other, _)| other == cell) {
+                return Err(format!("line {}: {} is already defined", i + 1, cell));
+            }
+            cells.push((cell, expr.to_string()));
+        }
+
+        Ok(Self { cells })
+    }
+
+    /// The value of every cell, or the rendered error.
+    pub fn evaluate(&self, calc: &mut Calculator) -> HashMap<Cell, Result<Value, String>> {
+        let mut results = HashMap::new();
+        for &(cell, _) in &self.cells {
+            self.visit(calc, cell, &mut vec![], &mut results);
+        }
+        results
+    }
+
+    fn visit(&self, calc: &mut Calculator, cell: Cell, stack: &mut Vec<Cell>, results: &mut HashMap<Cell, Result<Value, String>>) {
+        if results.contains_key(&cell) {
+            return;
+        }
+        if let Some(pos) = stack.iter().position(|&other| other == cell) {
+            let path: Vec<String> = stack[pos..].iter().chain([&cell]).map(Cell::to_string).collect();
+            for &other in &stack[pos..] {
+                results.insert(other, Err(format!("Circular reference {}", path.join(" -> "))));
+            }
+            return;
+        }
+        let Some(expr) = self.formula(cell) else { return };
+        let refs = references(expr);
+
+        stack.push(cell);
+        for &other in &refs {
+            self.visit(calc, other, stack, results);
+        }
+        stack.pop();
+
+        // Part of a cycle found while visiting the references.
+        if results.contains_key(&cell) {
+            return;
+        }
+
+        let mut values = vec![];
+        for &other in &refs {
+            match results.get(&other) {
+                Some(Err(_)) => {
+                    results.insert(cell, Err(format!("Refers to {} which has an error", other)));
+                    return;
+                }
+                Some(Ok(value)) => values.push((other, value.clone())),
+                None => values.push((other, Value::Number(0.))),
+            }
+        }
+
+        for (other, value) in &values {
+            calc.define(&other.to_string(), value.clone());
+        }
+        let result = calc.eval(expr.to_string()).map_err(|err| err.render(expr));
+        for &(other, _) in &values {
+            calc.undefine(&other.to_string());
+        }
+        results.insert(cell, result);
+    }
+
+    fn formula(&self, cell: Cell) -> Option<&str> {
+        self.cells.iter().find(|&&(other, _)| other == cell).map(|(_, expr)| expr.as_str())
+    }
+}
+
+/// The cells an expression refers to, each once.
+fn references(expr: &str) -> Vec<Cell> {
+    let mut ret = vec![];
+    let mut word = String::new();
+
+    for c in expr.chars().chain([' ']) {
+        // A name starts with a letter, `2A1` is `2 * A1`.
+        if c.is_ascii_alphabetic() || (c.is_ascii_digit() && !word.is_empty()) {
+            word.push(c);
+            continue;
+        }
+        if let Some(cell) = Cell::parse(&word) {
+            if !ret.contains(&cell) {
+                ret.push(cell);
+            }
+        }
+        word.clear();
+    }
+    ret
+}
+
+/// Evaluates the sheet in `path` and prints it as a table followed by the
+/// errors, returns whether every cell evaluated.
+pub(crate) fn run(calc: &mut Calculator, path: &Path) -> bool {
+    let sheet = match fs::read_to_string(path).map_err(|err| err.to_string()).and_then(|src| Sheet::parse(&src)) {
+        Ok(sheet) => sheet,
+        Err(msg) => {
+            println!("error: {}: {}", path.display(), msg);
+            return false;
+        }
+    };
+    if sheet.cells.is_empty() {
+        return true;
+    }
+    let results = sheet.evaluate(calc);
+
+    let columns = sheet.cells.iter().map(|(cell, _)| cell.column).max().unwrap() + 1;
+    let rows = sheet.cells.iter().map(|(cell, _)| cell.row).max().unwrap();
+    let text = |cell: Cell| match results.get(&cell) {
+        Some(Ok(value)) => value.to_string(),
+        Some(Err(_)) => "#ERR".to_string(),
+        None => String::new(),
+    };
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| (1..=rows).map(|row| text(Cell { column, row }).len()).max().unwrap().max(1))
+        .collect();
+
+    let mut header = " ".repeat(rows.to_string().len());
+    for (column, width) in widths.iter().enumerate() {
+        header.push_str(&format!("  {:>width$}", (b'A' + column as u8) as char, width = width));
+    }
+    println!("{}", header);
+    for row in 1..=rows {
+        let mut line = format!("{:>width$}", row, width = rows.to_string().len());
+        for (column, width) in widths.iter().enumerate() {
+            line.push_str(&format!("  {:>width$}", text(Cell { column: column as u8, row }), width = width));
+        }
+        println!("{}", line.trim_end());
+    }
+
+    let mut ok = true;
+    for (cell, _) in &sheet.cells {
+        if let Some(Err(msg)) = results.get(cell) {
+            println!("{}: {}", cell, msg.replace('\n', "\n    "));
+            ok = false;
+        }
+    }
+    ok
+}
@@ -0,0 +1,197 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use rustyline::completion::Completer;
+use rustyline::highlight::{Highlighter, MatchingBracketHighlighter};
+use rustyline::hint::{Hint, Hinter};
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use calculator::Calculator;
+
+/// Line editor integration for the REPL.
+///
+/// While the cursor is inside a function call it hints the parameters that
+/// are still missing, typing `clamp(` shows `x, min, max)` and `clamp(1, `
+/// shows `min, max)`. Tab completes the names of functions, constants and
+/// variables, `sq` to `sqrt(`. Otherwise it hints what the line evaluates
+/// to so far, `2 * 3` shows ` = 6`.
+pub(crate) struct CalcHelper {
+    /// Parameter names by function name.
+    params: HashMap<String, Vec<String>>,
+    /// Constants and variables.
+    names: Vec<String>,
+    /// What separates arguments, `;` with a decimal comma.
+    separator: char,
+    /// A copy of the REPL's calculator to preview results with.
+    calc: RefCell<Calculator>,
+    /// Highlights the bracket matching the one at the cursor, only when
+    /// colors are on, see `with_color`.
+    brackets: Option<MatchingBracketHighlighter>,
+}
+
+impl CalcHelper {
+    pub fn new(calc: &Calculator) -> Self {
+        let definitions: Vec<_> = calc.definitions().collect();
+        let params = calc.signatures()
+            .chain(definitions.iter().map(|(name, signature)| (*name, signature.as_str())))
+            .map(|(name, signature)| {
+                let params = signature[name.len() + 1..signature.len() - 1]
+                    .split(',')
+                    .map(|param| param.trim().to_string())
+                    .filter(|param| !param.is_empty())
+                    .collect();
+                (name.to_string(), params)
+            })
+            .collect();
+
+        let names = calc.constants().map(|(name, _)| name).chain(calc.vars().map(|(name, _)| name))
+            .chain(calc.formulas().map(|(name, _)| name))
+            .map(str::to_string)
+            .collect();
+
+        let separator = if calc.decimal_separator() == ',' { ';' } else { ',' };
+        Self { params, names, separator, calc: RefCell::new(calc.clone()), brackets: None }
+    }
+
+    /// Colors the hints and the matching brackets, off by default.
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.brackets = color.then(MatchingBracketHighlighter::new);
+        self
+    }
+
+    /// What `line` evaluates to, unless it doesn't or that is just the line
+    /// itself.
+    pub fn result_hint(&self, line: &str) -> Option<String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(':') {
+            return None;
+        }
+        let value = self.calc.borrow_mut().preview(line)?.to_string();
+        (value != line).then(|| format!(" = {}", value))
+    }
+
+    /// Where the name before `pos` starts and the names it can be completed
+    /// to, sorted. Functions complete with their `(`.
+    pub fn completions(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let start = line[..pos].rfind(|c: char| !c.is_ascii_alphanumeric() && c != '.' && c != '_' && c != ':').map_or(0, |i| i + 1);
+        let word = &line[start..pos];
+        if !word.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return (pos, vec![]);
+        }
+
+        let mut candidates: Vec<String> = self.params.keys()
+            .filter(|name| name.starts_with(word))
+            // Namespaced names complete to their namespace first, `s` to
+            // `stats::` and `p` to `phys.`.
+            .map(|name| match name.split_once("::") {
+                Some((namespace, _)) if !word.contains("::") => format!("{}::", namespace),
+                _ => format!("{}(", name),
+            })
+            .chain(self.names.iter().filter(|name| name.starts_with(word)).map(|name| match name.split_once('.') {
+                Some((namespace, _)) if !word.contains('.') => format!("{}.", namespace),
+                _ => name.clone(),
+            }))
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        (start, candidates)
+    }
+
+    /// The parameters still to be typed for the innermost unclosed call
+    /// before `pos`.
+    pub fn signature_hint(&self, line: &str, pos: usize) -> Option<String> {
+        let before: Vec<char> = line[..pos].chars().collect();
+
+        // Walk backwards to the '(' of the innermost open call, counting the
+        // commas of that call on the way.
+        let mut depth = 0;
+        let mut commas = 0;
+        let mut open = None;
+        for i in (0..before.len()).rev() {
+            match before[i] {
+                ')' => depth += 1,
+                '(' if depth == 0 => {
+                    open = Some(i);
+                    break;
+                }
+                '(' => depth -= 1,
+                c if c == self.separator && depth == 0 => commas += 1,
+                _ => {}
+            }
+        }
+        let open = open?;
+
+        let start = before[..open].iter()
+            .rposition(|&c| !c.is_ascii_alphanumeric() && c != '_' && c != ':')
+            .map_or(0, |i| i + 1);
+        let name: String = before[start..open].iter().collect();
+        let params = self.params.get(&name)?;
+
+        let last_comma = before.iter().rposition(|&c| c == self.separator).filter(|&i| i > open);
+        let current_empty = before[last_comma.unwrap_or(open) + 1..].iter().all(|c| c.is_whitespace());
+
+        let remaining = if current_empty { commas } else { commas + 1 };
+        if remaining >= params.len() {
+            return None;
+        }
+
+        let rest = params[remaining..].join(&format!("{} ", self.separator));
+        Some(if current_empty { format!("{})", rest) } else { format!("{} {})", self.separator, rest) })
+    }
+}
+
+/// A hint that is only displayed, accepting it doesn't insert anything.
+pub(crate) struct DisplayHint(String);
+
+impl Hint for DisplayHint {
+    fn display(&self) -> &str {
+        &self.0
+    }
+
+    fn completion(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl Hinter for CalcHelper {
+    type Hint = DisplayHint;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<DisplayHint> {
+        if pos < line.len() {
+            return None;
+        }
+        self.signature_hint(line, pos).or_else(|| self.result_hint(line)).map(DisplayHint)
+    }
+}
+
+impl Highlighter for CalcHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        match &self.brackets {
+            Some(brackets) => brackets.highlight(line, pos),
+            None => Cow::Borrowed(line),
+        }
+    }
+
+    fn highlight_char(&self, line: &str, pos: usize) -> bool {
+        self.brackets.as_ref().is_some_and(|brackets| brackets.highlight_char(line, pos))
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        match self.brackets {
+            Some(_) => Cow::Owned(format!("\x1b[2m{}\x1b[0m", hint)),
+            None => Cow::Borrowed(hint),
+        }
+    }
+}
+
+impl Completer for CalcHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.completions(line, pos))
+    }
+}
+
+impl Validator for CalcHelper {}
+
+impl Helper for CalcHelper {}
@@ -0,0 +1,189 @@
+use std::path::{Path, PathBuf};
+use crate::ast::{fold_children, Expr, Fold};
+use crate::calculator::Calculator;
+use crate::diagnostic::{Diagnostic, ErrorKind};
+
+/// Loads libraries of definitions, `.calc` files like
+///
+///   # finance.calc
+///   rate = 0.05
+///   fv(n, pmt) = pmt * ((1 + rate)^n - 1) / rate
+///
+/// into the namespace of their name, for `import "finance.calc"` in scripts
+/// and the REPL. The file calls its own definitions without the namespace,
+/// everyone else as `finance::fv(10, 100)`:
+///
+///   let importer = Importer::new(vec!["/usr/share/formulas".into()]);
+///   importer.import(&mut calc, "finance.calc", None)?;
+///
+/// A library imports others with lines of its own like `import "tax.calc"`.
+/// Like `Calculator::load_definitions`, a library that fails to load leaves
+/// the calculator as it was.
+#[derive(Clone, Debug, Default)]
+pub struct Importer {
+    /// Where files are looked for after the directory of the one importing
+    /// them.
+    path: Vec<PathBuf>,
+}
+
+/// A line of a library.
+enum Line<'a> {
+    Import(&'a str),
+    Definition(Expr),
+}
+
+impl Importer {
+    pub fn new(path: Vec<PathBuf>) -> Self {
+        Self { path }
+    }
+
+    pub fn path(&self) -> &[PathBuf] {
+        &self.path
+    }
+
+    /// The file of a line like `import "finance.calc"`, None for any other
+    /// line.
+    pub fn statement(line: &str) -> Option<&str> {
+        let rest = line.trim().strip_prefix("import")?;
+        if !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        let file = rest.trim().strip_prefix('"')?.strip_suffix('"')?;
+        (!file.is_empty() && !file.contains('"')).then_some(file)
+    }
+
+    /// Loads the library `file` into `calc` and returns the names it
+    /// defined, like `finance::fv`. It is looked for next to `from`, the
+    /// script importing it, or in the current directory, and then in the
+    /// search path.
+    pub fn import(&self, calc: &mut Calculator, file: &str, from: Option<&Path>) -> Result<Vec<String>, Diagnostic> {
+        let path = self.find(file, from.and_then(Path::parent))?;
+        let mut stack: Vec<PathBuf> = from.map(canonical).into_iter().collect();
+        self.load(calc, &path, &mut stack)
+    }
+
+    fn find(&self, file: &str, dir: Option<&Path>) -> Result<PathBuf, Diagnostic> {
+        let dirs: Vec<&Path> = std::iter::once(dir.unwrap_or(Path::new("."))).chain(self.path.iter().map(PathBuf::as_path)).collect();
+        dirs.iter().map(|dir| dir.join(file)).find(|path| path.is_file()).ok_or_else(|| {
+            let dirs: Vec<String> = dirs.iter().map(|dir| match dir.as_os_str().is_empty() {
+                true => ".".to_string(),
+                false => dir.display().to_string(),
+            }).collect();
+            Diagnostic::new(format!("Can't find '{}' in {}", file, dirs.join(", "))).with_kind(ErrorKind::Environment)
+        })
+    }
+
+    /// Loads the library at `path`, which `stack`, the libraries importing
+    /// it, mustn't have.
+    fn load(&self, calc: &mut Calculator, path: &Path, stack: &mut Vec<PathBuf>) -> Result<Vec<String>, Diagnostic> {
+        let src = std::fs::read_to_string(path).map_err(|err| Diagnostic::new(format!("Failed to read '{}': {}", path.display(), err))
+            .with_kind(ErrorKind::Environment))?;
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let mut chars = stem.chars();
+        if !chars.next().is_some_and(|c| c.is_ascii_alphabetic()) || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(Diagnostic::new(format!("'{}' can't be a namespace, expected a file name of letters, digits and '_' like finance.calc", stem))
+                .with_kind(ErrorKind::Environment));
+        }
+        // The labels are of the line, not of the import rendering the error.
+        let in_line = |i: usize| move |err: Diagnostic| Diagnostic { message: format!("{}: Line {}: {}", path.display(), i, err.message), labels: vec![], ..err };
+
+        // All the names first, a definition can call those after it.
+        let mut lines = vec![];
+        let mut names = vec![];
+        for (i, line) in src.lines().enumerate().map(|(i, line)| (i + 1, line.trim())) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(file) = Importer::statement(line) {
+                lines.push((i, Line::Import(file)));
+                continue;
+            }
+            let expr = calc.compile(line).map_err(in_line(i))?.ast();
+            match &expr {
+                Expr::Define { name, .. } | Expr::Assign { name, .. } => names.push(name.clone()),
+                _ => return Err(in_line(i)(Diagnostic::new("Expected a definition like 'f(x) = ...', 'x = ...' or an import")
+                    .with_expected("a definition"))),
+            }
+            lines.push((i, Line::Definition(expr)));
+        }
+
+        stack.push(canonical(path));
+        let mut trial = calc.clone();
+        let mut qualify = Qualify { namespace: &stem, names: &names, params: vec![] };
+        for (i, line) in lines {
+            match line {
+                Line::Import(file) => {
+                    let nested = self.find(file, path.parent()).map_err(in_line(i))?;
+                    if let Some(start) = stack.iter().position(|path| *path == canonical(&nested)) {
+                        let cycle: Vec<String> = stack[start..].iter().chain(std::iter::once(&stack[start]))
+                            .map(|path| path.file_name().unwrap_or_default().to_string_lossy().into_owned())
+                            .collect();
+                        return Err(in_line(i)(Diagnostic::new(format!("Import cycle: {}", cycle.join(" -> "))).with_kind(ErrorKind::Environment)));
+                    }
+                    self.load(&mut trial, &nested, stack)?;
+                }
+                Line::Definition(expr) => {
+                    trial.eval(qualify.fold(expr).to_string()).map_err(in_line(i))?;
+                }
+            }
+        }
+        stack.pop();
+        *calc = trial;
+        Ok(names.iter().map(|name| format!("{}::{}", stem, name)).collect())
+    }
+}
+
+/// The path with `.` and links resolved, to tell whether two are the same
+/// file.
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Puts the names a library defines in its namespace, where they aren't
+/// hidden by a parameter.
+struct Qualify<'a> {
+    namespace: &'a str,
+    names: &'a [String],
+    /// The parameters of the definitions and lambdas around the node.
+    params: Vec<String>,
+}
+
+impl Qualify<'_> {
+    fn name(&self, name: String) -> String {
+        match self.names.contains(&name) && !self.params.contains(&name) {
+            true => format!("{}::{}", self.namespace, name),
+            false => name,
+        }
+    }
+
+    /// `body` with `params` hiding the names.
+    fn body(&mut self, params: &[String], body: Expr) -> Expr {
+        let outer = self.params.len();
+        self.params.extend(params.iter().cloned());
+        let body = self.fold(body);
+        self.params.truncate(outer);
+        body
+    }
+}
+
+impl Fold for Qualify<'_> {
+    fn fold(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Name(name) => Expr::Name(self.name(name)),
+            Expr::Call { name, arguments } => Expr::Call {
+                name: self.name(name),
+                arguments: arguments.into_iter().map(|argument| self.fold(argument)).collect(),
+            },
+            Expr::Assign { name, value } => Expr::Assign { name: self.name(name), value: Box::new(self.fold(*value)) },
+            Expr::Define { name, params, body } => {
+                let body = Box::new(self.body(&params, *body));
+                Expr::Define { name: self.name(name), params, body }
+            }
+            Expr::Lambda { params, body } => {
+                let body = Box::new(self.body(&params, *body));
+                Expr::Lambda { params, body }
+            }
+            expr => fold_children(self, expr),
+        }
+    }
+}
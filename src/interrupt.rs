@@ -0,0 +1,25 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+static FLAG: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+/// Makes Ctrl-C set `flag` instead of killing the process, so a running
+/// evaluation can be cancelled. The line editor reads keys in raw mode, so
+/// Ctrl-C at the prompt still reaches it as a key press.
+pub(crate) fn install(flag: Arc<AtomicBool>) {
+    if FLAG.set(flag).is_err() {
+        return;
+    }
+
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(libc::SIGINT, handle as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn handle(_: libc::c_int) {
+    if let Some(flag) = FLAG.get() {
+        flag.store(true, Ordering::Relaxed);
+    }
+}
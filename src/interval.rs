@@ -0,0 +1,159 @@
+use core::fmt;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::calculator::Function;
+use crate::value::Value;
+
+/// The operator that makes an interval from a value and its uncertainty,
+/// `5 ± 0.1`.
+pub(crate) const PLUS_MINUS: &str = "±";
+
+/// Builtin functions of one argument that never decrease, or never
+/// increase, so their value over an interval lies between their values at
+/// the ends.
+pub(crate) const MONOTONIC: [&str; 15] = [
+    "sqrt", "cbrt", "exp", "exp2", "ln", "log2", "log10", "sinh", "asinh", "tanh",
+    "ceil", "floor", "round", "trunc", "atanh",
+];
+
+/// The numbers from `lo` to `hi`, for a measured value with its
+/// uncertainty. Results of operations contain every result the numbers in
+/// their operands can give.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interval {
+    lo: f64,
+    hi: f64,
+}
+
+impl Interval {
+    pub fn new(a: f64, b: f64) -> Self {
+        Self { lo: a.min(b), hi: a.max(b) }
+    }
+
+    /// `value ± uncertainty`.
+    pub fn around(value: f64, uncertainty: f64) -> Self {
+        Self::new(value - uncertainty.abs(), value + uncertainty.abs())
+    }
+
+    pub fn lo(&self) -> f64 {
+        self.lo
+    }
+
+    pub fn hi(&self) -> f64 {
+        self.hi
+    }
+
+    pub fn value(&self) -> f64 {
+        (self.lo + self.hi) / 2.
+    }
+
+    pub fn uncertainty(&self) -> f64 {
+        (self.hi - self.lo) / 2.
+    }
+
+    /// `f` over the interval for a function in `MONOTONIC`.
+    pub(crate) fn map(&self, name: &str, f: impl Fn(f64) -> f64) -> Result<Self, String> {
+        let (lo, hi) = (f(self.lo), f(self.hi));
+        if lo.is_nan() || hi.is_nan() {
+            return Err(format!("'{}' is undefined for part of {}", name, self));
+        }
+        Ok(Self::new(lo, hi))
+    }
+
+    fn contains_zero(&self) -> bool {
+        self.lo <= 0. && 0. <= self.hi
+    }
+
+    /// The smallest interval holding all of `xs`.
+    fn hull(xs: &[f64]) -> Self {
+        let lo = xs.iter().copied().fold(f64::INFINITY, f64::min);
+        let hi = xs.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        Self { lo, hi }
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        Self::hull(&[self.lo * other.lo, self.lo * other.hi, self.hi * other.lo, self.hi * other.hi])
+    }
+
+    fn div(&self, other: &Self) -> Result<Self, String> {
+        if other.contains_zero() {
+            return Err(format!("Can't divide by {}, it contains 0", other));
+        }
+        Ok(self.mul(&Self::new(1. / other.lo, 1. / other.hi)))
+    }
+
+    fn pow(&self, n: f64) -> Result<Self, String> {
+        let (lo, hi) = (self.lo.powf(n), self.hi.powf(n));
+        if lo.is_nan() || hi.is_nan() {
+            return Err(format!("Can't raise {} to {}, it contains negative numbers", self, n));
+        }
+        if n < 0. && self.contains_zero() {
+            return Err(format!("Can't raise {} to {}, it contains 0", self, n));
+        }
+        // Even powers are smallest at 0.
+        match self.contains_zero() && n > 0. {
+            true => Ok(Self::hull(&[lo, hi, 0.])),
+            false => Ok(Self::new(lo, hi)),
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    /// Like `5.00 ± 0.10`, with the uncertainty to 2 significant digits and
+    /// the value to as many places.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let uncertainty = self.uncertainty();
+        if uncertainty == 0. || !uncertainty.is_finite() {
+            return write!(f, "{} ± {}", self.value(), uncertainty);
+        }
+        // The exponent after rounding to 2 digits, 0.0999 is 1.0e-1.
+        let exponent: i32 = format!("{:.1e}", uncertainty).split_once('e').unwrap().1.parse().unwrap();
+        let places = (1 - exponent).max(0) as usize;
+        write!(f, "{:.*} ± {:.*}", places, self.value(), places, uncertainty)
+    }
+}
+
+/// An infix operator with an interval operand, and `±` making one. `None`
+/// if it involves no interval.
+pub(crate) fn infix(symbol: &str, lhs: &Value, rhs: &Value) -> Option<Result<Value, String>> {
+    let interval = |value: &Value| match value {
+        Value::Interval(i) => Some(*i),
+        value => value.number().map(|x| Interval::new(x, x)),
+    };
+    if symbol == PLUS_MINUS {
+        let ret = match (lhs, rhs.number()) {
+            (Value::Interval(i), Some(e)) => Ok(Interval::new(i.lo - e.abs(), i.hi + e.abs())),
+            (lhs, Some(e)) => lhs.number().map(|x| Interval::around(x, e)).ok_or(format!("Expected a number but got {}", lhs)),
+            (_, None) => Err(format!("Expected a number but got {}", rhs)),
+        };
+        return Some(ret.map(Value::Interval));
+    }
+    if !matches!(lhs, Value::Interval(_)) && !matches!(rhs, Value::Interval(_)) {
+        return None;
+    }
+    let (Some(a), Some(b)) = (interval(lhs), interval(rhs)) else {
+        return Some(Err(format!("Can't apply '{}' to {} and {}", symbol, lhs, rhs)));
+    };
+    let ret = match symbol {
+        "+" => Ok(Interval::new(a.lo + b.lo, a.hi + b.hi)),
+        "-" => Ok(Interval::new(a.lo - b.hi, a.hi - b.lo)),
+        "*" => Ok(a.mul(&b)),
+        "/" => a.div(&b),
+        "^" => match (lhs, rhs) {
+            (Value::Interval(a), Value::Number(n)) => a.pow(*n),
+            (Value::Number(x), Value::Interval(b)) if *x > 0. => Ok(Interval::new(x.powf(b.lo), x.powf(b.hi))),
+            _ => Err(format!("Can't raise {} to {}", lhs, rhs)),
+        },
+        _ => Err(format!("Can't apply '{}' to {} and {}", symbol, lhs, rhs)),
+    };
+    Some(ret.map(Value::Interval))
+}
+
+pub(crate) fn functions() -> Vec<(&'static str, Function)> {
+    vec![
+        ("interval(lo, hi)", Function::Values(2, |args| match (args[0].number(), args[1].number()) {
+            (Some(lo), Some(hi)) => Ok(Value::Interval(Interval::new(lo, hi))),
+            _ => Err(format!("Expected numbers but got {} and {}", args[0], args[1])),
+        })),
+    ]
+}
@@ -0,0 +1,81 @@
+use calculator::{Calculator, Diagnostic, Value};
+
+/// Evaluates `exprs` in order for `--json`, printing one object per
+/// expression:
+///
+///   {"expr": "1 + 2", "ok": true, "value": 3.0}
+///   {"expr": "1 +", "ok": false, "error": {"kind": "syntax error", "message": "Unexpected end of input", ...}}
+///
+/// Returns whether all of them evaluated.
+pub(crate) fn run(calc: &mut Calculator, exprs: impl Iterator<Item = String>) -> bool {
+    let mut ok = true;
+    for expr in exprs.filter(|expr| !expr.trim().is_empty()) {
+        let ret = calc.eval(expr.clone());
+        ok &= ret.is_ok();
+        println!("{}", result(calc, &expr, &ret));
+    }
+    ok
+}
+
+/// The object for the result of evaluating `expr`, with the warnings of
+/// the evaluation. Definitions have no value.
+pub(crate) fn result(calc: &Calculator, expr: &str, ret: &Result<Value, Diagnostic>) -> String {
+    let mut fields = vec![format!("\"expr\": {}", string(expr))];
+    match ret {
+        Ok(_) if calc.is_definition(expr) => fields.push("\"ok\": true".to_string()),
+        Ok(ret) => fields.extend(["\"ok\": true".to_string(), format!("\"value\": {}", value(calc, ret))]),
+        Err(err) => fields.extend(["\"ok\": false".to_string(), format!("\"error\": {}", error(err))]),
+    }
+    if !calc.warnings().is_empty() {
+        let warnings: Vec<String> = calc.warnings().iter().map(error).collect();
+        fields.push(format!("\"warnings\": [{}]", warnings.join(", ")));
+    }
+    format!("{{{}}}", fields.join(", "))
+}
+
+/// Numbers, booleans and lists as themselves, anything else as the string
+/// the REPL would print.
+fn value(calc: &Calculator, value: &Value) -> String {
+    match value {
+        &Value::Number(x) => number(x),
+        Value::Bool(b) => b.to_string(),
+        Value::List(xs) => format!("[{}]", xs.iter().map(|&x| number(x)).collect::<Vec<_>>().join(", ")),
+        value => string(&calc.format(value.clone())),
+    }
+}
+
+/// JSON has no infinities or NaN, they are written as strings.
+fn number(x: f64) -> String {
+    match x.is_finite() {
+        true => format!("{:?}", x),
+        false => string(&x.to_string()),
+    }
+}
+
+/// `{"kind": ..., "message": ..., "labels": [{"start": 0, "end": 1, "text": ...}], "hint": ...}`,
+/// the spans counting chars of the expression.
+fn error(err: &Diagnostic) -> String {
+    let labels: Vec<String> = err.labels.iter()
+        .map(|label| format!("{{\"start\": {}, \"end\": {}, \"text\": {}}}", label.span.start, label.span.end, string(&label.text)))
+        .collect();
+    let mut ret = format!("{{\"kind\": {}, \"message\": {}, \"labels\": [{}]", string(&err.kind.to_string()), string(&err.message), labels.join(", "));
+    if let Some(hint) = &err.hint {
+        ret.push_str(&format!(", \"hint\": {}", string(hint)));
+    }
+    ret + "}"
+}
+
+fn string(s: &str) -> String {
+    let mut ret = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\t' => ret.push_str("\\t"),
+            c if (c as u32) < 0x20 => ret.push_str(&format!("\\u{:04x}", c as u32)),
+            c => ret.push(c),
+        }
+    }
+    ret + "\""
+}
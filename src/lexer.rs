@@ -0,0 +1,422 @@
+use alloc::borrow::Cow;
+use core::num::IntErrorKind;
+use core::ops::Range;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum TokenKind {
+    Number(f64),
+    Name(String),
+    /// `$NAME`, the name is empty for a lone `$`.
+    Env(String),
+    /// One of the operator symbols, which fixity it has is up to the parser.
+    Symbol(String),
+    Open,
+    Close,
+    OpenBracket,
+    CloseBracket,
+    Comma,
+    /// `;` between statements.
+    Semicolon,
+    Equals,
+    /// `->` between the parameters and the body of a lambda.
+    Arrow,
+    /// `:=` between the name and the formula of a variable that follows
+    /// the variables it is computed from.
+    ColonEquals,
+    /// A char that doesn't start any token.
+    Unknown(char),
+    /// A malformed literal, with the message to report.
+    Invalid(String),
+    End,
+}
+
+/// A token with the columns of the original expression it was read from.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Token {
+    pub kind: TokenKind,
+    pub span: Range<usize>,
+}
+
+/// Splits `source` into tokens ending with `End`, each with the columns of
+/// the original expression it was read from: the position of its chars, or
+/// with `columns` the column of each char. Spaces separate tokens. Operators
+/// are read by the longest of `symbols` that matches, so `<>` is one token
+/// if it is an operator and two otherwise. A name that is one of the
+/// `symbols`, like `xor`, is an operator.
+///
+/// Math symbols pasted from documents read as what they stand for: `π` is
+/// the number pi, `×`, `÷` and `−` are `*`, `/` and `-`, and superscripts are
+/// powers, `x²` is `x^2` and `x⁻¹` is `x^-1`.
+///
+/// A number may end in an exponent, `1e-3` is 0.001, but `2e` is 2 times
+/// `e`.
+///
+/// With `si` an SI prefix touching a number scales it, `4.7k` is 4700 and
+/// `100m` is 0.1, unless more letters follow like in `2kg`.
+///
+/// With `comma` a `,` between digits is the decimal point, `3,14`, and a `;`
+/// inside parentheses or brackets separates arguments, `max(1,5; 2)`.
+///
+///   number: digits (('.' | ',') digits?)? ([eE] [+-]? [0-9]+)? | '0x' [0-9a-fA-F_]+ | '0o' [0-7_]+ | '0b' [01_]+
+///   digits: [0-9]+ ('_' [0-9]+)*
+///   name: [a-zA-Z][a-zA-Z0-9_]* (('.' | '::') [a-zA-Z][a-zA-Z0-9_]*)*
+///   env: '$' ([a-zA-Z_][a-zA-Z0-9_]* | [0-9]+)?
+pub(crate) fn tokenize(source: &str, columns: Option<&[usize]>, symbols: &[String], end: usize, si: bool, comma: bool) -> Vec<Token> {
+    let bytes = source.as_bytes();
+    let at = |i: usize| source.get(i..).and_then(|rest| rest.chars().next());
+    let after = |i: usize| at(i).and_then(|c| at(i + c.len_utf8()));
+    let mut columns = Columns { source, columns, byte: 0, chars: 0 };
+    let mut tokens = vec![];
+    let mut i = 0;
+    // Parentheses and brackets open, where a `;` separates arguments with
+    // `comma`.
+    let mut depth = 0usize;
+
+    while let Some(c) = at(i) {
+        if c == ' ' || c == '\t' {
+            i += 1;
+            continue;
+        }
+        let column = columns.start(i);
+        let kind = match c {
+            '0' if after(i).is_some_and(|c| "xob".contains(c)) => {
+                let letter = after(i).unwrap();
+                let (radix, name) = match letter {
+                    'x' => (16, "hexadecimal"),
+                    'o' => (8, "octal"),
+                    _ => (2, "binary"),
+                };
+                i += 2;
+                let mut digits = String::new();
+                while let Some(&c) = bytes.get(i).filter(|c| c.is_ascii_alphanumeric() || **c == b'_') {
+                    if c != b'_' {
+                        digits.push(c as char);
+                    }
+                    i += 1;
+                }
+                match u64::from_str_radix(&digits, radix) {
+                    Ok(value) => TokenKind::Number(value as f64),
+                    Err(err) if err.kind() == &IntErrorKind::PosOverflow => TokenKind::Invalid(format!("0{}{} doesn't fit in 64 bits", letter, digits)),
+                    Err(_) if digits.is_empty() => TokenKind::Invalid(format!("Expected {} digits after '0{}'", name, letter)),
+                    Err(_) => TokenKind::Invalid(format!("'{}' is not a valid {} number", digits, name)),
+                }
+            }
+            '0'..='9' => {
+                let start = i;
+                digits(bytes, start, &mut i);
+                let point = bytes.get(i) == Some(&b'.') || comma && bytes.get(i) == Some(&b',') && bytes.get(i + 1).is_some_and(u8::is_ascii_digit);
+                if point {
+                    i += 1;
+                    digits(bytes, start, &mut i);
+                }
+                // An exponent needs its digits, so `2e` is still 2 times e.
+                let sign = usize::from(matches!(bytes.get(i + 1), Some(b'+' | b'-')));
+                let scientific = matches!(bytes.get(i), Some(b'e' | b'E')) && bytes.get(i + 1 + sign).is_some_and(u8::is_ascii_digit);
+                let mantissa = i;
+                if scientific {
+                    i += 1 + sign;
+                    while bytes.get(i).is_some_and(u8::is_ascii_digit) {
+                        i += 1;
+                    }
+                }
+                let text: Cow<str> = match scientific {
+                    true => Cow::Owned(format!("{}{}", source[start..mantissa].trim_end_matches('.'), &source[mantissa..i])),
+                    false => Cow::Borrowed(source[start..i].trim_end_matches('.')),
+                };
+                let text = match text.contains(['_', ',']) {
+                    true => Cow::Owned(text.replace('_', "").replace(',', ".")),
+                    false => text,
+                };
+                let exponent = at(i)
+                    .filter(|_| si && !scientific)
+                    .and_then(|c| Some((c.len_utf8(), prefix(c)?)))
+                    .filter(|&(len, _)| !at(i + len).is_some_and(|c| c.is_alphanumeric() || c == '_'));
+                match exponent {
+                    Some((len, exponent)) => {
+                        i += len;
+                        // Parsed with the exponent so `0.1m` is as exact as `0.0001`.
+                        TokenKind::Number(format!("{}e{}", text, exponent).parse().unwrap())
+                    }
+                    None => TokenKind::Number(text.parse().unwrap()),
+                }
+            }
+            'a'..='z' | 'A'..='Z' => {
+                let start = i;
+                loop {
+                    while bytes.get(i).is_some_and(|&c| c.is_ascii_alphanumeric() || c == b'_') {
+                        i += 1;
+                    }
+                    // A namespace, `phys.c` or `stats::median`.
+                    match (bytes.get(i), bytes.get(i + 1), bytes.get(i + 2)) {
+                        (Some(b'.'), Some(c), _) if c.is_ascii_alphabetic() => i += 1,
+                        (Some(b':'), Some(b':'), Some(c)) if c.is_ascii_alphabetic() => i += 2,
+                        _ => break,
+                    }
+                }
+                let name = &source[start..i];
+                if symbols.iter().any(|symbol| symbol == name) {
+                    TokenKind::Symbol(name.to_string())
+                } else {
+                    TokenKind::Name(name.to_string())
+                }
+            }
+            'π' => {
+                i += c.len_utf8();
+                TokenKind::Number(core::f64::consts::PI)
+            }
+            '×' | '÷' | '−' => {
+                i += c.len_utf8();
+                TokenKind::Symbol(match c {
+                    '×' => "*",
+                    '÷' => "/",
+                    _ => "-",
+                }.to_string())
+            }
+            '⁻' | '⁰' | '¹' | '²' | '³' | '⁴' | '⁵' | '⁶' | '⁷' | '⁸' | '⁹' => {
+                let negative = c == '⁻';
+                if negative {
+                    i += c.len_utf8();
+                }
+                let mut digits = String::new();
+                while let Some((digit, len)) = at(i).and_then(|c| Some((superscript(c)?, c.len_utf8()))) {
+                    digits.push(digit);
+                    i += len;
+                }
+                tokens.push(Token { kind: TokenKind::Symbol("^".to_string()), span: column..column + 1 });
+                match digits.parse::<f64>() {
+                    Ok(exponent) => TokenKind::Number(if negative { -exponent } else { exponent }),
+                    Err(_) => TokenKind::Invalid("Expected a superscript digit after '⁻'".to_string()),
+                }
+            }
+            '$' => {
+                i += 1;
+                let start = i;
+                // `$2` numbers a result rather than naming a variable.
+                let output = bytes.get(i).is_some_and(u8::is_ascii_digit);
+                while bytes.get(i).is_some_and(|&c| match output {
+                    true => c.is_ascii_digit(),
+                    false => c.is_ascii_alphabetic() || c == b'_' || (c.is_ascii_digit() && i > start),
+                }) {
+                    i += 1;
+                }
+                TokenKind::Env(source[start..i].to_string())
+            }
+            '-' if bytes.get(i + 1) == Some(&b'>') && !symbols.iter().any(|symbol| symbol == "->") => {
+                i += 2;
+                TokenKind::Arrow
+            }
+            ':' if bytes.get(i + 1) == Some(&b'=') && !symbols.iter().any(|symbol| symbol == ":=") => {
+                i += 2;
+                TokenKind::ColonEquals
+            }
+            _ => match symbols.iter().find(|symbol| source[i..].starts_with(symbol.as_str())) {
+                Some(symbol) => {
+                    i += symbol.len();
+                    TokenKind::Symbol(symbol.clone())
+                }
+                None => {
+                    i += c.len_utf8();
+                    match c {
+                        '(' | '[' => depth += 1,
+                        ')' | ']' => depth = depth.saturating_sub(1),
+                        _ => {}
+                    }
+                    match c {
+                        '(' => TokenKind::Open,
+                        ')' => TokenKind::Close,
+                        '[' => TokenKind::OpenBracket,
+                        ']' => TokenKind::CloseBracket,
+                        ',' => TokenKind::Comma,
+                        ';' if comma && depth > 0 => TokenKind::Comma,
+                        ';' => TokenKind::Semicolon,
+                        '=' => TokenKind::Equals,
+                        c => TokenKind::Unknown(c),
+                    }
+                }
+            }
+        };
+        tokens.push(Token { kind, span: column..columns.end(i) });
+    }
+
+    tokens.push(Token { kind: TokenKind::End, span: end..end + 1 });
+    tokens
+}
+
+/// The columns of byte offsets of the source, asked for in increasing
+/// order so each char is counted once.
+struct Columns<'a> {
+    source: &'a str,
+    columns: Option<&'a [usize]>,
+    /// The last byte offset asked for and the number of chars before it.
+    byte: usize,
+    chars: usize,
+}
+
+impl Columns<'_> {
+    fn index(&mut self, byte: usize) -> usize {
+        self.chars += self.source[self.byte..byte].chars().count();
+        self.byte = byte;
+        self.chars
+    }
+
+    /// The column of the char at `byte`.
+    fn start(&mut self, byte: usize) -> usize {
+        let index = self.index(byte);
+        self.columns.map_or(index, |columns| columns[index])
+    }
+
+    /// The column just after the char before `byte`.
+    fn end(&mut self, byte: usize) -> usize {
+        let index = self.index(byte);
+        self.columns.map_or(index, |columns| columns[index - 1] + 1)
+    }
+}
+
+/// The power of ten an SI prefix stands for.
+fn prefix(c: char) -> Option<i32> {
+    let exponent = match c {
+        'f' => -15,
+        'p' => -12,
+        'n' => -9,
+        'u' | 'µ' => -6,
+        'm' => -3,
+        'k' => 3,
+        'M' => 6,
+        'G' => 9,
+        'T' => 12,
+        _ => return None,
+    };
+    Some(exponent)
+}
+
+/// The digit a superscript digit stands for.
+fn superscript(c: char) -> Option<char> {
+    let index = "⁰¹²³⁴⁵⁶⁷⁸⁹".chars().position(|digit| digit == c)?;
+    char::from_digit(index as u32, 10)
+}
+
+/// Moves `i` over the decimal digits of a number starting at `start`, a
+/// `_` between two digits separates groups like in `1_000_000`.
+fn digits(bytes: &[u8], start: usize, i: &mut usize) {
+    let digit = |i: usize| bytes.get(i).is_some_and(u8::is_ascii_digit);
+    while digit(*i) || (bytes.get(*i) == Some(&b'_') && *i > start && bytes[*i - 1] != b'.' && digit(*i + 1)) {
+        *i += 1;
+    }
+}
+
+/// Rewrites the formatting of numbers copied from documents, returning the
+/// text with the column each of its chars came from:
+///
+/// - Unicode minus and dashes become `-`, `×` and `÷` become `*` and `/`.
+/// - Non-breaking and thin spaces are dropped, so `1 234` is 1234.
+/// - Currency symbols next to a number are dropped, `$ 1,234.50` is 1234.5.
+/// - The `,` of a number grouped in threes like `1,234,567` is a thousands
+///   separator. Inside the parentheses of a call or the brackets of a list
+///   it could also separate arguments, so for `max(1,234)` the column of
+///   the ambiguous number and a message are returned as well: arguments
+///   need a space after the comma, `max(1, 234)`. With `comma`, where `,` is
+///   the decimal point, the separator is a `.` and never ambiguous,
+///   `1.234,50`.
+/// - Tabs between numbers separate the cells of a row copied from a
+///   spreadsheet, like arguments, `mean(1.5<tab>2)`, or a `;` with `comma`. A line that is only
+///   such a row is a list of the cells, and the commas in the cells are
+///   thousands separators.
+pub(crate) fn sanitize(expr: &str, comma: bool) -> (String, Vec<usize>, Option<(usize, String)>) {
+    let source: Vec<char> = expr.chars().collect();
+    let digit = |i: usize| source.get(i).is_some_and(char::is_ascii_digit);
+    let next = |i: usize| source[i + 1..].iter().copied().find(|&c| c != ' ');
+    let prev = |i: usize| source[..i].iter().copied().rev().find(|&c| c != ' ');
+    let thousands = if comma { '.' } else { ',' };
+    const CURRENCIES: [char; 5] = ['$', '€', '£', '¥', '₹'];
+
+    // The tabs that separate cells, the first of each run of them, and
+    // whether the whole line is a row.
+    let blank = |c: &&char| **c == ' ' || **c == '\t';
+    let cell = |i: usize| {
+        let before = source[..i].iter().rev().find(|c| !blank(c));
+        let after = source[i + 1..].iter().find(|c| !blank(c));
+        before.is_some_and(|&c| c.is_ascii_digit() || c == '%' || CURRENCIES.contains(&c))
+            && after.is_some_and(|&c| c.is_ascii_digit() || ['.', '-', '\u{2212}'].contains(&c) || CURRENCIES.contains(&c))
+    };
+    let cells: Vec<bool> = (0..source.len()).map(|i| source[i] == '\t' && (i == 0 || source[i - 1] != '\t') && cell(i)).collect();
+    let row = cells.contains(&true) && source.iter().enumerate().all(|(i, &c)| match c {
+        '\t' => cells[i] || source[..i].last() == Some(&'\t') || source[..i].iter().all(|&c| blank(&&c)) || source[i..].iter().all(|&c| blank(&&c)),
+        c => c.is_ascii_digit() || ['.', ',', '-', '\u{2212}', '%', ' ', '\u{00A0}', '\u{2009}', '\u{202F}'].contains(&c) || CURRENCIES.contains(&c),
+    });
+    let in_cell = |start: usize, end: usize| {
+        source[..start].iter().rev().find(|c| **c != ' ') == Some(&'\t') || source[end..].iter().find(|c| **c != ' ') == Some(&'\t')
+    };
+
+    // The thousands separators, and whether each open parenthesis or bracket
+    // separates arguments.
+    let mut separators = vec![false; source.len()];
+    let mut ambiguous = None;
+    let mut arguments = vec![];
+    let mut i = 0;
+    while i < source.len() {
+        match source[i] {
+            '(' => arguments.push(i > 0 && source[..i].iter().rev().take_while(|&&c| c.is_ascii_alphanumeric() || c == '_').last().is_some_and(char::is_ascii_alphabetic)),
+            '[' => arguments.push(true),
+            ')' | ']' => {
+                arguments.pop();
+            }
+            _ => {}
+        }
+        let continues = i > 0 && (source[i - 1].is_ascii_alphanumeric() || ['.', ',', '_'].contains(&source[i - 1]));
+        if !digit(i) || continues {
+            i += 1;
+            continue;
+        }
+        let (mut groups, mut commas) = (vec![], vec![]);
+        loop {
+            let start = i;
+            while digit(i) {
+                i += 1;
+            }
+            groups.push(i - start);
+            if source.get(i) != Some(&thousands) || !digit(i + 1) {
+                break;
+            }
+            commas.push(i);
+            i += 1;
+        }
+        if commas.is_empty() || groups[0] > 3 || groups[1..].iter().any(|&n| n != 3) {
+            continue;
+        }
+        if !comma && arguments.last() == Some(&true) && !in_cell(commas[0] - groups[0], i) {
+            let number: String = source[commas[0] - groups[0]..i].iter().collect();
+            ambiguous.get_or_insert((commas[0] - groups[0], format!("'{}' could be {} or separate arguments, write it without commas or with a space after them",
+                number, number.replace(',', ""))));
+        } else {
+            commas.into_iter().for_each(|i| separators[i] = true);
+        }
+    }
+
+    let (mut text, mut columns) = (String::new(), vec![]);
+    let mut push = |i: usize, c: char| {
+        text.push(c);
+        columns.push(i);
+    };
+    if row {
+        push(0, '[');
+    }
+    for (i, &c) in source.iter().enumerate() {
+        match c {
+            '\u{2212}' | '\u{2013}' | '\u{2014}' => push(i, '-'),
+            '×' => push(i, '*'),
+            '÷' => push(i, '/'),
+            '\u{00A0}' | '\u{2009}' | '\u{202F}' => {}
+            c if CURRENCIES.contains(&c)
+                && (next(i).is_some_and(|c| c.is_ascii_digit() || c == '.') || prev(i).is_some_and(|c| c.is_ascii_digit())) => {}
+            _ if separators[i] => {}
+            '\t' if cells[i] => push(i, if comma { ';' } else { ',' }),
+            _ => push(i, c),
+        }
+    }
+    if row {
+        push(source.len().saturating_sub(1), ']');
+    }
+    (text, columns, ambiguous)
+}
@@ -0,0 +1,115 @@
+//! An evaluator for arithmetic expressions, the library behind the
+//! `calculator` REPL.
+//!
+//! ```
+//! use calculator::{Calculator, ErrorKind, Function, Value};
+//!
+//! let mut calc = Calculator::new();
+//! calc.register_function("mix(a, b, t)", Function::ThreeArg(|a, b, t| a + (b - a) * t)).unwrap();
+//! calc.register_constant("tau", std::f64::consts::TAU).unwrap();
+//! assert_eq!(calc.eval("mix(0, 10, 0.5)".to_string()), Ok(Value::Number(5.)));
+//! assert_eq!(calc.eval("tau > 6".to_string()), Ok(Value::Bool(true)));
+//!
+//! let expr = "sqrt(2 +)";
+//! let err = calc.eval(expr.to_string()).unwrap_err();
+//! assert_eq!(err.kind, ErrorKind::Syntax { pos: 8, expected: Some("a number, a name or '('") });
+//! println!("{}", err.render(expr));
+//! ```
+//!
+//! Without the default `std` feature the parser and the evaluator are
+//! `no_std`, they need nothing but `alloc`, for formulas on a
+//! microcontroller. Reading files, environment variables and the clock
+//! need `std`.
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+
+mod lexer;
+mod parser;
+mod bigint;
+mod cache;
+mod calculator;
+mod calculus;
+#[cfg(not(feature = "std"))]
+mod clock;
+mod date;
+mod diagnostic;
+mod encoding;
+mod exact;
+mod expression;
+mod functions;
+#[cfg(feature = "std")]
+mod import;
+mod operator;
+mod plot;
+#[cfg(not(feature = "std"))]
+mod prelude;
+mod program;
+mod rational;
+mod interval;
+mod limits;
+#[cfg(not(feature = "std"))]
+mod math;
+mod numeric;
+#[cfg(feature = "matrix")]
+mod matrix;
+mod resolver;
+#[cfg(feature = "special-functions")]
+mod special;
+mod stats;
+mod symbolic;
+#[cfg(feature = "units")]
+mod units;
+mod value;
+#[cfg(feature = "std")]
+mod watch;
+pub mod ast;
+#[cfg(feature = "decimal")]
+pub mod decimal;
+pub mod format;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use crate::bigint::BigInt;
+pub use crate::calculator::{exponent, AngleUnit, Arithmetic, Bindings, Calculator, Function};
+pub use crate::date::{Date, Duration};
+#[cfg(feature = "decimal")]
+pub use crate::decimal::Decimal;
+pub use crate::diagnostic::{Diagnostic, ErrorKind, Label};
+pub use crate::expression::Expression;
+#[cfg(feature = "std")]
+pub use crate::import::Importer;
+pub use crate::interval::Interval;
+pub use crate::limits::Limits;
+pub use crate::numeric::Numeric;
+#[cfg(feature = "matrix")]
+pub use crate::matrix::Matrix;
+pub use crate::operator::{Assoc, Entry, Fixity, Operator, OperatorTable, PRESETS};
+pub use crate::plot::{Histogram, Plot};
+pub use crate::program::Program;
+pub use crate::rational::Rational;
+pub use crate::resolver::Resolver;
+pub use crate::stats::Stats;
+pub use crate::symbolic::{Formula, Lambda};
+#[cfg(feature = "units")]
+pub use crate::units::{Quantity, Unit};
+pub use crate::value::Value;
+#[cfg(feature = "std")]
+pub use crate::watch::Watch;
+
+/// Whether the platform has a clock. `wasm32-unknown-unknown` has none, and
+/// reading the time panics there. Without `std` the clock is `clock`, which
+/// doesn't tick.
+pub(crate) const CLOCK: bool = cfg!(feature = "std") && !cfg!(all(target_arch = "wasm32", target_os = "unknown"));
+
+#[cfg(feature = "std")]
+pub(crate) use std::collections::{HashMap, HashSet};
+// Without `std` there is no random state to seed a hasher with.
+#[cfg(not(feature = "std"))]
+pub(crate) use alloc::collections::{BTreeMap as HashMap, BTreeSet as HashSet};
+
+#[cfg(feature = "std")]
+pub(crate) use std::time::{Instant, SystemTime, UNIX_EPOCH};
+#[cfg(not(feature = "std"))]
+pub(crate) use crate::clock::{Instant, SystemTime, UNIX_EPOCH};
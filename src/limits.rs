@@ -0,0 +1,28 @@
+use core::time::Duration;
+
+/// Bounds on the size of the expressions `Calculator::eval` accepts and on
+/// the work of evaluating them, so untrusted input can't overflow the stack
+/// or run forever.
+///
+/// Every limit is off by default. Exceeding one fails with a `Limit` error
+/// pointing at where the expression went over it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Limits {
+    /// Chars in the expression, spaces included.
+    pub max_length: Option<usize>,
+    /// Numbers, names, operators, parentheses and commas.
+    pub max_tokens: Option<usize>,
+    /// Parentheses nested inside each other, including those of calls.
+    pub max_depth: Option<usize>,
+    /// Arguments of a single call.
+    pub max_arguments: Option<usize>,
+    /// Calls of defined functions and lambdas nested inside each other,
+    /// never more than 100.
+    pub max_call_depth: Option<usize>,
+    /// Operators applied, functions called and terms of sums added in one
+    /// evaluation.
+    pub max_steps: Option<usize>,
+    /// Time one evaluation can take, not checked on platforms without a
+    /// clock.
+    pub timeout: Option<Duration>,
+}
@@ -1,25 +1,311 @@
-mod parser;
-mod calculator;
+mod config;
+mod cli;
+mod helper;
+mod suite;
+mod interrupt;
+mod grid;
+mod script;
+mod rates;
+mod json;
+mod serve;
+mod rpn;
+mod clipboard;
+mod plugin;
 
-use rustyline::{DefaultEditor, Result};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::{BufRead, IsTerminal, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use rustyline::{Cmd, Editor, KeyEvent, Result};
+use rustyline::history::DefaultHistory;
 use rustyline::error::ReadlineError;
-use crate::calculator::Calculator;
+use calculator::ast::Expr;
+use calculator::format::{self, Formatter, Notation, Rounding, Template};
+use calculator::{AngleUnit, Arithmetic, Calculator, Diagnostic, ErrorKind, Fixity, Function, Importer, Operator, OperatorTable, Value};
+use crate::cli::{Command, Options, USAGE};
+use crate::config::Config;
+use crate::helper::CalcHelper;
+use crate::rpn::Stack;
+
+/// The name of the previous result.
+const ANS: &str = "ans";
+
+/// `:time` evaluates an expression this many times, or as many as fit in
+/// `TIME_BUDGET`.
+const TIME_RUNS: usize = 1000;
+const TIME_BUDGET: Duration = Duration::from_secs(1);
+
+/// The workspace the REPL starts in, see `Session::workspace`.
+const MAIN_WORKSPACE: &str = "main";
+
+/// Digits after the point of `:mode decimal` until `:precision` changes it.
+#[cfg(feature = "decimal")]
+const DECIMAL_PLACES: u32 = 28;
+
+/// REPL state that lives outside of the calculator.
+#[derive(Default)]
+struct Session {
+    /// Set by `:fmt`, results are printed with `{}` otherwise.
+    template: Option<Template>,
+    /// Every result so far, `out(1)` or `$1` is the first.
+    outputs: Rc<RefCell<Vec<Value>>>,
+    /// Results named with `:tag`, in the order they were tagged.
+    tags: Vec<(String, f64)>,
+    /// Results stored with `:sto`, which unlike tags `:clear` keeps.
+    registers: BTreeMap<String, Value>,
+    /// Set by `:base`, integer results are printed in this base.
+    base: Option<u32>,
+    /// Set by `:mode programmer`, integer results are printed in decimal,
+    /// hex and binary at once.
+    programmer: bool,
+    /// Set by `:echo all`, every statement of `a = 2; b = 3; a*b` prints
+    /// its value rather than just the last.
+    echo_all: bool,
+    /// Set by `:mode rpn`, lines are words run against this stack.
+    rpn: Option<Stack>,
+    /// Loaded at startup, listed by `:plugins`.
+    plugins: Vec<plugin::Plugin>,
+    /// Set by `--session` or `auto_session`, the session restored at
+    /// startup and saved on exit, see `config::session_path`.
+    name: Option<String>,
+    /// Where the sessions are kept, listed by `:session list`.
+    sessions: Option<std::path::PathBuf>,
+    /// Set by `:workspace name`, the workspace of the calculator in use,
+    /// `MAIN_WORKSPACE` until then.
+    workspace: Option<String>,
+    /// The calculators of the other workspaces, each with variables and
+    /// functions of its own.
+    workspaces: BTreeMap<String, Calculator>,
+    /// Loads the files of `import "finance.calc"` lines.
+    importer: Importer,
+    /// Set by `:quit`.
+    quit: bool,
+}
+
+const HELP: &str = "\
+Expressions are evaluated as they are typed, `:funcs` and `:consts` list what they can use.
+
+:help                 this text
+:funcs [namespace]    functions with their parameters, or those of a namespace like stats
+:use namespace        call the functions of a namespace without it, median for stats::median
+:consts [namespace]   constants, or those of a namespace like phys
+:vars                 variables and tags
+:clear                forget variables, defined functions, tags, ans and the results so far
+:precision [digits]  print results with that many digits, or as many as needed
+:format auto|fixed|sci|eng
+                      print results in fixed, scientific or engineering notation
+:group on|off         separate thousands with commas
+:rounding half-up|half-even|floor|ceil
+                      how round and the precision of results round
+:locale en|de|...     read and print numbers with a decimal point or, like de, a decimal comma
+:fmt [template]       print results through a printf-style template like \"%.2f\"
+:base hex|oct|bin|dec print integer results in another base
+:mode programmer|normal
+                      print integer results in decimal, hex and binary at once
+:mode rpn|algebraic   enter lines like 3 4 + 5 * against a stack, with drop, swap and dup
+:mode deg|rad         measure the angles of trigonometric functions in degrees or radians
+:mode decimal|fraction|bigint|float
+                      calculate exactly in decimal, fractions or integers of any size,
+                      or with floating point
+:mode strict|lenient  fail on division by zero and other results that aren't finite, or give inf and NaN
+:seed n               restart the random numbers from a seed
+:rates [update source]
+                      the exchange rates, or load them from a file or URL
+:tag name, :tags      name the last result, list the names
+:sto name             store the last result in a memory register, which :clear keeps
+:mem [clear]          list the registers, or clear them
+:plugins              the function packs loaded from ~/.config/calculator/plugins
+:copy                 put the last result on the clipboard, as it is printed
+:save file, :load file
+                      write the variables, defined functions and settings to a file, or restore them
+:session [list|delete name]
+                      the session saved on exit, the saved sessions, or forget one
+:workspace [name]     the workspaces, or switch to one with variables and functions of its own
+:workspace copy from name
+                      copy a variable or function from another workspace into this one
+:history              the results so far, out(n) or $n is the nth
+:echo all|last        print the value of every statement of a; b; c or just the last
+:explain expression   print each operation in the order it is performed
+:exact [expression]   the exact decimal value of a result
+:bits expression      the IEEE-754 bit pattern of a result
+:plot expr, x, a, b   a graph of expr for x from a to b, as plot(expr, x, a, b) draws
+:latex expression     the expression and its result in LaTeX
+:tree expression      how the expression is parsed, as a tree, without evaluating it
+:dot expression [> file.dot]
+                      the tree of the expression as a Graphviz graph, or written to a file
+:operators [preset]   the operators by precedence, or switch to a preset
+:stats [reset]        a summary of the session
+:time expression      how long the expression takes to evaluate, the min and mean of many runs
+:quit                 exit";
 
 fn main() -> Result<()> {
-    let mut repl = DefaultEditor::new()?;
-    let mut calc = Calculator::new();
+    let options = Options::parse(std::env::args().skip(1)).unwrap_or_else(|msg| {
+        eprintln!("{}", msg);
+        std::process::exit(2);
+    });
+    if options.help {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
+    let mut config = Config::load().unwrap_or_else(|msg| {
+        eprintln!("{}", msg);
+        Config::default()
+    });
+    config.edit_mode = options.edit_mode.unwrap_or(config.edit_mode);
+    config.history_ignore_dups &= !options.history_dups;
+    config.history_auto_add &= !options.no_auto_history;
+
+    let plugins = match config::config_dir().filter(|_| !options.no_plugins) {
+        Some(dir) => plugin::load_all(&dir.join("plugins")),
+        None => vec![],
+    };
+    let calculator = || {
+        let mut calc = Calculator::new();
+        calc.set_env_vars(options.env);
+        calc.set_sanitize(options.sanitize);
+        calc.set_si_prefixes(options.si);
+        calc.set_warnings(options.warn);
+        calc.set_strict(options.strict);
+        calc.set_operators(OperatorTable::preset(&config.operators).unwrap());
+        rates::load(&mut calc);
+        for plugin in &plugins {
+            plugin.register(&mut calc);
+        }
+        calc
+    };
+    let mut calc = calculator();
+    let importer = Importer::new(config.import_path.iter().cloned().chain(config::config_dir().map(|dir| dir.join("lib"))).collect());
+
+    if let Some(command) = &options.command {
+        let ok = match command {
+            Command::Test(paths) => suite::run(&mut calc, paths),
+            Command::Grid(path) => grid::run(&mut calc, path),
+            Command::Run(path) => script::run(&mut calc, &importer, path, options.quiet, options.check),
+            Command::Eval(exprs) if options.json => json::run(&mut calc, exprs.iter().cloned()),
+            Command::Eval(exprs) => batch(&mut calc, exprs.iter().cloned(), options.copy),
+            Command::Serve => serve::run(calculator, options.port.unwrap_or(8080), options.allow_origin.clone()),
+        };
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    if options.porcelain {
+        porcelain(&mut calc, std::io::stdin().lock(), &mut std::io::stdout());
+        return Ok(());
+    }
+    if !std::io::stdin().is_terminal() {
+        let lines = std::io::stdin().lock().lines().map_while(std::io::Result::ok);
+        let ok = if options.json { json::run(&mut calc, lines) } else { batch(&mut calc, lines, options.copy) };
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    if let Some(dir) = config::config_dir().filter(|_| !options.no_init) {
+        script::init(&mut calc, &importer, &dir.join("init.calc"));
+    }
+
+    let mut repl: Editor<CalcHelper, DefaultHistory> = Editor::with_config(rustyline::Config::builder()
+        .edit_mode(config.edit_mode)
+        .history_ignore_dups(config.history_ignore_dups)?
+        .history_ignore_space(config.history_ignore_space)
+        .max_history_size(config.history_size)?
+        .build())?;
+    let history = config::data_dir().filter(|_| !options.no_history).map(|dir| dir.join("history"));
+    if let Some(path) = history.as_ref().filter(|path| path.exists()) {
+        if let Err(err) = repl.load_history(path) {
+            eprintln!("Failed to read '{}': {}", path.display(), err);
+        }
+    }
+    // https://no-color.org, set to anything but empty.
+    let color = !options.no_color && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()) && std::io::stdout().is_terminal();
+    repl.set_helper(Some(CalcHelper::new(&calc).with_color(color)));
+    interrupt::install(calc.interrupt_flag());
+    if config.history_search {
+        repl.bind_sequence(KeyEvent::ctrl('R'), Cmd::ReverseSearchHistory);
+        repl.bind_sequence(KeyEvent::ctrl('S'), Cmd::ForwardSearchHistory);
+    } else {
+        repl.bind_sequence(KeyEvent::ctrl('R'), Cmd::Noop);
+        repl.bind_sequence(KeyEvent::ctrl('S'), Cmd::Noop);
+    }
+
+    let name = options.session.clone().or_else(|| config.auto_session.then(|| "default".to_string()));
+    let mut session = Session { plugins: plugins.clone(), name, sessions: config::sessions_dir(), importer, ..Session::default() };
+    session.register_outputs(&mut calc);
+    if let Err(msg) = session.restore(&mut calc) {
+        eprintln!("{}", msg);
+    }
 
-    loop {
-        let line = repl.readline("> ");
+    // The lines so far of an expression that continues on the next line.
+    let mut pending = String::new();
+
+    'repl: loop {
+        let line = repl.readline(if pending.is_empty() { "> " } else { ".. " });
         match line {
-            Ok(expr) => {
-                repl.add_history_entry(expr.as_str()).unwrap();
+            Ok(input) => {
+                // A bracketed paste can bring in several lines at once, each
+                // of them is its own expression.
+                for expr in input.lines().filter(|line| !line.trim().is_empty()) {
+                    if config.history_auto_add {
+                        repl.add_history_entry(expr).unwrap();
+                    }
+
+                    if !pending.is_empty() {
+                        pending = format!("{} {}", pending, expr.trim());
+                    } else if let Some(command) = expr.trim_start().strip_prefix(':') {
+                        if let Err(msg) = session.command(&mut calc, command) {
+                            println!("{}", msg);
+                        }
+                        if session.quit {
+                            break 'repl;
+                        }
+                        continue;
+                    } else if let Some(file) = Importer::statement(expr) {
+                        match session.importer.import(&mut calc, file, None) {
+                            Ok(names) => println!("{}", names.join(", ")),
+                            Err(err) => println!("{}", err.message),
+                        }
+                        continue;
+                    } else if session.rpn.is_some() {
+                        if let Err(msg) = session.rpn(&mut calc, expr) {
+                            println!("{}", msg);
+                        }
+                        continue;
+                    } else {
+                        pending = session.continuation(&calc, expr);
+                    }
 
-                match calc.eval(expr) {
-                    Ok(value) => println!("{}", value),
-                    Err(msg) => println!("{}", msg)
+                    let expr = &std::mem::take(&mut pending);
+                    match session.eval(&mut calc, expr) {
+                        Ok(values) => {
+                            for value in values {
+                                session.remember(&mut calc, value.clone());
+                                let display = session.display(&calc, value);
+                                match color {
+                                    true => println!("\x1b[32m{}\x1b[0m", display),
+                                    false => println!("{}", display),
+                                }
+                            }
+                        }
+                        // `1 +` or `(1 + 2` continues on the next line.
+                        Err(err) if err.kind == ErrorKind::Incomplete => {
+                            pending = expr.clone();
+                            continue;
+                        }
+                        Err(err) if color => println!("{}", err.render_ansi(expr)),
+                        Err(err) => println!("{}", err.render(expr))
+                    }
+                    for warning in calc.warnings() {
+                        match color {
+                            true => println!("warning: {}", warning.render_ansi(expr)),
+                            false => println!("warning: {}", warning.render(expr)),
+                        }
+                    }
                 }
+                // Completes and hints the names defined by these lines too.
+                repl.set_helper(Some(CalcHelper::new(&calc).with_color(color)));
             }
+            // Cancels an expression that was continued.
+            Err(ReadlineError::Interrupted) if !pending.is_empty() => pending.clear(),
             Err(ReadlineError::Interrupted) => {
                 println!("CTRL-C");
                 break;
@@ -35,22 +321,1724 @@ fn main() -> Result<()> {
         }
     }
 
+    if let Err(msg) = session.persist(&calc) {
+        eprintln!("{}", msg);
+    }
+    if let Some(path) = &history {
+        let saved = std::fs::create_dir_all(path.parent().unwrap()).and_then(|_| repl.save_history(path).map_err(std::io::Error::other));
+        if let Err(err) = saved {
+            eprintln!("Failed to write '{}': {}", path.display(), err);
+        }
+    }
+
+    Ok(())
+}
+
+impl Session {
+    fn command(&mut self, calc: &mut Calculator, line: &str) -> std::result::Result<(), String> {
+        let (name, arg) = match line.split_once(' ') {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (line.trim(), ""),
+        };
+
+        match name {
+            "help" => {
+                println!("{}", HELP);
+                Ok(())
+            }
+            "funcs" => {
+                // Like `:consts`, `:funcs stats` lists the `stats::` namespace.
+                let names: Vec<(String, String)> = calc.signatures()
+                    .map(|(name, signature)| (name.to_string(), signature.to_string()))
+                    .chain(calc.definitions().map(|(name, signature)| (name.to_string(), signature)))
+                    .collect();
+                let mut signatures: Vec<&str> = names.iter()
+                    .filter(|(name, _)| match arg {
+                        "" => !name.contains("::"),
+                        _ => name.strip_prefix(arg).is_some_and(|rest| rest.starts_with("::")),
+                    })
+                    .map(|(_, signature)| signature.as_str())
+                    .collect();
+                if signatures.is_empty() {
+                    return Err(format!("There are no functions in '{}'", arg));
+                }
+                signatures.sort();
+                for signature in signatures {
+                    println!("{}", signature);
+                }
+                if arg.is_empty() {
+                    let mut namespaces: Vec<_> = names.iter().filter_map(|(name, _)| name.split_once("::")).map(|(namespace, _)| namespace).collect();
+                    namespaces.sort();
+                    namespaces.dedup();
+                    for namespace in namespaces {
+                        println!("{}::*  (:funcs {}, :use {})", namespace, namespace, namespace);
+                    }
+                }
+                Ok(())
+            }
+            "use" => {
+                if arg.is_empty() {
+                    return Err("Usage: :use namespace".to_string());
+                }
+                let (imported, kept) = calc.use_namespace(arg)?;
+                println!("{}", imported.join(", "));
+                if !kept.is_empty() {
+                    println!("Kept {}, which are already functions", kept.join(", "));
+                }
+                Ok(())
+            }
+            "consts" => {
+                // `:consts phys` lists the `phys.` namespace, `:consts` the
+                // constants outside of one and the namespaces.
+                let mut constants: Vec<_> = calc.constants()
+                    .filter(|(name, _)| match arg {
+                        "" => !name.contains('.'),
+                        _ => name.strip_prefix(arg).is_some_and(|rest| rest.starts_with('.')),
+                    })
+                    .collect();
+                if constants.is_empty() {
+                    return Err(format!("There are no constants in '{}'", arg));
+                }
+                constants.sort_by(|a, b| a.0.cmp(b.0));
+                for (name, value) in constants {
+                    println!("{} = {}", name, value);
+                }
+                if arg.is_empty() {
+                    let mut namespaces: Vec<_> = calc.constants().filter_map(|(name, _)| name.split_once('.')).map(|(namespace, _)| namespace).collect();
+                    namespaces.sort();
+                    namespaces.dedup();
+                    for namespace in namespaces {
+                        println!("{}.*  (:consts {})", namespace, namespace);
+                    }
+                }
+                Ok(())
+            }
+            "vars" => {
+                let mut vars: Vec<_> = calc.vars().collect();
+                vars.sort_by(|a, b| a.0.cmp(b.0));
+                for (name, value) in vars {
+                    println!("{} = {}", name, self.display(calc, value));
+                }
+                let mut formulas: Vec<_> = calc.formulas().map(|(name, formula)| (name.to_string(), formula)).collect();
+                formulas.sort();
+                for (name, formula) in formulas {
+                    match calc.eval(name.clone()) {
+                        Ok(value) => println!("{} := {} = {}", name, formula, self.display(calc, value)),
+                        Err(_) => println!("{} := {}", name, formula),
+                    }
+                }
+                self.command(calc, "tags")
+            }
+            "clear" => {
+                calc.clear_vars();
+                calc.clear_definitions();
+                for (name, _) in self.tags.drain(..) {
+                    calc.undefine(&name);
+                }
+                calc.undefine(ANS);
+                self.outputs.borrow_mut().clear();
+                Ok(())
+            }
+            "quit" | "q" => {
+                self.quit = true;
+                Ok(())
+            }
+            "fmt" => {
+                self.template = if arg.is_empty() {
+                    None
+                } else {
+                    let arg = arg.strip_prefix('"').and_then(|arg| arg.strip_suffix('"')).unwrap_or(arg);
+                    Some(Template::parse(arg)?)
+                };
+                Ok(())
+            }
+            "time" => {
+                let (min, mean, runs) = time(calc, arg).map_err(|err| err.render(arg))?;
+                println!("min {:?}, mean {:?} over {} runs", min, mean, runs);
+                Ok(())
+            }
+            "stats" => {
+                match arg {
+                    "" => println!("{}", calc.stats()),
+                    "reset" => calc.reset_stats(),
+                    _ => return Err("Usage: :stats [reset]".to_string()),
+                }
+                Ok(())
+            }
+            "operators" => {
+                if !arg.is_empty() {
+                    let table = OperatorTable::preset(arg)
+                        .ok_or(format!("Unknown preset '{}', expected one of {}", arg, calculator::PRESETS.join(", ")))?;
+                    calc.set_operators(table);
+                }
+                for entry in calc.operators().entries() {
+                    let (usage, grouping) = match entry.operator {
+                        Operator::Prefix(_) => (format!("{}x", entry.symbol), "prefix".to_string()),
+                        Operator::Postfix(_) | Operator::FalliblePostfix(_) => (format!("x{}", entry.symbol), "postfix".to_string()),
+                        Operator::Comparison(_) => (format!("x {} y", entry.symbol), "comparison".to_string()),
+                        Operator::Logical(_) => (format!("x {} y", entry.symbol), "logical".to_string()),
+                        Operator::LogicalPrefix(_) => (format!("{} x", entry.symbol), "logical".to_string()),
+                        Operator::Infix(assoc, _) => (format!("x {} y", entry.symbol), format!("{:?}", assoc).to_lowercase()),
+                    };
+                    println!("{:>4}  {:<8} {}", entry.precedence, usage, grouping);
+                }
+                Ok(())
+            }
+            "tag" => {
+                let value = self.last_number(calc, "tag")?.ok_or("There is no result to tag yet")?;
+                check_name(calc, "tag", arg)?;
+                self.registers.remove(arg);
+                self.tags.retain(|(name, _)| name != arg);
+                self.tags.push((arg.to_string(), value));
+                calc.define(arg, value);
+                Ok(())
+            }
+            "sto" => {
+                let value = self.outputs.borrow().last().cloned().ok_or("There is no result to store yet")?;
+                check_name(calc, "sto", arg)?;
+                self.tags.retain(|(name, _)| name != arg);
+                calc.define(arg, value.clone());
+                self.registers.insert(arg.to_string(), value);
+                Ok(())
+            }
+            "plugins" => {
+                if self.plugins.is_empty() {
+                    println!("No plugins are loaded, they are read from {}", config::config_dir().unwrap_or_default().join("plugins").display());
+                }
+                for plugin in &self.plugins {
+                    let signatures: Vec<&str> = plugin.functions.iter().map(|(signature, _)| signature.as_str()).collect();
+                    println!("{} ({}): {}", plugin.name, plugin.path.display(), signatures.join(", "));
+                }
+                Ok(())
+            }
+            "copy" => {
+                let value = self.outputs.borrow().last().cloned().ok_or("There is no result to copy yet")?;
+                let text = self.display(calc, value);
+                let with = clipboard::copy(&text)?;
+                println!("Copied {} with {}", text, with);
+                Ok(())
+            }
+            "save" if arg.is_empty() => Err("Usage: :save file".to_string()),
+            "save" => std::fs::write(arg, self.save(calc)).map_err(|err| format!("Failed to write '{}': {}", arg, err)),
+            "load" if arg.is_empty() => Err("Usage: :load file".to_string()),
+            "load" => {
+                let src = std::fs::read_to_string(arg).map_err(|err| format!("Failed to read '{}': {}", arg, err))?;
+                self.load(calc, arg, &src)
+            }
+            "session" => self.session(arg),
+            "workspace" => self.workspace(calc, arg),
+            "mem" => match arg {
+                "" => {
+                    for (name, value) in &self.registers {
+                        println!("{} = {}", name, self.display(calc, value.clone()));
+                    }
+                    Ok(())
+                }
+                "clear" => {
+                    for name in std::mem::take(&mut self.registers).keys() {
+                        calc.undefine(name);
+                    }
+                    Ok(())
+                }
+                _ => Err("Usage: :mem [clear]".to_string()),
+            },
+            "history" => {
+                for (i, value) in self.outputs.borrow().iter().enumerate() {
+                    println!("out[{}] = {}", i + 1, self.display(calc, value.clone()));
+                }
+                Ok(())
+            }
+            "tags" => {
+                for (name, value) in &self.tags {
+                    println!("{} = {}", name, self.display(calc, Value::Number(*value)));
+                }
+                Ok(())
+            }
+            "explain" => {
+                let (value, steps) = calc.explain(arg.to_string()).map_err(|err| err.render(arg))?;
+                for step in steps {
+                    println!("{}", step);
+                }
+                self.remember(calc, value);
+                Ok(())
+            }
+            "exact" => {
+                let value = match arg {
+                    "" => self.last_number(calc, "show")?.ok_or("There is no result yet, use :exact expression")?,
+                    _ => number(calc, arg)?,
+                };
+                println!("shortest  {:?}", value);
+                println!("exact     {}", format::exact(value));
+                println!("hex       {}", format::hex(value));
+                Ok(())
+            }
+            #[cfg(feature = "decimal")]
+            "precision" if matches!(calc.arithmetic(), Arithmetic::Decimal { .. }) => {
+                let places = arg.parse().ok().filter(|&places| places <= calculator::decimal::MAX_PLACES)
+                    .ok_or(format!("Usage: :precision digits, from 0 to {} in decimal mode", calculator::decimal::MAX_PLACES))?;
+                calc.set_arithmetic(Arithmetic::Decimal { places });
+                Ok(())
+            }
+            "precision" => {
+                let precision = match arg {
+                    "" => None,
+                    _ => Some(arg.parse().ok().filter(|&digits| digits <= 17)
+                        .ok_or("Usage: :precision [digits], from 0 to 17")?),
+                };
+                calc.set_formatter(Formatter { precision, ..calc.formatter() });
+                Ok(())
+            }
+            "format" => {
+                let notation = match arg {
+                    "auto" => Notation::Auto,
+                    "fixed" => Notation::Fixed,
+                    "sci" => Notation::Scientific,
+                    "eng" => Notation::Engineering,
+                    _ => return Err("Usage: :format auto|fixed|sci|eng".to_string()),
+                };
+                calc.set_formatter(Formatter { notation, ..calc.formatter() });
+                Ok(())
+            }
+            "group" => {
+                let grouping = match arg {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err("Usage: :group on|off".to_string()),
+                };
+                calc.set_formatter(Formatter { grouping, ..calc.formatter() });
+                Ok(())
+            }
+            "rounding" => {
+                let rounding = match arg {
+                    "half-up" => Rounding::HalfUp,
+                    "half-even" => Rounding::HalfEven,
+                    "floor" => Rounding::Floor,
+                    "ceil" => Rounding::Ceil,
+                    _ => return Err("Usage: :rounding half-up|half-even|floor|ceil".to_string()),
+                };
+                calc.set_rounding(rounding);
+                Ok(())
+            }
+            "locale" => {
+                let separator = decimal_separator(arg).ok_or("Usage: :locale language, like en or de")?;
+                calc.set_decimal_separator(separator)
+            }
+            "base" => {
+                self.base = match arg {
+                    "hex" => Some(16),
+                    "oct" => Some(8),
+                    "bin" => Some(2),
+                    "dec" => None,
+                    _ => return Err("Usage: :base hex|oct|bin|dec".to_string()),
+                };
+                Ok(())
+            }
+            "mode" => {
+                match arg {
+                    "programmer" => self.programmer = true,
+                    "normal" => self.programmer = false,
+                    "rpn" => self.rpn = Some(Stack::default()),
+                    "algebraic" => self.rpn = None,
+                    "deg" => calc.set_angle_unit(AngleUnit::Degrees),
+                    "rad" => calc.set_angle_unit(AngleUnit::Radians),
+                    #[cfg(feature = "decimal")]
+                    "decimal" => calc.set_arithmetic(Arithmetic::Decimal { places: DECIMAL_PLACES }),
+                    #[cfg(not(feature = "decimal"))]
+                    "decimal" => return Err("Built without the decimal feature".to_string()),
+                    "fraction" => calc.set_arithmetic(Arithmetic::Rational),
+                    "bigint" => calc.set_arithmetic(Arithmetic::Integer),
+                    "float" => calc.set_arithmetic(Arithmetic::Float),
+                    "strict" => calc.set_strict(true),
+                    "lenient" => calc.set_strict(false),
+                    _ => return Err("Usage: :mode programmer|normal|rpn|algebraic|deg|rad|decimal|fraction|bigint|float|strict|lenient".to_string()),
+                }
+                Ok(())
+            }
+            "echo" => {
+                self.echo_all = match arg {
+                    "all" => true,
+                    "last" => false,
+                    _ => return Err("Usage: :echo all|last".to_string()),
+                };
+                Ok(())
+            }
+            "seed" => {
+                let seed = arg.parse().map_err(|_| "Usage: :seed n, a whole number from 0")?;
+                calc.seed(seed);
+                Ok(())
+            }
+            "rates" => {
+                match arg.split_once(' ') {
+                    None if arg.is_empty() => {
+                        let mut rates: Vec<_> = calc.rates().collect();
+                        rates.sort_by(|a, b| a.0.cmp(b.0));
+                        for (code, rate) in rates {
+                            println!("{} = {}", code, rate);
+                        }
+                    }
+                    Some(("update", source)) => println!("Loaded {} exchange rates", rates::update(calc, source.trim())?),
+                    _ => return Err("Usage: :rates [update path-or-url]".to_string()),
+                }
+                Ok(())
+            }
+            "plot" => {
+                // Only the arguments of one call, `sin(x), x, 0, 1); (2` would
+                // close it early and run something else.
+                let expr = format!("plot({})", arg);
+                let compiled = calc.compile(&expr).map_err(|err| err.render(&expr))?;
+                if !matches!(compiled.ast(), Expr::Call { name, .. } if name == "plot") {
+                    return Err("Usage: :plot f(x), x, low, high".to_string());
+                }
+                match calc.evaluate(&compiled).map_err(|err| err.render(&expr))? {
+                    plot @ Value::Plot(_) => println!("{}", self.display(calc, plot)),
+                    _ => return Err("Usage: :plot f(x), x, low, high".to_string()),
+                }
+                Ok(())
+            }
+            "tree" if arg.is_empty() => Err("Usage: :tree expression".to_string()),
+            "tree" => {
+                let ast = calc.compile(arg).map_err(|err| err.render(arg))?.ast();
+                println!("{}", ast.to_tree());
+                Ok(())
+            }
+            "dot" if arg.is_empty() => Err("Usage: :dot expression [> file.dot]".to_string()),
+            "dot" => {
+                // `x > y` compares, only a `.dot` file after the last ` > ` is written to.
+                let (expr, path) = match arg.rsplit_once(" > ") {
+                    Some((expr, path)) if path.trim().ends_with(".dot") => (expr.trim(), Some(path.trim())),
+                    _ => (arg, None),
+                };
+                let dot = calc.compile(expr).map_err(|err| err.render(expr))?.ast().to_dot();
+                match path {
+                    Some(path) => std::fs::write(path, dot).map_err(|err| format!("Failed to write '{}': {}", path, err)),
+                    None => {
+                        print!("{}", dot);
+                        Ok(())
+                    }
+                }
+            }
+            "latex" => {
+                let latex = calc.compile(arg).map_err(|err| err.render(arg))?.to_latex();
+                // Formulas in unknowns like `1/2*sin(x)` print without a result.
+                match calc.eval(arg.to_string()) {
+                    Ok(value @ Value::Number(_)) => println!("{} = {}", latex, calc.format(value)),
+                    Ok(Value::Formula(formula)) => println!("{} = {}", latex, formula.to_latex()),
+                    _ => println!("{}", latex),
+                }
+                Ok(())
+            }
+            "bits" => {
+                let value = number(calc, arg)?;
+                let bits = value.to_bits();
+                println!("0x{:016X}", bits);
+                println!("sign {}, exponent {} (0x{:03X}), mantissa 0x{:013X}",
+                         bits >> 63, calculator::exponent(value), (bits >> 52) & 0x7ff, bits & ((1 << 52) - 1));
+                Ok(())
+            }
+            _ => Err(format!("Unknown command ':{}', :help lists them", name))
+        }
+    }
+
+    /// Runs the words of `line` against the stack of `:mode rpn` and prints
+    /// the stack, the top last as level 1.
+    fn rpn(&mut self, calc: &mut Calculator, line: &str) -> std::result::Result<(), String> {
+        let stack = self.rpn.as_mut().unwrap();
+        stack.enter(calc, line)?;
+        let values = stack.values().to_vec();
+        for (i, &value) in values.iter().enumerate() {
+            println!("{}: {}", values.len() - i, self.display(calc, Value::Number(value)));
+        }
+        if let Some(&top) = values.last() {
+            self.remember(calc, Value::Number(top));
+        }
+        Ok(())
+    }
+
+    /// The values of `expr` to print: that of the last statement, or with
+    /// `:echo all` of each one. Definitions have none.
+    fn eval(&self, calc: &mut Calculator, expr: &str) -> std::result::Result<Vec<Value>, Diagnostic> {
+        if !self.echo_all {
+            let value = calc.eval(expr.to_string()).map_err(|err| pasted_row(calc, expr, err))?;
+            return Ok(if calc.is_definition(expr) { vec![] } else { vec![value] });
+        }
+        let values = calc.eval_each(expr.to_string()).map_err(|err| pasted_row(calc, expr, err))?;
+        // The parsed statements, as a `;` may also separate arguments.
+        let statements = match calc.compile(expr).map(|expr| expr.ast()) {
+            Ok(Expr::Sequence(statements)) => statements,
+            Ok(statement) => vec![statement],
+            Err(_) => vec![],
+        };
+        Ok(values.into_iter().zip(statements).filter(|(_, statement)| !matches!(statement, Expr::Define { .. })).map(|(value, _)| value).collect())
+    }
+
+    /// Keeps `value` as the previous result, which expressions can refer to
+    /// as `ans`, and numbers it for `out(n)`.
+    fn remember(&mut self, calc: &mut Calculator, value: Value) {
+        self.outputs.borrow_mut().push(value.clone());
+        calc.define(ANS, value);
+    }
+
+    /// The number of the last result for commands that only take one, None
+    /// before the first. Fails when the result isn't a number, like a list.
+    fn last_number(&self, calc: &Calculator, command: &str) -> std::result::Result<Option<f64>, String> {
+        match self.outputs.borrow().last() {
+            None => Ok(None),
+            Some(value) => value.number().map(Some)
+                .ok_or_else(|| format!("Can't {} {}, the last result isn't a number", command, calc.format(value.clone()))),
+        }
+    }
+
+    /// The settings, defined functions and variables of the session as
+    /// lines of commands and statements, which `load` restores. Numbers are
+    /// written with a decimal point, the locale is set last.
+    fn save(&self, calc: &Calculator) -> String {
+        let formatter = calc.formatter();
+        let arithmetic = match calc.arithmetic() {
+            Arithmetic::Float => "float",
+            #[cfg(feature = "decimal")]
+            Arithmetic::Decimal { .. } => "decimal",
+            Arithmetic::Rational => "fraction",
+            Arithmetic::Integer => "bigint",
+        };
+        let mut lines = vec![
+            "# Saved by :save, restored by :load".to_string(),
+            ":locale en".to_string(),
+            format!(":mode {}", if calc.angle_unit() == AngleUnit::Degrees { "deg" } else { "rad" }),
+            format!(":mode {}", arithmetic),
+            format!(":mode {}", if calc.strict() { "strict" } else { "lenient" }),
+            format!(":mode {}", if self.programmer { "programmer" } else { "normal" }),
+            formatter.precision.map_or(":precision".to_string(), |digits| format!(":precision {}", digits)),
+            format!(":format {}", match formatter.notation {
+                Notation::Auto => "auto",
+                Notation::Fixed => "fixed",
+                Notation::Scientific => "sci",
+                Notation::Engineering => "eng",
+            }),
+            format!(":group {}", if formatter.grouping { "on" } else { "off" }),
+            format!(":rounding {}", match calc.rounding() {
+                Rounding::HalfUp => "half-up",
+                Rounding::HalfEven => "half-even",
+                Rounding::Floor => "floor",
+                Rounding::Ceil => "ceil",
+            }),
+            format!(":base {}", match self.base {
+                Some(16) => "hex",
+                Some(8) => "oct",
+                Some(2) => "bin",
+                _ => "dec",
+            }),
+            format!(":echo {}", if self.echo_all { "all" } else { "last" }),
+        ];
+        let mut names: Vec<&str> = calc.definitions().map(|(name, _)| name).collect();
+        names.sort();
+        lines.extend(names.into_iter().filter_map(|name| calc.definition(name)));
+        let mut vars: Vec<_> = calc.vars().collect();
+        vars.sort_by(|a, b| a.0.cmp(b.0));
+        // Each value in the mode its literals need, then back to the saved one.
+        let mut mode = arithmetic;
+        for (name, value) in vars {
+            let Some(source) = value.to_source() else { continue };
+            let needs = match value {
+                #[cfg(feature = "decimal")]
+                Value::Decimal(_) => "decimal",
+                Value::Fraction(_) => "fraction",
+                Value::Integer(_) => "bigint",
+                _ => "float",
+            };
+            if needs != mode {
+                lines.push(format!(":mode {}", needs));
+                mode = needs;
+            }
+            lines.push(format!("{} = {}", name, source));
+        }
+        if mode != arithmetic {
+            lines.push(format!(":mode {}", arithmetic));
+        }
+        let mut formulas: Vec<_> = calc.formulas().collect();
+        formulas.sort();
+        lines.extend(formulas.into_iter().map(|(name, formula)| format!("{} := {}", name, formula)));
+        if calc.decimal_separator() == ',' {
+            lines.push(":locale de".to_string());
+        }
+        lines.join("\n") + "\n"
+    }
+
+    /// Runs the commands and statements of `src`, which `save` wrote to
+    /// `path`. A line that fails doesn't stop the others, the errors are
+    /// returned together.
+    fn load(&mut self, calc: &mut Calculator, path: &str, src: &str) -> std::result::Result<(), String> {
+        let mut errors = vec![];
+        for (i, line) in src.lines().enumerate().map(|(i, line)| (i + 1, line.trim())) {
+            let ret = match line.strip_prefix(':') {
+                _ if line.is_empty() || line.starts_with('#') => Ok(()),
+                Some(command) => self.command(calc, command),
+                None => calc.eval(line.to_string()).map(|_| ()).map_err(|err| err.message),
+            };
+            if let Err(msg) = ret {
+                errors.push(format!("{}:{}: {}", path, i, msg));
+            }
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors.join("\n")) }
+    }
+
+    /// `:session`, `:session list` or `:session delete name`.
+    fn session(&mut self, arg: &str) -> std::result::Result<(), String> {
+        let dir = self.sessions.clone().ok_or("There is no directory to keep sessions in")?;
+        match arg.split_once(' ').map(|(command, rest)| (command, rest.trim())).unwrap_or((arg, "")) {
+            ("", _) => match &self.name {
+                Some(name) => println!("Session '{}', saved to {} on exit", name, config::session_path(&dir, name)?.display()),
+                None => println!("This session isn't saved, start with --session name or set auto_session"),
+            },
+            ("list", "") => {
+                let mut names: Vec<String> = std::fs::read_dir(&dir).into_iter().flatten().flatten()
+                    .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".calc").map(str::to_string))
+                    .collect();
+                names.sort();
+                if names.is_empty() {
+                    println!("No sessions are saved in {}", dir.display());
+                }
+                for name in names {
+                    let current = self.name.as_ref() == Some(&name);
+                    println!("{}{}", name, if current { " (this one)" } else { "" });
+                }
+            }
+            ("delete", name) if !name.is_empty() => {
+                let path = config::session_path(&dir, name)?;
+                std::fs::remove_file(&path).map_err(|err| format!("Failed to delete '{}': {}", path.display(), err))?;
+                // Or it would be saved again on exit.
+                if self.name.as_deref() == Some(name) {
+                    self.name = None;
+                }
+            }
+            _ => return Err("Usage: :session [list|delete name]".to_string()),
+        }
+        Ok(())
+    }
+
+    /// `:workspace` lists the workspaces, `:workspace name` or `:workspace
+    /// switch name` switches to one, a new one starting with the settings of
+    /// this one but none of its variables and functions, and `:workspace
+    /// copy from name` copies a variable or function of another one.
+    fn workspace(&mut self, calc: &mut Calculator, arg: &str) -> std::result::Result<(), String> {
+        let current = self.workspace.clone().unwrap_or(MAIN_WORKSPACE.to_string());
+        let words: Vec<&str> = arg.split_whitespace().collect();
+        match words[..] {
+            [] => {
+                let mut names: Vec<&str> = self.workspaces.keys().map(String::as_str).chain([current.as_str()]).collect();
+                names.sort();
+                for name in names {
+                    println!("{}{}", name, if name == current { " (this one)" } else { "" });
+                }
+            }
+            ["copy", from, name] => {
+                let other = self.workspaces.get(from).ok_or(format!("There is no other workspace '{}'", from))?;
+                match (other.get_var(name), other.definition(name)) {
+                    (Some(value), _) => calc.set_var(name, value)?,
+                    (None, Some(definition)) => {
+                        calc.eval(definition).map_err(|err| err.message)?;
+                    }
+                    (None, None) => return Err(format!("'{}' has no variable or function '{}'", from, name)),
+                }
+            }
+            ["switch", name] if !self.workspaces.contains_key(name) && name != current => {
+                return Err(format!("There is no workspace '{}', :workspace {} starts it", name, name));
+            }
+            ["switch", name] | [name] if name != "copy" && name != "switch" => {
+                if name == current {
+                    return Ok(());
+                }
+                let next = self.workspaces.remove(name).unwrap_or_else(|| {
+                    let mut fresh = calc.clone();
+                    fresh.clear_vars();
+                    fresh.clear_definitions();
+                    fresh
+                });
+                self.workspaces.insert(current, std::mem::replace(calc, next));
+                self.workspace = Some(name.to_string());
+            }
+            _ => return Err("Usage: :workspace [name], :workspace switch name or :workspace copy from name".to_string()),
+        }
+        Ok(())
+    }
+
+    /// Loads the session named at startup, if it was saved before.
+    fn restore(&mut self, calc: &mut Calculator) -> std::result::Result<(), String> {
+        let (Some(name), Some(dir)) = (&self.name, &self.sessions) else { return Ok(()) };
+        let path = config::session_path(dir, name)?;
+        if !path.exists() {
+            return Ok(());
+        }
+        let src = std::fs::read_to_string(&path).map_err(|err| format!("Failed to read '{}': {}", path.display(), err))?;
+        self.load(calc, &path.display().to_string(), &src)
+    }
+
+    /// Saves the session named at startup, as `:save` does.
+    fn persist(&self, calc: &Calculator) -> std::result::Result<(), String> {
+        let (Some(name), Some(dir)) = (&self.name, &self.sessions) else { return Ok(()) };
+        let path = config::session_path(dir, name)?;
+        std::fs::create_dir_all(dir).and_then(|_| std::fs::write(&path, self.save(calc)))
+            .map_err(|err| format!("Failed to write '{}': {}", path.display(), err))
+    }
+
+    /// Registers `out(n)`, the `n`th result of the session.
+    fn register_outputs(&self, calc: &mut Calculator) {
+        let outputs = self.outputs.clone();
+        calc.register_function("out(n)", Function::closure(1, move |args| {
+            let outputs = outputs.borrow();
+            let n = args[0];
+            let value = Some(n).filter(|n| n.fract() == 0. && *n >= 1.).and_then(|n| outputs.get(n as usize - 1))
+                .ok_or(format!("There is no out[{}], there are {} results so far", n, outputs.len()))?;
+            value.number().ok_or(format!("out[{}] is {}, not a number", n, value))
+        })).unwrap();
+    }
+
+    /// Prepends `ans`, the previous result, if `expr` starts with an infix
+    /// operator, so `* 1.2` after `100` evaluates `ans * 1.2`.
+    ///
+    /// A symbol that is also a prefix operator only continues when it is
+    /// followed by a space, `- 3` subtracts while `-3` stays a number.
+    fn continuation(&self, calc: &Calculator, expr: &str) -> String {
+        if self.outputs.borrow().is_empty() {
+            return expr.to_string();
+        }
+        let rest = expr.trim_start();
+        let entries = calc.operators().entries();
+        let continues = entries.iter()
+            .filter(|entry| entry.operator.fixity() == Fixity::Infix && rest.starts_with(&entry.symbol))
+            .any(|entry| {
+                let prefix = entries.iter().any(|other| matches!(other.operator, Operator::Prefix(_)) && other.symbol == entry.symbol);
+                let after = &rest[entry.symbol.len()..];
+                // `xor` continues, `xorig` is a name.
+                let word = entry.symbol.chars().all(|c| c.is_ascii_alphabetic());
+                !(word && after.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')) && (!prefix || after.starts_with(' '))
+            });
+
+        if continues {
+            format!("{} {}", ANS, rest)
+        } else {
+            expr.to_string()
+        }
+    }
+
+    fn display(&self, calc: &Calculator, value: Value) -> String {
+        if let Value::Plot(plot) = &value {
+            let (columns, rows) = terminal_size();
+            return plot.render(columns, (rows / 2).max(5));
+        }
+        let Value::Number(value) = value else { return calc.format(value) };
+        if let (true, Some(hex), Some(bin)) = (self.programmer, format::radix(value, 16), format::radix(value, 2)) {
+            return format!("{}  {}  {}", value, hex, bin);
+        }
+        if let Some(digits) = self.base.and_then(|base| format::radix(value, base)) {
+            return digits;
+        }
+        match &self.template {
+            Some(template) => template.format(value),
+            None => calc.format(Value::Number(value)),
+        }
+    }
+}
+
+/// The columns and rows of the terminal, or 80 by 24 when it can't tell.
+fn terminal_size() -> (usize, usize) {
+    #[cfg(unix)]
+    unsafe {
+        let mut size: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) == 0 && size.ws_col > 0 {
+            return (size.ws_col as usize, size.ws_row as usize);
+        }
+    }
+    (80, 24)
+}
+
+/// The shortest and the mean time of evaluating `expr`, which is compiled
+/// once, and how many times it was evaluated.
+fn time(calc: &mut Calculator, expr: &str) -> std::result::Result<(Duration, Duration, usize), Diagnostic> {
+    let compiled = calc.compile(expr)?;
+    let (mut min, mut total, mut runs) = (Duration::MAX, Duration::ZERO, 0);
+    while runs < TIME_RUNS && total < TIME_BUDGET {
+        let start = Instant::now();
+        calc.evaluate(&compiled)?;
+        let elapsed = start.elapsed();
+        min = min.min(elapsed);
+        total += elapsed;
+        runs += 1;
+    }
+    Ok((min, total / runs as u32, runs))
+}
+
+/// The decimal separator of a language given to `:locale`, like `de` or
+/// `de_DE.UTF-8`.
+fn decimal_separator(locale: &str) -> Option<char> {
+    let language = locale.split(['_', '-', '.']).next()?.to_lowercase();
+    match language.as_str() {
+        "en" | "ja" | "ko" | "zh" | "he" | "th" | "hi" | "c" => Some('.'),
+        "de" | "fr" | "es" | "it" | "nl" | "pt" | "ru" | "pl" | "cs" | "sv" | "da" | "nb" | "fi" | "tr" | "uk" | "el" | "id" => Some(','),
+        _ => None,
+    }
+}
+
+/// `err` with a hint for a row of cells pasted from a spreadsheet, which
+/// `--sanitize` reads as a list.
+fn pasted_row(calc: &Calculator, expr: &str, err: Diagnostic) -> Diagnostic {
+    let cells: Vec<&str> = expr.split('\t').map(str::trim).filter(|cell| !cell.is_empty()).collect();
+    let numeric = |cell: &&str| cell.contains(|c: char| c.is_ascii_digit()) && cell.chars().all(|c| c.is_ascii_digit() || ".,-$€£¥₹% ".contains(c));
+    if calc.sanitize() || err.hint.is_some() || cells.len() < 2 || !cells.iter().all(numeric) {
+        return err;
+    }
+    err.with_hint("a row of cells pasted with tabs is a list with --sanitize")
+}
+
+/// Checks the name given to `:tag` or `:sto`.
+fn check_name(calc: &Calculator, command: &str, name: &str) -> std::result::Result<(), String> {
+    let mut chars = name.chars();
+    if !chars.next().is_some_and(|c| c.is_ascii_alphabetic()) || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("Usage: :{} name, where the name is made of letters, digits and '_'", command));
+    }
+    if calc.is_builtin(name) {
+        return Err(format!("'{}' is a builtin and can't be used as a name", name));
+    }
     Ok(())
 }
 
+/// Evaluates an argument of a command that needs a number.
+fn number(calc: &mut Calculator, expr: &str) -> std::result::Result<f64, String> {
+    match calc.eval(expr.to_string()).map_err(|err| err.render(expr))? {
+        Value::Number(x) => Ok(x),
+        value => Err(format!("Expected a number but got {}", value)),
+    }
+}
+
+/// Evaluates `exprs` in order for a script, printing each result and
+/// writing errors to stderr, then with `copy` puts the last result on the
+/// clipboard. Returns whether all of them evaluated and it was copied.
+fn batch(calc: &mut Calculator, exprs: impl Iterator<Item = String>, copy: bool) -> bool {
+    let mut ok = true;
+    let mut last = None;
+    for expr in exprs.filter(|expr| !expr.trim().is_empty()) {
+        match calc.eval(expr.clone()) {
+            Ok(_) if calc.is_definition(&expr) => {}
+            Ok(value) => {
+                let text = value.to_string();
+                println!("{}", text);
+                last = Some(text);
+            }
+            Err(err) => {
+                eprintln!("{}", err.render(&expr));
+                ok = false;
+            }
+        }
+        for warning in calc.warnings() {
+            eprintln!("warning: {}", warning.render(&expr));
+        }
+    }
+    if let Some(text) = last.filter(|_| copy) {
+        if let Err(msg) = clipboard::copy(&text) {
+            eprintln!("{}", msg);
+            ok = false;
+        }
+    }
+    ok
+}
+
+/// Reads expressions from `input` without line editing and writes nothing
+/// but the raw result of each one to `output`, errors go to stderr.
+/// Definitions like `f(x) = x` have no result to write.
+fn porcelain(calc: &mut Calculator, input: impl BufRead, output: &mut impl Write) {
+    for line in input.lines() {
+        let Ok(expr) = line else { break };
+
+        match calc.eval(expr.clone()) {
+            Ok(_) if calc.is_definition(&expr) => {}
+            Ok(value) => {
+                if writeln!(output, "{}", value).is_err() {
+                    break;
+                }
+            }
+            Err(msg) => eprintln!("{}", msg)
+        }
+        for warning in calc.warnings() {
+            eprintln!("warning: {}", warning);
+        }
+    }
+}
+
+#[cfg(test)]
 mod test {
-    use crate::Calculator;
+    use calculator::format::{Formatter, Notation};
+    use calculator::{AngleUnit, Calculator, ErrorKind, Importer, Value};
+    use crate::{time, Session, TIME_RUNS};
+    use crate::config::Config;
+    use crate::grid::{Cell, Sheet};
+    use crate::helper::CalcHelper;
+    use crate::rpn::Stack;
 
     #[test]
-    fn test_parse() {
+    fn test_bitwise_operators() {
         let mut calc = Calculator::new();
+        let mut session = Session::default();
+        session.command(&mut calc, "mode programmer").unwrap();
+        assert_eq!(session.display(&calc, Value::Number(10.)), "10  0xa  0b1010");
+        assert_eq!(session.display(&calc, Value::Number(0.5)), "0.5");
+        session.remember(&mut calc, Value::Number(3.));
+        assert_eq!(session.continuation(&calc, "xor 1"), "ans xor 1");
+        assert_eq!(session.continuation(&calc, "xorig"), "xorig");
+    }
 
-        let mut parse = |expr: &str| -> Result<f64, String> {
-            calc.eval(expr.to_string())
+    #[test]
+    fn test_ans() {
+        let mut calc = Calculator::new();
+        let mut session = Session::default();
+        assert!(calc.eval("ans".to_string()).is_err());
+
+        session.remember(&mut calc, Value::Number(4.));
+        assert_eq!(calc.eval("ans * 2".to_string()), Ok(Value::Number(8.)));
+        session.remember(&mut calc, Value::Bool(true));
+        assert_eq!(calc.eval("ans && 1 < 2".to_string()), Ok(Value::Bool(true)));
+        assert!(session.last_number(&calc, "tag").is_err());
+        assert!(calc.eval("ans = 1".to_string()).is_err());
+
+        session.command(&mut calc, "explain 2 + 3").unwrap();
+        assert_eq!(calc.eval("ans".to_string()), Ok(Value::Number(5.)));
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut calc = Calculator::new();
+        let mut session = Session::default();
+        calc.eval("x = 2".to_string()).unwrap();
+        calc.eval("sq(x) = x*x".to_string()).unwrap();
+        session.remember(&mut calc, Value::Number(4.));
+        session.command(&mut calc, "tag four").unwrap();
+        assert!(calc.definitions().any(|(_, signature)| signature == "sq(x)"));
+
+        session.command(&mut calc, "clear").unwrap();
+        for expr in ["x", "sq(1)", "four", "ans"] {
+            assert!(calc.eval(expr.to_string()).is_err(), "{}", expr);
+        }
+        assert_eq!(session.last_number(&calc, "tag"), Ok(None));
+        assert!(session.tags.is_empty());
+
+        assert!(!session.quit);
+        session.command(&mut calc, "quit").unwrap();
+        assert!(session.quit);
+        assert_eq!(session.command(&mut calc, "nope"), Err("Unknown command ':nope', :help lists them".to_string()));
+    }
+
+    #[test]
+    fn test_batch() {
+        let args = |args: &[&str]| crate::cli::Options::parse(args.iter().map(|arg| arg.to_string()));
+        let Some(crate::cli::Command::Eval(exprs)) = args(&["--warn", "1+2", "3*4"]).unwrap().command else { panic!() };
+        assert_eq!(exprs, ["1+2", "3*4"]);
+        assert!(matches!(args(&["test"]).unwrap().command, Some(crate::cli::Command::Test(_))));
+        let Some(crate::cli::Command::Eval(exprs)) = args(&["-2^2", "--json", "--", "test", "--x"]).unwrap().command else { panic!() };
+        assert_eq!(exprs, ["-2^2", "test", "--x"]);
+        assert!(matches!(args(&["--bogus"]), Err(msg) if msg.starts_with("Unknown option '--bogus'")));
+
+        let mut calc = Calculator::new();
+        let lines = |lines: &[&str]| lines.iter().map(|line| line.to_string()).collect::<Vec<_>>().into_iter();
+        assert!(crate::batch(&mut calc, lines(&["x = 2", "", "sq(y) = y*y", "sq(x)"]), false));
+        assert!(!crate::batch(&mut calc, lines(&["1 +", "x"]), false));
+        let mut output = vec![];
+        crate::porcelain(&mut calc, "f(x) = x + 1\narea := 2 * 3\nf(area)\n1 +\n".as_bytes(), &mut output);
+        assert_eq!(String::from_utf8(output).unwrap(), "7\n");
+
+        assert!(args(&["--copy", "1+2"]).unwrap().copy);
+        assert_eq!(crate::clipboard::osc52("0.5"), "\x1b]52;c;MC41\x07");
+        assert_eq!(crate::clipboard::osc52("3.25"), "\x1b]52;c;My4yNQ==\x07");
+        assert_eq!(Session::default().command(&mut calc, "copy"), Err("There is no result to copy yet".to_string()));
+    }
+
+    #[test]
+    fn test_script() {
+        let src = "# payment\nrate = 0.05 / 12; n = 360 # 30 years\n\n;200000 * rate / (1 - (1 + rate)^-n)\n";
+        assert_eq!(crate::script::statements(src), [(2, "rate = 0.05 / 12"), (2, "n = 360"), (4, "200000 * rate / (1 - (1 + rate)^-n)")]);
+
+        let path = std::env::temp_dir().join(format!("calculator-script-{}.calc", std::process::id()));
+        std::fs::write(&path, "x = 2; sq(y) = y*y\nsq(x) + 1\n").unwrap();
+        assert!(crate::script::run(&mut Calculator::new(), &Importer::default(), &path, true, false));
+        std::fs::write(&path, "x = 2\nx +\nx").unwrap();
+        assert!(!crate::script::run(&mut Calculator::new(), &Importer::default(), &path, false, false));
+
+        let calc = Calculator::new();
+        let src = "x = (1 + 2\ny = 3; z = 4 4 + * 5\nnope(1)\n1 +";
+        let msg = crate::script::check(&calc, &path, src).unwrap_err();
+        let lines: Vec<&str> = msg.lines().filter(|line| line.starts_with("error") || !line.starts_with(' ')).collect();
+        let at = |line| format!("error: {}:{}", path.display(), line);
+        assert_eq!(lines, [at(1).as_str(), &at(2), &at(2), &at(4), "4 syntax errors"]);
+        std::fs::write(&path, "x = 2\nnope(x)").unwrap();
+        assert!(crate::script::run(&mut Calculator::new(), &Importer::default(), &path, false, true));
+        std::fs::write(&path, "x = 2 +* 1\nnope(x)").unwrap();
+        assert!(!crate::script::run(&mut Calculator::new(), &Importer::default(), &path, false, true));
+        assert!(crate::script::check(&calc, &path, "y = 3\nnope(1)").is_ok());
+        let mut warn = Calculator::new();
+        warn.set_warnings(true);
+        assert!(crate::script::check(&warn, &path, "2^3^2").is_ok());
+
+        let mut calc = Calculator::new();
+        std::fs::write(&path, "tau = 2*pi\nvat(x) = x*1.19\nbad +\nafter = 1").unwrap();
+        crate::script::init(&mut calc, &Importer::default(), &path);
+        assert_eq!(calc.eval("vat(100)".to_string()), Ok(Value::Number(119.)));
+        assert!(calc.eval("after".to_string()).is_err());
+        std::fs::remove_file(&path).unwrap();
+        crate::script::init(&mut calc, &Importer::default(), &path);
+
+        // The library next to the script, `import` isn't a syntax error.
+        let lib = std::env::temp_dir().join(format!("lib{}.calc", std::process::id()));
+        std::fs::write(&lib, "half(x) = x / 2").unwrap();
+        std::fs::write(&path, format!("import \"lib{}.calc\"\nlib{}::half(3)\nimport \"nope.calc\"", std::process::id(), std::process::id())).unwrap();
+        assert!(crate::script::run(&mut calc, &Importer::default(), &path, false, true));
+        assert!(!crate::script::run(&mut calc, &Importer::default(), &path, false, false));
+        assert_eq!(calc.eval(format!("lib{}::half(3)", std::process::id())), Ok(Value::Number(1.5)));
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&lib).unwrap();
+    }
+
+    #[test]
+    fn test_implicit_product() {
+        let mut calc = Calculator::new();
+        let sheet = Sheet::parse("A1 = 2\nA2 = 3A1").unwrap();
+        assert_eq!(sheet.evaluate(&mut calc)[&Cell::parse("A2").unwrap()], Ok(Value::Number(6.)));
+    }
+
+    #[test]
+    fn test_grid() {
+        let mut calc = Calculator::new();
+        let sheet = Sheet::parse("A3 = A1 + A2\nA1 = 2\nA2 = A1 * 3\nB1 = B3\nB2 = B1 + 1\nB3 = B2\nC1 = A3 + B1 + C9").unwrap();
+        let results = sheet.evaluate(&mut calc);
+        let cell = |name| results[&Cell::parse(name).unwrap()].clone();
+
+        assert_eq!(cell("A3"), Ok(Value::Number(8.)));
+        assert_eq!(cell("B1"), Err("Circular reference B1 -> B3 -> B2 -> B1".to_string()));
+        assert_eq!(cell("B2"), cell("B1"));
+        assert_eq!(cell("C1"), Err("Refers to B1 which has an error".to_string()));
+        assert_eq!(calc.eval("A1".to_string()).map_err(|err| err.kind), Err(ErrorKind::UnknownVariable));
+
+        assert!(Sheet::parse("AA1 = 1").is_err());
+        assert!(Sheet::parse("A1 = 1\nA1 = 2").is_err());
+    }
+
+    #[test]
+    fn test_bases() {
+        let mut calc = Calculator::new();
+        let mut session = Session::default();
+        session.command(&mut calc, "base hex").unwrap();
+        assert_eq!((session.display(&calc, Value::Number(255.)), session.display(&calc, Value::Number(0.5))), ("0xff".to_string(), "0.5".to_string()));
+        session.command(&mut calc, "base dec").unwrap();
+        assert_eq!(session.display(&calc, Value::Number(255.)), "255");
+    }
+
+    #[test]
+    fn test_tag() {
+        let mut calc = Calculator::new();
+        let mut session = Session::default();
+        assert!(session.command(&mut calc, "tag total").is_err());
+
+        session.remember(&mut calc, Value::Number(1200.));
+        session.command(&mut calc, "tag total").unwrap();
+        assert_eq!(calc.eval("total * 2".to_string()), Ok(Value::Number(2400.)));
+        // Not an older number after a result that isn't one.
+        let list = calc.eval("[1, 2]".to_string()).unwrap();
+        session.remember(&mut calc, list);
+        assert_eq!(session.command(&mut calc, "tag pair"), Err("Can't tag [1, 2], the last result isn't a number".to_string()));
+        session.remember(&mut calc, Value::Bool(true));
+        assert!(session.command(&mut calc, "exact").is_err());
+        assert_eq!(calc.eval("pair".to_string()).map_err(|err| err.message), Err("Unknown variable 'pair'".to_string()));
+        assert!(session.command(&mut calc, "tag sqrt").is_err());
+        assert!(session.command(&mut calc, "tag 2x").is_err());
+    }
+
+    #[test]
+    fn test_continuation() {
+        let mut calc = Calculator::new();
+        let mut session = Session::default();
+        assert_eq!(session.continuation(&calc, "* 2"), "* 2");
+
+        session.remember(&mut calc, Value::Number(-4.));
+        assert_eq!(session.continuation(&calc, " * 1.5"), "ans * 1.5");
+        assert_eq!(session.continuation(&calc, "- 3"), "ans - 3");
+        assert_eq!(session.continuation(&calc, "-3"), "-3");
+        assert_eq!(session.continuation(&calc, "2 + 2"), "2 + 2");
+
+        // It continues from the result itself, not a number printed from it.
+        let continued = |calc: &mut Calculator, session: &mut Session, expr: &str| {
+            let expr = session.continuation(calc, expr);
+            let value = session.eval(calc, &expr).unwrap().pop().unwrap();
+            session.remember(calc, value.clone());
+            calc.format(value)
         };
+        session.command(&mut calc, "mode fraction").unwrap();
+        assert_eq!(continued(&mut calc, &mut session, "1/3"), "1/3");
+        assert_eq!(continued(&mut calc, &mut session, "* 3"), "1");
+        session.command(&mut calc, "mode float").unwrap();
+        assert_eq!(continued(&mut calc, &mut session, "1/0"), "inf");
+        assert_eq!(continued(&mut calc, &mut session, "- 1"), "inf");
+        assert_eq!(continued(&mut calc, &mut session, "[1, 2]"), "[1, 2]");
+        assert_eq!(continued(&mut calc, &mut session, "* 2"), "[2, 4]");
+    }
+
+    #[test]
+    fn test_signature_hint() {
+        let helper = CalcHelper::new(&Calculator::new());
+        let hint = |line: &str| helper.signature_hint(line, line.len());
+
+        assert_eq!(hint("clamp("), Some("x, min, max)".to_string()));
+        assert_eq!(hint("max(1, "), Some("...)".to_string()));
+        assert_eq!(hint("1 + clamp(2"), Some(", min, max)".to_string()));
+        assert_eq!(hint("clamp(sin(1), "), Some("min, max)".to_string()));
+        assert_eq!(hint("clamp(1, 2, 3"), None);
+        assert_eq!(hint("sin(1) + 2"), None);
+    }
+
+    #[test]
+    fn test_completions() {
+        let mut calc = Calculator::new();
+        calc.eval("sq(x) = x*x".to_string()).unwrap();
+        calc.eval("speed = 3".to_string()).unwrap();
+        let helper = CalcHelper::new(&calc);
+        let complete = |line: &str| helper.completions(line, line.len());
+
+        assert_eq!(complete("sq"), (0, vec!["sq(".to_string(), "sqrt(".to_string()]));
+        assert_eq!(complete("2 * sp"), (4, vec!["speed".to_string()]));
+        assert_eq!(complete("1 + p"), (4, vec!["phys.".to_string(), "pi".to_string(), "plot(".to_string(), "polyroots(".to_string(), "popcount(".to_string(), "prod(".to_string()]));
+        assert_eq!(complete("phys.k"), (0, vec!["phys.kB".to_string()]));
+        assert_eq!(complete("stats"), (0, vec!["stats::".to_string()]));
+        assert_eq!(complete("x + stats::med"), (4, vec!["stats::median(".to_string()]));
+        assert_eq!(helper.signature_hint("stats::median(", 14), Some("x, ...)".to_string()));
+        assert_eq!(complete("12"), (2, vec![]));
+        assert_eq!(helper.signature_hint("sq(", 3), Some("x)".to_string()));
+    }
+
+    #[test]
+    fn test_result_hint() {
+        let mut calc = Calculator::new();
+        calc.eval("x = 2".to_string()).unwrap();
+        let helper = CalcHelper::new(&calc);
+
+        assert_eq!(helper.result_hint("x * 3"), Some(" = 6".to_string()));
+        assert_eq!(helper.result_hint("1 < x"), Some(" = true".to_string()));
+        assert_eq!(helper.result_hint("x = 5"), Some(" = 5".to_string()));
+        assert_eq!(helper.result_hint("x"), Some(" = 2".to_string()));
+        assert_eq!(helper.result_hint("sq(x) = x*x"), None);
+        assert_eq!(helper.result_hint("x + "), None);
+        assert_eq!(helper.result_hint("42"), None);
+        assert_eq!(helper.result_hint(":stats"), None);
+        assert_eq!(helper.result_hint("y = x + 1"), Some(" = 3".to_string()));
+        assert_eq!(helper.result_hint("y"), None);
+        assert_eq!(calc.stats().evaluations, 1);
+    }
+
+    #[test]
+    fn test_suite() {
+        assert!(crate::suite::run(&mut Calculator::new(), &["tests".into()]));
+    }
+
+    #[test]
+    fn test_config() {
+        let config = Config::parse("# history\nhistory_ignore_space = true\nhistory_search=off\n").unwrap();
+        assert!(config.history_ignore_dups);
+        assert!(config.history_ignore_space);
+        assert!(!config.history_search);
+
+        assert_eq!(Config::parse("history_size = 10").unwrap().history_size, 10);
+        assert!(Config::parse("history_size = lots").is_err());
+        assert!(Config::parse("history_limit = 10").is_err());
+        assert!(Config::parse("history_ignore_dups = maybe").is_err());
+
+        let config = Config::parse("edit_mode = vi\nhistory_auto_add = off").unwrap();
+        assert_eq!(config.edit_mode, rustyline::EditMode::Vi);
+        assert!(!config.history_auto_add);
+        assert_eq!(Config::parse("edit_mode = nano").err(), Some("line 1: Expected 'emacs' or 'vi' for 'edit_mode'".to_string()));
+        let config = Config::parse("import_path = /opt/formulas:~/formulas").unwrap();
+        assert_eq!(config.import_path, [std::path::PathBuf::from("/opt/formulas"), home::home_dir().unwrap().join("formulas")]);
+        let args = |args: &[&str]| crate::cli::Options::parse(args.iter().map(|arg| arg.to_string()));
+        let options = args(&["--edit-mode", "vi", "--history-dups", "--no-auto-history"]).unwrap();
+        assert_eq!(options.edit_mode, Some(rustyline::EditMode::Vi));
+        assert!(options.history_dups && options.no_auto_history);
+        assert!(args(&["--edit-mode"]).is_err());
+    }
+
+    #[test]
+    fn test_formatter() {
+        let mut calc = Calculator::new();
+        let mut session = Session::default();
+        session.command(&mut calc, "precision 3").unwrap();
+        assert_eq!(session.display(&calc, Value::Number(2. / 3.)), "0.667");
+        session.command(&mut calc, "format sci").unwrap();
+        assert_eq!(calc.format(Value::Number(1500.)), "1.500e+03");
+        assert_eq!(calc.format(Value::Bool(true)), "true");
+        session.command(&mut calc, "format eng").unwrap();
+        assert_eq!(calc.format(Value::Number(0.0047)), "4.70m");
+        session.command(&mut calc, "format sci").unwrap();
+        session.command(&mut calc, "precision").unwrap();
+        assert_eq!(calc.formatter(), Formatter { notation: Notation::Scientific, ..Formatter::default() });
+        assert!(session.command(&mut calc, "precision many").is_err());
+        assert!(session.command(&mut calc, "format hex").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "decimal")]
+    fn test_decimal() {
+        let mut calc = Calculator::new();
+        calc.set_arithmetic(calculator::Arithmetic::Decimal { places: 28 });
+        let mut session = Session::default();
+        session.command(&mut calc, "precision 2").unwrap();
+        assert_eq!(calc.eval("2 / 3".to_string()), Ok(Value::Decimal(calculator::Decimal::parse("0.67").unwrap())));
+        session.command(&mut calc, "mode float").unwrap();
+        assert_eq!(calc.eval("0.1 + 0.2".to_string()), Ok(Value::Number(0.1 + 0.2)));
+    }
+
+    #[test]
+    fn test_fraction() {
+        let mut calc = Calculator::new();
+        let mut session = Session::default();
+        session.command(&mut calc, "mode fraction").unwrap();
+        let mut eval = |expr: &str| calc.eval(expr.to_string()).map(|value| value.to_string()).map_err(|err| err.message);
+
+        assert_eq!(eval("1/3 + 1/6"), Ok("1/2".to_string()));
+        assert_eq!(eval("x = 2/3 - 0.25"), Ok("5/12".to_string()));
+        assert_eq!(eval("x * 12 + (-1/2)^-2"), Ok("9".to_string()));
+        assert_eq!(eval("1/3 < 0.34"), Ok("true".to_string()));
+        assert_eq!(eval("20! / 18!"), Ok("380".to_string()));
+        // Irrational functions fall back to floats.
+        assert_eq!(eval("sqrt(4/9)"), Ok((2f64 / 3.).to_string()));
+        assert_eq!(eval("1/0"), Ok("inf".to_string()));
+
+        let mut calc = Calculator::new();
+        let mut eval = |expr: &str| calc.eval(expr.to_string()).map(|value| value.to_string()).map_err(|err| err.message);
+        assert_eq!(eval("frac(0.75)"), Ok("3/4".to_string()));
+        assert_eq!(eval("frac(0.75) + 1"), Ok("1.75".to_string()));
+        assert_eq!(eval("frac(0.1 + 0.2)"), Ok("3/10".to_string()));
+        assert_eq!(eval("frac(-1.5)"), Ok("-3/2".to_string()));
+        assert_eq!(eval("frac(pi)"), Ok("103993/33102".to_string()));
+        assert_eq!(eval("frac(1/0)"), Err("inf has no simple fraction".to_string()));
+    }
+
+    #[test]
+    fn test_rates() {
+        let rates = crate::rates::parse("# from the bank\nbase = \"USD\"\nEUR = 0.8\n").unwrap();
+        assert_eq!(rates, crate::rates::parse(r#"{"base_code": "USD", "rates": {"EUR": 0.8}, "time": 1714521600}"#).unwrap());
+        assert_eq!(crate::rates::parse("EUR = cheap"), Err("line 1: Expected a number for 'EUR'".to_string()));
+        assert_eq!(crate::rates::parse("{}"), Err("Found no exchange rates".to_string()));
+    }
+
+    #[test]
+    #[cfg(feature = "units")]
+    fn test_currency_conversion() {
+        let rates = crate::rates::parse("base = \"USD\"\nEUR = 0.8\n").unwrap();
+        let mut calc = Calculator::new();
+        let mut eval = |expr: &str| calc.eval(expr.to_string()).map(|value| value.to_string()).map_err(|err| err.message);
+        assert_eq!(eval("100 USD"), Err("Unknown currency 'USD'".to_string()));
+
+        calc.set_rates(rates);
+        let mut eval = |expr: &str| calc.eval(expr.to_string()).map(|value| value.to_string()).map_err(|err| err.message);
+        assert_eq!(eval("100 USD + 50 EUR in USD"), Ok("162.5 USD".to_string()));
+        assert_eq!(eval("100 USD in EUR"), Ok("80 EUR".to_string()));
+        assert_eq!(eval("20 EUR / 4 h"), Ok("5 EUR/h".to_string()));
+        assert_eq!(eval("3 GBP"), Err("Unknown currency 'GBP'".to_string()));
+        assert_eq!(eval("5 USD + 2 m"), Err("Can't add 5 USD and 2 m, one is currency and the other m".to_string()));
+    }
+
+    #[test]
+    fn test_json() {
+        assert!(crate::cli::Options::parse(["--json".to_string()].into_iter()).unwrap().json);
+        let mut calc = Calculator::new();
+        let mut json = |expr: &str| {
+            let ret = calc.eval(expr.to_string());
+            crate::json::result(&calc, expr, &ret)
+        };
+        assert_eq!(json("1 + 2"), r#"{"expr": "1 + 2", "ok": true, "value": 3.0}"#);
+        assert_eq!(json("sq(x) = x*x"), r#"{"expr": "sq(x) = x*x", "ok": true}"#);
+        assert_eq!(json("sq(2) > 3"), r#"{"expr": "sq(2) > 3", "ok": true, "value": true}"#);
+        assert_eq!(json("[1/0, 2.5]"), r#"{"expr": "[1/0, 2.5]", "ok": true, "value": ["inf", 2.5]}"#);
+        #[cfg(feature = "units")]
+        assert_eq!(json("3 m in ft"), r#"{"expr": "3 m in ft", "ok": true, "value": "9.84251968503937 ft"}"#);
+        assert_eq!(json("nope(1)"), concat!(r#"{"expr": "nope(1)", "ok": false, "error": {"kind": "unknown function", "#,
+            r#""message": "Unknown function 'nope'", "labels": [{"start": 0, "end": 4, "text": "not a function"}]}}"#));
+        assert!(json("\"a").starts_with(r#"{"expr": "\"a", "ok": false, "error": {"kind": "syntax error", "message": "Expected a number, a name or '(' but got '\"'""#));
+
+        calc.set_warnings(true);
+        let ret = calc.eval("1/0".to_string());
+        assert!(crate::json::result(&calc, "1/0", &ret).contains(r#""warnings": [{"kind": "warning", "message": "'1/0' overflows to inf""#));
+    }
+
+    #[test]
+    fn test_serve() {
+        let args = |args: &[&str]| crate::cli::Options::parse(args.iter().map(|arg| arg.to_string()));
+        assert_eq!(args(&["serve", "--port", "9000"]).unwrap().port, Some(9000));
+        assert!(args(&["serve", "--port"]).is_err());
+        assert!(args(&["1+2", "--port", "9000"]).is_err());
+
+        let mut server = crate::serve::Server::new(Calculator::new, None);
+        let ok = |body: &str| (200, body.to_string());
+        assert_eq!(server.respond("POST", "/eval?session=a", "x = 2\n\nx * 3"),
+            ok("{\"expr\": \"x = 2\", \"ok\": true, \"value\": 2.0}\n{\"expr\": \"x * 3\", \"ok\": true, \"value\": 6.0}\n"));
+        assert_eq!(server.respond("POST", "/eval?session=a", "x + 1"), ok("{\"expr\": \"x + 1\", \"ok\": true, \"value\": 3.0}\n"));
+        assert!(server.respond("POST", "/eval?session=b", "x + 1").1.contains("Unknown variable 'x'"));
+        assert!(server.respond("POST", "/eval", "x + 1").1.contains("Unknown variable 'x'"));
+        assert!(server.respond("POST", "/eval", &"(".repeat(200)).1.contains("\"kind\": \"limit exceeded\""));
+        assert_eq!(server.respond("GET", "/eval", "").0, 405);
+        assert_eq!(server.respond("POST", "/", "1").0, 404);
+
+        // The least recently used session makes room for a new one.
+        for i in 0..98 {
+            server.respond("POST", &format!("/eval?session=s{}", i), "y = 1");
+        }
+        server.respond("POST", "/eval?session=a", "x");
+        server.respond("POST", "/eval?session=s98", "y = 1");
+        server.respond("POST", "/eval?session=s99", "y = 1");
+        assert!(server.respond("POST", "/eval?session=a", "x").1.contains("\"value\": 2.0"));
+        assert!(server.respond("POST", "/eval?session=s1", "y").1.contains("\"value\": 1.0"));
+        assert!(server.respond("POST", "/eval?session=s0", "y").1.contains("Unknown variable 'y'"));
+
+        let read = |request: &str| crate::serve::read(&mut std::io::Cursor::new(request.as_bytes().to_vec())).unwrap();
+        let Ok((method, target, body)) = read("POST /eval HTTP/1.1\r\nContent-Length: 3\r\n\r\n1+2") else { panic!() };
+        assert_eq!((method.as_str(), target.as_str(), body.as_slice()), ("POST", "/eval", b"1+2".as_slice()));
+        assert_eq!(read(&format!("POST /{} HTTP/1.1\r\n\r\n", "x".repeat(10_000))).unwrap_err().0, 431);
+        assert_eq!(read(&format!("POST /eval HTTP/1.1\r\n{}\r\n", "A: b\r\n".repeat(101))).unwrap_err().0, 431);
+        assert_eq!(read("POST /eval HTTP/1.1\r\nContent-Length: 100000\r\n\r\n").unwrap_err().0, 413);
+        assert_eq!(args(&["serve", "--allow-origin", "http://localhost:3000"]).unwrap().allow_origin.as_deref(), Some("http://localhost:3000"));
+        assert!(args(&["1+2", "--allow-origin", "http://localhost:3000"]).is_err());
+    }
+
+    #[test]
+    fn test_rpn() {
+        let mut calc = Calculator::new();
+        let mut stack = Stack::default();
+        stack.enter(&mut calc, "3 4 + 5 *").unwrap();
+        assert_eq!(stack.values(), [35.]);
+        stack.enter(&mut calc, "10 swap - dup 2 /").unwrap();
+        assert_eq!(stack.values(), [-25., -12.5]);
+        stack.enter(&mut calc, "drop abs 5 !").unwrap();
+        assert_eq!(stack.values(), [25., 120.]);
+        stack.enter(&mut calc, "max 100 10 swap log pi -1.5 hypot(3,4)").unwrap();
+        assert_eq!(stack.values(), [120., 2., std::f64::consts::PI, -1.5, 5.]);
+
+        assert_eq!(stack.enter(&mut calc, "1 2 3 [1]"), Err("[1]: Expected a number but got [1]".to_string()));
+        assert_eq!(stack.values().len(), 5);
+        stack.enter(&mut calc, "clear 2 3").unwrap();
+        assert_eq!(stack.values(), [2., 3.]);
+        let mut stack = Stack::default();
+        stack.enter(&mut calc, "clear").unwrap();
+        assert_eq!(stack.enter(&mut calc, "1 +"), Err("+: Expected 2 numbers on the stack but got 1".to_string()));
+        assert_eq!(stack.enter(&mut calc, "drop"), Err("drop: The stack is empty".to_string()));
 
-        assert_eq!(parse("1+2"), Ok(3.));
-        assert_eq!(parse("1+2*3"), Ok(7.));
-        assert_eq!(parse("(1+3)%3"), Ok(1.));
+        let mut session = Session::default();
+        session.command(&mut calc, "mode rpn").unwrap();
+        session.rpn(&mut calc, "6 7 *").unwrap();
+        assert_eq!(calc.eval("ans".to_string()), Ok(Value::Number(42.)));
+        session.command(&mut calc, "mode algebraic").unwrap();
+        assert!(session.rpn.is_none());
+    }
+
+    #[test]
+    fn test_semicolons() {
+        let mut calc = Calculator::new();
+        let mut session = Session::default();
+        assert_eq!(session.eval(&mut calc, "x = 1; y(t) = t; x + 1"), Ok(vec![Value::Number(2.)]));
+        session.command(&mut calc, "echo all").unwrap();
+        assert_eq!(session.eval(&mut calc, "x = 1; y(t) = t; x + 1"), Ok(vec![Value::Number(1.), Value::Number(2.)]));
+        assert!(session.command(&mut calc, "echo some").is_err());
+    }
+
+    #[test]
+    fn test_outputs() {
+        let mut calc = Calculator::new();
+        let mut session = Session::default();
+        session.register_outputs(&mut calc);
+        for expr in ["6*7", "[1, 2]", "out(1) + $1"] {
+            let value = calc.eval(expr.to_string()).unwrap();
+            session.remember(&mut calc, value);
+        }
+        assert_eq!(calc.eval("$3 / out(1)".to_string()), Ok(Value::Number(2.)));
+        assert_eq!(calc.compile("$3 / 2").unwrap().to_string(), "out(3)/2");
+        assert_eq!(calc.eval("$4".to_string()).unwrap_err().message, "There is no out[4], there are 3 results so far");
+        assert_eq!(calc.eval("out(2)".to_string()).unwrap_err().message, "out[2] is [1, 2], not a number");
+        assert!(calc.eval("out(1.5)".to_string()).is_err());
+        session.command(&mut calc, "clear").unwrap();
+        assert!(calc.eval("$1".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut calc = Calculator::new();
+        let mut session = Session::default();
+        assert!(session.command(&mut calc, "sto m1").is_err());
+        session.remember(&mut calc, Value::Number(42.));
+        session.command(&mut calc, "sto m1").unwrap();
+        session.remember(&mut calc, Value::List(vec![1., 2.]));
+        session.command(&mut calc, "sto m2").unwrap();
+        assert_eq!(calc.eval("m1 + 1".to_string()), Ok(Value::Number(43.)));
+        assert_eq!(session.command(&mut calc, "sto pi"), Err("'pi' is a builtin and can't be used as a name".to_string()));
+        assert!(session.command(&mut calc, "sto 1m").is_err());
+
+        session.command(&mut calc, "clear").unwrap();
+        assert_eq!(calc.eval("m2".to_string()), Ok(Value::List(vec![1., 2.])));
+        assert!(session.command(&mut calc, "mem").is_ok());
+        session.command(&mut calc, "mem clear").unwrap();
+        assert!(session.registers.is_empty());
+        assert!(calc.eval("m1".to_string()).is_err());
+        assert!(session.command(&mut calc, "mem all").is_err());
+    }
+
+    #[test]
+    fn test_time() {
+        let mut calc = Calculator::new();
+        let (min, mean, runs) = time(&mut calc, "sin(pi/6) + sqrt(2)").unwrap();
+        assert_eq!(runs, TIME_RUNS);
+        assert!(min <= mean);
+        assert_eq!(time(&mut calc, "1 +").unwrap_err().kind, ErrorKind::Incomplete);
+        assert_eq!(time(&mut calc, "nope(1)").unwrap_err().kind, ErrorKind::UnknownFunction);
+        let mut session = Session::default();
+        assert!(session.command(&mut calc, "time 2^10").is_ok());
+    }
+
+    #[test]
+    fn test_strict() {
+        let mut calc = Calculator::new();
+        let mut session = Session::default();
+        session.command(&mut calc, "mode lenient").unwrap();
+        assert!(calc.eval("0/0".to_string()).unwrap().number().unwrap().is_nan());
+    }
+
+    #[test]
+    fn test_pasted_row() {
+        let mut calc = Calculator::new();
+        let session = Session::default();
+        let err = session.eval(&mut calc, "1.2\t3.4\t5.6").unwrap_err();
+        assert_eq!(err.hint.as_deref(), Some("a row of cells pasted with tabs is a list with --sanitize"));
+        assert_eq!(session.eval(&mut calc, "1\tx").unwrap_err().hint, None);
+        calc.set_sanitize(true);
+        assert_eq!(session.eval(&mut calc, "1.2\t3.4\t5.6"), Ok(vec![Value::List(vec![1.2, 3.4, 5.6])]));
+    }
+
+    #[test]
+    fn test_decimal_comma() {
+        let mut calc = Calculator::new();
+        let mut session = Session::default();
+        assert_eq!(session.command(&mut calc, "locale de_DE.UTF-8"), Ok(()));
+        assert_eq!(calc.decimal_separator(), ',');
+        let mut eval = |expr: &str| calc.eval(expr.to_string()).map(|value| calc.format(value));
+
+        assert_eq!(eval("3,5 * 2"), Ok("7".to_string()));
+        assert_eq!(eval("max(1,5; 2,25) + 0.25"), Ok("2,5".to_string()));
+        assert_eq!(eval("round(1,25; 1)"), Ok("1,3".to_string()));
+        assert_eq!(eval("a = 1,5; [a; a * 2]"), Ok("[1,5; 3]".to_string()));
+        // A comma that isn't between digits still separates.
+        assert_eq!(eval("max(1, 2)"), Ok("2".to_string()));
+        assert_eq!(eval("max(1,2)"), Ok("1,2".to_string()));
+        assert!(calc.is_definition("f(x; y) = x + y") && !calc.is_definition("f(x; y) = x + y; f(1; 2)"));
+        session.command(&mut calc, "echo all").unwrap();
+        let values = session.eval(&mut calc, "g(x; y) = x * y; g(2; 3); max(1; 4)").unwrap();
+        assert_eq!(values, [Value::Number(6.), Value::Number(4.)]);
+
+        calc.set_formatter(Formatter { grouping: true, ..calc.formatter() });
+        calc.set_sanitize(true);
+        assert_eq!(calc.eval("1.234,5 + 1000".to_string()).map(|value| calc.format(value)), Ok("2.234,5".to_string()));
+        let helper = CalcHelper::new(&calc);
+        assert_eq!(helper.signature_hint("clamp(1; ", 9), Some("min; max)".to_string()));
+
+        assert_eq!(session.command(&mut calc, "locale en"), Ok(()));
+        assert!(calc.eval("max(1,234, 5)".to_string()).is_err());
+        assert_eq!(calc.eval("1,234 + 5".to_string()), Ok(Value::Number(1239.)));
+        assert!(calc.set_decimal_separator('\'').is_err());
+        assert_eq!(session.command(&mut calc, "locale xx"), Err("Usage: :locale language, like en or de".to_string()));
+    }
+
+    #[test]
+    fn test_rounding() {
+        let mut calc = Calculator::new();
+        let mut session = Session::default();
+        let round = |calc: &mut Calculator, expr: &str| calc.eval(expr.to_string()).unwrap().number().unwrap();
+        assert_eq!(round(&mut calc, "round(2.5) + round(-2.5)"), 0.);
+        // As written, not as the float just below 2.675.
+        assert_eq!(round(&mut calc, "round(2.675, 2)"), 2.68);
+        assert_eq!(round(&mut calc, "round(1250, -2)"), 1300.);
+
+        session.command(&mut calc, "rounding half-even").unwrap();
+        assert_eq!(round(&mut calc, "round(2.5)"), 2.);
+        assert_eq!(round(&mut calc, "round(3.5)"), 4.);
+        assert_eq!(round(&mut calc, "round(-2.5)"), -2.);
+        assert_eq!(round(&mut calc, "round(2.675, 2)"), 2.68);
+        assert_eq!(round(&mut calc, "round(2.665, 2)"), 2.66);
+        assert_eq!(round(&mut calc, "round(2.6651, 2)"), 2.67);
+        assert_eq!(round(&mut calc, "round(1250, -2)"), 1200.);
+        session.command(&mut calc, "precision 2").unwrap();
+        assert_eq!(session.display(&calc, Value::Number(0.125)), "0.12");
+        assert_eq!(session.display(&calc, Value::Number(0.135)), "0.14");
+
+        session.command(&mut calc, "rounding floor").unwrap();
+        assert_eq!(round(&mut calc, "round(2.9) + round(-2.1, 0)"), -1.);
+        assert_eq!(round(&mut calc, "round(0.004, 2)"), 0.);
+        assert_eq!(session.display(&calc, Value::Number(2. / 3.)), "0.66");
+        session.command(&mut calc, "rounding ceil").unwrap();
+        assert_eq!(round(&mut calc, "round(0.004, 2) + round(-2.9)"), -1.99);
+        assert_eq!(session.display(&calc, Value::Number(0.121)), "0.13");
+        let program = calc.compile("round(2.1)").unwrap().to_program().unwrap();
+        assert_eq!(calc.run_program(&program), Ok(Value::Number(3.)));
+        assert_eq!(session.command(&mut calc, "rounding up"), Err("Usage: :rounding half-up|half-even|floor|ceil".to_string()));
+    }
+
+    #[test]
+    fn test_color() {
+        use rustyline::highlight::Highlighter;
+
+        let err = Calculator::new().eval("sqrt(1, 2)".to_string()).unwrap_err();
+        assert_eq!(err.render_ansi("sqrt(1, 2)"), "\x1b[1;31mExpected 1 argument for function 'sqrt'\x1b[0m\n  sqrt(1, 2)\n  \x1b[1;36m^^^^^^^^^^\x1b[0m called with 2");
+        assert_eq!(err.render("sqrt(1, 2)"), "Expected 1 argument for function 'sqrt'\n  sqrt(1, 2)\n  ^^^^^^^^^^ called with 2");
+
+        let helper = CalcHelper::new(&Calculator::new()).with_color(true);
+        let line = "2 * (1 + (2))";
+        assert!(helper.highlight_char(line, line.len()));
+        assert_eq!(helper.highlight(line, line.len()), "2 * \x1b[1;34m(\x1b[0m1 + (2))");
+        assert_eq!(helper.highlight_hint(" = 6"), "\x1b[2m = 6\x1b[0m");
+        let helper = CalcHelper::new(&Calculator::new());
+        assert!(!helper.highlight_char(line, line.len()));
+        assert_eq!(helper.highlight(line, line.len()), line);
+        assert_eq!(helper.highlight_hint(" = 6"), " = 6");
+
+        assert!(crate::cli::Options::parse(["--no-color".to_string()].into_iter()).unwrap().no_color);
+    }
+
+    #[test]
+    fn test_save() {
+        let (mut calc, mut session) = (Calculator::new(), Session::default());
+        for command in ["mode deg", "precision 3", "base hex", "locale de"] {
+            session.command(&mut calc, command).unwrap();
+        }
+        for expr in ["x = 1,5", "xs = [1; 2,5]", "g := x * 2", "sq(y) = y^2", "f(a; b) = if(a > b; a; sq(b))"] {
+            calc.eval(expr.to_string()).unwrap();
+        }
+        let path = std::env::temp_dir().join(format!("calculator-session-{}.calc", std::process::id()));
+        let path = path.to_str().unwrap();
+        session.command(&mut calc, &format!("save {}", path)).unwrap();
+        let saved = std::fs::read_to_string(path).unwrap();
+        assert!(saved.contains("\n:mode deg\n") && saved.contains("\n:precision 3\n") && saved.ends_with("\nx = 1.5\nxs = [1.0, 2.5]\ng := x * 2\n:locale de\n"));
+        assert!(saved.contains("\nf(a, b) = if(a > b, a, sq(b))\nsq(y) = y ^ 2\n"));
+
+        let (mut calc, mut session) = (Calculator::new(), Session::default());
+        session.command(&mut calc, &format!("load {}", path)).unwrap();
+        assert_eq!(calc.eval("sin(90) + f(x; 2) + xs[1] + g".to_string()), Ok(Value::Number(10.5)));
+        assert_eq!((calc.formatter().precision, calc.decimal_separator(), session.base), (Some(3), ',', Some(16)));
+
+        std::fs::write(path, "y = 2\nbad +\n:mode nope\nz = y").unwrap();
+        let msg = session.command(&mut calc, &format!("load {}", path)).unwrap_err();
+        let lines: Vec<&str> = msg.lines().map(|line| line.split(": ").next().unwrap()).collect();
+        assert_eq!(lines, [format!("{}:2", path), format!("{}:3", path)]);
+        assert_eq!(calc.eval("z".to_string()), Ok(Value::Number(2.)));
+        std::fs::remove_file(path).unwrap();
+        assert!(session.command(&mut calc, &format!("load {}", path)).is_err());
+    }
+
+    #[test]
+    fn test_dot() {
+        let (mut calc, mut session) = (Calculator::new(), Session::default());
+        let path = std::env::temp_dir().join(format!("calculator-{}.dot", std::process::id()));
+        session.command(&mut calc, &format!("dot x > 1 > {}", path.display())).unwrap();
+        let dot = std::fs::read_to_string(&path).unwrap();
+        assert!(dot.starts_with("digraph {\n") && dot.contains("    n0 [label=\">\"];\n    n1 [label=\"x\"];\n"));
+        std::fs::remove_file(&path).unwrap();
+        assert!(session.command(&mut calc, "dot x > 1").is_ok());
+        assert_eq!(session.command(&mut calc, "dot"), Err("Usage: :dot expression [> file.dot]".to_string()));
+    }
+
+    #[test]
+    fn test_workspaces() {
+        let (mut calc, mut session) = (Calculator::new(), Session::default());
+        session.command(&mut calc, "mode deg").unwrap();
+        calc.eval("grav = 9.81".to_string()).unwrap();
+        calc.eval("fall(t) = grav * t^2 / 2".to_string()).unwrap();
+        session.command(&mut calc, "workspace budget").unwrap();
+        assert_eq!(calc.eval("grav".to_string()).unwrap_err().kind, ErrorKind::UnknownVariable);
+        assert_eq!(calc.eval("sin(90)".to_string()), Ok(Value::Number(1.)));
+        calc.eval("grav = 1200".to_string()).unwrap();
+
+        session.command(&mut calc, "workspace switch main").unwrap();
+        assert_eq!(calc.eval("fall(2)".to_string()), Ok(Value::Number(19.62)));
+        session.command(&mut calc, "workspace budget").unwrap();
+        assert_eq!(calc.eval("grav".to_string()), Ok(Value::Number(1200.)));
+        session.command(&mut calc, "workspace copy main fall").unwrap();
+        assert_eq!(calc.eval("fall(1)".to_string()), Ok(Value::Number(600.)));
+        session.command(&mut calc, "workspace copy main grav").unwrap();
+        assert_eq!(calc.eval("grav".to_string()), Ok(Value::Number(9.81)));
+        assert_eq!(session.workspace.as_deref(), Some("budget"));
+        assert!(session.command(&mut calc, "workspace").is_ok());
+
+        assert_eq!(session.command(&mut calc, "workspace switch nope"), Err("There is no workspace 'nope', :workspace nope starts it".to_string()));
+        assert_eq!(session.command(&mut calc, "workspace copy nope grav"), Err("There is no other workspace 'nope'".to_string()));
+        assert_eq!(session.command(&mut calc, "workspace copy main h"), Err("'main' has no variable or function 'h'".to_string()));
+        assert!(session.command(&mut calc, "workspace copy").is_err());
+    }
+
+    #[test]
+    fn test_use() {
+        let (mut calc, mut session) = (Calculator::new(), Session::default());
+        calc.eval("game::blend(a, b, t) = a + (b - a) * t".to_string()).unwrap();
+        calc.eval("unit(x) = x".to_string()).unwrap();
+        calc.eval("game::unit(x) = clamp(x, 0, 1)".to_string()).unwrap();
+        session.command(&mut calc, "use game").unwrap();
+        assert_eq!(calc.eval("blend(0, 10, 0.25)".to_string()), Ok(Value::Number(2.5)));
+        assert_eq!(calc.eval("unit(2)".to_string()), Ok(Value::Number(2.)));
+        assert!(session.command(&mut calc, "funcs game").is_ok());
+        assert!(session.command(&mut calc, "funcs").is_ok());
+        assert_eq!(session.command(&mut calc, "funcs nope"), Err("There are no functions in 'nope'".to_string()));
+        assert_eq!(session.command(&mut calc, "use nope"), Err("There are no functions in 'nope'".to_string()));
+        assert_eq!(session.command(&mut calc, "use"), Err("Usage: :use namespace".to_string()));
+    }
+
+    #[test]
+    fn test_sessions() {
+        let dir = std::env::temp_dir().join(format!("calculator-sessions-{}", std::process::id()));
+        let (mut calc, mut session) = (Calculator::new(), Session { name: Some("work".to_string()), sessions: Some(dir.clone()), ..Session::default() });
+        session.restore(&mut calc).unwrap();
+        calc.eval("rate = 0.07".to_string()).unwrap();
+        calc.eval("vat(x) = x * 1.19".to_string()).unwrap();
+        session.persist(&calc).unwrap();
+        assert!(dir.join("work.calc").exists());
+
+        let (mut calc, mut session) = (Calculator::new(), Session { name: Some("work".to_string()), sessions: Some(dir.clone()), ..Session::default() });
+        session.restore(&mut calc).unwrap();
+        assert_eq!(calc.eval("vat(100) * rate".to_string()).map(|value| value.number().unwrap().round()), Ok(8.));
+        session.command(&mut calc, "session list").unwrap();
+        session.command(&mut calc, "session delete work").unwrap();
+        assert!(!dir.join("work.calc").exists() && session.name.is_none());
+        assert!(session.command(&mut calc, "session delete work").is_err());
+        assert_eq!(session.command(&mut calc, "session delete ../x"), Err("'../x' is not a valid session name, expected letters, digits, '-' and '_'".to_string()));
+        assert_eq!(session.command(&mut calc, "session nope"), Err("Usage: :session [list|delete name]".to_string()));
+        std::fs::remove_dir(&dir).unwrap();
+
+        assert!(Config::parse("auto_session = on").unwrap().auto_session);
+        let args = |args: &[&str]| crate::cli::Options::parse(args.iter().map(|arg| arg.to_string()));
+        assert_eq!(args(&["--session", "work"]).map(|options| options.session), Ok(Some("work".to_string())));
+        assert!(args(&["--session"]).is_err());
+    }
+
+    #[test]
+    fn test_save_values() {
+        let (mut calc, mut session) = (Calculator::new(), Session::default());
+        session.command(&mut calc, "precision 3").unwrap();
+        calc.set_var("a", 0.1 + 0.2).unwrap();
+        calc.set_var("b", -1e-300).unwrap();
+        calc.set_var("c", f64::NEG_INFINITY).unwrap();
+        for expr in ["t = 1 < 2", "xs = [0.1, 1e20]", "i = interval(0.1, 0.3)", "day = date(2024, 2, 29) + seconds(3723.5)",
+                     "dur = hours(1.5)", "fx = diff(x^3, x)", "sq = x -> x^2"] {
+            calc.eval(expr.to_string()).unwrap();
+        }
+        #[cfg(feature = "matrix")]
+        calc.eval("m = [[1, 2.5], [3, 4]]".to_string()).unwrap();
+        #[cfg(feature = "units")]
+        for expr in ["q = 2.5 km/h", "hz = 3 / s"] {
+            calc.eval(expr.to_string()).unwrap();
+        }
+        for (mode, expr) in [("fraction", "r = -22/7"), ("bigint", "n = 2^100"), ("float", "nan = 0/0")] {
+            session.command(&mut calc, &format!("mode {}", mode)).unwrap();
+            calc.eval(expr.to_string()).unwrap();
+        }
+        #[cfg(feature = "decimal")]
+        {
+            session.command(&mut calc, "mode decimal").unwrap();
+            calc.eval("dec = 1/3".to_string()).unwrap();
+        }
+        session.command(&mut calc, "mode fraction").unwrap();
+
+        let path = std::env::temp_dir().join(format!("calculator-values-{}.calc", std::process::id()));
+        let path = path.to_str().unwrap();
+        session.command(&mut calc, &format!("save {}", path)).unwrap();
+        let (mut loaded, mut session) = (Calculator::new(), Session::default());
+        session.command(&mut loaded, &format!("load {}", path)).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        // Formulas compare by their text, the spans they were parsed at differ.
+        let sorted = |calc: &Calculator| {
+            let mut vars: Vec<(String, Value)> = calc.vars().filter(|(name, _)| !["nan", "fx"].contains(name)).map(|(name, value)| (name.to_string(), value)).collect();
+            vars.sort_by(|a, b| a.0.cmp(&b.0));
+            vars
+        };
+        assert_eq!(sorted(&loaded), sorted(&calc));
+        let var = |calc: &Calculator, name: &str| calc.vars().find(|var| var.0 == name).map(|var| var.1);
+        assert!(var(&loaded, "nan").and_then(|value| value.number()).is_some_and(f64::is_nan));
+        assert!(matches!(var(&loaded, "fx"), Some(Value::Formula(formula)) if formula.to_string() == "3*x^2"));
+        assert_eq!(loaded.arithmetic(), calculator::Arithmetic::Rational);
+    }
+
+    #[test]
+    fn test_plugins() {
+        use std::ffi::c_int;
+        use crate::plugin::{functions, load, Plugin, PluginFunction};
+
+        unsafe extern "C" fn twice(args: *const f64, out: *mut f64) -> c_int {
+            *out = *args * 2.;
+            0
+        }
+        unsafe extern "C" fn fail(_: *const f64, _: *mut f64) -> c_int {
+            7
+        }
+        let list = [
+            PluginFunction { signature: c"twice(x)".as_ptr(), call: twice },
+            PluginFunction { signature: c"fail(a, b)".as_ptr(), call: fail },
+        ];
+        let loaded = unsafe { functions(list.as_ptr(), list.len()) }.unwrap();
+        assert_eq!(loaded.iter().map(|(signature, _)| signature.as_str()).collect::<Vec<_>>(), ["twice(x)", "fail(a, b)"]);
+        let plugin = Plugin { name: "pack".to_string(), path: "pack.so".into(), functions: loaded };
+        let mut calc = Calculator::new();
+        plugin.register(&mut calc);
+        assert_eq!(calc.eval("twice(21)".to_string()), Ok(Value::Number(42.)));
+        assert_eq!(calc.eval("fail(1, 2)".to_string()).unwrap_err().message, "'fail' failed with code 7");
+        assert_eq!(calc.eval("fail(1)".to_string()).unwrap_err().kind, ErrorKind::Arity { expected: 2, got: 1 });
+
+        let list = [PluginFunction { signature: c"sqrt(x)".as_ptr(), call: twice }];
+        assert!(unsafe { functions(list.as_ptr(), 1) }.is_err());
+        assert!(unsafe { functions(std::ptr::null(), 0) }.is_err());
+        assert!(load(std::path::Path::new("/nonexistent/pack.so")).err().unwrap().starts_with("Failed to load the plugin '/nonexistent/pack.so': "));
+        assert!(crate::cli::Options::parse(["--no-plugins".to_string()].into_iter()).unwrap().no_plugins);
+    }
+
+    #[test]
+    fn test_random() {
+        let mut calc = Calculator::new();
+        let mut session = Session::default();
+        session.command(&mut calc, "seed 7").unwrap();
+        let a = calc.eval("rand()".to_string());
+        session.command(&mut calc, "seed 7").unwrap();
+        assert_eq!(calc.eval("rand()".to_string()), a);
+        assert!(session.command(&mut calc, "seed x").is_err());
+    }
+
+    #[test]
+    fn test_angle_unit() {
+        let mut calc = Calculator::new();
+        let mut session = Session::default();
+        assert_eq!(calc.eval("deg(pi) + rad(180)".to_string()), Ok(Value::Number(180. + std::f64::consts::PI)));
+
+        session.command(&mut calc, "mode deg").unwrap();
+        assert_eq!(calc.angle_unit(), AngleUnit::Degrees);
+        let mut eval = |expr: &str| calc.eval(expr.to_string()).unwrap().number().unwrap();
+        assert_eq!(eval("sin(90) + cos(180) + sin(180)"), 0.);
+        assert!((eval("tan(45)") - 1.).abs() < 1e-15);
+        assert!((eval("asin(0.5)") - 30.).abs() < 1e-12);
+        assert_eq!(eval("atan(1)"), 45.);
+        assert_eq!(eval("deg(pi)"), 180.);
+
+        session.command(&mut calc, "mode rad").unwrap();
+        assert_eq!(calc.eval("asin(1)".to_string()), Ok(Value::Number(std::f64::consts::FRAC_PI_2)));
+        assert!(session.command(&mut calc, "mode grad").is_err());
+    }
+
+    #[test]
+    fn test_digit_separators() {
+        let mut calc = Calculator::new();
+        let mut session = Session::default();
+        session.command(&mut calc, "group on").unwrap();
+        assert_eq!(session.display(&calc, Value::Number(5e6)), "5,000,000");
+    }
+
+    #[test]
+    fn test_plot_command() {
+        let mut calc = Calculator::new();
+        let mut session = Session::default();
+        session.command(&mut calc, "plot sin(x), x, 0, 1").unwrap();
+        assert_eq!(session.command(&mut calc, "plot sin(x),x,0,1); (2"), Err("Usage: :plot f(x), x, low, high".to_string()));
+        assert!(session.command(&mut calc, "plot x, x, 0, 1); y = 3").is_err());
+        assert!(calc.eval("y".to_string()).is_err());
     }
 }
@@ -0,0 +1,676 @@
+//! The float functions of `std` that `core` doesn't have, for building
+//! without the `std` feature. They follow fdlibm like the C libraries do and
+//! are within a few ulps of those of `std`.
+// The constants are fdlibm's, with the digits it gives them.
+#![allow(clippy::excessive_precision)]
+use core::f64::consts::{FRAC_2_PI, FRAC_PI_2, FRAC_PI_4, LN_2, LOG10_E, LOG2_E, PI, SQRT_2};
+
+/// The methods of `f64` that need `std`, which the prelude brings in where
+/// there is no `std`.
+pub(crate) trait Float {
+    fn trunc(self) -> f64;
+    fn floor(self) -> f64;
+    fn ceil(self) -> f64;
+    fn round(self) -> f64;
+    fn fract(self) -> f64;
+    fn sqrt(self) -> f64;
+    fn cbrt(self) -> f64;
+    fn hypot(self, other: f64) -> f64;
+    fn exp(self) -> f64;
+    fn exp2(self) -> f64;
+    fn exp_m1(self) -> f64;
+    fn ln(self) -> f64;
+    fn ln_1p(self) -> f64;
+    fn log(self, base: f64) -> f64;
+    fn log10(self) -> f64;
+    fn log2(self) -> f64;
+    fn powi(self, n: i32) -> f64;
+    fn powf(self, n: f64) -> f64;
+    fn sin(self) -> f64;
+    fn cos(self) -> f64;
+    fn tan(self) -> f64;
+    fn asin(self) -> f64;
+    fn acos(self) -> f64;
+    fn atan(self) -> f64;
+    fn atan2(self, other: f64) -> f64;
+    fn sinh(self) -> f64;
+    fn cosh(self) -> f64;
+    fn tanh(self) -> f64;
+    fn asinh(self) -> f64;
+    fn acosh(self) -> f64;
+    fn atanh(self) -> f64;
+}
+
+impl Float for f64 {
+    fn trunc(self) -> f64 {
+        let bits = self.to_bits();
+        let exponent = ((bits >> 52) & 0x7ff) as i32 - 1023;
+        match exponent {
+            // Already whole, or infinite or NaN.
+            52.. => self,
+            ..0 => f64::from_bits(bits & 1 << 63),
+            _ => f64::from_bits(bits & !((1 << (52 - exponent)) - 1)),
+        }
+    }
+
+    fn floor(self) -> f64 {
+        let t = self.trunc();
+        if t > self { t - 1. } else { t }
+    }
+
+    fn ceil(self) -> f64 {
+        let t = self.trunc();
+        if t < self { t + 1. } else { t }
+    }
+
+    /// Halfway cases away from zero.
+    fn round(self) -> f64 {
+        let t = self.trunc();
+        if (self - t).abs() >= 0.5 { t + self.signum() } else { t }
+    }
+
+    fn fract(self) -> f64 {
+        self - self.trunc()
+    }
+
+    /// Correctly rounded, from the integer square root of the significand.
+    fn sqrt(self) -> f64 {
+        if self == 0. || self == f64::INFINITY {
+            return self;
+        }
+        if self.is_nan() || self < 0. {
+            return f64::NAN;
+        }
+        let (significand, exponent) = significand(self);
+        // self = significand * 2^exponent with an even exponent.
+        let (significand, exponent) = match exponent & 1 {
+            0 => (significand, exponent),
+            _ => (significand << 1, exponent - 1),
+        };
+        let scaled = (significand as u128) << 58;
+        let root = scaled.isqrt();
+        // The bit below the root rounds ties the right way.
+        let inexact = (root * root != scaled) as u128;
+        scalbn(((root << 1) | inexact) as f64, (exponent - 58) / 2 - 1)
+    }
+
+    fn cbrt(self) -> f64 {
+        if self == 0. || !self.is_finite() {
+            return self;
+        }
+        let y = (self.abs().ln() / 3.).exp().copysign(self);
+        // A step of Newton's method, in a form that doesn't overflow.
+        y - (y - self / (y * y)) / 3.
+    }
+
+    fn hypot(self, other: f64) -> f64 {
+        let (a, b) = (self.abs(), other.abs());
+        if a.is_infinite() || b.is_infinite() {
+            return f64::INFINITY;
+        }
+        if a.is_nan() || b.is_nan() {
+            return f64::NAN;
+        }
+        let (a, b) = if a < b { (b, a) } else { (a, b) };
+        if a == 0. {
+            return 0.;
+        }
+        let r = b / a;
+        a * (1. + r * r).sqrt()
+    }
+
+    fn exp(self) -> f64 {
+        const P1: f64 = 1.66666666666666019037e-01;
+        const P2: f64 = -2.77777777770155933842e-03;
+        const P3: f64 = 6.61375632143793436117e-05;
+        const P4: f64 = -1.65339022054652515390e-06;
+        const P5: f64 = 4.13813679705723846039e-08;
+        if self.is_nan() {
+            return self;
+        }
+        if self > 709.782712893383973096 {
+            return f64::INFINITY;
+        }
+        if self < -745.13321910194110842 {
+            return 0.;
+        }
+        // self = k*ln(2) + r with |r| <= ln(2)/2.
+        let k = (LOG2_E * self + 0.5f64.copysign(self)) as i32;
+        let hi = self - k as f64 * LN2_HI;
+        let lo = k as f64 * LN2_LO;
+        let r = hi - lo;
+        let z = r * r;
+        let c = r - z * (P1 + z * (P2 + z * (P3 + z * (P4 + z * P5))));
+        scalbn(1. + (r * c / (2. - c) - lo + hi), k)
+    }
+
+    fn exp2(self) -> f64 {
+        if self.is_nan() || self.abs() >= 1100. {
+            return (self * LN_2).exp();
+        }
+        let k = self.round();
+        scalbn(((self - k) * LN_2).exp(), k as i32)
+    }
+
+    fn exp_m1(self) -> f64 {
+        let u = self.exp();
+        if u == 1. {
+            return self;
+        }
+        if u - 1. == -1. || u.is_infinite() {
+            return u - 1.;
+        }
+        (u - 1.) * self / u.ln()
+    }
+
+    fn ln(self) -> f64 {
+        const LG1: f64 = 6.666666666666735130e-01;
+        const LG2: f64 = 3.999999999940941908e-01;
+        const LG3: f64 = 2.857142874366239149e-01;
+        const LG4: f64 = 2.222219843214978396e-01;
+        const LG5: f64 = 1.818357216161805012e-01;
+        const LG6: f64 = 1.531383769920937332e-01;
+        const LG7: f64 = 1.479819860511658591e-01;
+        if self == 0. {
+            return f64::NEG_INFINITY;
+        }
+        if self.is_nan() || self < 0. {
+            return f64::NAN;
+        }
+        if self == f64::INFINITY {
+            return self;
+        }
+        // self = 2^k * m with sqrt(2)/2 < m < sqrt(2).
+        let (m, k) = unit(self);
+        let (m, k) = if m > SQRT_2 { (m * 0.5, k + 1) } else { (m, k) };
+        let f = m - 1.;
+        let s = f / (2. + f);
+        let z = s * s;
+        let w = z * z;
+        let r = z * (LG1 + w * (LG3 + w * (LG5 + w * LG7))) + w * (LG2 + w * (LG4 + w * LG6));
+        let hfsq = 0.5 * f * f;
+        let dk = k as f64;
+        dk * LN2_HI - ((hfsq - (s * (hfsq + r) + dk * LN2_LO)) - f)
+    }
+
+    fn ln_1p(self) -> f64 {
+        let u = 1. + self;
+        match u == 1. || u == 0. || u.is_infinite() {
+            true => if u == 1. { self } else { u.ln() },
+            // With what rounding 1 + self lost.
+            false => u.ln() + (self - (u - 1.)) / u,
+        }
+    }
+
+    fn log(self, base: f64) -> f64 {
+        self.ln() / base.ln()
+    }
+
+    /// Exact for powers of ten, `log10(1000)` is 3.
+    fn log10(self) -> f64 {
+        let y = self.ln() * LOG10_E;
+        let n = y.round();
+        let power = 10f64.powi(n.abs().min(22.) as i32);
+        match n.abs() <= 22. && (if n < 0. { 1. / power } else { power }) == self {
+            true => n,
+            false => y,
+        }
+    }
+
+    /// Exact for powers of two.
+    fn log2(self) -> f64 {
+        let bits = self.to_bits();
+        match self > 0. && self.is_normal() && bits & ((1 << 52) - 1) == 0 {
+            true => ((bits >> 52) as i32 - 1023) as f64,
+            false => self.ln() * LOG2_E,
+        }
+    }
+
+    fn powi(self, n: i32) -> f64 {
+        let mut base = self;
+        let mut rest = n.unsigned_abs();
+        let mut ret = 1.;
+        while rest > 0 {
+            if rest & 1 == 1 {
+                ret *= base;
+            }
+            base *= base;
+            rest >>= 1;
+        }
+        if n < 0 { 1. / ret } else { ret }
+    }
+
+    /// The special cases of C99, otherwise `x^n * x^f` for `y = n + f`, the
+    /// power of the whole part in double-double precision.
+    fn powf(self, y: f64) -> f64 {
+        let x = self;
+        if y == 0. || x == 1. {
+            return 1.;
+        }
+        if x.is_nan() || y.is_nan() {
+            return f64::NAN;
+        }
+        let integer = y == y.trunc();
+        let odd = integer && y.abs() < 2f64.powi(53) && (y * 0.5) != (y * 0.5).trunc();
+        if y.is_infinite() {
+            return match x.abs() {
+                1. => 1.,
+                a if (a < 1.) == (y > 0.) => 0.,
+                _ => f64::INFINITY,
+            };
+        }
+        if x == 0. || x.is_infinite() {
+            let magnitude = if (x == 0.) == (y < 0.) { f64::INFINITY } else { 0. };
+            return if odd { magnitude.copysign(x) } else { magnitude };
+        }
+        if x < 0. {
+            return match (integer, odd) {
+                (false, _) => f64::NAN,
+                (true, true) => -(-x).powf(y),
+                (true, false) => (-x).powf(y),
+            };
+        }
+        if y == 0.5 {
+            return x.sqrt();
+        }
+        if y.abs() >= 2f64.powi(31) {
+            return (y * x.ln()).exp();
+        }
+
+        let n = y.trunc();
+        let f = y - n;
+        let (whole, exponent) = powi_exact(x, n.abs() as u64);
+        let (whole, exponent) = match n < 0. {
+            true => (1. / whole, -exponent),
+            false => (whole, exponent),
+        };
+        // x^f = m^f * 2^(e*f) with m near 1, e*f split into k + r exactly.
+        let (m, e) = unit(x);
+        let (m, e) = if m > SQRT_2 { (m * 0.5, e + 1) } else { (m, e) };
+        let (th, tl) = two_product(e as f64, f);
+        let k = th.round();
+        let r = (th - k) + tl;
+        let fraction = (f * m.ln() + r * LN_2).exp();
+        scalbn(whole * fraction, (exponent + k as i64).clamp(-2200, 2200) as i32)
+    }
+
+    fn sin(self) -> f64 {
+        let (n, y0, y1) = reduce(self);
+        match n & 3 {
+            0 => kernel_sin(y0, y1),
+            1 => kernel_cos(y0, y1),
+            2 => -kernel_sin(y0, y1),
+            _ => -kernel_cos(y0, y1),
+        }
+    }
+
+    fn cos(self) -> f64 {
+        let (n, y0, y1) = reduce(self);
+        match n & 3 {
+            0 => kernel_cos(y0, y1),
+            1 => -kernel_sin(y0, y1),
+            2 => -kernel_cos(y0, y1),
+            _ => kernel_sin(y0, y1),
+        }
+    }
+
+    fn tan(self) -> f64 {
+        let (n, y0, y1) = reduce(self);
+        let (sin, cos) = (kernel_sin(y0, y1), kernel_cos(y0, y1));
+        match n & 1 {
+            0 => sin / cos,
+            _ => -cos / sin,
+        }
+    }
+
+    fn asin(self) -> f64 {
+        self.atan2(((1. - self) * (1. + self)).sqrt())
+    }
+
+    fn acos(self) -> f64 {
+        ((1. - self) * (1. + self)).sqrt().atan2(self)
+    }
+
+    fn atan(self) -> f64 {
+        const ATAN_HI: [f64; 4] = [4.63647609000806093515e-01, FRAC_PI_4, 9.82793723247329054082e-01, FRAC_PI_2];
+        const ATAN_LO: [f64; 4] = [2.26987774529616870924e-17, 3.06161699786838301793e-17, 1.39033110312309984516e-17, 6.12323399573676603587e-17];
+        const AT: [f64; 11] = [
+            3.33333333333329318027e-01, -1.99999999998764832476e-01, 1.42857142725034663711e-01, -1.11111104054623557880e-01,
+            9.09088713343650656196e-02, -7.69187620504482999495e-02, 6.66107313738753120669e-02, -5.83357013379057348645e-02,
+            4.97687799461593236017e-02, -3.65315727442169155270e-02, 1.62858201153657823623e-02,
+        ];
+        if self.is_nan() {
+            return self;
+        }
+        let a = self.abs();
+        if a < 2f64.powi(-27) {
+            return self;
+        }
+        if a >= 2f64.powi(66) {
+            return (ATAN_HI[3] + ATAN_LO[3]).copysign(self);
+        }
+        let (id, x) = match a {
+            _ if a < 0.4375 => (None, self),
+            _ if a < 0.6875 => (Some(0), (2. * a - 1.) / (2. + a)),
+            _ if a < 1.1875 => (Some(1), (a - 1.) / (a + 1.)),
+            _ if a < 2.4375 => (Some(2), (a - 1.5) / (1. + 1.5 * a)),
+            _ => (Some(3), -1. / a),
+        };
+        let z = x * x;
+        let w = z * z;
+        let s1 = z * (AT[0] + w * (AT[2] + w * (AT[4] + w * (AT[6] + w * (AT[8] + w * AT[10])))));
+        let s2 = w * (AT[1] + w * (AT[3] + w * (AT[5] + w * (AT[7] + w * AT[9]))));
+        match id {
+            None => x - x * (s1 + s2),
+            Some(id) => (ATAN_HI[id] - ((x * (s1 + s2) - ATAN_LO[id]) - x)).copysign(self),
+        }
+    }
+
+    /// The angle of the point (`other`, `self`).
+    fn atan2(self, other: f64) -> f64 {
+        let (y, x) = (self, other);
+        if x.is_nan() || y.is_nan() {
+            return f64::NAN;
+        }
+        if y == 0. {
+            return if x.is_sign_positive() { y } else { PI.copysign(y) };
+        }
+        if x == 0. {
+            return FRAC_PI_2.copysign(y);
+        }
+        if x.is_infinite() {
+            let angle = match (y.is_infinite(), x > 0.) {
+                (true, true) => FRAC_PI_4,
+                (true, false) => 3. * FRAC_PI_4,
+                (false, true) => 0.,
+                (false, false) => PI,
+            };
+            return angle.copysign(y);
+        }
+        if y.is_infinite() {
+            return FRAC_PI_2.copysign(y);
+        }
+        let z = (y / x).abs().atan();
+        if x > 0. { z.copysign(y) } else { (PI - z).copysign(y) }
+    }
+
+    fn sinh(self) -> f64 {
+        let a = self.abs();
+        let half = 0.5f64.copysign(self);
+        if a < 709. {
+            let t = a.exp_m1();
+            return half * (t + t / (t + 1.));
+        }
+        let e = (0.5 * a).exp();
+        half * e * e
+    }
+
+    fn cosh(self) -> f64 {
+        let a = self.abs();
+        if a < 709. {
+            let t = a.exp();
+            return 0.5 * (t + 1. / t);
+        }
+        let e = (0.5 * a).exp();
+        0.5 * e * e
+    }
+
+    fn tanh(self) -> f64 {
+        if self.is_nan() {
+            return self;
+        }
+        let a = self.abs();
+        if a > 22. {
+            return 1f64.copysign(self);
+        }
+        let t = (2. * a).exp_m1();
+        (t / (t + 2.)).copysign(self)
+    }
+
+    fn asinh(self) -> f64 {
+        let a = self.abs();
+        let ret = match a {
+            _ if a > 2f64.powi(28) => a.ln() + LN_2,
+            _ => (a + a * a / (1. + (1. + a * a).sqrt())).ln_1p(),
+        };
+        ret.copysign(self)
+    }
+
+    fn acosh(self) -> f64 {
+        match self {
+            x if x > 2f64.powi(28) => x.ln() + LN_2,
+            x if x >= 1. => {
+                let t = x - 1.;
+                (t + (2. * t + t * t).sqrt()).ln_1p()
+            }
+            _ => f64::NAN,
+        }
+    }
+
+    fn atanh(self) -> f64 {
+        let a = self.abs();
+        (0.5 * (2. * a / (1. - a)).ln_1p()).copysign(self)
+    }
+}
+
+const LN2_HI: f64 = 6.93147180369123816490e-01;
+const LN2_LO: f64 = 1.90821492927058770002e-10;
+
+/// `x * 2^n` without overflowing on the way.
+fn scalbn(x: f64, n: i32) -> f64 {
+    let two_1023 = f64::from_bits(0x7fe0_0000_0000_0000);
+    let two_969_down = f64::from_bits(0x0010_0000_0000_0000) * f64::from_bits(0x4340_0000_0000_0000);
+    let (mut y, mut n) = (x, n);
+    for _ in 0..2 {
+        if n > 1023 {
+            y *= two_1023;
+            n -= 1023;
+        } else if n < -1022 {
+            y *= two_969_down;
+            n += 1022 - 53;
+        }
+    }
+    y * f64::from_bits(((0x3ff + n.clamp(-1022, 1023)) as u64) << 52)
+}
+
+/// The significand of a positive finite `x` with the implicit bit and the
+/// power of two it is multiplied by.
+fn significand(x: f64) -> (u64, i32) {
+    let bits = x.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i32;
+    match exponent {
+        0 => {
+            let shift = bits.leading_zeros() - 11;
+            (bits << shift, -1074 - shift as i32)
+        }
+        _ => ((bits & ((1 << 52) - 1)) | 1 << 52, exponent - 1075),
+    }
+}
+
+/// A positive finite `x` as `m * 2^k` with `1 <= m < 2`.
+fn unit(x: f64) -> (f64, i32) {
+    let (significand, exponent) = significand(x);
+    let shift = significand.leading_zeros() as i32 - 11;
+    let m = f64::from_bits(((significand << shift) & ((1 << 52) - 1)) | 0x3ff0_0000_0000_0000);
+    (m, exponent + 52 - shift)
+}
+
+/// `a * b` as a sum of two floats, exactly.
+fn two_product(a: f64, b: f64) -> (f64, f64) {
+    let split = |x: f64| {
+        let t = 134217729. * x;
+        let hi = t - (t - x);
+        (hi, x - hi)
+    };
+    let p = a * b;
+    let ((ah, al), (bh, bl)) = (split(a), split(b));
+    (p, ((ah * bh - p) + ah * bl + al * bh) + al * bl)
+}
+
+/// `x^n` for a positive `x` as `m * 2^e`, with `m` in double-double
+/// precision before it is rounded, which the power of a float to a large `n`
+/// otherwise needs many digits more than it has for.
+fn powi_exact(x: f64, n: u64) -> (f64, i64) {
+    let multiply = |(ah, al, ae): (f64, f64, i64), (bh, bl, be): (f64, f64, i64)| {
+        let (p, e) = two_product(ah, bh);
+        let e = e + ah * bl + al * bh;
+        let (hi, lo) = (p + e, e - ((p + e) - p));
+        // Back to 1 <= hi < 2, so the next product neither overflows nor
+        // splits inexactly.
+        let (m, k) = unit(hi);
+        let scale = hi / m;
+        (m, lo / scale, ae + be + k as i64)
+    };
+    let (m, k) = unit(x);
+    let mut base = (m, 0., k as i64);
+    let mut ret = (1., 0., 0);
+    let mut rest = n;
+    while rest > 0 {
+        if rest & 1 == 1 {
+            ret = multiply(ret, base);
+        }
+        rest >>= 1;
+        if rest > 0 {
+            base = multiply(base, base);
+        }
+    }
+    (ret.0 + ret.1, ret.2)
+}
+
+/// `x` as `n * pi/2 + y0 + y1` with `|y0 + y1| <= pi/4`. Up to 2^20 * pi/2
+/// with three parts of pi/2, each carrying 33 bits more than the one before,
+/// past it with the bits of 2/pi.
+fn reduce(x: f64) -> (i64, f64, f64) {
+    const PIO2_1: f64 = 1.57079632673412561417e+00;
+    const PIO2_1T: f64 = 6.07710050650619224932e-11;
+    const PIO2_2: f64 = 6.07710050630396597660e-11;
+    const PIO2_2T: f64 = 2.02226624879595063154e-21;
+    const PIO2_3: f64 = 2.02226624871116645580e-21;
+    const PIO2_3T: f64 = 8.47842766036889956997e-32;
+    if !x.is_finite() {
+        return (0, f64::NAN, 0.);
+    }
+    if x.abs() <= FRAC_PI_4 {
+        return (0, x, 0.);
+    }
+    if x.abs() >= 1647099.3291652855 {
+        return reduce_large(x);
+    }
+    let n = (x * FRAC_2_PI).round();
+    let mut r = x - n * PIO2_1;
+    let mut w = n * PIO2_1T;
+    for (part, tail) in [(PIO2_2, PIO2_2T), (PIO2_3, PIO2_3T)] {
+        let t = r;
+        w = n * part;
+        r = t - w;
+        w = n * tail - ((t - r) - w);
+    }
+    let y0 = r - w;
+    (n as i64, y0, (r - y0) - w)
+}
+
+/// The bits of 2/pi after the point, enough for the largest float.
+const TWO_OVER_PI: [u64; 20] = [
+    0xa2f9836e4e441529, 0xfc2757d1f534ddc0, 0xdb6295993c439041, 0xfe5163abdebbc561,
+    0xb7246e3a424dd2e0, 0x06492eea09d1921c, 0xfe1deb1cb129a73e, 0xe88235f52ebb4484,
+    0xe99c7026b45f7e41, 0x3991d639835339f4, 0x9c845f8bbdf9283b, 0x1ff897ffde05980f,
+    0xef2f118b5a0a6d1f, 0x6d367ecf27cb09b7, 0x4f463f669e5fea2d, 0x7527bac7ebe5f17b,
+    0x3d0739f78a5292ea, 0x6bfb5fb11f8d5d08, 0x56033046fc7b6bab, 0xf0cfbc209af4361d,
+];
+
+/// `reduce` for a large finite `x = m * 2^e`. Bits of 2/pi worth 4 or more
+/// times `m` give whole turns, so `m` is multiplied by only the 192 bits
+/// after them, which leave the remainder over 60 bits however close `x` is
+/// to a multiple of pi/2.
+fn reduce_large(x: f64) -> (i64, f64, f64) {
+    let (m, e) = significand(x.abs());
+    // The bits of 2/pi from the `start`th after the point.
+    let bits = |start: usize| {
+        let (limb, offset) = ((start - 1) / 64, (start - 1) % 64);
+        match offset {
+            0 => TWO_OVER_PI[limb],
+            _ => TWO_OVER_PI[limb] << offset | TWO_OVER_PI[limb + 1] >> (64 - offset),
+        }
+    };
+    let start = (e - 1).max(1) as usize;
+    let window = [bits(start + 128), bits(start + 64), bits(start)];
+    // m * window, least significant limb first.
+    let mut product = [0u64; 4];
+    let mut carry = 0u128;
+    for (i, limb) in window.into_iter().enumerate() {
+        let t = m as u128 * limb as u128 + carry;
+        product[i] = t as u64;
+        carry = t >> 64;
+    }
+    product[3] = carry as u64;
+    // The 128 bits of the product from the `lo`th.
+    let at = |lo: usize| {
+        (0..128).step_by(64).fold(0u128, |acc, shift| {
+            let bit = lo + shift;
+            let (limb, offset) = (bit / 64, bit % 64);
+            let word = product.get(limb).map_or(0, |&w| w >> offset)
+                | match offset {
+                    0 => 0,
+                    _ => product.get(limb + 1).map_or(0, |&w| w << (64 - offset)),
+                };
+            acc | (word as u128) << shift
+        })
+    };
+    // The point is after this many bits of the product.
+    let point = (start as i32 + 191 - e) as usize;
+    let mut n = (at(point) & 3) as i64;
+    let fraction = at(point - 128);
+    let (magnitude, negative) = match fraction >> 127 {
+        0 => (fraction, false),
+        _ => {
+            n += 1;
+            (fraction.wrapping_neg(), true)
+        }
+    };
+    let hi = magnitude as f64;
+    let lo = (magnitude as i128 - hi as u128 as i128) as f64;
+    let (hi, lo) = (scalbn(hi, -128), scalbn(lo, -128));
+    // Times pi/2 in double-double.
+    const PIO2_LO: f64 = 6.12323399573676603587e-17;
+    let (p, err) = two_product(hi, FRAC_PI_2);
+    let err = err + hi * PIO2_LO + lo * FRAC_PI_2;
+    let y0 = p + err;
+    let y1 = err - (y0 - p);
+    match (negative, x < 0.) {
+        (false, false) => (n, y0, y1),
+        (true, false) => (n, -y0, -y1),
+        (false, true) => (-n, -y0, -y1),
+        (true, true) => (-n, y0, y1),
+    }
+}
+
+/// The sine of `x + y` for `|x + y| <= pi/4`, `y` the tail of `x`.
+fn kernel_sin(x: f64, y: f64) -> f64 {
+    const S1: f64 = -1.66666666666666324348e-01;
+    const S2: f64 = 8.33333333332248946124e-03;
+    const S3: f64 = -1.98412698298579493134e-04;
+    const S4: f64 = 2.75573137070700676789e-06;
+    const S5: f64 = -2.50507602534068634195e-08;
+    const S6: f64 = 1.58969099521155010221e-10;
+    let z = x * x;
+    let w = z * z;
+    let r = S2 + z * (S3 + z * S4) + z * w * (S5 + z * S6);
+    let v = z * x;
+    x - ((z * (0.5 * y - v * r) - y) - v * S1)
+}
+
+/// The cosine of `x + y` for `|x + y| <= pi/4`.
+fn kernel_cos(x: f64, y: f64) -> f64 {
+    const C1: f64 = 4.16666666666666019037e-02;
+    const C2: f64 = -1.38888888888741095749e-03;
+    const C3: f64 = 2.48015872894767294178e-05;
+    const C4: f64 = -2.75573143513906633035e-07;
+    const C5: f64 = 2.08757232129817482790e-09;
+    const C6: f64 = -1.13596475577881948265e-11;
+    let z = x * x;
+    let w = z * z;
+    let r = z * (C1 + z * (C2 + z * C3)) + w * w * (C4 + z * (C5 + z * C6));
+    let hz = 0.5 * z;
+    let w = 1. - hz;
+    w + (((1. - w) - hz) + (z * r - x * y))
+}
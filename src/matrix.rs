@@ -0,0 +1,272 @@
+use core::fmt;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::calculator::{integer, Function};
+use crate::value::Value;
+
+/// Numbers in rows of the same length, written `[[1, 2], [3, 4]]` with the
+/// `matrix` feature.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Matrix {
+    rows: usize,
+    columns: usize,
+    /// Row by row.
+    data: Vec<f64>,
+}
+
+impl Matrix {
+    pub fn from_rows(rows: Vec<Vec<f64>>) -> Result<Self, String> {
+        let columns = rows.first().map_or(0, Vec::len);
+        if columns == 0 {
+            return Err("Expected a matrix with at least one number".to_string());
+        }
+        if let Some(row) = rows.iter().find(|row| row.len() != columns) {
+            return Err(format!("Expected rows of {} numbers but got a row of {}", columns, row.len()));
+        }
+        Ok(Self { rows: rows.len(), columns, data: rows.concat() })
+    }
+
+    pub fn identity(n: usize) -> Self {
+        let data = (0..n * n).map(|i| if i / n == i % n { 1. } else { 0. }).collect();
+        Self { rows: n, columns: n, data }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn row(&self, row: usize) -> &[f64] {
+        &self.data[row * self.columns..(row + 1) * self.columns]
+    }
+
+    pub fn get(&self, row: usize, column: usize) -> f64 {
+        self.data[row * self.columns + column]
+    }
+
+    pub fn transpose(&self) -> Self {
+        let data = (0..self.data.len()).map(|i| self.get(i % self.rows, i / self.rows)).collect();
+        Self { rows: self.columns, columns: self.rows, data }
+    }
+
+    pub fn map(&self, f: impl FnMut(f64) -> Result<f64, String>) -> Result<Self, String> {
+        let data = self.data.iter().copied().map(f).collect::<Result<_, _>>()?;
+        Ok(Self { data, ..*self })
+    }
+
+    /// `f` applied to the numbers in the same place of two matrices of the
+    /// same shape.
+    pub fn zip(&self, other: &Self, f: fn(f64, f64) -> f64) -> Result<Self, String> {
+        if (self.rows, self.columns) != (other.rows, other.columns) {
+            return Err(format!("Expected matrices of the same shape but got {} and {}", self.shape(), other.shape()));
+        }
+        let data = self.data.iter().zip(&other.data).map(|(&x, &y)| f(x, y)).collect();
+        Ok(Self { data, ..*self })
+    }
+
+    /// The matrix product.
+    pub fn mul(&self, other: &Self) -> Result<Self, String> {
+        if self.columns != other.rows {
+            return Err(format!("Can't multiply a {} matrix by a {} matrix", self.shape(), other.shape()));
+        }
+        let data = (0..self.rows * other.columns)
+            .map(|i| (0..self.columns).map(|k| self.get(i / other.columns, k) * other.get(k, i % other.columns)).sum())
+            .collect();
+        Ok(Self { rows: self.rows, columns: other.columns, data })
+    }
+
+    /// The matrix times itself `n` times, the inverse's for a negative `n`.
+    pub fn pow(&self, n: i64) -> Result<Self, String> {
+        self.square()?;
+        let mut base = if n < 0 { self.inv()? } else { self.clone() };
+        let mut ret = Self::identity(self.rows);
+        let mut n = n.unsigned_abs();
+        while n > 0 {
+            if n & 1 == 1 {
+                ret = ret.mul(&base)?;
+            }
+            base = base.mul(&base)?;
+            n >>= 1;
+        }
+        Ok(ret)
+    }
+
+    /// The determinant, by Gaussian elimination.
+    pub fn det(&self) -> Result<f64, String> {
+        self.square()?;
+        let (mut m, n) = (self.data.clone(), self.rows);
+        let mut ret = 1.;
+        for column in 0..n {
+            let pivot = (column..n).max_by(|&a, &b| m[a * n + column].abs().total_cmp(&m[b * n + column].abs())).unwrap();
+            if m[pivot * n + column] == 0. {
+                return Ok(0.);
+            }
+            if pivot != column {
+                for k in 0..n {
+                    m.swap(pivot * n + k, column * n + k);
+                }
+                ret = -ret;
+            }
+            ret *= m[column * n + column];
+            for row in column + 1..n {
+                let factor = m[row * n + column] / m[column * n + column];
+                for k in column..n {
+                    m[row * n + k] -= factor * m[column * n + k];
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    /// The inverse, by Gauss-Jordan elimination.
+    pub fn inv(&self) -> Result<Self, String> {
+        self.square()?;
+        let n = self.rows;
+        let (mut m, mut ret) = (self.clone(), Self::identity(n));
+        for column in 0..n {
+            let pivot = (column..n).max_by(|&a, &b| m.get(a, column).abs().total_cmp(&m.get(b, column).abs())).unwrap();
+            if m.get(pivot, column).abs() < 1e-12 {
+                return Err("The matrix is singular, it has no inverse".to_string());
+            }
+            for k in 0..n {
+                m.data.swap(pivot * n + k, column * n + k);
+                ret.data.swap(pivot * n + k, column * n + k);
+            }
+            let scale = m.get(column, column);
+            for k in 0..n {
+                m.data[column * n + k] /= scale;
+                ret.data[column * n + k] /= scale;
+            }
+            for row in (0..n).filter(|&row| row != column) {
+                let factor = m.get(row, column);
+                for k in 0..n {
+                    m.data[row * n + k] -= factor * m.get(column, k);
+                    ret.data[row * n + k] -= factor * ret.get(column, k);
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    /// The `x` of `self * x = b`, by Gaussian elimination.
+    pub fn solve(&self, b: &[f64]) -> Result<Vec<f64>, String> {
+        self.square()?;
+        let n = self.rows;
+        if b.len() != n {
+            return Err(format!("Expected a vector of {} numbers but got {}", n, b.len()));
+        }
+        let (mut m, mut x) = (self.data.clone(), b.to_vec());
+        for column in 0..n {
+            let pivot = (column..n).max_by(|&a, &b| m[a * n + column].abs().total_cmp(&m[b * n + column].abs())).unwrap();
+            if m[pivot * n + column].abs() < 1e-12 {
+                return Err("The matrix is singular, the system has no unique solution".to_string());
+            }
+            for k in 0..n {
+                m.swap(pivot * n + k, column * n + k);
+            }
+            x.swap(pivot, column);
+            for row in column + 1..n {
+                let factor = m[row * n + column] / m[column * n + column];
+                for k in column..n {
+                    m[row * n + k] -= factor * m[column * n + k];
+                }
+                x[row] -= factor * x[column];
+            }
+        }
+        for row in (0..n).rev() {
+            let known: f64 = (row + 1..n).map(|k| m[row * n + k] * x[k]).sum();
+            x[row] = (x[row] - known) / m[row * n + row];
+        }
+        Ok(x)
+    }
+
+    fn square(&self) -> Result<(), String> {
+        if self.rows == self.columns {
+            Ok(())
+        } else {
+            Err(format!("Expected a square matrix but got a {} one", self.shape()))
+        }
+    }
+
+    /// Like `2x3` for 2 rows of 3.
+    fn shape(&self) -> String {
+        format!("{}x{}", self.rows, self.columns)
+    }
+}
+
+impl fmt::Display for Matrix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows: Vec<String> = (0..self.rows).map(|row| Value::List(self.row(row).to_vec()).to_string()).collect();
+        write!(f, "[{}]", rows.join(", "))
+    }
+}
+
+/// An infix operator between a matrix and another value, `None` if neither
+/// is a matrix. `*` between matrices and lists is the matrix product, as is
+/// `^` with an integer power, the other operators apply number by number.
+pub(crate) fn infix(symbol: &str, lhs: &Value, rhs: &Value, f: fn(f64, f64) -> f64) -> Option<Result<Value, String>> {
+    let ret = match (lhs, rhs) {
+        (Value::Matrix(a), Value::Matrix(b)) if symbol == "*" => a.mul(b).map(Value::Matrix),
+        (Value::Matrix(a), Value::Matrix(b)) => a.zip(b, f).map(Value::Matrix),
+        (Value::Matrix(a), &Value::Number(y)) if symbol == "^" => integer(y).and_then(|n| a.pow(n)).map(Value::Matrix),
+        (Value::Matrix(a), &Value::Number(y)) => a.map(|x| Ok(f(x, y))).map(Value::Matrix),
+        (&Value::Number(x), Value::Matrix(b)) => b.map(|y| Ok(f(x, y))).map(Value::Matrix),
+        (Value::Matrix(a), Value::List(v)) if symbol == "*" => column(v).and_then(|v| a.mul(&v)).map(|m| Value::List(m.data)),
+        (Value::List(v), Value::Matrix(b)) if symbol == "*" => row(v).and_then(|v| v.mul(b)).map(|m| Value::List(m.data)),
+        (Value::Matrix(_), _) | (_, Value::Matrix(_)) => Err(format!("Can't apply '{}' to {} and {}", symbol, lhs, rhs)),
+        _ => return None,
+    };
+    Some(ret)
+}
+
+fn row(v: &[f64]) -> Result<Matrix, String> {
+    Matrix::from_rows(vec![v.to_vec()])
+}
+
+fn column(v: &[f64]) -> Result<Matrix, String> {
+    row(v).map(|m| m.transpose())
+}
+
+/// Functions of matrices and of lists as vectors.
+pub(crate) fn functions() -> Vec<(&'static str, Function)> {
+    vec![
+        ("det(A)", Function::Values(1, |args| Ok(Value::Number(matrix(&args[0])?.det()?)))),
+        ("inv(A)", Function::Values(1, |args| Ok(Value::Matrix(matrix(&args[0])?.inv()?)))),
+        ("linsolve(A, b)", Function::Values(2, |args| {
+            let b = args[1].list().ok_or_else(|| format!("Expected a list but got {}", args[1]))?;
+            Ok(Value::List(matrix(&args[0])?.solve(b)?))
+        })),
+        ("transpose(A)", Function::Values(1, |args| match &args[0] {
+            Value::List(v) => column(v).map(Value::Matrix),
+            value => Ok(Value::Matrix(matrix(value)?.transpose())),
+        })),
+        ("dot(a, b)", Function::Values(2, |args| {
+            let (a, b) = vectors(&args[0], &args[1])?;
+            Ok(Value::Number(a.iter().zip(b).map(|(x, y)| x * y).sum()))
+        })),
+        ("cross(a, b)", Function::Values(2, |args| match vectors(&args[0], &args[1])? {
+            (&[a1, a2, a3], &[b1, b2, b3]) => Ok(Value::List(vec![a2 * b3 - a3 * b2, a3 * b1 - a1 * b3, a1 * b2 - a2 * b1])),
+            (a, _) => Err(format!("Expected vectors of 3 numbers but got {}", a.len())),
+        })),
+    ]
+}
+
+fn matrix(value: &Value) -> Result<&Matrix, String> {
+    match value {
+        Value::Matrix(m) => Ok(m),
+        value => Err(format!("Expected a matrix but got {}", value)),
+    }
+}
+
+/// Two lists of the same length.
+fn vectors<'a>(a: &'a Value, b: &'a Value) -> Result<(&'a [f64], &'a [f64]), String> {
+    let list = |value: &'a Value| value.list().ok_or_else(|| format!("Expected a list but got {}", value));
+    let (a, b) = (list(a)?, list(b)?);
+    if a.len() != b.len() {
+        return Err(format!("Expected lists of the same length but got {} and {} numbers", a.len(), b.len()));
+    }
+    Ok((a, b))
+}
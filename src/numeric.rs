@@ -0,0 +1,75 @@
+use crate::bigint::BigInt;
+use crate::calculator::Arithmetic;
+#[cfg(feature = "decimal")]
+use crate::decimal::{self, Decimal};
+use crate::exact::Exact;
+use crate::rational::Rational;
+use crate::value::Value;
+
+/// A number type to give a `Calculator` and get its results in, so an
+/// embedder can keep to the one that suits it: `f64`, `f32`, `Rational`,
+/// `BigInt` or, with the `decimal` feature, `Decimal`.
+///
+///   calc.set_var("price", Decimal::parse("19.99").unwrap())?;
+///   let total: Decimal = calc.eval_as("price * 3")?; // exactly 59.97
+///   let area: f32 = calc.eval_as("pi * 2^2")?;
+///
+/// The calculator itself isn't generic over it. A `Value` holds any of
+/// them and the `Arithmetic` decides which an expression is evaluated
+/// with, so one calculator serves all of them and the REPL switches with
+/// `:mode`. `Calculator::eval_as` evaluates with the arithmetic of the type.
+pub trait Numeric: Sized + Into<Value> {
+    /// The arithmetic that gives results of this type.
+    const ARITHMETIC: Arithmetic;
+    /// What `from_value` accepts, for errors, like "an integer".
+    const EXPECTED: &'static str;
+
+    /// The value as this type, None if it isn't a number or doesn't fit.
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+impl Numeric for f64 {
+    const ARITHMETIC: Arithmetic = Arithmetic::Float;
+    const EXPECTED: &'static str = "a number";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        value.number()
+    }
+}
+
+impl Numeric for f32 {
+    const ARITHMETIC: Arithmetic = Arithmetic::Float;
+    const EXPECTED: &'static str = "a number";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        value.number().map(|x| x as f32)
+    }
+}
+
+impl Numeric for Rational {
+    const ARITHMETIC: Arithmetic = Arithmetic::Rational;
+    const EXPECTED: &'static str = "a fraction";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        <Rational as Exact>::from_value(value)
+    }
+}
+
+impl Numeric for BigInt {
+    const ARITHMETIC: Arithmetic = Arithmetic::Integer;
+    const EXPECTED: &'static str = "an integer";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        <BigInt as Exact>::from_value(value)
+    }
+}
+
+#[cfg(feature = "decimal")]
+impl Numeric for Decimal {
+    const ARITHMETIC: Arithmetic = Arithmetic::Decimal { places: decimal::MAX_PLACES };
+    const EXPECTED: &'static str = "a decimal";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        <Decimal as Exact>::from_value(value)
+    }
+}
@@ -0,0 +1,263 @@
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::calculator::factorial;
+
+/// The operator converting a quantity to another unit, `60 mph in km/h`.
+pub(crate) const IN: &str = "in";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Assoc {
+    Left,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Operator {
+    /// Written before its operand, like `-x`.
+    Prefix(fn(f64) -> f64),
+    /// Written after its operand, like `x°`.
+    Postfix(fn(f64) -> f64),
+    /// A postfix operator that can reject its operand, like `n!`.
+    FalliblePostfix(fn(f64) -> Result<f64, String>),
+    /// Written between its operands, like `a + b`.
+    Infix(Assoc, fn(f64, f64) -> f64),
+    /// An infix operator comparing two numbers, like `a < b`.
+    Comparison(fn(f64, f64) -> bool),
+    /// An infix operator combining two booleans, like `a && b`. The right
+    /// operand is only evaluated if it can change the result.
+    Logical(fn(bool, bool) -> bool),
+    /// A prefix operator on a boolean, like `not a`.
+    LogicalPrefix(fn(bool) -> bool),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fixity {
+    Prefix,
+    Postfix,
+    Infix,
+}
+
+impl Operator {
+    pub fn fixity(&self) -> Fixity {
+        match self {
+            Operator::Prefix(_) | Operator::LogicalPrefix(_) => Fixity::Prefix,
+            Operator::Postfix(_) | Operator::FalliblePostfix(_) => Fixity::Postfix,
+            Operator::Infix(..) | Operator::Comparison(_) | Operator::Logical(_) => Fixity::Infix,
+        }
+    }
+
+    /// How an infix operator groups, comparisons and logical operators group
+    /// to the left.
+    pub fn assoc(&self) -> Option<Assoc> {
+        match self {
+            Operator::Prefix(_) | Operator::Postfix(_) | Operator::FalliblePostfix(_) | Operator::LogicalPrefix(_) => None,
+            Operator::Infix(assoc, _) => Some(*assoc),
+            Operator::Comparison(_) | Operator::Logical(_) => Some(Assoc::Left),
+        }
+    }
+}
+
+/// An operator for a symbol the parser read.
+#[derive(Clone, Copy)]
+pub(crate) struct Match {
+    pub precedence: u32,
+    pub operator: Operator,
+}
+
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub symbol: String,
+    pub precedence: u32,
+    pub operator: Operator,
+}
+
+/// The operators the parser knows about, with their precedence.
+///
+/// Higher precedence binds tighter. A prefix operator applies to everything
+/// to its right that binds at least as tight as itself, so with the default
+/// prefix `-` at 25 `-2^2` is `-(2^2)`, while the standard one at 30 makes it
+/// `(-2)^2`.
+///
+/// Grouping conventions differ between tools, the presets cover the common
+/// ones:
+///
+/// | preset     | `^`                   | prefix `-`         | `-2^2` | `2^3^2` |
+/// |------------|-----------------------|--------------------|--------|---------|
+/// | `standard` | 20, left, like `*`    | 30, above `^`      | 4      | 64      |
+/// | `math`     | 30, right             | 25, below `^`      | -4     | 512     |
+/// | `bc`       | 30, right             | 40, above `^`      | 4      | 512     |
+/// | `excel`    | 30, left              | 40, above `^`      | 4      | 64      |
+///
+/// `+` and `-` are at 10, `*`, `/` and `%` at 20 and the factorial `!` at
+/// 40 in all of them, like the percent `%` that no operand follows, below
+/// them are the bitwise `<<` and `>>` at 9, `&` at 8, `xor` at 7 and `|` at
+/// 6, then the comparisons at 5, `not` at 4, `&&` and `and` at 3, `||` and
+/// `or` at 2 and the unit
+/// conversion `in` at 1. `±` is at 11. The prefix `~`
+/// and `√` group like prefix `-`. The bitwise
+/// operators truncate their operands to 64-bit integers. `math`
+/// is the default, following the usual mathematical conventions.
+#[derive(Clone, Debug)]
+pub struct OperatorTable {
+    entries: Vec<Entry>,
+    /// The symbols of `entries`, the longest first, kept for the lexer.
+    symbols: Vec<String>,
+}
+
+pub const PRESETS: [&str; 4] = ["standard", "math", "bc", "excel"];
+
+impl Default for OperatorTable {
+    fn default() -> Self {
+        Self::preset("math").unwrap()
+    }
+}
+
+impl OperatorTable {
+    pub fn new() -> Self {
+        Self { entries: vec![], symbols: vec![] }
+    }
+
+    /// The `standard` preset, where `^` groups like `*`.
+    pub fn standard() -> Self {
+        let mut table = Self::new();
+        let operators = [
+            // @formatter:off
+            ("+", 10, Operator::Infix(Assoc::Left, |a, b| a + b)),
+            ("-", 10, Operator::Infix(Assoc::Left, |a, b| a - b)),
+            ("*", 20, Operator::Infix(Assoc::Left, |a, b| a * b)),
+            ("/", 20, Operator::Infix(Assoc::Left, |a, b| a / b)),
+            ("%", 20, Operator::Infix(Assoc::Left, |a, b| a % b)),
+            // Makes an interval, which the calculator does rather than this.
+            ("±", 11, Operator::Infix(Assoc::Left, |a, _| a)),
+            // Converts a quantity to another unit, also done by the calculator.
+            ("in", 1, Operator::Infix(Assoc::Left, |a, _| a)),
+            ("^", 20, Operator::Infix(Assoc::Left, |a, b| a.powf(b))),
+            ("-", 30, Operator::Prefix(|a| -a)),
+            ("~", 30, Operator::Prefix(|a| !(a as i64) as f64)),
+            ("√", 30, Operator::Prefix(f64::sqrt)),
+            ("!", 40, Operator::FalliblePostfix(factorial)),
+            // A percentage when no operand follows, `15%`.
+            ("%", 40, Operator::Postfix(|a| a / 100.)),
+            ("<<", 9, Operator::Infix(Assoc::Left, shift)),
+            (">>", 9, Operator::Infix(Assoc::Left, |a, n| shift(a, -n))),
+            ("&", 8, Operator::Infix(Assoc::Left, |a, b| (a as i64 & b as i64) as f64)),
+            ("xor", 7, Operator::Infix(Assoc::Left, |a, b| (a as i64 ^ b as i64) as f64)),
+            ("|", 6, Operator::Infix(Assoc::Left, |a, b| (a as i64 | b as i64) as f64)),
+            ("<", 5, Operator::Comparison(|a, b| a < b)),
+            ("<=", 5, Operator::Comparison(|a, b| a <= b)),
+            (">", 5, Operator::Comparison(|a, b| a > b)),
+            (">=", 5, Operator::Comparison(|a, b| a >= b)),
+            ("==", 5, Operator::Comparison(|a, b| a == b)),
+            ("!=", 5, Operator::Comparison(|a, b| a != b)),
+            ("&&", 3, Operator::Logical(|a, b| a && b)),
+            ("||", 2, Operator::Logical(|a, b| a || b)),
+            ("and", 3, Operator::Logical(|a, b| a && b)),
+            ("or", 2, Operator::Logical(|a, b| a || b)),
+            ("not", 4, Operator::LogicalPrefix(|a| !a)),
+            // @formatter:on
+        ];
+        for (symbol, precedence, operator) in operators {
+            table.add(symbol, precedence, operator).unwrap();
+        }
+        table
+    }
+
+    /// One of the `PRESETS`.
+    pub fn preset(name: &str) -> Option<Self> {
+        let mut table = Self::standard();
+        let (power, assoc, negate) = match name {
+            "standard" => return Some(table),
+            "math" => (30, Assoc::Right, 25),
+            "bc" => (30, Assoc::Right, 40),
+            "excel" => (30, Assoc::Left, 40),
+            _ => return None
+        };
+        table.regroup("^", Fixity::Infix, power, Some(assoc)).unwrap();
+        table.regroup("-", Fixity::Prefix, negate, None).unwrap();
+        table.regroup("~", Fixity::Prefix, negate, None).unwrap();
+        table.regroup("√", Fixity::Prefix, negate, None).unwrap();
+        Some(table)
+    }
+
+    /// Changes the precedence, and for infix operators optionally the
+    /// associativity, of an existing operator.
+    pub fn regroup(&mut self, symbol: &str, fixity: Fixity, precedence: u32, assoc: Option<Assoc>) -> Result<(), String> {
+        let entry = self.entries.iter_mut()
+            .find(|entry| entry.symbol == symbol && entry.operator.fixity() == fixity)
+            .ok_or(format!("There is no {:?} operator '{}'", fixity, symbol).to_lowercase())?;
+
+        entry.precedence = precedence;
+        if let (Some(assoc), Operator::Infix(current, _)) = (assoc, &mut entry.operator) {
+            *current = assoc;
+        }
+        Ok(())
+    }
+
+    /// The operators from the tightest binding to the loosest.
+    pub fn entries(&self) -> Vec<&Entry> {
+        let mut entries: Vec<&Entry> = self.entries.iter().collect();
+        entries.sort_by_key(|entry| core::cmp::Reverse(entry.precedence));
+        entries
+    }
+
+    /// Adds an operator, replacing one with the same symbol and fixity.
+    ///
+    /// A symbol is either punctuation like `<>` or a word of ASCII letters
+    /// like `xor`, which can't be used as a name anymore.
+    pub fn add(&mut self, symbol: &str, precedence: u32, operator: Operator) -> Result<(), String> {
+        let word = !symbol.is_empty() && symbol.chars().all(|c| c.is_ascii_alphabetic());
+        if !word && (symbol.is_empty() || symbol == "=" || symbol.chars().any(|c| c.is_alphanumeric() || c.is_whitespace() || "(),.$_".contains(c))) {
+            return Err(format!("'{}' can't be used as an operator symbol", symbol));
+        }
+
+        self.entries.retain(|entry| !(entry.symbol == symbol && entry.operator.fixity() == operator.fixity()));
+        self.entries.push(Entry {
+            symbol: symbol.to_string(),
+            precedence,
+            operator,
+        });
+        if !self.symbols.iter().any(|known| known == symbol) {
+            self.symbols.push(symbol.to_string());
+            self.symbols.sort_by_key(|symbol| core::cmp::Reverse(symbol.chars().count()));
+        }
+        Ok(())
+    }
+
+    /// Every symbol once, the longest first, for the lexer.
+    pub(crate) fn symbols(&self) -> &[String] {
+        &self.symbols
+    }
+
+    pub(crate) fn prefix(&self, symbol: &str) -> Option<Match> {
+        self.find(symbol, Fixity::Prefix)
+    }
+
+    pub(crate) fn postfix(&self, symbol: &str) -> Option<Match> {
+        self.find(symbol, Fixity::Postfix)
+    }
+
+    pub(crate) fn infix(&self, symbol: &str) -> Option<Match> {
+        self.find(symbol, Fixity::Infix)
+    }
+
+    fn find(&self, symbol: &str, fixity: Fixity) -> Option<Match> {
+        self.entries.iter()
+            .find(|entry| entry.symbol == symbol && entry.operator.fixity() == fixity)
+            .map(|entry| Match {
+                precedence: entry.precedence,
+                operator: entry.operator,
+            })
+    }
+}
+
+/// Shifts the integer part of `a` left by `n` bits, or arithmetically right
+/// for negative `n`.
+fn shift(a: f64, n: f64) -> f64 {
+    let (a, n) = (a as i64, n as i64);
+    match n {
+        0..=63 => (a << n) as f64,
+        -63..=-1 => (a >> -n) as f64,
+        _ if n > 0 || a >= 0 => 0.,
+        _ => -1.,
+    }
+}
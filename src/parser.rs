@@ -1,34 +1,184 @@
-pub(crate) struct Parser {
+use core::ops::Range;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::diagnostic::Diagnostic;
+use crate::lexer::{self, tokenize, Token, TokenKind};
+use crate::operator::OperatorTable;
+#[cfg(feature = "units")]
+use crate::units::{self, Unit};
+
+/// A cursor over the tokens of an expression.
+pub(crate) struct Parser<'a> {
     index: usize,
-    expr: Vec<char>,
+    tokens: Vec<Token>,
+    source: &'a str,
 }
 
-impl Parser {
-    pub fn new(expr: String) -> Self {
-        Parser {
-            index: 0,
-            expr: expr.chars().filter(|&c| c != ' ').collect(),
-        }
+impl<'a> Parser<'a> {
+    /// A parser of `expr`, which with `sanitize` normalizes numbers pasted
+    /// from documents first, see `lexer::sanitize`, with `si` reads SI prefixes
+    /// on numbers and with `comma` a decimal comma, see `lexer::tokenize`.
+    pub fn with_options(expr: &'a str, operators: &OperatorTable, sanitize: bool, si: bool, comma: bool) -> Self {
+        let end = expr.chars().count();
+        let tokens = match sanitize {
+            true => {
+                let (text, columns, ambiguous) = lexer::sanitize(expr, comma);
+                let mut tokens = tokenize(&text, Some(&columns), operators.symbols(), end, si, comma);
+                if let Some((column, msg)) = ambiguous {
+                    if let Some(token) = tokens.iter_mut().find(|token| token.span.start == column) {
+                        token.kind = TokenKind::Invalid(msg);
+                    }
+                }
+                tokens
+            }
+            false => tokenize(expr, None, operators.symbols(), end, si, comma),
+        };
+        Parser { index: 0, tokens, source: expr }
+    }
+
+    /// The tokens of the expression, ending with `End`.
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
     }
 
     pub fn advance(&mut self) {
-        self.index += 1;
+        if self.index + 1 < self.tokens.len() {
+            self.index += 1;
+        }
     }
 
-    pub fn peek(&self) -> char {
-        if self.index >= self.expr.len() {
-            '\0'
-        } else {
-            self.expr[self.index]
+    pub fn peek(&self) -> &TokenKind {
+        &self.tokens[self.index].kind
+    }
+
+    fn peek_at(&self, offset: usize) -> &TokenKind {
+        let last = self.tokens.len() - 1;
+        &self.tokens[(self.index + offset).min(last)].kind
+    }
+
+    /// Whether the expression continues with `identifier =`.
+    pub fn at_assignment(&self) -> bool {
+        matches!(self.peek(), TokenKind::Name(_)) && self.peek_at(1) == &TokenKind::Equals
+    }
+
+    /// Whether the expression continues with `identifier :=`.
+    pub fn at_formula(&self) -> bool {
+        matches!(self.peek(), TokenKind::Name(_)) && self.peek_at(1) == &TokenKind::ColonEquals
+    }
+
+    /// Whether the expression continues with `identifier(identifier, ...) =`.
+    pub fn at_definition(&self) -> bool {
+        if !matches!(self.peek(), TokenKind::Name(_)) || self.peek_at(1) != &TokenKind::Open {
+            return false;
+        }
+        let mut i = 2;
+        loop {
+            if !matches!(self.peek_at(i), TokenKind::Name(_)) {
+                return false;
+            }
+            match self.peek_at(i + 1) {
+                TokenKind::Comma => i += 2,
+                TokenKind::Close => return self.peek_at(i + 2) == &TokenKind::Equals,
+                _ => return false,
+            }
+        }
+    }
+
+    /// Whether the expression continues with `(identifier, ...) ->` or
+    /// `identifier ->`.
+    pub fn at_lambda(&self) -> bool {
+        match self.peek() {
+            TokenKind::Name(_) => return self.peek_at(1) == &TokenKind::Arrow,
+            TokenKind::Open => {}
+            _ => return false,
+        }
+        let mut i = 1;
+        loop {
+            if !matches!(self.peek_at(i), TokenKind::Name(_)) {
+                return false;
+            }
+            match self.peek_at(i + 1) {
+                TokenKind::Comma => i += 2,
+                TokenKind::Close => return self.peek_at(i + 2) == &TokenKind::Arrow,
+                _ => return false,
+            }
+        }
+    }
+
+    /// Whether the current token is a name or '(' written right after a
+    /// number or ')', like `2pi` or `(1+2)(3+4)`. Two numbers can only touch
+    /// when one is a `π`, `2π`. A unit can follow a number after a space,
+    /// `5 km`.
+    pub fn at_implicit_product(&self) -> bool {
+        if self.at_unit() {
+            return true;
+        }
+        let Some(prev) = self.index.checked_sub(1).map(|i| &self.tokens[i]) else { return false };
+        matches!(prev.kind, TokenKind::Number(_) | TokenKind::Close)
+            && matches!(self.peek(), TokenKind::Name(_) | TokenKind::Open | TokenKind::Number(_))
+            && prev.span.end == self.position()
+    }
+
+    /// Whether the token after the current one starts an operand, so that
+    /// `%` in `7 % 4` is the remainder and in `7% + 1` a percentage.
+    pub fn operand_follows(&self) -> bool {
+        matches!(self.peek_at(1), TokenKind::Number(_) | TokenKind::Name(_) | TokenKind::Env(_) | TokenKind::Open | TokenKind::OpenBracket)
+    }
+
+    /// Whether the current token is a unit or currency right after a number,
+    /// `5 km`.
+    #[cfg(feature = "units")]
+    pub fn at_unit(&self) -> bool {
+        let Some(prev) = self.index.checked_sub(1).map(|i| &self.tokens[i]) else { return false };
+        matches!((&prev.kind, self.peek()), (TokenKind::Number(_), TokenKind::Name(name)) if Unit::lookup(name).is_some() || units::is_currency(name))
+    }
+
+    /// There are no units without the `units` feature.
+    #[cfg(not(feature = "units"))]
+    pub fn at_unit(&self) -> bool {
+        false
+    }
+
+    /// Columns of the current token in the original expression.
+    pub fn span(&self) -> Range<usize> {
+        self.tokens[self.index].span.clone()
+    }
+
+    /// Column of the current token in the original expression.
+    pub fn position(&self) -> usize {
+        self.span().start
+    }
+
+    /// Column just after the previous token in the original expression.
+    pub fn end(&self) -> usize {
+        match self.index {
+            0 => 0,
+            i => self.tokens[i - 1].span.end,
         }
     }
 
-    pub fn consume(&mut self, c: char, msg: &str) -> Result<(), String> {
-        if self.peek() == c {
+    /// The original text between two columns.
+    pub fn slice(&self, span: Range<usize>) -> String {
+        self.source.chars().skip(span.start).take(span.len()).collect()
+    }
+
+    /// Reads a `kind` token, or fails with what was `expected` like `')'`.
+    pub fn consume(&mut self, kind: TokenKind, expected: &'static str) -> Result<(), Diagnostic> {
+        if self.peek() == &kind {
             self.advance();
             Ok(())
         } else {
-            Err(msg.to_string())
+            Err(Diagnostic::new(format!("Expected {}", expected))
+                .with_expected(expected)
+                .with_label(self.span(), self.unexpected()))
+        }
+    }
+
+    /// Label text describing the current token.
+    pub fn unexpected(&self) -> String {
+        match self.peek() {
+            TokenKind::End => "input ends here".to_string(),
+            _ => format!("unexpected '{}'", self.slice(self.span())),
         }
     }
 }
@@ -0,0 +1,171 @@
+use core::fmt;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+
+/// How many points `plot` evaluates the function at, enough for a graph as
+/// wide as any terminal.
+pub(crate) const SAMPLES: usize = 400;
+
+/// The size a plot is printed at when nothing tells it the window's.
+const WIDTH: usize = 60;
+const HEIGHT: usize = 15;
+
+/// The graph of `plot(sin(x), x, -pi, pi)`, drawn in braille dots by
+/// `render` or at 60 by 15 chars by `Display`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plot {
+    /// `NaN` where the function has no value.
+    points: Vec<(f64, f64)>,
+}
+
+impl Plot {
+    pub(crate) fn new(points: Vec<(f64, f64)>) -> Self {
+        Self { points }
+    }
+
+    pub fn points(&self) -> &[(f64, f64)] {
+        &self.points
+    }
+
+    /// The graph `width` chars wide including the labels and `height`
+    /// lines high including the line with the range of `x`.
+    pub fn render(&self, width: usize, height: usize) -> String {
+        let finite = || self.points.iter().map(|&(_, y)| y).filter(|y| y.is_finite());
+        let (mut low, mut high) = finite().fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), y| (low.min(y), high.max(y)));
+        if low > high {
+            return "The function has no finite values to plot".to_string();
+        }
+        if low == high {
+            (low, high) = (low - 1., high + 1.);
+        }
+        let labels = [short(high), short(low)];
+        let margin = labels.iter().map(String::len).max().unwrap();
+        let columns = width.saturating_sub(margin + 2).max(1);
+        let rows = height.saturating_sub(1).max(1);
+
+        // Each char holds 2 by 4 dots.
+        let (dots_x, dots_y) = (columns * 2, rows * 4);
+        let mut cells = vec![vec![0u8; columns]; rows];
+        let mut set = |x: usize, y: usize| {
+            cells[y / 4][x / 2] |= match (x % 2, y % 4) {
+                (0, 3) => 0x40,
+                (1, 3) => 0x80,
+                (column, row) => 1 << (row + 3 * column),
+            }
+        };
+        let last = self.points.len() - 1;
+        let mut previous: Option<usize> = None;
+        for x in 0..dots_x {
+            let (_, y) = self.points[(x * last + (dots_x - 1) / 2) / (dots_x - 1).max(1)];
+            if !y.is_finite() {
+                previous = None;
+                continue;
+            }
+            let y = ((high - y) / (high - low) * (dots_y - 1) as f64).round() as usize;
+            // Steep parts are joined up to the previous column, jumps across
+            // most of the graph are more likely poles.
+            let (from, to) = match previous {
+                Some(previous) if previous.abs_diff(y) <= dots_y / 2 => (previous.min(y), previous.max(y)),
+                _ => (y, y),
+            };
+            for y in from..=to {
+                set(x, y);
+            }
+            previous = Some(y);
+        }
+
+        let mut lines: Vec<String> = cells.iter().enumerate().map(|(i, row)| {
+            let label = match i {
+                0 => &labels[0],
+                i if i == rows - 1 => &labels[1],
+                _ => "",
+            };
+            let dots: String = row.iter().map(|&bits| char::from_u32(0x2800 + bits as u32).unwrap()).collect();
+            format!("{:>margin$} │{}", label, dots)
+        }).collect();
+        let (from, to) = (short(self.points[0].0), short(self.points[last].0));
+        let gap = columns.saturating_sub(from.len() + to.len()).max(1);
+        lines.push(format!("{:margin$}  {}{:gap$}{}", "", from, "", to));
+        lines.join("\n")
+    }
+}
+
+impl fmt::Display for Plot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(WIDTH, HEIGHT))
+    }
+}
+
+/// The most bins `hist` sorts numbers into.
+pub(crate) const MAX_BINS: usize = 100;
+
+/// How wide the bar of the fullest bin is drawn.
+const BAR: usize = 40;
+
+/// How many numbers of `hist(xs, bins)` fall into each of `bins` ranges of
+/// the same width from the least number to the greatest, which `Display`
+/// shows as a bar chart.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    /// One more than there are bins.
+    edges: Vec<f64>,
+    counts: Vec<usize>,
+}
+
+impl Histogram {
+    /// `xs` are finite, at least one of them, and there are 1 to `MAX_BINS`
+    /// `bins`.
+    pub(crate) fn new(xs: &[f64], bins: usize) -> Self {
+        let (mut low, mut high) = xs.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), &x| (low.min(x), high.max(x)));
+        if low == high {
+            (low, high) = (low - 0.5, high + 0.5);
+        }
+        let width = (high - low) / bins as f64;
+        let edges = (0..=bins).map(|i| if i == bins { high } else { low + width * i as f64 }).collect();
+        let mut counts = vec![0; bins];
+        for &x in xs {
+            // The greatest number goes in the last bin, which is closed.
+            counts[(((x - low) / width) as usize).min(bins - 1)] += 1;
+        }
+        Self { edges, counts }
+    }
+
+    /// The ends of the bins, each bin from one to the next.
+    pub fn edges(&self) -> &[f64] {
+        &self.edges
+    }
+
+    pub fn counts(&self) -> &[usize] {
+        &self.counts
+    }
+}
+
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let last = self.counts.len() - 1;
+        let ranges: Vec<String> = (0..=last)
+            .map(|i| format!("[{}, {}{}", short(self.edges[i]), short(self.edges[i + 1]), if i == last { ']' } else { ')' }))
+            .collect();
+        let most = self.counts.iter().copied().max().unwrap_or(0).max(1);
+        let (margin, digits) = (ranges.iter().map(|range| range.chars().count()).max().unwrap_or(0), most.to_string().len());
+        let lines: Vec<String> = ranges.iter().zip(&self.counts).map(|(range, &count)| {
+            // A bin with any numbers has at least a sliver of a bar.
+            let bar = match count {
+                0 => 0,
+                count => ((count * BAR + most / 2) / most).max(1),
+            };
+            format!("{:margin$} {:>digits$} {}", range, count, "█".repeat(bar)).trim_end().to_string()
+        }).collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// `x` to about 4 significant digits for a label.
+fn short(x: f64) -> String {
+    let places = if x == 0. { 0 } else { (3 - x.abs().log10().floor() as i32).clamp(0, 17) as usize };
+    let ret = format!("{:.*}", places, x);
+    match ret.contains('.') {
+        true => ret.trim_end_matches('0').trim_end_matches('.').to_string(),
+        false => ret,
+    }
+}
@@ -0,0 +1,118 @@
+use std::ffi::{c_char, c_int, CStr};
+use std::path::{Path, PathBuf};
+use calculator::{Calculator, Function};
+
+/// An entry of the array a plugin returns, see `include/calculator_plugin.h`.
+#[repr(C)]
+pub(crate) struct PluginFunction {
+    pub signature: *const c_char,
+    pub call: unsafe extern "C" fn(*const f64, *mut f64) -> c_int,
+}
+
+/// A function pack loaded from a shared library, which stays loaded until
+/// the process exits.
+#[derive(Clone)]
+pub(crate) struct Plugin {
+    /// The file name without the extension, like `finance`.
+    pub name: String,
+    pub path: PathBuf,
+    pub functions: Vec<(String, Function)>,
+}
+
+impl Plugin {
+    /// Adds the functions to `calc`, also in the plugin's namespace like
+    /// `geo::haversine`, for `:use`.
+    pub fn register(&self, calc: &mut Calculator) {
+        for (signature, function) in &self.functions {
+            // Checked when the plugin was loaded.
+            calc.register_function(signature, function.clone()).unwrap();
+            // The file name may not be one, like `libgeo-1.0`.
+            let _ = calc.register_function(&format!("{}::{}", self.name, signature), function.clone());
+        }
+    }
+}
+
+/// Loads the plugins in `dir`, the `.so`, `.dylib` and `.dll` files in it
+/// by name, writing those that fail to stderr.
+pub(crate) fn load_all(dir: &Path) -> Vec<Plugin> {
+    let Ok(entries) = std::fs::read_dir(dir) else { return vec![] };
+    let mut paths: Vec<PathBuf> = entries.filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "so" || extension == "dylib" || extension == "dll"))
+        .collect();
+    paths.sort();
+    paths.into_iter().filter_map(|path| load(&path).map_err(|msg| eprintln!("{}", msg)).ok()).collect()
+}
+
+/// Opens the shared library at `path` and reads its functions.
+pub(crate) fn load(path: &Path) -> Result<Plugin, String> {
+    let context = |msg: String| format!("Failed to load the plugin '{}': {}", path.display(), msg);
+    let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    let (list, count) = open(path).map_err(context)?;
+    let functions = unsafe { functions(list, count) }.map_err(context)?;
+    Ok(Plugin { name, path: path.to_path_buf(), functions })
+}
+
+#[cfg(unix)]
+fn open(path: &Path) -> Result<(*const PluginFunction, usize), String> {
+    use std::os::unix::ffi::OsStrExt;
+    let dlerror = || unsafe {
+        let msg = libc::dlerror();
+        if msg.is_null() { "unknown error".to_string() } else { CStr::from_ptr(msg).to_string_lossy().into_owned() }
+    };
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).map_err(|err| err.to_string())?;
+    unsafe {
+        // Never closed, the functions point into it.
+        let library = libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+        if library.is_null() {
+            return Err(dlerror());
+        }
+        let symbol = libc::dlsym(library, c"calc_plugin_functions".as_ptr());
+        if symbol.is_null() {
+            return Err("it doesn't export calc_plugin_functions".to_string());
+        }
+        let entry: unsafe extern "C" fn(*mut usize) -> *const PluginFunction = std::mem::transmute(symbol);
+        let mut count = 0;
+        let list = entry(&mut count);
+        Ok((list, count))
+    }
+}
+
+#[cfg(not(unix))]
+fn open(_: &Path) -> Result<(*const PluginFunction, usize), String> {
+    Err("plugins are only supported on Unix".to_string())
+}
+
+/// The functions of the `count` entries at `list`, checked by registering
+/// them with a fresh calculator.
+///
+/// # Safety
+///
+/// `list` points at `count` entries with nul-terminated signatures, and
+/// their functions take as many arguments as the signatures say.
+pub(crate) unsafe fn functions(list: *const PluginFunction, count: usize) -> Result<Vec<(String, Function)>, String> {
+    if list.is_null() {
+        return Err("calc_plugin_functions returned null".to_string());
+    }
+    let mut calc = Calculator::new();
+    let mut ret = vec![];
+    for entry in std::slice::from_raw_parts(list, count) {
+        if entry.signature.is_null() {
+            return Err("a function has no signature".to_string());
+        }
+        let signature = CStr::from_ptr(entry.signature).to_str().map_err(|_| "a signature isn't UTF-8".to_string())?.to_string();
+        // Malformed signatures are rejected by `register_function` below.
+        let (name, params) = signature.split_once('(').unwrap_or((&signature, ""));
+        let arity = params.trim_end_matches(')').split(',').filter(|param| !param.trim().is_empty()).count();
+        let (name, call) = (name.trim().to_string(), entry.call);
+        let function = Function::closure(arity, move |args| {
+            let mut out = 0.;
+            match call(args.as_ptr(), &mut out) {
+                0 => Ok(out),
+                code => Err(format!("'{}' failed with code {}", name, code)),
+            }
+        });
+        calc.register_function(&signature, function.clone())?;
+        ret.push((signature, function));
+    }
+    Ok(ret)
+}
@@ -0,0 +1,7 @@
+//! What the prelude of `std` has and that of `core` doesn't, for building
+//! without the `std` feature.
+pub(crate) use alloc::boxed::Box;
+pub(crate) use alloc::string::{String, ToString};
+pub(crate) use alloc::vec::Vec;
+pub(crate) use alloc::{format, vec};
+pub(crate) use crate::math::Float;
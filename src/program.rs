@@ -0,0 +1,219 @@
+use core::ops::Range;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::calculator::{is_series, is_special, CONDITIONAL, SUM};
+use crate::expression::{Expression, Node};
+use crate::interval::PLUS_MINUS;
+use crate::operator::{Operator, IN};
+
+/// An `Expression` compiled to instructions for a stack machine by
+/// `Expression::to_program`, which `Calculator::run_program` runs without
+/// walking the tree or allocating a `Value` per node.
+///
+/// The names and functions are looked up once per run. When one of them
+/// isn't a number or a builtin taking numbers, or with a setting the
+/// instructions don't follow, like strict mode, degrees, exact arithmetic,
+/// `explain` or warnings, the expression is evaluated as `evaluate` would.
+#[derive(Clone, Debug)]
+pub struct Program {
+    pub(crate) code: Vec<Op>,
+    /// The names `Load` reads, each with the span of its first use.
+    pub(crate) names: Vec<(String, Range<usize>)>,
+    /// The function and the number of arguments of every call.
+    pub(crate) calls: Vec<(String, usize)>,
+    pub(crate) spans: Vec<Range<usize>>,
+    /// How many numbers the stack holds at most.
+    pub(crate) depth: usize,
+    /// Whether the result is a boolean, 1 for true and 0 for false.
+    pub(crate) boolean: bool,
+    pub(crate) expression: Expression,
+}
+
+/// An instruction. Booleans are numbers on the stack, 1 and 0. `usize`s
+/// index the `names`, `calls` and `spans` of the program, or the code for
+/// jumps.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Op {
+    Push(f64),
+    Load(usize),
+    /// Counts a step at a span against the limits, before the operands like
+    /// the tree does.
+    Step(usize),
+    Unary(fn(f64) -> f64),
+    FallibleUnary(fn(f64) -> Result<f64, String>, usize),
+    Binary(fn(f64, f64) -> f64),
+    Compare(fn(f64, f64) -> bool),
+    /// Replaces the left operand by the result and jumps past the right one
+    /// when it can't change the result.
+    ShortCircuit(fn(bool, bool) -> bool, usize),
+    Logical(fn(bool, bool) -> bool),
+    LogicalUnary(fn(bool) -> bool),
+    /// Calls a builtin with the arguments on top of the stack, errors point
+    /// at the span.
+    Call(usize, usize),
+    /// Counts a call of `if` in the stats.
+    Count(usize),
+    /// Pops a condition and jumps if it is false.
+    JumpUnless(usize),
+    Jump(usize),
+}
+
+impl Program {
+    /// The program of `expression`, or None if it does anything but compute
+    /// numbers and booleans: lists, assignments, definitions, lambdas, `$VAR`,
+    /// special forms other than `if`, operators like `±` and `in`, or
+    /// operands of the wrong type.
+    pub(crate) fn new(expression: &Expression) -> Option<Self> {
+        let mut program = Program {
+            code: vec![],
+            names: vec![],
+            calls: vec![],
+            spans: vec![],
+            depth: 0,
+            boolean: false,
+            expression: expression.clone(),
+        };
+        let mut height = 0;
+        program.boolean = program.emit(&expression.node, &mut height)?;
+        Some(program)
+    }
+
+    /// Emits the code of `node`, which leaves one more number on the stack
+    /// of `height`. Returns whether it is a boolean.
+    fn emit(&mut self, node: &Node, height: &mut usize) -> Option<bool> {
+        let boolean = match node {
+            &Node::Number(x) => {
+                self.push(Op::Push(x), height);
+                false
+            }
+            Node::Name(name, span) => {
+                let index = match self.names.iter().position(|(known, _)| known == name) {
+                    Some(index) => index,
+                    None => {
+                        self.names.push((name.clone(), span.clone()));
+                        self.names.len() - 1
+                    }
+                };
+                self.push(Op::Load(index), height);
+                false
+            }
+            Node::Unary { operator: Operator::LogicalPrefix(f), operand, span, .. } => {
+                self.step(span);
+                if !self.emit(operand, height)? {
+                    return None;
+                }
+                self.code.push(Op::LogicalUnary(*f));
+                true
+            }
+            Node::Unary { operator, operand, span, .. } => {
+                self.step(span);
+                if self.emit(operand, height)? {
+                    return None;
+                }
+                match *operator {
+                    Operator::Prefix(f) | Operator::Postfix(f) => self.code.push(Op::Unary(f)),
+                    Operator::FalliblePostfix(f) => {
+                        let span = self.span(span);
+                        self.code.push(Op::FallibleUnary(f, span));
+                    }
+                    _ => return None,
+                }
+                false
+            }
+            Node::Binary { symbol, operator, lhs, rhs, span } => {
+                let percent = matches!(&**rhs, Node::Unary { symbol, operator: Operator::Postfix(_), .. } if symbol == "%");
+                if symbol == PLUS_MINUS || symbol == IN || percent && (symbol == "+" || symbol == "-") {
+                    return None;
+                }
+                self.step(span);
+                let (expected, op) = match *operator {
+                    Operator::Infix(_, f) => (false, Op::Binary(f)),
+                    Operator::Comparison(f) => (false, Op::Compare(f)),
+                    Operator::Logical(f) => (true, Op::Logical(f)),
+                    _ => return None,
+                };
+                if self.emit(lhs, height)? != expected {
+                    return None;
+                }
+                let jump = self.code.len();
+                if let Op::Logical(f) = op {
+                    self.code.push(Op::ShortCircuit(f, 0));
+                }
+                if self.emit(rhs, height)? != expected {
+                    return None;
+                }
+                self.code.push(op);
+                if let Op::ShortCircuit(f, _) = self.code[jump] {
+                    self.code[jump] = Op::ShortCircuit(f, self.code.len());
+                }
+                *height -= 1;
+                !matches!(operator, Operator::Infix(..))
+            }
+            Node::Call { name, arguments, span, .. } if name == CONDITIONAL && arguments.len() == 3 => {
+                self.step(span);
+                self.calls.push((name.clone(), 3));
+                self.code.push(Op::Count(self.calls.len() - 1));
+                if !self.emit(&arguments[0], height)? {
+                    return None;
+                }
+                let unless = self.code.len();
+                self.code.push(Op::JumpUnless(0));
+                *height -= 1;
+                let then = self.emit(&arguments[1], height)?;
+                let jump = self.code.len();
+                self.code.push(Op::Jump(0));
+                self.code[unless] = Op::JumpUnless(self.code.len());
+                *height -= 1;
+                if self.emit(&arguments[2], height)? != then {
+                    return None;
+                }
+                self.code[jump] = Op::Jump(self.code.len());
+                then
+            }
+            Node::Call { name, arguments, span, .. } => {
+                if is_special(name) || name == SUM && is_series(arguments) {
+                    return None;
+                }
+                self.step(span);
+                for argument in arguments {
+                    if self.emit(argument, height)? {
+                        return None;
+                    }
+                }
+                self.calls.push((name.clone(), arguments.len()));
+                let span = self.span(span);
+                self.code.push(Op::Call(self.calls.len() - 1, span));
+                *height -= arguments.len();
+                self.push_height(height);
+                false
+            }
+            Node::Env(..) | Node::List(..) | Node::Index { .. } | Node::Assign { .. } | Node::Define { .. } | Node::Lambda { .. } | Node::Sequence(_) => return None,
+        };
+        Some(boolean)
+    }
+
+    fn push(&mut self, op: Op, height: &mut usize) {
+        self.code.push(op);
+        self.push_height(height);
+    }
+
+    fn push_height(&mut self, height: &mut usize) {
+        *height += 1;
+        self.depth = self.depth.max(*height);
+    }
+
+    fn step(&mut self, span: &Range<usize>) {
+        let span = self.span(span);
+        self.code.push(Op::Step(span));
+    }
+
+    fn span(&mut self, span: &Range<usize>) -> usize {
+        self.spans.push(span.clone());
+        self.spans.len() - 1
+    }
+
+    /// The expression the program was compiled from.
+    pub fn expression(&self) -> &Expression {
+        &self.expression
+    }
+}
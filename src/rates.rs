@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use calculator::Calculator;
+use crate::config;
+
+/// Where `:rates update` keeps the rates for later sessions.
+fn path() -> Option<PathBuf> {
+    config::data_dir().map(|dir| dir.join("rates"))
+}
+
+/// Reads exchange rates, how many of each currency one unit of a base
+/// currency buys. Either one `CODE = rate` per line, with `#` comments and
+/// an optional `base = "USD"`, or JSON like
+/// `{"base": "USD", "rates": {"EUR": 0.92}}` as exchange rate services give.
+/// The base currency has a rate of 1.
+pub(crate) fn parse(src: &str) -> Result<HashMap<String, f64>, String> {
+    let mut rates = match src.trim_start().starts_with('{') {
+        true => parse_json(src)?,
+        false => parse_lines(src)?,
+    };
+    rates.retain(|code, _| is_code(code));
+    if rates.is_empty() {
+        return Err("Found no exchange rates".to_string());
+    }
+    if let Some((code, _)) = rates.iter().find(|(_, &rate)| !(rate.is_finite() && rate > 0.)) {
+        return Err(format!("The rate of {} has to be a positive number", code));
+    }
+    Ok(rates)
+}
+
+fn parse_lines(src: &str) -> Result<HashMap<String, f64>, String> {
+    let mut rates = HashMap::new();
+    for (i, line) in src.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        // Table headers like `[rates]` group nothing here.
+        if line.is_empty() || line.starts_with('[') {
+            continue;
+        }
+        let (key, value) = line.split_once('=')
+            .ok_or(format!("line {}: Expected 'CODE = rate'", i + 1))?;
+        let (key, value) = (key.trim().trim_matches('"'), value.trim());
+        if key == "base" {
+            rates.insert(value.trim_matches('"').to_string(), 1.);
+            continue;
+        }
+        let rate = value.parse().map_err(|_| format!("line {}: Expected a number for '{}'", i + 1, key))?;
+        rates.insert(key.to_string(), rate);
+    }
+    Ok(rates)
+}
+
+/// Every `"KEY": number` pair in the document, and `"base"` or `"base_code"`
+/// naming the base, however they are nested.
+fn parse_json(src: &str) -> Result<HashMap<String, f64>, String> {
+    let mut rates = HashMap::new();
+    let mut rest = src;
+    while let Some(start) = rest.find('"') {
+        let Some(end) = rest[start + 1..].find('"').map(|end| start + 1 + end) else {
+            return Err("Expected a closing '\"' in the JSON".to_string());
+        };
+        let key = &rest[start + 1..end];
+        rest = &rest[end + 1..];
+        let Some(value) = rest.trim_start().strip_prefix(':').map(str::trim_start) else { continue };
+        if let Some(value) = value.strip_prefix('"') {
+            if key == "base" || key == "base_code" {
+                rates.insert(value.split('"').next().unwrap().to_string(), 1.);
+            }
+            continue;
+        }
+        let number: String = value.chars().take_while(|c| c.is_ascii_digit() || "+-.eE".contains(*c)).collect();
+        if let Ok(rate) = number.parse() {
+            rates.insert(key.to_string(), rate);
+        }
+    }
+    Ok(rates)
+}
+
+fn is_code(code: &str) -> bool {
+    code.len() == 3 && code.chars().all(|c| c.is_ascii_uppercase())
+}
+
+/// The rates as `parse` reads them back, sorted by code.
+fn write(rates: &HashMap<String, f64>) -> String {
+    let mut lines: Vec<String> = rates.iter().map(|(code, rate)| format!("{} = {}", code, rate)).collect();
+    lines.sort();
+    lines.join("\n") + "\n"
+}
+
+/// Gives `calc` the rates saved by the last `:rates update`, if there are
+/// any.
+pub(crate) fn load(calc: &mut Calculator) {
+    let Some(path) = path().filter(|path| path.exists()) else { return };
+    match fs::read_to_string(&path).map_err(|err| err.to_string()).and_then(|src| parse(&src)) {
+        Ok(rates) => calc.set_rates(rates),
+        Err(msg) => eprintln!("Failed to read '{}': {}", path.display(), msg),
+    }
+}
+
+/// Reads rates from a file or an `http(s)://` URL, the latter through
+/// `curl`, gives them to `calc` and saves them for later sessions. Returns
+/// how many currencies there are.
+pub(crate) fn update(calc: &mut Calculator, source: &str) -> Result<usize, String> {
+    let src = if source.starts_with("http://") || source.starts_with("https://") {
+        let output = Command::new("curl").args(["-fsSL", source]).output()
+            .map_err(|err| format!("Failed to run curl: {}", err))?;
+        if !output.status.success() {
+            return Err(format!("Failed to download '{}': {}", source, String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    } else {
+        fs::read_to_string(source).map_err(|err| format!("Failed to read '{}': {}", source, err))?
+    };
+    let rates = parse(&src).map_err(|msg| format!("{}: {}", source, msg))?;
+    if let Some(path) = path() {
+        fs::create_dir_all(path.parent().unwrap())
+            .and_then(|_| fs::write(&path, write(&rates)))
+            .map_err(|err| format!("Failed to write '{}': {}", path.display(), err))?;
+    }
+    let count = rates.len();
+    calc.set_rates(rates);
+    Ok(count)
+}
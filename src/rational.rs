@@ -0,0 +1,229 @@
+use core::cmp::Ordering;
+use core::fmt;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::exact::Exact;
+use crate::value::Value;
+
+/// An exact fraction `numerator / denominator` in lowest terms, so
+/// `1/3 + 1/6` is `1/2`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rational {
+    numerator: i128,
+    /// Always positive.
+    denominator: i128,
+}
+
+impl Rational {
+    /// `None` for a zero denominator or one that doesn't fit.
+    pub fn new(numerator: i128, denominator: i128) -> Option<Self> {
+        if denominator == 0 {
+            return None;
+        }
+        let g = gcd(numerator, denominator);
+        let sign = denominator.signum();
+        Some(Self { numerator: numerator / g * sign, denominator: (denominator / g).checked_abs()? })
+    }
+
+    pub fn numerator(&self) -> i128 {
+        self.numerator
+    }
+
+    pub fn denominator(&self) -> i128 {
+        self.denominator
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+
+    /// The simplest fraction within about 1e-9 of `x`, from its continued
+    /// fraction, `0.75` is `3/4` and `pi` is `103993/33102`.
+    pub fn approximate(x: f64) -> Option<Self> {
+        if !x.is_finite() || x.abs() >= 1e18 {
+            return None;
+        }
+        let (mut h, mut h1, mut k, mut k1) = (1i128, 0i128, 0i128, 1i128);
+        let mut rest = x;
+        loop {
+            let a = rest.floor();
+            (h, h1) = ((a as i128).checked_mul(h)?.checked_add(h1)?, h);
+            (k, k1) = ((a as i128).checked_mul(k)?.checked_add(k1)?, k);
+            if (x - h as f64 / k as f64).abs() <= 1e-9 * x.abs().max(1.) || rest == a {
+                return Self::new(h, k);
+            }
+            rest = 1. / (rest - a);
+        }
+    }
+
+    /// The terms of the continued fraction, `[0, 1, 3]` for `3/4`, which
+    /// ends at the last one since the fraction is exact.
+    pub fn continued_fraction(self) -> Vec<i128> {
+        let (mut numerator, mut denominator) = (self.numerator, self.denominator);
+        let mut terms = vec![];
+        while denominator != 0 {
+            let term = numerator.div_euclid(denominator);
+            terms.push(term);
+            (numerator, denominator) = (denominator, numerator.rem_euclid(denominator));
+        }
+        terms
+    }
+
+    /// The terms of the continued fraction of `x` as far as the first
+    /// convergent that rounds to `x`, `[0, 3]` for `1/3` rather than those
+    /// of `0.3333333333333333`.
+    pub(crate) fn continued_fraction_of(x: f64) -> Option<Vec<i128>> {
+        let terms = Self::from_f64(x)?.continued_fraction();
+        let (mut h, mut h1, mut k, mut k1) = (1i128, 0i128, 0i128, 1i128);
+        for (i, &term) in terms.iter().enumerate() {
+            (h, h1) = (term.checked_mul(h)?.checked_add(h1)?, h);
+            (k, k1) = (term.checked_mul(k)?.checked_add(k1)?, k);
+            if h as f64 / k as f64 == x {
+                return Some(terms[..=i].to_vec());
+            }
+        }
+        Some(terms)
+    }
+
+    /// The closest fraction to this one with a denominator of at most
+    /// `max_denominator`, `355/113` for pi and 1000. It walks down the
+    /// Stern-Brocot tree a run of turns at a time, which are the terms of the
+    /// continued fraction, and ends between the last convergent that fits and
+    /// the closest mediant on the way to the next.
+    pub fn best_approximation(self, max_denominator: i128) -> Self {
+        if self.denominator <= max_denominator {
+            return self;
+        }
+        let (mut h, mut h1, mut k, mut k1) = (1i128, 0i128, 0i128, 1i128);
+        let (mut numerator, mut denominator) = (self.numerator, self.denominator);
+        loop {
+            let term = numerator.div_euclid(denominator);
+            match term.checked_mul(k).and_then(|k| k.checked_add(k1)) {
+                Some(next) if next <= max_denominator => {
+                    (h, h1, k, k1) = (h1 + term * h, h, next, k);
+                    (numerator, denominator) = (denominator, numerator.rem_euclid(denominator));
+                }
+                _ => break,
+            }
+        }
+        // As many turns of the next run as fit, against the convergent.
+        let turns = (max_denominator - k1) / k;
+        let convergent = Self { numerator: h, denominator: k };
+        let Some(mediant) = turns.checked_mul(h).and_then(|h| h.checked_add(h1)).and_then(|h| Self::new(h, k1 + turns * k)) else {
+            return convergent;
+        };
+        let x = self.to_f64();
+        if (mediant.to_f64() - x).abs() < (convergent.to_f64() - x).abs() { mediant } else { convergent }
+    }
+
+    /// The fraction of the continued fraction `terms`, `None` if it doesn't
+    /// fit or a term after the first isn't positive.
+    pub fn from_continued_fraction(terms: &[i128]) -> Option<Self> {
+        let (&last, rest) = terms.split_last()?;
+        if terms[1..].iter().any(|&term| term <= 0) {
+            return None;
+        }
+        // From the last term back, each step is `term + 1/(h/k)`.
+        let (mut h, mut k) = (last, 1i128);
+        for &term in rest.iter().rev() {
+            (h, k) = (term.checked_mul(h)?.checked_add(k)?, h);
+        }
+        Self::new(h, k)
+    }
+}
+
+impl Exact for Rational {
+    /// Reads the shortest digits of `x`, `0.1` is `1/10`.
+    fn from_f64(x: f64) -> Option<Self> {
+        if !x.is_finite() {
+            return None;
+        }
+        let text = x.to_string();
+        let (int, frac) = text.split_once('.').unwrap_or((&text, ""));
+        let denominator = 10i128.checked_pow(u32::try_from(frac.len()).ok()?)?;
+        Self::new(format!("{}{}", int, frac).parse().ok()?, denominator)
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Fraction(r) => Some(*r),
+            &Value::Number(x) => Self::from_f64(x),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Fraction(self)
+    }
+
+    fn add(&self, other: &Self) -> Option<Self> {
+        let g = gcd(self.denominator, other.denominator);
+        let numerator = self.numerator.checked_mul(other.denominator / g)?
+            .checked_add(other.numerator.checked_mul(self.denominator / g)?)?;
+        Self::new(numerator, (self.denominator / g).checked_mul(other.denominator)?)
+    }
+
+    fn sub(&self, other: &Self) -> Option<Self> {
+        self.add(&other.neg()?)
+    }
+
+    fn mul(&self, other: &Self) -> Option<Self> {
+        // Cancel crosswise first so the products stay small.
+        let (a, b) = (gcd(self.numerator, other.denominator), gcd(other.numerator, self.denominator));
+        Self::new(
+            (self.numerator / a).checked_mul(other.numerator / b)?,
+            (self.denominator / b).checked_mul(other.denominator / a)?,
+        )
+    }
+
+    fn div(&self, other: &Self) -> Option<Self> {
+        self.mul(&Self::new(other.denominator, other.numerator)?)
+    }
+
+    fn neg(&self) -> Option<Self> {
+        Some(Self { numerator: self.numerator.checked_neg()?, ..*self })
+    }
+
+    /// Only whole powers are exact.
+    fn pow(&self, exponent: &Self) -> Option<Self> {
+        if exponent.denominator != 1 {
+            return None;
+        }
+        let n = u32::try_from(exponent.numerator.unsigned_abs()).ok()?;
+        let ret = Self::new(self.numerator.checked_pow(n)?, self.denominator.checked_pow(n)?)?;
+        if exponent.numerator < 0 { Self::new(ret.denominator, ret.numerator) } else { Some(ret) }
+    }
+
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.numerator.checked_mul(other.denominator), other.numerator.checked_mul(self.denominator)) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => self.to_f64().total_cmp(&other.to_f64()),
+        }
+    }
+
+    fn factorial(&self) -> Option<Self> {
+        if self.denominator != 1 || self.numerator < 0 {
+            return None;
+        }
+        let numerator = (2..=self.numerator).try_fold(1i128, |ret, i| ret.checked_mul(i))?;
+        Self::new(numerator, 1)
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.denominator {
+            1 => write!(f, "{}", self.numerator),
+            denominator => write!(f, "{}/{}", self.numerator, denominator),
+        }
+    }
+}
+
+/// Positive, and 1 for two zeros.
+fn gcd(a: i128, b: i128) -> i128 {
+    let (mut a, mut b) = (a.unsigned_abs(), b.unsigned_abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.max(1) as i128
+}
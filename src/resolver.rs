@@ -0,0 +1,24 @@
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::HashMap;
+
+/// Looks up the names that aren't constants or variables, so their values
+/// can come from a database or a spreadsheet row without copying all of
+/// them into the calculator first. See `Calculator::set_resolver`.
+///
+/// A closure taking the name is a resolver, as is a map of names to values.
+pub trait Resolver {
+    fn resolve(&self, name: &str) -> Option<f64>;
+}
+
+impl<F: Fn(&str) -> Option<f64>> Resolver for F {
+    fn resolve(&self, name: &str) -> Option<f64> {
+        self(name)
+    }
+}
+
+impl Resolver for HashMap<String, f64> {
+    fn resolve(&self, name: &str) -> Option<f64> {
+        self.get(name).copied()
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use calculator::{Calculator, Fixity};
+
+/// The stack of `:mode rpn`, where `3 4 + 5 *` pushes 3 and 4, replaces
+/// them with their sum and multiplies that by 5.
+///
+/// Each word of a line is one of:
+///
+///   drop, swap, dup      remove, exchange or copy the top of the stack
+///   clear                empty the stack
+///   an infix operator    replaces the top two numbers, `-` and `/` take
+///                        the top from the one below
+///   a prefix or postfix operator, like `!`, replaces the top number
+///   a function           replaces as many numbers as it has parameters,
+///                        a variadic one like `max` the whole stack
+///
+/// Anything else, like `2.5`, `-3` or `pi`, is evaluated and pushed.
+#[derive(Default)]
+pub(crate) struct Stack {
+    /// The top last.
+    values: Vec<f64>,
+}
+
+impl Stack {
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    /// Runs the words of `line` in order. If one of them fails the stack is
+    /// left as it was before the line.
+    pub fn enter(&mut self, calc: &mut Calculator, line: &str) -> Result<(), String> {
+        let mut values = self.values.clone();
+        for word in line.split_whitespace() {
+            step(calc, &mut values, word).map_err(|msg| format!("{}: {}", word, msg))?;
+        }
+        self.values = values;
+        Ok(())
+    }
+}
+
+fn step(calc: &mut Calculator, values: &mut Vec<f64>, word: &str) -> Result<(), String> {
+    let empty = || "The stack is empty".to_string();
+    match word {
+        "drop" => {
+            values.pop().ok_or_else(empty)?;
+            return Ok(());
+        }
+        "dup" => {
+            let top = *values.last().ok_or_else(empty)?;
+            values.push(top);
+            return Ok(());
+        }
+        "clear" => {
+            values.clear();
+            return Ok(());
+        }
+        "swap" => {
+            let n = values.len();
+            if n < 2 {
+                return Err(format!("Expected 2 numbers on the stack but got {}", n));
+            }
+            values.swap(n - 1, n - 2);
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let fixities: Vec<Fixity> = calc.operators().entries().iter()
+        .filter(|entry| entry.symbol == word)
+        .map(|entry| entry.operator.fixity())
+        .collect();
+    // The operands are bound to `a0`, `a1` and so on, the deepest first.
+    let (arity, expr) = if fixities.contains(&Fixity::Infix) {
+        (2, format!("a0 {} a1", word))
+    } else if fixities.contains(&Fixity::Prefix) {
+        (1, format!("{}a0", word))
+    } else if fixities.contains(&Fixity::Postfix) {
+        (1, format!("a0{}", word))
+    } else if let Some(signature) = signature(calc, word) {
+        let params: Vec<&str> = signature[word.len() + 1..signature.len() - 1].split(", ").filter(|param| !param.is_empty()).collect();
+        let arity = match params.last() {
+            Some(&"...") => values.len().max(params.len() - 1),
+            _ => params.len(),
+        };
+        let names: Vec<String> = (0..arity).map(|i| format!("a{}", i)).collect();
+        (arity, format!("{}({})", word, names.join(", ")))
+    } else {
+        let value = calc.eval(word.to_string()).map_err(|err| err.message)?;
+        values.push(value.number().ok_or(format!("Expected a number but got {}", value))?);
+        return Ok(());
+    };
+
+    if values.len() < arity {
+        return Err(format!("Expected {} numbers on the stack but got {}", arity, values.len()));
+    }
+    let operands = values.split_off(values.len() - arity);
+    let bindings: HashMap<String, f64> = operands.iter().enumerate().map(|(i, &x)| (format!("a{}", i), x)).collect();
+    let value = calc.eval_with(&expr, &bindings).map_err(|err| err.message)?;
+    values.push(value.number().ok_or(format!("Expected a number but got {}", value))?);
+    Ok(())
+}
+
+/// The signature of a builtin or user function, like `log(base, x)`, the
+/// overload with the most parameters.
+fn signature(calc: &Calculator, name: &str) -> Option<String> {
+    calc.signatures().filter(|&(found, _)| found == name).last().map(|(_, signature)| signature.to_string())
+        .or_else(|| calc.definitions().find(|&(found, _)| found == name).map(|(_, signature)| signature))
+}
@@ -0,0 +1,113 @@
+use std::fs;
+use std::path::Path;
+use calculator::{Calculator, Diagnostic, ErrorKind, Importer, Value};
+
+/// The statements of a script with the line each is on. Statements are
+/// separated by newlines or `;`, and a `#` comments out the rest of the line:
+///
+///   # monthly payment
+///   import "finance.calc"
+///   rate = 0.05 / 12; n = 360
+///   200000 * rate / (1 - (1 + rate)^-n)
+pub(crate) fn statements(src: &str) -> Vec<(usize, &str)> {
+    src.lines()
+        .enumerate()
+        .flat_map(|(i, line)| line.split('#').next().unwrap().split(';').map(move |stmt| (i + 1, stmt.trim())))
+        .filter(|(_, stmt)| !stmt.is_empty())
+        .collect()
+}
+
+/// Executes the script in `path`, printing the result of every statement or
+/// with `quiet` only that of the last one. A script with syntax errors isn't
+/// executed, they are all reported at once. Otherwise it stops at the first
+/// error. With `only_check` it stops after reporting the syntax errors.
+/// `import "finance.calc"` lines load a library through `importer`.
+/// Returns whether the script ran to the end.
+pub(crate) fn run(calc: &mut Calculator, importer: &Importer, path: &Path, quiet: bool, only_check: bool) -> bool {
+    let mut last = None;
+    let ret = read(path).and_then(|src| {
+        check(calc, path, &src)?;
+        if only_check {
+            return Ok(());
+        }
+        execute(calc, importer, path, &src, |value| match quiet {
+            true => last = Some(value),
+            false => println!("{}", value),
+        })
+    });
+    if let Some(value) = last {
+        println!("{}", value);
+    }
+    match ret {
+        Ok(()) => true,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            false
+        }
+    }
+}
+
+/// Executes the startup script in `path` if there is one, without printing
+/// results, so the REPL starts with the definitions in it:
+///
+///   tau = 2*pi
+///   vat(x) = x*1.19
+pub(crate) fn init(calc: &mut Calculator, importer: &Importer, path: &Path) {
+    if !path.exists() {
+        return;
+    }
+    if let Err(msg) = read(path).and_then(|src| execute(calc, importer, path, &src, |_| {})) {
+        eprintln!("{}", msg);
+    }
+}
+
+fn read(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|err| format!("error: {}: {}", path.display(), err))
+}
+
+/// The syntax errors of every statement in the script, all of those of a
+/// statement rather than the first, see `Calculator::check`, without
+/// evaluating any of them. Imports aren't checked until they run. Warnings
+/// are printed but don't fail the check.
+pub(crate) fn check(calc: &Calculator, path: &Path, src: &str) -> Result<(), String> {
+    let mut errors = vec![];
+    for (line, stmt) in statements(src).into_iter().filter(|(_, stmt)| Importer::statement(stmt).is_none()) {
+        for err in calc.check(stmt) {
+            match err.kind {
+                ErrorKind::Warning => eprintln!("{}", error(path, line, stmt, &err)),
+                _ => errors.push(error(path, line, stmt, &err)),
+            }
+        }
+    }
+    match errors.len() {
+        0 => Ok(()),
+        1 => Err(errors.concat()),
+        n => Err(format!("{}\n{} syntax errors", errors.join("\n"), n)),
+    }
+}
+
+/// Passes the value of every statement in the script to `output`, the
+/// error is rendered with its location.
+fn execute(calc: &mut Calculator, importer: &Importer, path: &Path, src: &str, mut output: impl FnMut(Value)) -> Result<(), String> {
+    for (line, stmt) in statements(src) {
+        if let Some(file) = Importer::statement(stmt) {
+            importer.import(calc, file, Some(path)).map_err(|err| error(path, line, stmt, &err))?;
+            continue;
+        }
+        match calc.eval(stmt.to_string()) {
+            Ok(_) if calc.is_definition(stmt) => {}
+            Ok(value) => output(value),
+            Err(err) => return Err(error(path, line, stmt, &err)),
+        }
+    }
+    Ok(())
+}
+
+fn error(path: &Path, line: usize, stmt: &str, err: &Diagnostic) -> String {
+    let rendered: Vec<String> = err.render(stmt).lines().map(|line| format!("  {}", line)).collect();
+    let severity = match err.kind {
+        ErrorKind::Warning => "warning",
+        _ => "error",
+    };
+    format!("{}: {}:{}\n{}", severity, path.display(), line, rendered.join("\n"))
+}
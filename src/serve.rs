@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use calculator::{Calculator, Limits};
+use crate::json;
+
+/// The largest request body `serve` reads.
+const MAX_BODY: usize = 64 * 1024;
+
+/// The longest request line or header `serve` reads, and how many headers.
+const MAX_LINE: usize = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+
+/// How many sessions are kept, the one used least recently is dropped for a
+/// new one.
+const MAX_SESSIONS: usize = 100;
+
+/// How long one expression can evaluate, and a client can take to send a
+/// request.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The calculators of `calculator serve`, one per session.
+///
+/// `POST /eval` evaluates the lines of the body like `--json` and responds
+/// with their objects, one per line. With `?session=name` the variables and
+/// functions defined are kept for later requests of the same session,
+/// without one every request starts afresh. Only `MAX_SESSIONS` are kept.
+pub(crate) struct Server<F> {
+    /// Makes the calculator of a new session.
+    calculator: F,
+    /// Each with the request it was last used by, counting from 1.
+    sessions: HashMap<String, (Calculator, u64)>,
+    requests: u64,
+    /// The `Access-Control-Allow-Origin` of responses, none unless given.
+    allow_origin: Option<String>,
+}
+
+impl<F: Fn() -> Calculator> Server<F> {
+    pub fn new(calculator: F, allow_origin: Option<String>) -> Self {
+        Self { calculator, sessions: HashMap::new(), requests: 0, allow_origin }
+    }
+
+    /// The status and body of the response to a request.
+    pub fn respond(&mut self, method: &str, target: &str, body: &str) -> (u16, String) {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        match (method, path) {
+            ("POST", "/eval") => {}
+            (_, "/eval") => return (405, "Expected POST /eval\n".to_string()),
+            _ => return (404, format!("There is no {}, expected POST /eval\n", path)),
+        }
+        let session = query.split('&').find_map(|param| param.strip_prefix("session="));
+        self.requests += 1;
+        if session.is_some_and(|session| !self.sessions.contains_key(session)) && self.sessions.len() >= MAX_SESSIONS {
+            let oldest = self.sessions.iter().min_by_key(|(_, (_, used))| used).map(|(name, _)| name.clone());
+            self.sessions.remove(&oldest.unwrap());
+        }
+        let mut fresh = None;
+        let calc = match session {
+            Some(session) => {
+                let (calc, used) = self.sessions.entry(session.to_string()).or_insert_with(|| (limited((self.calculator)()), 0));
+                *used = self.requests;
+                calc
+            }
+            None => fresh.insert(limited((self.calculator)())),
+        };
+
+        let mut lines = vec![];
+        for expr in body.lines().filter(|line| !line.trim().is_empty()) {
+            let ret = calc.eval(expr.to_string());
+            lines.push(json::result(calc, expr, &ret) + "\n");
+        }
+        (200, lines.concat())
+    }
+
+    /// Reads one request from `stream` and writes the response, then closes
+    /// the connection.
+    fn handle(&mut self, stream: TcpStream) -> std::io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let (status, body) = match read(&mut reader)? {
+            Ok((method, target, body)) => match String::from_utf8(body) {
+                Ok(body) => self.respond(&method, &target, &body),
+                Err(_) => (400, "Expected a UTF-8 body\n".to_string()),
+            },
+            Err(rejected) => rejected,
+        };
+        let reason = match status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            431 => "Request Header Fields Too Large",
+            _ => "Payload Too Large",
+        };
+        let kind = if status == 200 { "application/x-ndjson" } else { "text/plain" };
+        let origin = self.allow_origin.as_ref().map_or(String::new(), |origin| format!("Access-Control-Allow-Origin: {}\r\n", origin));
+        let mut stream = stream;
+        write!(stream, "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+            status, reason, kind, body.len(), origin, body)
+    }
+}
+
+/// The method, target and body of the request in `reader`, or the status and
+/// body of the response rejecting it when a line, the headers or the body are
+/// too long.
+type Request = Result<(String, String, Vec<u8>), (u16, String)>;
+
+pub(crate) fn read(reader: &mut impl BufRead) -> std::io::Result<Request> {
+    let too_long = || Ok(Err((431, format!("Expected lines of at most {} bytes and {} headers\n", MAX_LINE, MAX_HEADERS))));
+    let mut line = String::new();
+    if reader.by_ref().take(MAX_LINE as u64 + 1).read_line(&mut line)? > MAX_LINE {
+        return too_long();
+    }
+    let mut words = line.split_whitespace();
+    let (method, target) = (words.next().unwrap_or("").to_string(), words.next().unwrap_or("").to_string());
+
+    let mut length = 0;
+    for headers in 0.. {
+        line.clear();
+        let read = reader.by_ref().take(MAX_LINE as u64 + 1).read_line(&mut line)?;
+        if read == 0 || line.trim().is_empty() {
+            break;
+        }
+        if read > MAX_LINE || headers == MAX_HEADERS {
+            return too_long();
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    if length > MAX_BODY {
+        return Ok(Err((413, format!("Expected a body of at most {} bytes\n", MAX_BODY))));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Ok((method, target, body)))
+}
+
+/// Bounds the expressions of clients, who may not be trusted.
+fn limited(mut calc: Calculator) -> Calculator {
+    calc.set_limits(Limits {
+        max_length: Some(10_000),
+        max_tokens: Some(5_000),
+        max_depth: Some(100),
+        max_arguments: Some(1_000),
+        max_call_depth: None,
+        max_steps: None,
+        timeout: Some(TIMEOUT),
+    });
+    calc
+}
+
+/// Serves `POST /eval` on `port` of localhost until the process is killed,
+/// to pages of `allow_origin` too if given. Returns false if it can't listen
+/// on the port.
+pub(crate) fn run(calculator: impl Fn() -> Calculator, port: u16, allow_origin: Option<String>) -> bool {
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("Failed to listen on port {}: {}", port, err);
+            return false;
+        }
+    };
+    eprintln!("Serving POST /eval on http://127.0.0.1:{}", port);
+    let mut server = Server::new(calculator, allow_origin);
+    for stream in listener.incoming() {
+        let handled = stream.and_then(|stream| {
+            stream.set_read_timeout(Some(TIMEOUT))?;
+            stream.set_write_timeout(Some(TIMEOUT))?;
+            server.handle(stream)
+        });
+        if let Err(err) = handled {
+            eprintln!("{}", err);
+        }
+    }
+    true
+}
@@ -0,0 +1,119 @@
+//! Gamma, beta and error functions, with the `special-functions` feature.
+use core::f64::consts::PI;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::calculator::{factorial, Function};
+
+/// Special functions for statistics and physics.
+pub(crate) fn functions() -> Vec<(&'static str, Function)> {
+    vec![
+        ("gamma(x)", Function::Fallible(1, |args| gamma(args[0]))),
+        ("lgamma(x)", Function::Fallible(1, |args| lgamma(args[0]))),
+        ("beta(a, b)", Function::Fallible(2, |args| beta(args[0], args[1]))),
+        ("erf(x)", Function::OneArg(erf)),
+        ("erfc(x)", Function::OneArg(erfc)),
+    ]
+}
+
+/// Coefficients of the Lanczos approximation with g = 7, good to about 15
+/// digits.
+const LANCZOS_G: f64 = 7.;
+#[allow(clippy::excessive_precision)]
+const LANCZOS: [f64; 9] = [
+    0.99999999999980993,
+    676.5203681218851,
+    -1259.1392167224028,
+    771.32342877765313,
+    -176.61502916214059,
+    12.507343278686905,
+    -0.13857109526572012,
+    9.9843695780195716e-6,
+    1.5056327351493116e-7,
+];
+
+/// The poles of gamma, where neither it nor its logarithm has a value.
+fn pole(x: f64) -> Result<(), String> {
+    if x <= 0. && x.fract() == 0. {
+        return Err(format!("The gamma function is undefined at the non-positive integer {}", x));
+    }
+    Ok(())
+}
+
+/// `t` and the series of the Lanczos approximation for `x >= 0.5`, with
+/// `gamma(x) = sqrt(2pi) * t^(x - 0.5) * e^-t * series`.
+fn lanczos(x: f64) -> (f64, f64) {
+    let x = x - 1.;
+    let series = LANCZOS[1..].iter()
+        .enumerate()
+        .fold(LANCZOS[0], |sum, (i, p)| sum + p / (x + i as f64 + 1.));
+    (x + LANCZOS_G + 0.5, series)
+}
+
+fn gamma(x: f64) -> Result<f64, String> {
+    pole(x)?;
+    if x.fract() == 0. {
+        return factorial(x - 1.);
+    }
+    if x < 0.5 {
+        // Reflection, gamma(x) * gamma(1 - x) = pi / sin(pi * x).
+        return Ok(PI / ((PI * x).sin() * gamma(1. - x)?));
+    }
+    let (t, series) = lanczos(x);
+    // Halving the power keeps it from overflowing before e^-t shrinks it.
+    let power = t.powf((x - 0.5) / 2.);
+    Ok((2. * PI).sqrt() * power * (-t).exp() * power * series)
+}
+
+/// The logarithm of the absolute value of gamma, which stays finite far past
+/// where gamma overflows.
+fn lgamma(x: f64) -> Result<f64, String> {
+    pole(x)?;
+    if x < 0.5 {
+        return Ok((PI / (PI * x).sin().abs()).ln() - lgamma(1. - x)?);
+    }
+    let (t, series) = lanczos(x);
+    Ok(0.5 * (2. * PI).ln() + (x - 0.5) * t.ln() - t + series.ln())
+}
+
+fn beta(a: f64, b: f64) -> Result<f64, String> {
+    if a > 0. && b > 0. {
+        return Ok((lgamma(a)? + lgamma(b)? - lgamma(a + b)?).exp());
+    }
+    Ok(gamma(a)? * gamma(b)? / gamma(a + b)?)
+}
+
+fn erf(x: f64) -> f64 {
+    if x.is_nan() {
+        return x;
+    }
+    if x.abs() >= 2. {
+        return x.signum() * (1. - erfc(x.abs()));
+    }
+    // erf(x) = 2/sqrt(pi) * e^(-x^2) * sum of 2^n x^(2n+1) / (1*3*...*(2n+1)),
+    // the terms are all positive so nothing cancels.
+    let mut term = x;
+    let mut sum = x;
+    let mut n = 0.;
+    while term.abs() > sum.abs() * f64::EPSILON {
+        n += 1.;
+        term *= 2. * x * x / (2. * n + 1.);
+        sum += term;
+    }
+    2. / PI.sqrt() * (-x * x).exp() * sum
+}
+
+fn erfc(x: f64) -> f64 {
+    if x.is_nan() {
+        return x;
+    }
+    if x < 2. {
+        return 1. - erf(x);
+    }
+    // The continued fraction e^(-x^2)/sqrt(pi) / (x + (1/2)/(x + 1/(x + (3/2)/(x + ...)))),
+    // evaluated from the back.
+    let mut fraction = x;
+    for k in (1..=60).rev() {
+        fraction = x + k as f64 / 2. / fraction;
+    }
+    (-x * x).exp() / PI.sqrt() / fraction
+}
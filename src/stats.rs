@@ -0,0 +1,47 @@
+use core::fmt;
+use core::time::Duration;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::HashMap;
+
+/// Counters the calculator keeps about its evaluations.
+#[derive(Clone, Default)]
+pub struct Stats {
+    pub evaluations: usize,
+    /// Errors by `ErrorKind::name`.
+    pub errors: HashMap<&'static str, usize>,
+    /// Function calls by function name.
+    pub calls: HashMap<String, usize>,
+    /// Evaluations that found their expression in the cache, see
+    /// `Calculator::set_cache_size`.
+    pub cache_hits: usize,
+    /// Time spent inside `eval`.
+    pub time: Duration,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors: usize = self.errors.values().sum();
+        writeln!(f, "{} expressions evaluated, {} failed", self.evaluations, errors)?;
+        if self.cache_hits > 0 {
+            writeln!(f, "{} found compiled in the cache", self.cache_hits)?;
+        }
+
+        let mut errors: Vec<_> = self.errors.iter().collect();
+        errors.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (kind, count) in errors {
+            writeln!(f, "  {:>5} {}", count, kind)?;
+        }
+
+        let mut calls: Vec<_> = self.calls.iter().collect();
+        calls.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        if !calls.is_empty() {
+            writeln!(f, "most used functions:")?;
+            for (name, count) in calls.iter().take(5) {
+                writeln!(f, "  {:>5} {}", count, name)?;
+            }
+        }
+
+        write!(f, "total compute time: {:?}", self.time)
+    }
+}
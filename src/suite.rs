@@ -0,0 +1,79 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use calculator::Calculator;
+
+/// Runs `.calc` test files, directories are searched recursively.
+///
+/// Every line that isn't blank or a `#` comment is evaluated and fails the
+/// run if it doesn't evaluate, so expectations are written with the
+/// `assert` functions:
+///
+///   # clamp
+///   asserteq(clamp(5, 0, 1), 1)
+///   assertnear(sin(pi), 0, 0.000001)
+///
+/// Prints every failure with its location and a summary, and returns
+/// whether everything passed.
+pub(crate) fn run(calc: &mut Calculator, paths: &[PathBuf]) -> bool {
+    let mut files = vec![];
+    let mut passed = 0;
+    let mut failed = 0;
+
+    for path in paths {
+        if let Err(err) = collect(path, &mut files) {
+            println!("error: {}: {}", path.display(), err);
+            failed += 1;
+        }
+    }
+
+    for file in &files {
+        let src = match fs::read_to_string(file) {
+            Ok(src) => src,
+            Err(err) => {
+                println!("error: {}: {}", file.display(), err);
+                failed += 1;
+                continue;
+            }
+        };
+
+        for (i, line) in src.lines().enumerate() {
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+
+            match calc.eval(line.to_string()) {
+                Ok(_) => passed += 1,
+                Err(err) => {
+                    println!("FAIL {}:{}", file.display(), i + 1);
+                    for line in err.render(line).lines() {
+                        println!("  {}", line);
+                    }
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    println!("{} files: {} passed, {} failed", files.len(), passed, failed);
+    failed == 0
+}
+
+fn collect(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if !path.is_dir() {
+        fs::metadata(path)?;
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    let mut entries = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    for entry in entries {
+        if entry.is_dir() || entry.extension().is_some_and(|ext| ext == "calc") {
+            collect(&entry, files)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,706 @@
+use core::fmt;
+use core::ops::Range;
+use alloc::sync::Arc;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::HashMap;
+use crate::expression::Node;
+use crate::operator::{Assoc, Match, Operator, OperatorTable};
+use crate::value::Value;
+
+/// An expression kept as a formula rather than evaluated, like the
+/// derivative `diff(x^2, x)`, printed as `2*x`.
+#[derive(Clone, Debug)]
+pub struct Formula {
+    node: Arc<Node>,
+    text: String,
+    latex: String,
+}
+
+impl Formula {
+    pub(crate) fn new(node: Node, operators: &OperatorTable) -> Self {
+        let (text, latex) = (print(&node, operators), latex(&node, operators));
+        Self { node: Arc::new(node), text, latex }
+    }
+
+    pub(crate) fn node(&self) -> &Node {
+        &self.node
+    }
+
+    /// The formula in LaTeX, like `Expression::to_latex`.
+    pub fn to_latex(&self) -> String {
+        self.latex.clone()
+    }
+}
+
+impl PartialEq for Formula {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// A function written in an expression, `(x) -> x^2`, for `map`, `filter`,
+/// `reduce`, `integrate` and `solve`. It keeps the arguments of the user
+/// function it was written in.
+#[derive(Clone, Debug)]
+pub struct Lambda(Arc<Closure>);
+
+/// Behind an `Arc` to keep `Value` small.
+#[derive(Debug)]
+struct Closure {
+    params: Vec<String>,
+    body: Arc<Node>,
+    scope: HashMap<String, Value>,
+    text: String,
+}
+
+impl Lambda {
+    pub(crate) fn new(params: Vec<String>, body: Arc<Node>, scope: HashMap<String, Value>, operators: &OperatorTable) -> Self {
+        let text = format!("({}) -> {}", params.join(", "), print(&body, operators));
+        Self(Arc::new(Closure { params, body, scope, text }))
+    }
+
+    pub fn params(&self) -> &[String] {
+        &self.0.params
+    }
+
+    pub(crate) fn body(&self) -> &Node {
+        &self.0.body
+    }
+
+    /// The arguments it was written with bound to `values`.
+    pub(crate) fn scope(&self, values: Vec<Value>) -> HashMap<String, Value> {
+        let mut scope = self.0.scope.clone();
+        scope.extend(self.0.params.iter().cloned().zip(values));
+        scope
+    }
+}
+
+impl PartialEq for Lambda {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.text == other.0.text
+    }
+}
+
+impl fmt::Display for Lambda {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.text)
+    }
+}
+
+/// How tight `node` binds with the precedences of `operators`, negative
+/// numbers like a prefix `-`.
+fn precedence(node: &Node, operators: &OperatorTable) -> u32 {
+    let found = match node {
+        Node::Binary { symbol, .. } => operators.infix(symbol),
+        Node::Unary { symbol, operator: Operator::Prefix(_) | Operator::LogicalPrefix(_), .. } => operators.prefix(symbol),
+        Node::Unary { symbol, .. } => operators.postfix(symbol),
+        Node::Number(x) if x.is_sign_negative() => operators.prefix("-"),
+        Node::Lambda { .. } | Node::Sequence(_) => return 0,
+        _ => return u32::MAX,
+    };
+    found.map_or(0, |found| found.precedence)
+}
+
+/// `node` written out with the parentheses the precedences of `operators`
+/// need. Operators that bind looser than `*` get spaces around them.
+pub(crate) fn print(node: &Node, operators: &OperatorTable) -> String {
+    let group = |node: &Node, parens: bool| match parens {
+        true => format!("({})", print(node, operators)),
+        false => print(node, operators),
+    };
+    let tight = precedence(node, operators);
+    match node {
+        Node::Number(x) => x.to_string(),
+        Node::Name(name, _) => name.clone(),
+        Node::Env(name, _) => format!("${}", name),
+        Node::Unary { symbol, operator: Operator::LogicalPrefix(_), operand, .. } => {
+            format!("{} {}", symbol, group(operand, precedence(operand, operators) <= tight))
+        }
+        Node::Unary { symbol, operator: Operator::Prefix(_), operand, .. } => {
+            format!("{}{}", symbol, group(operand, precedence(operand, operators) <= tight))
+        }
+        Node::Unary { symbol, operand, .. } => format!("{}{}", group(operand, precedence(operand, operators) < tight), symbol),
+        Node::Binary { symbol, operator, lhs, rhs, .. } => {
+            let (left, right) = (precedence(lhs, operators), precedence(rhs, operators));
+            let assoc = operator.assoc();
+            let lhs = group(lhs, left < tight || left == tight && assoc == Some(Assoc::Right));
+            let rhs = group(rhs, right < tight || right == tight && assoc == Some(Assoc::Left));
+            let product = operators.infix("*").map_or(u32::MAX, |found| found.precedence);
+            match tight < product || symbol.chars().any(char::is_alphabetic) {
+                true => format!("{} {} {}", lhs, symbol, rhs),
+                false => format!("{}{}{}", lhs, symbol, rhs),
+            }
+        }
+        Node::List(items, _) => {
+            let items: Vec<String> = items.iter().map(|item| print(item, operators)).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Node::Index { list, index, .. } => format!("{}[{}]", group(list, tight < u32::MAX), print(index, operators)),
+        Node::Call { name, arguments, .. } => {
+            let arguments: Vec<String> = arguments.iter().map(|argument| print(argument, operators)).collect();
+            format!("{}({})", name, arguments.join(", "))
+        }
+        Node::Assign { name, value, .. } => format!("{} = {}", name, print(value, operators)),
+        Node::Define { name, params, body, .. } if params.is_empty() => format!("{} := {}", name, print(body, operators)),
+        Node::Define { name, params, body, .. } => format!("{}({}) = {}", name, params.join(", "), print(body, operators)),
+        Node::Lambda { params, body, .. } => format!("({}) -> {}", params.join(", "), print(body, operators)),
+        Node::Sequence(statements) => statements.iter().map(|statement| print(statement, operators)).collect::<Vec<_>>().join("; "),
+    }
+}
+
+/// `node` in LaTeX, `1/2*sin(x)` as `\frac{1}{2}\sin\left(x\right)`. Groups
+/// like `print`, except that fractions and exponents are in braces.
+pub(crate) fn latex(node: &Node, operators: &OperatorTable) -> String {
+    let group = |node: &Node, parens: bool| match parens {
+        true => format!("\\left({}\\right)", latex(node, operators)),
+        false => latex(node, operators),
+    };
+    let list = |nodes: &[Node]| nodes.iter().map(|node| latex(node, operators)).collect::<Vec<_>>().join(", ");
+    let tight = precedence(node, operators);
+    // A fraction is in braces wherever it is.
+    let binds = |node: &Node| match node {
+        Node::Binary { symbol, .. } if symbol == "/" => u32::MAX,
+        node => precedence(node, operators),
+    };
+    let atomic = |node: &Node| match node {
+        Node::Number(x) => !x.is_sign_negative(),
+        _ => precedence(node, operators) == u32::MAX && !matches!(node, Node::Assign { .. } | Node::Define { .. }),
+    };
+    match node {
+        Node::Number(x) if x.is_nan() => "\\mathrm{NaN}".to_string(),
+        Node::Number(x) if x.is_infinite() => format!("{}\\infty", if *x < 0. { "-" } else { "" }),
+        Node::Number(x) => x.to_string(),
+        Node::Name(name, _) => latex_name(name),
+        Node::Env(name, _) => format!("\\mathrm{{\\${}}}", name.replace('_', "\\_")),
+        Node::Unary { operator: Operator::LogicalPrefix(_), operand, .. } => format!("\\lnot {}", group(operand, binds(operand) <= tight)),
+        Node::Unary { symbol, operator: Operator::Prefix(_), operand, .. } => match symbol.as_str() {
+            "√" => format!("\\sqrt{{{}}}", latex(operand, operators)),
+            symbol => {
+                let symbol = if symbol == "~" { "\\lnot " } else { symbol };
+                format!("{}{}", symbol, group(operand, binds(operand) <= tight))
+            }
+        },
+        Node::Unary { symbol, operand, .. } => {
+            let symbol = match symbol.as_str() {
+                "%" => "\\%",
+                "°" => "^{\\circ}",
+                symbol => symbol,
+            };
+            format!("{}{}", group(operand, binds(operand) < tight), symbol)
+        }
+        Node::Binary { symbol, lhs, rhs, .. } if symbol == "/" => {
+            format!("\\frac{{{}}}{{{}}}", latex(lhs, operators), latex(rhs, operators))
+        }
+        Node::Binary { symbol, lhs, rhs, .. } if symbol == "^" => {
+            format!("{}^{{{}}}", group(lhs, !atomic(lhs)), latex(rhs, operators))
+        }
+        Node::Binary { symbol, operator, lhs, rhs, .. } => {
+            let (left, right) = (binds(lhs), binds(rhs));
+            let assoc = operator.assoc();
+            let coefficient = atomic(lhs) && matches!(**lhs, Node::Number(_)) || matches!(&**lhs, Node::Binary { symbol, .. } if symbol == "/");
+            let lhs = group(lhs, left < tight || left == tight && assoc == Some(Assoc::Right));
+            let rhs = group(rhs, right < tight || right == tight && assoc == Some(Assoc::Left));
+            let symbol = match symbol.as_str() {
+                // `2x` and `\frac{1}{2}\sin\left(x\right)`, but `2 \cdot 3`.
+                "*" if coefficient && !rhs.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '.') && !rhs.starts_with("\\frac") => {
+                    return format!("{}{}", lhs, rhs);
+                }
+                "*" => "\\cdot",
+                "==" => "=",
+                "!=" => "\\neq",
+                "<=" => "\\leq",
+                ">=" => "\\geq",
+                "&&" | "and" => "\\land",
+                "||" | "or" => "\\lor",
+                "±" => "\\pm",
+                "%" => "\\bmod",
+                "&" => "\\&",
+                "|" => "\\mid",
+                "<<" => "\\ll",
+                ">>" => "\\gg",
+                "xor" => "\\oplus",
+                "in" => "\\to",
+                symbol if symbol.chars().any(char::is_alphabetic) => return format!("{} \\mathbin{{\\mathrm{{{}}}}} {}", lhs, symbol, rhs),
+                symbol => symbol,
+            };
+            format!("{} {} {}", lhs, symbol, rhs)
+        }
+        Node::List(items, _) if !items.is_empty() && items.iter().all(|item| matches!(item, Node::List(..))) => {
+            let rows: Vec<String> = items.iter().map(|row| match row {
+                Node::List(row, _) => row.iter().map(|item| latex(item, operators)).collect::<Vec<_>>().join(" & "),
+                _ => unreachable!(),
+            }).collect();
+            format!("\\begin{{bmatrix}} {} \\end{{bmatrix}}", rows.join(" \\\\ "))
+        }
+        Node::List(items, _) => format!("\\left[{}\\right]", list(items)),
+        Node::Index { list, index, .. } => format!("{}_{{{}}}", group(list, !atomic(list)), latex(index, operators)),
+        Node::Call { name, arguments, .. } => match (name.as_str(), arguments.as_slice()) {
+            ("sqrt", [x]) => format!("\\sqrt{{{}}}", latex(x, operators)),
+            ("cbrt", [x]) => format!("\\sqrt[3]{{{}}}", latex(x, operators)),
+            ("abs", [x]) => format!("\\left|{}\\right|", latex(x, operators)),
+            ("exp", [x]) => format!("e^{{{}}}", latex(x, operators)),
+            ("floor", [x]) => format!("\\left\\lfloor {}\\right\\rfloor", latex(x, operators)),
+            ("ceil", [x]) => format!("\\left\\lceil {}\\right\\rceil", latex(x, operators)),
+            ("log2", [x]) => format!("\\log_{{2}}\\left({}\\right)", latex(x, operators)),
+            ("log10" | "log", [x]) => format!("\\log_{{10}}\\left({}\\right)", latex(x, operators)),
+            ("log", [base, x]) => format!("\\log_{{{}}}\\left({}\\right)", latex(base, operators), latex(x, operators)),
+            (name, arguments) => {
+                let name = match name {
+                    "sin" | "cos" | "tan" | "sec" | "csc" | "cot" | "sinh" | "cosh" | "tanh" | "ln" | "log" | "max" | "min" | "det"
+                    | "gcd" | "arg" => format!("\\{}", name),
+                    "asin" | "acos" | "atan" => format!("\\arc{}", &name[1..]),
+                    name if name.chars().count() == 1 => name.to_string(),
+                    name => format!("\\operatorname{{{}}}", name.replace('_', "\\_")),
+                };
+                format!("{}\\left({}\\right)", name, list(arguments))
+            }
+        },
+        Node::Assign { name, value, .. } => format!("{} = {}", latex_name(name), latex(value, operators)),
+        Node::Define { name, params, body, .. } if params.is_empty() => format!("{} := {}", latex_name(name), latex(body, operators)),
+        Node::Define { name, params, body, .. } => {
+            let params: Vec<String> = params.iter().map(|param| latex_name(param)).collect();
+            format!("{}\\left({}\\right) = {}", latex_name(name), params.join(", "), latex(body, operators))
+        }
+        Node::Lambda { params, body } => {
+            let params: Vec<String> = params.iter().map(|param| latex_name(param)).collect();
+            let params = match params.as_slice() {
+                [param] => param.clone(),
+                params => format!("\\left({}\\right)", params.join(", ")),
+            };
+            format!("{} \\mapsto {}", params, latex(body, operators))
+        }
+        Node::Sequence(statements) => statements.iter().map(|statement| latex(statement, operators)).collect::<Vec<_>>().join(";\\quad "),
+    }
+}
+
+/// `x` as is, `pi` as `\pi` and longer names upright.
+fn latex_name(name: &str) -> String {
+    const GREEK: [&str; 35] = [
+        "alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta", "theta", "iota", "kappa", "lambda", "mu", "nu", "xi", "pi", "rho",
+        "sigma", "tau", "upsilon", "phi", "chi", "psi", "omega", "Gamma", "Delta", "Theta", "Lambda", "Xi", "Pi", "Sigma", "Upsilon",
+        "Phi", "Psi", "Omega", "varphi",
+    ];
+    match name {
+        name if GREEK.contains(&name) => format!("\\{}", name),
+        name if name.chars().count() == 1 => name.to_string(),
+        name => format!("\\mathrm{{{}}}", name.replace('_', "\\_")),
+    }
+}
+
+/// `node` with the nodes `f` gives in place of the ones it's called with,
+/// top down. Where `f` gives `None` the children are rewritten instead.
+/// The bodies of definitions and lambdas are left alone.
+pub(crate) fn rewrite<E>(node: &Node, f: &mut dyn FnMut(&Node) -> Option<Result<Node, E>>) -> Result<Node, E> {
+    if let Some(ret) = f(node) {
+        return ret;
+    }
+    Ok(match node {
+        Node::Number(_) | Node::Name(..) | Node::Env(..) | Node::Define { .. } | Node::Lambda { .. } => node.clone(),
+        Node::Unary { symbol, operator, operand, span } => Node::Unary {
+            symbol: symbol.clone(),
+            operator: *operator,
+            operand: Box::new(rewrite(operand, f)?),
+            span: span.clone(),
+        },
+        Node::Binary { symbol, operator, lhs, rhs, span } => Node::Binary {
+            symbol: symbol.clone(),
+            operator: *operator,
+            lhs: Box::new(rewrite(lhs, f)?),
+            rhs: Box::new(rewrite(rhs, f)?),
+            span: span.clone(),
+        },
+        Node::List(items, span) => Node::List(items.iter().map(|item| rewrite(item, f)).collect::<Result<_, _>>()?, span.clone()),
+        Node::Index { list, index, span } => Node::Index {
+            list: Box::new(rewrite(list, f)?),
+            index: Box::new(rewrite(index, f)?),
+            span: span.clone(),
+        },
+        Node::Call { name, arguments, name_span, span } => Node::Call {
+            name: name.clone(),
+            arguments: arguments.iter().map(|argument| rewrite(argument, f)).collect::<Result<_, _>>()?,
+            name_span: name_span.clone(),
+            span: span.clone(),
+        },
+        Node::Assign { name, value, name_span } => Node::Assign {
+            name: name.clone(),
+            value: Box::new(rewrite(value, f)?),
+            name_span: name_span.clone(),
+        },
+        Node::Sequence(statements) => Node::Sequence(statements.iter().map(|statement| rewrite(statement, f)).collect::<Result<_, _>>()?),
+    })
+}
+
+/// Whether `name` appears in `node`.
+pub(crate) fn mentions(node: &Node, name: &str) -> bool {
+    rewrite(node, &mut |node| match node {
+        Node::Name(found, _) if found == name => Some(Err(())),
+        _ => None,
+    }).is_err()
+}
+
+/// Whether `node` has the same value wherever it is evaluated, with no
+/// names and no random numbers.
+pub(crate) fn is_constant(node: &Node) -> bool {
+    rewrite(node, &mut |node| match node {
+        Node::Name(..) | Node::Env(..) | Node::Assign { .. } | Node::Define { .. } | Node::Lambda { .. } => Some(Err(())),
+        Node::Call { name, arguments, .. } if arguments.is_empty() || name == "rand" || name == "randint" => Some(Err(())),
+        _ => None,
+    }).is_ok()
+}
+
+fn number(node: &Node) -> Option<f64> {
+    match node {
+        &Node::Number(x) => Some(x),
+        _ => None,
+    }
+}
+
+/// The operand of a prefix `-`.
+fn negated(node: &Node) -> Option<&Node> {
+    match node {
+        Node::Unary { symbol, operator: Operator::Prefix(_), operand, .. } if symbol == "-" => Some(operand),
+        _ => None,
+    }
+}
+
+/// The lhs and rhs of `symbol`.
+fn operands<'a>(node: &'a Node, symbol: &str) -> Option<(&'a Node, &'a Node)> {
+    match node {
+        Node::Binary { symbol: s, operator: Operator::Infix(..), lhs, rhs, .. } if s == symbol => Some((lhs, rhs)),
+        _ => None,
+    }
+}
+
+/// The number a product starts with and the rest of it, `None` for a
+/// number.
+fn coefficient(node: Node) -> (f64, Option<Node>) {
+    if let Some(x) = number(&node) {
+        return (x, None);
+    }
+    match operands(&node, "*") {
+        Some((Node::Number(x), rest)) => (*x, Some(rest.clone())),
+        _ => (1., Some(node)),
+    }
+}
+
+/// Differentiates by one variable. The nodes it makes have the span of the
+/// call asking for the derivative, and it folds constants as it goes, so
+/// `x^3` gives `3*x^2` rather than `3*x^(3 - 1)*1`.
+pub(crate) struct Derivative<'a> {
+    algebra: Algebra<'a>,
+    var: &'a str,
+    /// Whether trigonometric functions take and give degrees.
+    degrees: bool,
+}
+
+impl<'a> Derivative<'a> {
+    pub fn new(operators: &'a OperatorTable, var: &'a str, span: Range<usize>, degrees: bool) -> Self {
+        Self { algebra: Algebra::new(operators, span), var, degrees }
+    }
+
+    pub fn of(&self, node: &Node) -> Result<Node, String> {
+        if !mentions(node, self.var) {
+            return Ok(Node::Number(0.));
+        }
+        match node {
+            Node::Name(..) => Ok(Node::Number(1.)),
+            Node::List(items, span) => Ok(Node::List(items.iter().map(|item| self.of(item)).collect::<Result<_, _>>()?, span.clone())),
+            Node::Unary { .. } if negated(node).is_some() => self.algebra.neg(self.of(negated(node).unwrap())?),
+            Node::Binary { symbol, operator: Operator::Infix(..), lhs, rhs, .. } if matches!(symbol.as_str(), "+" | "-" | "*" | "/" | "^") => {
+                let (u, v) = (lhs.as_ref().clone(), rhs.as_ref().clone());
+                let (du, dv) = (self.of(&u)?, self.of(&v)?);
+                match symbol.as_str() {
+                    "+" => self.algebra.add(du, dv),
+                    "-" => self.algebra.sub(du, dv),
+                    "*" => {
+                        let (a, b) = (self.algebra.mul(du, v)?, self.algebra.mul(u, dv)?);
+                        self.algebra.add(a, b)
+                    }
+                    "/" if number(&dv) == Some(0.) => self.algebra.div(du, v),
+                    "/" => {
+                        let (a, b) = (self.algebra.mul(du, v.clone())?, self.algebra.mul(u, dv)?);
+                        let square = self.algebra.pow(v, Node::Number(2.))?;
+                        self.algebra.div(self.algebra.sub(a, b)?, square)
+                    }
+                    // u^n is n*u^(n - 1)*u'.
+                    _ if number(&dv) == Some(0.) => {
+                        let power = self.algebra.pow(u, self.algebra.sub(v.clone(), Node::Number(1.))?)?;
+                        self.algebra.mul(self.algebra.mul(v, power)?, du)
+                    }
+                    // a^v is a^v*ln(a)*v', and e^v just e^v*v'.
+                    _ if number(&du) == Some(0.) => {
+                        let power = match &u {
+                            Node::Name(name, _) if name == "e" => self.algebra.pow(u, v)?,
+                            _ => self.algebra.mul(self.algebra.pow(u.clone(), v)?, self.algebra.call("ln", u))?,
+                        };
+                        self.algebra.mul(power, dv)
+                    }
+                    // u^v is u^v*(v'*ln(u) + v*u'/u).
+                    _ => {
+                        let log = self.algebra.mul(dv, self.algebra.call("ln", u.clone()))?;
+                        let ratio = self.algebra.div(self.algebra.mul(v.clone(), du)?, u.clone())?;
+                        self.algebra.mul(self.algebra.pow(u, v)?, self.algebra.add(log, ratio)?)
+                    }
+                }
+            }
+            Node::Call { name, arguments, .. } if arguments.len() == 1 => {
+                let u = &arguments[0];
+                let Some(outer) = self.outer(name, u)? else {
+                    return Err(format!("Can't differentiate '{}'", print(node, self.algebra.operators)));
+                };
+                let ret = self.algebra.mul(outer, self.of(u)?)?;
+                match name.as_str() {
+                    "sin" | "cos" | "tan" if self.degrees => self.algebra.div(self.algebra.mul(ret, self.algebra.name("pi"))?, Node::Number(180.)),
+                    "asin" | "acos" | "atan" if self.degrees => self.algebra.div(self.algebra.mul(ret, Node::Number(180.))?, self.algebra.name("pi")),
+                    _ => Ok(ret),
+                }
+            }
+            node => Err(format!("Can't differentiate '{}'", print(node, self.algebra.operators))),
+        }
+    }
+
+    /// The derivative of the builtin `name` at `u`, if it has one.
+    fn outer(&self, name: &str, u: &Node) -> Result<Option<Node>, String> {
+        let one = || Node::Number(1.);
+        let square = || self.algebra.pow(u.clone(), Node::Number(2.));
+        let ret = match name {
+            "sin" => self.algebra.call("cos", u.clone()),
+            "cos" => self.algebra.neg(self.algebra.call("sin", u.clone()))?,
+            "tan" => self.algebra.div(one(), self.algebra.pow(self.algebra.call("cos", u.clone()), Node::Number(2.))?)?,
+            "asin" => self.algebra.div(one(), self.algebra.call("sqrt", self.algebra.sub(one(), square()?)?))?,
+            "acos" => self.algebra.neg(self.algebra.div(one(), self.algebra.call("sqrt", self.algebra.sub(one(), square()?)?))?)?,
+            "atan" => self.algebra.div(one(), self.algebra.add(one(), square()?)?)?,
+            "sinh" => self.algebra.call("cosh", u.clone()),
+            "cosh" => self.algebra.call("sinh", u.clone()),
+            "tanh" => self.algebra.div(one(), self.algebra.pow(self.algebra.call("cosh", u.clone()), Node::Number(2.))?)?,
+            "asinh" => self.algebra.div(one(), self.algebra.call("sqrt", self.algebra.add(square()?, one())?))?,
+            "acosh" => self.algebra.div(one(), self.algebra.call("sqrt", self.algebra.sub(square()?, one())?))?,
+            "atanh" => self.algebra.div(one(), self.algebra.sub(one(), square()?)?)?,
+            "exp" => self.algebra.call("exp", u.clone()),
+            "ln" => self.algebra.div(one(), u.clone())?,
+            "log10" | "log" => self.algebra.div(one(), self.algebra.mul(u.clone(), self.algebra.call("ln", Node::Number(10.)))?)?,
+            "log2" => self.algebra.div(one(), self.algebra.mul(u.clone(), self.algebra.call("ln", Node::Number(2.)))?)?,
+            "sqrt" => self.algebra.div(one(), self.algebra.mul(Node::Number(2.), self.algebra.call("sqrt", u.clone()))?)?,
+            "cbrt" => self.algebra.div(one(), self.algebra.mul(Node::Number(3.), self.algebra.pow(self.algebra.call("cbrt", u.clone()), Node::Number(2.))?)?)?,
+            "abs" => self.algebra.call("sign", u.clone()),
+            _ => return Ok(None),
+        };
+        Ok(Some(ret))
+    }
+}
+
+/// Builds nodes with the span of the call asking for them, folding
+/// constants and dropping identities as it goes, `0 + x*1` is `x`.
+pub(crate) struct Algebra<'a> {
+    operators: &'a OperatorTable,
+    span: Range<usize>,
+}
+
+impl<'a> Algebra<'a> {
+    pub fn new(operators: &'a OperatorTable, span: Range<usize>) -> Self {
+        Self { operators, span }
+    }
+
+    /// `node` with its constants folded, identities dropped and like terms
+    /// added up, `2*x + 3*x - 0` is `5*x`.
+    pub fn simplify(&self, node: &Node) -> Result<Node, String> {
+        match node {
+            _ if negated(node).is_some() || operands(node, "+").is_some() || operands(node, "-").is_some() => {
+                let mut terms = vec![];
+                self.terms(node, 1., &mut terms)?;
+                self.collect(terms)
+            }
+            Node::Binary { symbol, operator: Operator::Infix(..), lhs, rhs, .. } if matches!(symbol.as_str(), "*" | "/" | "^") => {
+                let (lhs, rhs) = (self.simplify(lhs)?, self.simplify(rhs)?);
+                match symbol.as_str() {
+                    "*" => self.mul(lhs, rhs),
+                    "/" => self.div(lhs, rhs),
+                    _ => self.pow(lhs, rhs),
+                }
+            }
+            _ => {
+                let mut root = true;
+                rewrite(node, &mut |child| match core::mem::take(&mut root) {
+                    true => None,
+                    false => Some(self.simplify(child)),
+                })
+            }
+        }
+    }
+
+    /// The simplified terms of the sum `node` as coefficients and the rest,
+    /// `a - (b - 2*c)` has `a`, `-b` and `2*c`.
+    fn terms(&self, node: &Node, sign: f64, terms: &mut Vec<(f64, Option<Node>)>) -> Result<(), String> {
+        if let Some((lhs, rhs)) = operands(node, "+") {
+            self.terms(lhs, sign, terms)?;
+            return self.terms(rhs, sign, terms);
+        }
+        if let Some((lhs, rhs)) = operands(node, "-") {
+            self.terms(lhs, sign, terms)?;
+            return self.terms(rhs, -sign, terms);
+        }
+        if let Some(operand) = negated(node) {
+            return self.terms(operand, -sign, terms);
+        }
+        let node = self.simplify(node)?;
+        if operands(&node, "+").is_some() || operands(&node, "-").is_some() || negated(&node).is_some() {
+            return self.terms(&node, sign, terms);
+        }
+        // `x/2` is half of `x`.
+        let (x, rest) = match operands(&node, "/") {
+            Some((lhs, &Node::Number(n))) => {
+                let (x, rest) = coefficient(lhs.clone());
+                (x / n, rest)
+            }
+            _ => coefficient(node),
+        };
+        terms.push((sign * x, rest));
+        Ok(())
+    }
+
+    /// The sum of `terms` with the like ones added up, the number last.
+    fn collect(&self, terms: Vec<(f64, Option<Node>)>) -> Result<Node, String> {
+        let mut like: Vec<(String, f64, Node)> = vec![];
+        let mut constant = 0.;
+        for (x, rest) in terms {
+            let Some(rest) = rest else {
+                constant += x;
+                continue;
+            };
+            let text = print(&rest, self.operators);
+            match like.iter_mut().find(|(found, ..)| *found == text) {
+                Some((_, sum, _)) => *sum += x,
+                None => like.push((text, x, rest)),
+            }
+        }
+        let mut ret = Node::Number(0.);
+        for (_, x, rest) in like {
+            ret = match x < 0. {
+                true => self.sub(ret, self.mul(Node::Number(-x), rest)?)?,
+                false => self.add(ret, self.mul(Node::Number(x), rest)?)?,
+            };
+        }
+        self.add(ret, Node::Number(constant))
+    }
+
+    fn name(&self, name: &str) -> Node {
+        Node::Name(name.to_string(), self.span.clone())
+    }
+
+    fn call(&self, name: &str, argument: Node) -> Node {
+        Node::Call { name: name.to_string(), arguments: vec![argument], name_span: self.span.clone(), span: self.span.clone() }
+    }
+
+    fn binary(&self, symbol: &str, lhs: Node, rhs: Node) -> Result<Node, String> {
+        let Some(Match { operator, .. }) = self.operators.infix(symbol) else {
+            return Err(format!("There is no '{}' operator to write it with", symbol));
+        };
+        Ok(Node::Binary { symbol: symbol.to_string(), operator, lhs: Box::new(lhs), rhs: Box::new(rhs), span: self.span.clone() })
+    }
+
+    fn neg(&self, node: Node) -> Result<Node, String> {
+        if let Some(x) = number(&node) {
+            return Ok(Node::Number(-x));
+        }
+        if let Some(operand) = negated(&node) {
+            return Ok(operand.clone());
+        }
+        for symbol in ["*", "/"] {
+            if let Some((lhs, rhs)) = operands(&node, symbol) {
+                if let Some(x) = number(lhs) {
+                    return self.binary(symbol, Node::Number(-x), rhs.clone());
+                }
+            }
+        }
+        let Some(Match { operator, .. }) = self.operators.prefix("-") else {
+            return Err("There is no prefix '-' operator to write it with".to_string());
+        };
+        Ok(Node::Unary { symbol: "-".to_string(), operator, operand: Box::new(node), span: self.span.clone() })
+    }
+
+    fn add(&self, lhs: Node, rhs: Node) -> Result<Node, String> {
+        match (number(&lhs), number(&rhs)) {
+            (Some(a), Some(b)) => Ok(Node::Number(a + b)),
+            (Some(0.), _) => Ok(rhs),
+            (_, Some(0.)) => Ok(lhs),
+            (_, Some(b)) if b < 0. => self.binary("-", lhs, Node::Number(-b)),
+            _ if negated(&rhs).is_some() => self.binary("-", lhs, negated(&rhs).unwrap().clone()),
+            _ => self.binary("+", lhs, rhs),
+        }
+    }
+
+    fn sub(&self, lhs: Node, rhs: Node) -> Result<Node, String> {
+        match (number(&lhs), number(&rhs)) {
+            (Some(a), Some(b)) => Ok(Node::Number(a - b)),
+            (Some(0.), _) => self.neg(rhs),
+            (_, Some(0.)) => Ok(lhs),
+            _ if negated(&rhs).is_some() => self.binary("+", lhs, negated(&rhs).unwrap().clone()),
+            _ => self.binary("-", lhs, rhs),
+        }
+    }
+
+    fn mul(&self, lhs: Node, rhs: Node) -> Result<Node, String> {
+        if let Some(operand) = negated(&lhs) {
+            return self.neg(self.mul(operand.clone(), rhs)?);
+        }
+        if let Some(operand) = negated(&rhs) {
+            return self.neg(self.mul(lhs, operand.clone())?);
+        }
+        // The numbers of both sides go in front, `2*x*(3*y)` is `6*x*y`.
+        let (a, lhs) = coefficient(lhs);
+        let (b, rhs) = coefficient(rhs);
+        let product = match (lhs, rhs) {
+            _ if a * b == 0. => return Ok(Node::Number(0.)),
+            (None, None) => return Ok(Node::Number(a * b)),
+            (Some(node), None) | (None, Some(node)) => node,
+            // `a*(1/b)` is `a/b`.
+            (Some(lhs), Some(rhs)) => match (operands(&lhs, "/"), operands(&rhs, "/")) {
+                (_, Some((one, denominator))) if number(one) == Some(1.) => self.div(lhs.clone(), denominator.clone())?,
+                (Some((one, denominator)), _) if number(one) == Some(1.) => self.div(rhs.clone(), denominator.clone())?,
+                _ => self.binary("*", lhs, rhs)?,
+            },
+        };
+        self.scale(a * b, product)
+    }
+
+    /// `x*node`, with `x` in front of a product and in the numerator of a
+    /// quotient.
+    fn scale(&self, x: f64, node: Node) -> Result<Node, String> {
+        if x == 1. {
+            return Ok(node);
+        }
+        if let Some((lhs, rhs)) = operands(&node, "*") {
+            return self.binary("*", self.scale(x, lhs.clone())?, rhs.clone());
+        }
+        if let Some((Node::Number(n), denominator)) = operands(&node, "/") {
+            return self.div(Node::Number(x * n), denominator.clone());
+        }
+        self.binary("*", Node::Number(x), node)
+    }
+
+    fn div(&self, lhs: Node, rhs: Node) -> Result<Node, String> {
+        match (number(&lhs), number(&rhs)) {
+            (Some(a), Some(b)) if b != 0. => Ok(Node::Number(a / b)),
+            (Some(0.), _) => Ok(Node::Number(0.)),
+            (_, Some(1.)) => Ok(lhs),
+            _ if negated(&lhs).is_some() => self.neg(self.div(negated(&lhs).unwrap().clone(), rhs)?),
+            _ => self.binary("/", lhs, rhs),
+        }
+    }
+
+    fn pow(&self, lhs: Node, rhs: Node) -> Result<Node, String> {
+        match (number(&lhs), number(&rhs)) {
+            (Some(a), Some(b)) if a.powf(b).is_finite() => Ok(Node::Number(a.powf(b))),
+            (_, Some(0.)) => Ok(Node::Number(1.)),
+            (_, Some(1.)) => Ok(lhs),
+            _ => self.binary("^", lhs, rhs),
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use core::fmt;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::operator::IN;
+use crate::value::Value;
+
+/// Powers of the SI base units and money, in the order of `BASE`.
+type Dimension = [i32; 8];
+
+const BASE: [&str; 8] = ["m", "kg", "s", "A", "K", "mol", "cd", "currency"];
+
+/// Every unit with its size in SI base units.
+const UNITS: [(&str, f64, Dimension); 67] = [
+    // @formatter:off
+    ("m", 1., [1, 0, 0, 0, 0, 0, 0, 0]),
+    ("km", 1e3, [1, 0, 0, 0, 0, 0, 0, 0]),
+    ("cm", 1e-2, [1, 0, 0, 0, 0, 0, 0, 0]),
+    ("mm", 1e-3, [1, 0, 0, 0, 0, 0, 0, 0]),
+    ("um", 1e-6, [1, 0, 0, 0, 0, 0, 0, 0]),
+    ("nm", 1e-9, [1, 0, 0, 0, 0, 0, 0, 0]),
+    ("inch", 0.0254, [1, 0, 0, 0, 0, 0, 0, 0]),
+    ("ft", 0.3048, [1, 0, 0, 0, 0, 0, 0, 0]),
+    ("yd", 0.9144, [1, 0, 0, 0, 0, 0, 0, 0]),
+    ("mi", 1609.344, [1, 0, 0, 0, 0, 0, 0, 0]),
+    ("nmi", 1852., [1, 0, 0, 0, 0, 0, 0, 0]),
+    ("au", 149_597_870_700., [1, 0, 0, 0, 0, 0, 0, 0]),
+    ("ly", 9_460_730_472_580_800., [1, 0, 0, 0, 0, 0, 0, 0]),
+    ("kg", 1., [0, 1, 0, 0, 0, 0, 0, 0]),
+    ("g", 1e-3, [0, 1, 0, 0, 0, 0, 0, 0]),
+    ("mg", 1e-6, [0, 1, 0, 0, 0, 0, 0, 0]),
+    ("t", 1e3, [0, 1, 0, 0, 0, 0, 0, 0]),
+    ("lb", 0.453_592_37, [0, 1, 0, 0, 0, 0, 0, 0]),
+    ("oz", 0.028349523125, [0, 1, 0, 0, 0, 0, 0, 0]),
+    ("s", 1., [0, 0, 1, 0, 0, 0, 0, 0]),
+    ("ms", 1e-3, [0, 0, 1, 0, 0, 0, 0, 0]),
+    ("us", 1e-6, [0, 0, 1, 0, 0, 0, 0, 0]),
+    ("ns", 1e-9, [0, 0, 1, 0, 0, 0, 0, 0]),
+    ("min", 60., [0, 0, 1, 0, 0, 0, 0, 0]),
+    ("h", 3600., [0, 0, 1, 0, 0, 0, 0, 0]),
+    ("day", 86400., [0, 0, 1, 0, 0, 0, 0, 0]),
+    ("week", 604_800., [0, 0, 1, 0, 0, 0, 0, 0]),
+    // A Julian year, 365.25 days.
+    ("year", 31_557_600., [0, 0, 1, 0, 0, 0, 0, 0]),
+    ("A", 1., [0, 0, 0, 1, 0, 0, 0, 0]),
+    ("mA", 1e-3, [0, 0, 0, 1, 0, 0, 0, 0]),
+    ("K", 1., [0, 0, 0, 0, 1, 0, 0, 0]),
+    ("mol", 1., [0, 0, 0, 0, 0, 1, 0, 0]),
+    ("cd", 1., [0, 0, 0, 0, 0, 0, 1, 0]),
+    ("mph", 0.447_04, [1, 0, -1, 0, 0, 0, 0, 0]),
+    ("kph", 1. / 3.6, [1, 0, -1, 0, 0, 0, 0, 0]),
+    ("knot", 1852. / 3600., [1, 0, -1, 0, 0, 0, 0, 0]),
+    ("ha", 1e4, [2, 0, 0, 0, 0, 0, 0, 0]),
+    ("acre", 4046.8564224, [2, 0, 0, 0, 0, 0, 0, 0]),
+    ("L", 1e-3, [3, 0, 0, 0, 0, 0, 0, 0]),
+    ("l", 1e-3, [3, 0, 0, 0, 0, 0, 0, 0]),
+    ("mL", 1e-6, [3, 0, 0, 0, 0, 0, 0, 0]),
+    ("ml", 1e-6, [3, 0, 0, 0, 0, 0, 0, 0]),
+    ("gal", 0.003_785_411_784, [3, 0, 0, 0, 0, 0, 0, 0]),
+    ("Hz", 1., [0, 0, -1, 0, 0, 0, 0, 0]),
+    ("kHz", 1e3, [0, 0, -1, 0, 0, 0, 0, 0]),
+    ("MHz", 1e6, [0, 0, -1, 0, 0, 0, 0, 0]),
+    ("GHz", 1e9, [0, 0, -1, 0, 0, 0, 0, 0]),
+    ("N", 1., [1, 1, -2, 0, 0, 0, 0, 0]),
+    ("lbf", 4.4482216152605, [1, 1, -2, 0, 0, 0, 0, 0]),
+    ("Pa", 1., [-1, 1, -2, 0, 0, 0, 0, 0]),
+    ("kPa", 1e3, [-1, 1, -2, 0, 0, 0, 0, 0]),
+    ("bar", 1e5, [-1, 1, -2, 0, 0, 0, 0, 0]),
+    ("atm", 101_325., [-1, 1, -2, 0, 0, 0, 0, 0]),
+    ("psi", 6894.757293168, [-1, 1, -2, 0, 0, 0, 0, 0]),
+    ("J", 1., [2, 1, -2, 0, 0, 0, 0, 0]),
+    ("kJ", 1e3, [2, 1, -2, 0, 0, 0, 0, 0]),
+    ("MJ", 1e6, [2, 1, -2, 0, 0, 0, 0, 0]),
+    ("cal", 4.184, [2, 1, -2, 0, 0, 0, 0, 0]),
+    ("kcal", 4184., [2, 1, -2, 0, 0, 0, 0, 0]),
+    ("Wh", 3600., [2, 1, -2, 0, 0, 0, 0, 0]),
+    ("kWh", 3.6e6, [2, 1, -2, 0, 0, 0, 0, 0]),
+    ("eV", 1.602_176_634e-19, [2, 1, -2, 0, 0, 0, 0, 0]),
+    ("W", 1., [2, 1, -3, 0, 0, 0, 0, 0]),
+    ("kW", 1e3, [2, 1, -3, 0, 0, 0, 0, 0]),
+    ("hp", 745.6998715822702, [2, 1, -3, 0, 0, 0, 0, 0]),
+    ("V", 1., [2, 1, -3, -1, 0, 0, 0, 0]),
+    ("ohm", 1., [2, 1, -3, -2, 0, 0, 0, 0]),
+    // @formatter:on
+];
+
+/// A unit like `km` or `km/h`, made of named units raised to powers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Unit {
+    /// Without zero powers, in the order they were written.
+    terms: Vec<(String, i32)>,
+    /// Its size in SI base units, `km` is 1000.
+    factor: f64,
+    dimension: Dimension,
+}
+
+impl Unit {
+    /// One of the builtin units.
+    pub fn lookup(name: &str) -> Option<Self> {
+        let &(name, factor, dimension) = UNITS.iter().find(|unit| unit.0 == name)?;
+        Some(Self { terms: vec![(name.to_string(), 1)], factor, dimension })
+    }
+
+    /// A currency `rate` of which one unit of the base currency buys.
+    pub(crate) fn currency(code: &str, rate: f64) -> Self {
+        let mut dimension = [0; 8];
+        dimension[7] = 1;
+        Self { terms: vec![(code.to_string(), 1)], factor: 1. / rate, dimension }
+    }
+
+    pub fn factor(&self) -> f64 {
+        self.factor
+    }
+
+    /// The SI base units of the same dimension, `km/h` is `m/s`.
+    fn base(&self) -> Self {
+        let terms = BASE.iter().zip(self.dimension).filter(|&(_, power)| power != 0).map(|(name, power)| (name.to_string(), power)).collect();
+        Self { terms, factor: 1., dimension: self.dimension }
+    }
+
+    /// `self * other^sign`, `sign` is -1 for a quotient.
+    fn mul(&self, other: &Self, sign: i32) -> Self {
+        let mut terms = self.terms.clone();
+        for (name, power) in &other.terms {
+            match terms.iter_mut().find(|term| term.0 == *name) {
+                Some(term) => term.1 += sign * power,
+                None => terms.push((name.clone(), sign * power)),
+            }
+        }
+        terms.retain(|term| term.1 != 0);
+        let mut dimension = self.dimension;
+        for (d, o) in dimension.iter_mut().zip(other.dimension) {
+            *d += sign * o;
+        }
+        Self { terms, factor: self.factor * other.factor.powi(sign), dimension }
+    }
+
+    /// `None` if a power gets too large.
+    fn powi(&self, n: i32) -> Option<Self> {
+        let mut dimension = self.dimension;
+        for d in dimension.iter_mut() {
+            *d = d.checked_mul(n).filter(|d| d.abs() <= 1000)?;
+        }
+        let terms = self.terms.iter().map(|(name, power)| (name.clone(), power * n)).filter(|term| term.1 != 0).collect();
+        Some(Self { terms, factor: self.factor.powi(n), dimension })
+    }
+}
+
+impl fmt::Display for Unit {
+    /// Like `kg*m/s^2`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.terms.is_empty() {
+            return write!(f, "a plain number");
+        }
+        let join = |positive: bool| {
+            let terms: Vec<String> = self.terms.iter().filter(|term| (term.1 > 0) == positive).map(|(name, power)| {
+                match power.abs() {
+                    1 => name.clone(),
+                    n => format!("{}^{}", name, n),
+                }
+            }).collect();
+            (terms.len(), terms.join("*"))
+        };
+        match (join(true), join(false)) {
+            ((_, over), (0, _)) => write!(f, "{}", over),
+            ((0, _), _) => {
+                let terms: Vec<String> = self.terms.iter().map(|(name, power)| format!("{}^{}", name, power)).collect();
+                write!(f, "{}", terms.join("*"))
+            }
+            ((_, over), (1, under)) => write!(f, "{}/{}", over, under),
+            ((_, over), (_, under)) => write!(f, "{}/({})", over, under),
+        }
+    }
+}
+
+/// Whether `name` looks like a currency code, three capital letters like
+/// `EUR`.
+pub(crate) fn is_currency(name: &str) -> bool {
+    name.len() == 3 && name.chars().all(|c| c.is_ascii_uppercase())
+}
+
+/// A number of some unit, `5 km`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quantity {
+    value: f64,
+    unit: Unit,
+}
+
+impl Quantity {
+    /// A plain number when the units cancel out, `5 km / 1 m` is 5000.
+    pub(crate) fn of(value: f64, unit: Unit) -> Value {
+        match unit.dimension == [0; 8] {
+            true => Value::Number(value * unit.factor),
+            false => Value::Quantity(Self { value, unit }),
+        }
+    }
+
+    /// In `unit`.
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    pub fn unit(&self) -> &Unit {
+        &self.unit
+    }
+
+    /// The same quantity in `unit`, `None` if it is of another dimension.
+    pub fn to(&self, unit: &Unit) -> Option<Self> {
+        (unit.dimension == self.unit.dimension).then(|| Self { value: self.value * self.unit.factor / unit.factor, unit: unit.clone() })
+    }
+
+    pub(crate) fn map(&self, f: impl Fn(f64) -> f64) -> Self {
+        Self { value: f(self.value), unit: self.unit.clone() }
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.value, self.unit)
+    }
+}
+
+/// An infix operator with a quantity operand, and `in` converting one.
+/// `None` if it involves no quantity.
+pub(crate) fn infix(symbol: &str, lhs: &Value, rhs: &Value) -> Option<Result<Value, String>> {
+    // Plain numbers are quantities without a unit.
+    let quantity = |value: &Value| match value {
+        Value::Quantity(q) => Some(q.clone()),
+        value => value.number().map(|x| Quantity { value: x, unit: Unit { terms: vec![], factor: 1., dimension: [0; 8] } }),
+    };
+    if symbol == IN {
+        let ret = match (quantity(lhs), rhs) {
+            (Some(q), Value::Quantity(target)) if target.value == 1. => q.to(&target.unit).map(Value::Quantity).ok_or_else(|| {
+                format!("Can't convert {} to {}, one is {} and the other {}", lhs, target.unit, q.unit.base(), target.unit.base())
+            }),
+            (Some(_), _) => Err(format!("Expected a unit after 'in' but got {}", rhs)),
+            (None, _) => Err(format!("Expected a quantity but got {}", lhs)),
+        };
+        return Some(ret);
+    }
+    if !matches!(lhs, Value::Quantity(_)) && !matches!(rhs, Value::Quantity(_)) {
+        return None;
+    }
+    let (Some(a), Some(b)) = (quantity(lhs), quantity(rhs)) else {
+        return Some(Err(format!("Can't apply '{}' to {} and {}", symbol, lhs, rhs)));
+    };
+    let ret = match symbol {
+        "+" | "-" if a.unit.dimension != b.unit.dimension => {
+            let verb = if symbol == "+" { "add" } else { "subtract" };
+            Err(format!("Can't {} {} and {}, one is {} and the other {}", verb, lhs, rhs, a.unit.base(), b.unit.base()))
+        }
+        // In the unit of the left operand, `5 km + 300 m` is 5.3 km.
+        "+" => Ok(Quantity::of(a.value + b.value * b.unit.factor / a.unit.factor, a.unit)),
+        "-" => Ok(Quantity::of(a.value - b.value * b.unit.factor / a.unit.factor, a.unit)),
+        "*" => Ok(Quantity::of(a.value * b.value, a.unit.mul(&b.unit, 1))),
+        "/" => Ok(Quantity::of(a.value / b.value, a.unit.mul(&b.unit, -1))),
+        "^" => match rhs {
+            &Value::Number(n) if n.fract() == 0. && n.abs() <= 1000. => match a.unit.powi(n as i32) {
+                Some(unit) => Ok(Quantity::of(a.value.powi(n as i32), unit)),
+                None => Err(format!("Can't raise {} to {}, the power of its unit is too large", lhs, rhs)),
+            },
+            _ => Err(format!("Can't raise {} to {}, only to a whole number", lhs, rhs)),
+        },
+        _ => Err(format!("Can't apply '{}' to {} and {}", symbol, lhs, rhs)),
+    };
+    Some(ret)
+}
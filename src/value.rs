@@ -0,0 +1,225 @@
+use core::fmt;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::bigint::BigInt;
+use crate::date::{Date, Duration};
+#[cfg(feature = "decimal")]
+use crate::decimal::Decimal;
+use crate::interval::Interval;
+#[cfg(feature = "matrix")]
+use crate::matrix::Matrix;
+use crate::rational::Rational;
+use crate::plot::{Histogram, Plot};
+use crate::symbolic::{Formula, Lambda};
+#[cfg(feature = "units")]
+use crate::units::Quantity;
+
+/// The result of evaluating an expression.
+///
+/// Arithmetic and functions work on numbers, comparisons like `a < b` give
+/// booleans that `&&` and `||` combine. Using one where the other is
+/// expected is a `Type` error. Lists like `[1, 2, 3]` hold numbers that
+/// arithmetic applies to one by one, and with the `matrix` feature lists of
+/// lists are matrices. `Arithmetic::Decimal` gives `Decimal`s,
+/// `Arithmetic::Rational` `Fraction`s and `Arithmetic::Integer`
+/// `Integer`s, which are numbers to everything else, as is the `Radix` of
+/// `tobase`. `5 ± 0.1` is an
+/// `Interval`, `5 km` a `Quantity`, and `date(2024, 5, 1)` and
+/// `days(90)` are a `Date` and a `Duration`. `diff(x^2, x)` is the
+/// `Formula` `2*x`, `(x) -> x^2` a `Lambda`, `plot(sin(x), x, -pi, pi)`
+/// a `Plot` and `hist(xs, 10)` a `Histogram`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Bool(bool),
+    List(Vec<f64>),
+    #[cfg(feature = "matrix")]
+    Matrix(Matrix),
+    #[cfg(feature = "decimal")]
+    Decimal(Decimal),
+    Fraction(Rational),
+    Integer(BigInt),
+    /// A whole number written in a base from 2 to 36, `tobase(255, 16)`.
+    Radix(i64, u32),
+    Interval(Interval),
+    #[cfg(feature = "units")]
+    Quantity(Quantity),
+    Date(Date),
+    Duration(Duration),
+    Formula(Formula),
+    Lambda(Lambda),
+    Plot(Plot),
+    Histogram(Histogram),
+}
+
+impl Value {
+    pub fn number(&self) -> Option<f64> {
+        match self {
+            &Value::Number(x) => Some(x),
+            #[cfg(feature = "decimal")]
+            Value::Decimal(d) => Some(d.to_f64()),
+            Value::Fraction(r) => Some(r.to_f64()),
+            Value::Integer(n) => Some(n.to_f64()),
+            &Value::Radix(n, _) => Some(n as f64),
+            _ => None,
+        }
+    }
+
+    pub fn bool(&self) -> Option<bool> {
+        match self {
+            &Value::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn list(&self) -> Option<&[f64]> {
+        match self {
+            Value::List(xs) => Some(xs),
+            _ => None,
+        }
+    }
+
+    /// An expression that evaluates back to the value, or `None` for a plot.
+    /// Fractions, integers and decimals are literals of `Arithmetic::Rational`,
+    /// `Arithmetic::Integer` and `Arithmetic::Decimal`, everything else is read
+    /// with `Arithmetic::Float`.
+    pub fn to_source(&self) -> Option<String> {
+        let list = |xs: &[f64]| format!("[{}]", xs.iter().map(|&x| float(x)).collect::<Vec<_>>().join(", "));
+        Some(match self {
+            &Value::Number(x) => float(x),
+            Value::Bool(b) => (if *b { "1 == 1" } else { "1 == 0" }).to_string(),
+            Value::List(xs) => list(xs),
+            #[cfg(feature = "matrix")]
+            Value::Matrix(m) => {
+                let rows: Vec<String> = (0..m.rows()).map(|i| list(&(0..m.columns()).map(|j| m.get(i, j)).collect::<Vec<_>>())).collect();
+                format!("[{}]", rows.join(", "))
+            }
+            #[cfg(feature = "decimal")]
+            Value::Decimal(d) => match d.to_string().split_once('.') {
+                Some((int, frac)) => format!("({})/1e{}", integer(&format!("{}{}", int, frac)), frac.len()),
+                None => integer(&d.to_string()),
+            },
+            Value::Fraction(r) => format!("({})/({})", integer(&r.numerator().to_string()), integer(&r.denominator().to_string())),
+            Value::Integer(n) => integer(&n.to_string()),
+            Value::Radix(n, base) => format!("tobase({}, {})", n, base),
+            Value::Interval(i) => format!("interval({}, {})", float(i.lo()), float(i.hi())),
+            #[cfg(feature = "units")]
+            Value::Quantity(q) => format!("{} {}", float(q.value()), q.unit()),
+            Value::Date(d) => d.to_source(),
+            Value::Duration(d) => format!("seconds({})", float(d.seconds())),
+            Value::Formula(formula) => format!("simplify({})", formula),
+            Value::Lambda(lambda) => lambda.to_string(),
+            Value::Plot(_) | Value::Histogram(_) => return None,
+        })
+    }
+}
+
+/// A whole number written in `digits` as sums and products of numbers small
+/// enough to read exactly as floats, since literals are read as floats even
+/// in the exact arithmetics: `(1*1e15 + 267650600228229)*1e15 + 401496703205376`
+/// for 2^100.
+fn integer(digits: &str) -> String {
+    let (sign, digits) = match digits.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", digits),
+    };
+    if digits.len() <= 15 {
+        return format!("{}{}", sign, digits);
+    }
+    let first = (digits.len() - 1) % 15 + 1;
+    let mut expr = digits[..first].to_string();
+    for start in (first..digits.len()).step_by(15) {
+        if expr.contains('+') {
+            expr = format!("({})", expr);
+        }
+        expr = format!("{}*1e15 + {}", expr, &digits[start..start + 15]);
+    }
+    match sign {
+        "" => expr,
+        _ => format!("-({})", expr),
+    }
+}
+
+/// `x` as it's read back exactly, with the infinities and NaN written as
+/// divisions.
+fn float(x: f64) -> String {
+    match x {
+        x if x.is_nan() => "0/0".to_string(),
+        f64::INFINITY => "1/0".to_string(),
+        f64::NEG_INFINITY => "-1/0".to_string(),
+        x => format!("{:?}", x),
+    }
+}
+
+impl From<f64> for Value {
+    fn from(x: f64) -> Self {
+        Value::Number(x)
+    }
+}
+
+/// The number the `f32` prints as, `0.1` rather than its binary value.
+impl From<f32> for Value {
+    fn from(x: f32) -> Self {
+        Value::Number(x.to_string().parse().unwrap())
+    }
+}
+
+impl From<Rational> for Value {
+    fn from(r: Rational) -> Self {
+        Value::Fraction(r)
+    }
+}
+
+impl From<BigInt> for Value {
+    fn from(n: BigInt) -> Self {
+        Value::Integer(n)
+    }
+}
+
+#[cfg(feature = "decimal")]
+impl From<Decimal> for Value {
+    fn from(d: Decimal) -> Self {
+        Value::Decimal(d)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<Vec<f64>> for Value {
+    fn from(xs: Vec<f64>) -> Self {
+        Value::List(xs)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(x) => write!(f, "{}", x),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::List(xs) => {
+                let xs: Vec<String> = xs.iter().map(f64::to_string).collect();
+                write!(f, "[{}]", xs.join(", "))
+            }
+            #[cfg(feature = "matrix")]
+            Value::Matrix(m) => write!(f, "{}", m),
+            #[cfg(feature = "decimal")]
+            Value::Decimal(d) => write!(f, "{}", d),
+            Value::Fraction(r) => write!(f, "{}", r),
+            Value::Integer(n) => write!(f, "{}", n),
+            &Value::Radix(n, base) => write!(f, "{}", crate::format::in_base(n, base)),
+            Value::Interval(i) => write!(f, "{}", i),
+            #[cfg(feature = "units")]
+            Value::Quantity(q) => write!(f, "{}", q),
+            Value::Date(d) => write!(f, "{}", d),
+            Value::Duration(d) => write!(f, "{}", d),
+            Value::Formula(formula) => write!(f, "{}", formula),
+            Value::Lambda(lambda) => write!(f, "{}", lambda),
+            Value::Plot(plot) => write!(f, "{}", plot),
+            Value::Histogram(hist) => write!(f, "{}", hist),
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Exports for running the calculator in a browser, built with
+//! `cargo rustc --lib --release --target wasm32-unknown-unknown --no-default-features --features wasm --crate-type cdylib`.
+//!
+//! Strings cross the boundary as UTF-8 in the module's memory: the caller
+//! copies an expression into a buffer from `alloc`, and reads the result
+//! as `output_len` bytes from `output_ptr`.
+//!
+//! ```js
+//! const { instance } = await WebAssembly.instantiateStreaming(fetch("calculator.wasm"));
+//! const { memory, new_calculator, eval: evaluate, alloc, dealloc, output_ptr, output_len } = instance.exports;
+//! const calc = new_calculator();
+//! function evalIn(calc, expr) {
+//!     const bytes = new TextEncoder().encode(expr);
+//!     const ptr = alloc(bytes.length);
+//!     new Uint8Array(memory.buffer, ptr, bytes.length).set(bytes);
+//!     const ok = evaluate(calc, ptr, bytes.length) === 0;
+//!     dealloc(ptr, bytes.length);
+//!     const text = new TextDecoder().decode(new Uint8Array(memory.buffer, output_ptr(), output_len()));
+//!     if (!ok) throw new Error(text);
+//!     return text;
+//! }
+//! evalIn(calc, "1/2*sin(pi/6)"); // "0.25"
+//! ```
+
+use std::cell::RefCell;
+use crate::calculator::Calculator;
+
+thread_local! {
+    /// The result or error of the last `eval`.
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// A calculator for `eval`, freed by `free_calculator`.
+#[no_mangle]
+pub extern "C" fn new_calculator() -> *mut Calculator {
+    Box::into_raw(Box::new(Calculator::new()))
+}
+
+/// # Safety
+///
+/// `calc` is from `new_calculator` and isn't used after.
+#[no_mangle]
+pub unsafe extern "C" fn free_calculator(calc: *mut Calculator) {
+    drop(Box::from_raw(calc));
+}
+
+/// A buffer of `len` bytes for passing an expression.
+#[no_mangle]
+pub extern "C" fn alloc(len: usize) -> *mut u8 {
+    let mut buffer = Vec::<u8>::with_capacity(len);
+    let ptr = buffer.as_mut_ptr();
+    std::mem::forget(buffer);
+    ptr
+}
+
+/// # Safety
+///
+/// `ptr` is from `alloc` with the same `len`.
+#[no_mangle]
+pub unsafe extern "C" fn dealloc(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, 0, len));
+}
+
+/// Evaluates the `len` bytes of UTF-8 at `ptr`. The output is the result
+/// as the REPL prints it and 0 is returned, or the rendered error and 1.
+///
+/// # Safety
+///
+/// `calc` is from `new_calculator` and `ptr` points at `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn eval(calc: *mut Calculator, ptr: *const u8, len: usize) -> u32 {
+    let calc = &mut *calc;
+    let (output, status) = match std::str::from_utf8(std::slice::from_raw_parts(ptr, len)) {
+        Err(_) => ("Expected an expression in UTF-8".to_string(), 1),
+        Ok(expr) => match calc.eval(expr.to_string()) {
+            Ok(value) => (calc.format(value), 0),
+            Err(err) => (err.render(expr), 1),
+        },
+    };
+    OUTPUT.with(|out| *out.borrow_mut() = output);
+    status
+}
+
+#[no_mangle]
+pub extern "C" fn output_ptr() -> *const u8 {
+    OUTPUT.with(|out| out.borrow().as_ptr())
+}
+
+#[no_mangle]
+pub extern "C" fn output_len() -> usize {
+    OUTPUT.with(|out| out.borrow().len())
+}
@@ -0,0 +1,62 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use crate::calculator::Calculator;
+use crate::diagnostic::{Diagnostic, ErrorKind};
+
+/// A `.calc` file of definitions like `damage(level) = 10 * 1.2^level`,
+/// kept in sync with a running `Calculator` so its formulas can be tuned
+/// without a restart:
+///
+///   let mut watch = Watch::new("formulas.calc");
+///   // Once a frame, or whenever it suits the program.
+///   if let Err(err) = watch.poll(&mut calc) {
+///       eprintln!("{}", err.message);
+///   }
+///
+/// Each change swaps in the new versions of the definitions at once, see
+/// `Calculator::load_definitions`, and forgets those the file no longer
+/// has. A file that fails to load leaves the previous versions in place.
+pub struct Watch {
+    path: PathBuf,
+    /// When the file was last loaded or failed to, None before the first
+    /// `poll`.
+    modified: Option<SystemTime>,
+    /// The names the file defined when it last loaded.
+    names: Vec<String>,
+}
+
+impl Watch {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), modified: None, names: vec![] }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The names the file defined when it last loaded.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Loads the file into `calc` if it changed since the last call, and
+    /// returns whether it did. A file that fails isn't tried again until it
+    /// changes once more.
+    pub fn poll(&mut self, calc: &mut Calculator) -> Result<bool, Diagnostic> {
+        let failed = |err: std::io::Error| Diagnostic::new(format!("Failed to read '{}': {}", self.path.display(), err))
+            .with_kind(ErrorKind::Environment);
+        let modified = std::fs::metadata(&self.path).and_then(|metadata| metadata.modified()).map_err(failed)?;
+        if self.modified == Some(modified) {
+            return Ok(false);
+        }
+        self.modified = Some(modified);
+        let src = std::fs::read_to_string(&self.path).map_err(failed)?;
+        let names = calc.load_definitions(&src)
+            .map_err(|err| Diagnostic { message: format!("{}: {}", self.path.display(), err.message), ..err })?;
+        for name in self.names.iter().filter(|name| !names.contains(name)) {
+            calc.forget(name);
+        }
+        self.names = names;
+        Ok(true)
+    }
+}
@@ -0,0 +1,1887 @@
+use std::time::Duration;
+use calculator::format::{self, Formatter, Notation, Template};
+use calculator::{AngleUnit, Arithmetic, Assoc, BigInt, Bindings, Calculator, Diagnostic, ErrorKind, Fixity, Function, Importer, Limits, Operator, OperatorTable, Value, Watch};
+
+#[test]
+fn test_parse() {
+    let mut calc = Calculator::new();
+
+    let mut parse = |expr: &str| -> Result<Value, Diagnostic> {
+        calc.eval(expr.to_string())
+    };
+
+    assert_eq!(parse("1+2"), Ok(Value::Number(3.)));
+    assert_eq!(parse("1+2*3"), Ok(Value::Number(7.)));
+    assert_eq!(parse("(1+3)%3"), Ok(Value::Number(1.)));
+    assert_eq!(parse("2*-3"), Ok(Value::Number(-6.)));
+    assert_eq!(parse("8-2-1"), Ok(Value::Number(5.)));
+    assert_eq!(parse("2*3^2"), Ok(Value::Number(18.)));
+    assert_eq!(parse("-2^2"), Ok(Value::Number(-4.)));
+    assert_eq!(parse("2^3^2"), Ok(Value::Number(512.)));
+    assert_eq!(parse("2^-1"), Ok(Value::Number(0.5)));
+}
+
+#[test]
+fn test_comparisons() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string());
+
+    assert_eq!(eval("1 + 1 == 2"), Ok(Value::Bool(true)));
+    assert_eq!(eval("2^10 > 1000 && 1 != 1"), Ok(Value::Bool(false)));
+    assert_eq!(eval("1 > 2 || 3 <= 3"), Ok(Value::Bool(true)));
+    assert_eq!(eval("big = 2 >= 1").and_then(|_| eval("big && 0.5 < 1")), Ok(Value::Bool(true)));
+    // The right side isn't evaluated once the left decides.
+    assert_eq!(eval("1 > 2 && undefined"), Ok(Value::Bool(false)));
+    assert_eq!(eval("x = 0").and_then(|_| eval("x != 0 && 1/x > 2 || x == 0")), Ok(Value::Bool(true)));
+    assert_eq!(eval("x != 0 and 1/x > 2 or not x < 1"), Ok(Value::Bool(false)));
+    assert_eq!(eval("not 1 > 2 and not not 2 > 1"), Ok(Value::Bool(true)));
+    assert_eq!(eval("not 1").unwrap_err().message, "Expected a boolean but got 1");
+
+    let err = eval("(1 < 2) + 1").unwrap_err();
+    assert_eq!((err.kind, err.message.as_str()), (ErrorKind::Type, "Expected a number but got true"));
+    assert_eq!(eval("sqrt(1 < 2)").unwrap_err().kind, ErrorKind::Type);
+    assert_eq!(eval("1 && 2").unwrap_err().message, "Expected a boolean but got 1");
+
+    // Comparisons chain, each operand in between is compared to both
+    // of its neighbours.
+    assert_eq!(eval("1 < 2 < 3"), Ok(Value::Bool(true)));
+    assert_eq!(eval("x = 10").and_then(|_| eval("0 <= x < 10")), Ok(Value::Bool(false)));
+    assert_eq!(eval("0 <= x - 1 < 10 == 10 >= 5"), Ok(Value::Bool(true)));
+    assert_eq!(eval("3 > 2 > 2"), Ok(Value::Bool(false)));
+    assert_eq!(eval("1 < 2 < 3 && 4 > 5 > 6 || 1 < 1 + 1 < 3"), Ok(Value::Bool(true)));
+    assert_eq!(eval("(1 < 2) < 3").unwrap_err().kind, ErrorKind::Type);
+    assert_eq!(calc.compile("not (1 < 2 or x == 1)").unwrap().to_string(), "not (1 < 2 or x == 1)");
+}
+
+#[test]
+fn test_bitwise_operators() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string());
+
+    assert_eq!(eval("12 & 10 | 1"), Ok(Value::Number(9.)));
+    assert_eq!(eval("12 xor 10"), Ok(Value::Number(6.)));
+    assert_eq!(eval("1 << 4 + 1"), Ok(Value::Number(32.)));
+    assert_eq!(eval("-16 >> 2"), Ok(Value::Number(-4.)));
+    assert_eq!(eval("1 << 64"), Ok(Value::Number(0.)));
+    assert_eq!(eval("~0"), Ok(Value::Number(-1.)));
+    assert_eq!(eval("5.9 & 3"), Ok(Value::Number(1.)));
+    assert_eq!(eval("0xF0 | 0x0F == 255"), Ok(Value::Bool(true)));
+    assert!(eval("xor = 1").is_err());
+}
+
+#[test]
+fn test_factorial() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("5! + 0!"), Ok(Value::Number(121.)));
+    assert_eq!(eval("-3!"), Ok(Value::Number(-6.)));
+    assert_eq!(eval("2^3!"), Ok(Value::Number(64.)));
+    assert_eq!(eval("171!"), Ok(Value::Number(f64::INFINITY)));
+    assert_eq!(eval("(-1)!"), Err("Expected a non-negative integer but got -1".to_string()));
+    assert_eq!(eval("2.5!"), Err("Expected a non-negative integer but got 2.5".to_string()));
+    assert_eq!(eval("3! != 6"), Ok(Value::Bool(false)));
+
+    assert_eq!(eval("ncr(5, 2)"), Ok(Value::Number(10.)));
+    assert_eq!(eval("ncr(60, 30)"), Ok(Value::Number(118264581564861424.)));
+    assert_eq!(eval("npr(5, 2)"), Ok(Value::Number(20.)));
+    assert_eq!(eval("ncr(2, 5)"), Ok(Value::Number(0.)));
+    assert!(eval("npr(5, -1)").is_err());
+}
+
+#[test]
+fn test_conditional() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string());
+
+    assert_eq!(eval("x = 0").and_then(|_| eval("if(x != 0, 1/x, 0)")), Ok(Value::Number(0.)));
+    assert_eq!(eval("if(2 > 1, sqrt(4), undefined)"), Ok(Value::Number(2.)));
+    eval("fact(n) = if(n <= 1, 1, n * fact(n - 1))").unwrap();
+    assert_eq!(eval("fact(10)"), Ok(Value::Number(3628800.)));
+
+    assert_eq!(eval("if(1, 2, 3)").unwrap_err().message, "Expected a boolean but got 1");
+    assert_eq!(eval("if(1 > 0, 2)").unwrap_err().kind, ErrorKind::Arity { expected: 3, got: 2 });
+    assert_eq!(eval("if = 1").unwrap_err().kind, ErrorKind::Assignment);
+    assert!(calc.register_constant("if", 1.).is_err());
+}
+
+#[test]
+fn test_implicit_product() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string());
+
+    assert_eq!(eval("2pi"), eval("2*pi"));
+    assert_eq!(eval("3(4+1)"), Ok(Value::Number(15.)));
+    assert_eq!(eval("(1+2)(3+4)"), Ok(Value::Number(21.)));
+    assert_eq!(eval("2sin(0) + 1"), Ok(Value::Number(1.)));
+    assert_eq!(eval("x = 3").and_then(|_| eval("2x^2")), Ok(Value::Number(18.)));
+    assert_eq!(eval("1/2x"), Ok(Value::Number(1.5)));
+    assert_eq!(eval("1e-3"), Ok(Value::Number(0.001)));
+    assert_eq!(eval("1e3"), Ok(Value::Number(1000.)));
+    assert_eq!(eval("2.5E+2"), Ok(Value::Number(250.)));
+    assert_eq!(eval("2e"), Ok(Value::Number(2. * std::f64::consts::E)));
+    assert!(eval("2 pi").is_err());
+    assert!(eval("pi 2").is_err());
+}
+
+#[test]
+fn test_syntax_errors() {
+    let mut calc = Calculator::new();
+    let mut render = |expr: &str| calc.eval(expr.to_string()).unwrap_err().render(expr);
+
+    assert_eq!(render("2*(3+4"), "Expected ')'\n  2*(3+4\n    ^ this '(' was never closed\n        ^ input ends here");
+    assert_eq!(render("1 2"), "Expected an operator or the end of the expression\n  1 2\n    ^ unexpected '2'");
+    assert_eq!(render("1 + #"), "Expected a number, a name or '(' but got '#'\n  1 + #\n      ^ unexpected '#'");
+    assert_eq!(render("max(1,"), "Unexpected end of input\n  max(1,\n        ^ expected a number, a name or '('");
+    assert_eq!(calc.eval("0.1 + 0.2".to_string()), Ok(Value::Number(0.1 + 0.2)));
+
+    let err: Box<dyn std::error::Error> = Box::new(calc.eval("foo(1)".to_string()).unwrap_err());
+    assert_eq!(err.to_string(), "Unknown function 'foo'");
+}
+
+#[test]
+fn test_sanitize() {
+    let mut calc = Calculator::new();
+    calc.set_sanitize(true);
+    let mut eval = |expr: &str| calc.eval(expr.to_string());
+
+    assert_eq!(eval("$ 1,234 + 1,000,000"), Ok(Value::Number(1001234.)));
+    assert_eq!(eval("\u{2212}3 × 2€"), Ok(Value::Number(-6.)));
+    assert_eq!(eval("200 * 5%"), Ok(Value::Number(10.)));
+    assert_eq!(eval("7 % 4 + max(1, 234)"), Ok(Value::Number(237.)));
+    assert_eq!(eval("(1,234) * 2 + max(1,5)"), Ok(Value::Number(2473.)));
+    let err = eval("max(1,234)").unwrap_err();
+    assert_eq!((err.message.as_str(), err.labels[0].span.clone()),
+        ("'1,234' could be 1234 or separate arguments, write it without commas or with a space after them", 4..5));
+    assert!(eval("[2, 1,000]").is_err());
+    assert_eq!(eval("1\u{202F}000 ÷ 8"), Ok(Value::Number(125.)));
+    assert_eq!(eval("\u{2212}x").unwrap_err().labels[0].span, 1..2);
+    assert_eq!(eval("1\u{202F}000 × §").unwrap_err().labels[0].span, 8..9);
+
+    assert_eq!(eval("1.2\t3.4\t5.6"), Ok(Value::List(vec![1.2, 3.4, 5.6])));
+    assert_eq!(eval("$1,234.50\t-2\t\t3\t"), Ok(Value::List(vec![1234.5, -2., 3.])));
+    assert_eq!(eval("mean(1,234\t766)"), Ok(Value::Number(1000.)));
+    assert_eq!(eval("5%\t10%"), Ok(Value::List(vec![0.05, 0.1])));
+    assert_eq!(eval("[1.5\t2] * 2"), Ok(Value::List(vec![3., 4.])));
+    assert_eq!(eval("1\t2 + x").unwrap_err().labels[0].span, 1..2);
+    assert_eq!(eval("(1 + 2").unwrap_err().labels[1].text, "this '(' was never closed");
+    calc.set_decimal_separator(',').unwrap();
+    assert_eq!(calc.eval("1.234,5\t0,5".to_string()), Ok(Value::List(vec![1234.5, 0.5])));
+}
+
+#[test]
+fn test_exact() {
+    assert_eq!(format::exact(0.1), "0.1000000000000000055511151231257827021181583404541015625");
+    assert_eq!(format::exact(-1048576.5), "-1048576.5");
+    assert_eq!(format::exact(2f64.powi(70)), "1180591620717411303424");
+    assert_eq!(format::exact(f64::MIN_POSITIVE / 4.).len(), 2 + 1022 + 2);
+    assert_eq!(format::exact(0.), "0");
+
+    assert_eq!(format::hex(0.1), "0x1.999999999999ap-4");
+    assert_eq!(format::hex(-2.), "-0x1p+1");
+    assert_eq!(format::hex(f64::MIN_POSITIVE / 2.), "0x0.8p-1022");
+    assert_eq!(format::hex(0.), "0x0p+0");
+}
+
+#[test]
+fn test_bases() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("0x1F + 0o755 + 0b1010"), Ok(Value::Number(31. + 493. + 10.)));
+    assert_eq!(eval("tobase(10, 2)"), Ok(Value::Radix(10, 2)));
+    assert_eq!(eval("tobase(-493, 8)").map(|value| value.to_string()), Ok("-0o755".to_string()));
+    assert_eq!(eval("tobase(1295, 36)").map(|value| value.to_string()), Ok("zz₃₆".to_string()));
+    assert_eq!(eval("tobase(255, 16) + 1"), Ok(Value::Number(256.)));
+    assert_eq!(eval("0b102"), Err("'102' is not a valid binary number".to_string()));
+    assert_eq!(eval("0x"), Err("Expected hexadecimal digits after '0x'".to_string()));
+    assert_eq!(eval("0x10000000000000000"), Err("0x10000000000000000 doesn't fit in 64 bits".to_string()));
+    assert_eq!(eval("tobase(5, 37)"), Err("Base 37 is out of range 2..36".to_string()));
+
+    assert_eq!(format::radix(-31., 16), Some("-0x1f".to_string()));
+    assert_eq!(format::radix(10., 2), Some("0b1010".to_string()));
+    assert_eq!(format::radix(0.5, 16), None);
+}
+
+#[test]
+fn test_stability_warnings() {
+    let mut calc = Calculator::new();
+    calc.set_warnings(true);
+    let mut warnings = |expr: &str| {
+        calc.eval(expr.to_string()).unwrap();
+        calc.warnings().iter().map(|warning| warning.message.clone()).collect::<Vec<_>>()
+    };
+
+    assert_eq!(warnings("sqrt(1000000000001) - 1000000"), ["'sqrt(1000000000001) - 1000000' cancels about 12 of 16 significant digits"]);
+    assert_eq!(warnings("(10^200) * (10^200)"), ["'(10^200) * (10^200)' overflows to inf"]);
+    assert_eq!(warnings("1000000.5 - 1000000"), Vec::<String>::new());
+    assert_eq!(warnings("2 - 2"), Vec::<String>::new());
+    assert_eq!(warnings("(1e16+1)-1e16"), ["'(1e16+1)-1e16' cancels about 16 of 16 significant digits"]);
+    assert_eq!(warnings("1e8 - 1e8"), Vec::<String>::new());
+}
+
+#[test]
+fn test_limits() {
+    let mut calc = Calculator::new();
+    let deep = format!("{}1{}", "(".repeat(50), ")".repeat(50));
+    assert_eq!(calc.eval(deep.clone()), Ok(Value::Number(1.)));
+
+    calc.set_limits(Limits { max_length: Some(20), ..Limits::default() });
+    assert_eq!(calc.eval("1 + 2".to_string()), Ok(Value::Number(3.)));
+    let err = calc.eval("1 + 2 + 3 + 4 + 5 + 6".to_string()).unwrap_err();
+    assert_eq!((err.kind, err.labels[0].span.clone()), (ErrorKind::Limit, 20..21));
+
+    calc.set_limits(Limits { max_tokens: Some(6), ..Limits::default() });
+    assert_eq!(calc.eval("max(1, 2)".to_string()), Ok(Value::Number(2.)));
+    assert_eq!(calc.eval("max(1, -2)".to_string()).unwrap_err().labels[0].span, 9..10);
+
+    calc.set_limits(Limits { max_depth: Some(2), ..Limits::default() });
+    assert_eq!(calc.eval("(abs(-1))".to_string()), Ok(Value::Number(1.)));
+    assert_eq!(calc.eval("(abs((1)))".to_string()).unwrap_err().labels[0].span, 5..6);
+
+    calc.set_limits(Limits { max_arguments: Some(2), ..Limits::default() });
+    assert_eq!(calc.eval("clamp(1, 2, 3)".to_string()).unwrap_err().labels[0].span, 12..13);
+
+    calc.set_limits(Limits { max_call_depth: Some(10), ..Limits::default() });
+    calc.eval("f(n) = if(n < 1, 0, 1 + f(n - 1))".to_string()).unwrap();
+    assert_eq!(calc.eval("f(9)".to_string()), Ok(Value::Number(9.)));
+    let err = calc.eval("f(10)".to_string()).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::Limit);
+    assert!(err.message.ends_with("In 'f': Function calls are nested more than 10 deep"));
+
+    calc.set_limits(Limits { max_steps: Some(2), ..Limits::default() });
+    assert_eq!(calc.eval("1 + 2 * 3".to_string()), Ok(Value::Number(7.)));
+    let err = calc.eval("1 + 2 * 3 - 4".to_string()).unwrap_err();
+    assert_eq!((err.kind, err.labels[0].span.clone()), (ErrorKind::Limit, 4..9));
+    assert!(calc.eval("sum(k, 1, 10, k)".to_string()).is_err());
+
+    calc.set_limits(Limits { timeout: Some(Duration::from_millis(50)), ..Limits::default() });
+    let err = calc.eval("sum(k, 1, 10^7, sin(k))".to_string()).unwrap_err();
+    assert_eq!((err.kind, err.message.as_str()), (ErrorKind::Limit, "Evaluation takes longer than 50ms"));
+    assert!(calc.eval("sum(k, 1, 100, k)".to_string()).is_ok());
+}
+
+#[test]
+fn test_explain() {
+    let mut calc = Calculator::new();
+    let explain = |calc: &mut Calculator, expr: &str| calc.explain(expr.to_string()).unwrap().1;
+
+    assert_eq!(explain(&mut calc, "2 + 3 * 4^2"), ["4^2 = 16", "3*16 = 48", "2+48 = 50"]);
+    calc.set_operators(OperatorTable::standard());
+    assert_eq!(explain(&mut calc, "2+3*4^2"), ["3*4 = 12", "12^2 = 144", "2+144 = 146"]);
+    assert_eq!(explain(&mut calc, "-max(1, 2)"), ["max(1, 2) = 2", "-2 = -2"]);
+    assert!(explain(&mut calc, "1").is_empty());
+    assert!(calc.eval("2+2".to_string()).is_ok() && calc.explain("x".to_string()).is_err());
+}
+
+#[test]
+fn test_operator_presets() {
+    let eval = |preset: &str, expr: &str| {
+        let mut calc = Calculator::new();
+        calc.set_operators(OperatorTable::preset(preset).unwrap());
+        calc.eval(expr.to_string()).unwrap().number().unwrap()
+    };
+
+    assert_eq!(eval("standard", "-2^2 + 2^3^2"), 68.);
+    assert_eq!(eval("math", "-2^2 + 2^3^2"), 508.);
+    assert_eq!(eval("math", "2*3^2 - 2^-1"), 17.5);
+    assert_eq!(eval("bc", "-2^2 + 2^3^2"), 516.);
+    assert_eq!(eval("excel", "-2^2 + 2^3^2"), 68.);
+
+    let mut table = OperatorTable::standard();
+    table.regroup("-", Fixity::Prefix, 15, None).unwrap();
+    let mut calc = Calculator::new();
+    calc.set_operators(table);
+    assert_eq!(calc.eval("-2*3 + 1".to_string()), Ok(Value::Number(-5.)));
+
+    let mut table = OperatorTable::default();
+    let power = table.entries().iter().find(|entry| entry.symbol == "^").unwrap().precedence;
+    table.regroup("-", Fixity::Prefix, power + 1, None).unwrap();
+    calc.set_operators(table);
+    assert_eq!(calc.eval("-2^2".to_string()), Ok(Value::Number(4.)));
+}
+
+#[test]
+fn test_variables() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string());
+
+    assert_eq!(eval("x = 3*7"), Ok(Value::Number(21.)));
+    assert_eq!(eval("x^2 + 1"), Ok(Value::Number(442.)));
+    assert_eq!(eval("x = x + 1"), Ok(Value::Number(22.)));
+    assert_eq!(eval("x"), Ok(Value::Number(22.)));
+    assert_eq!(eval("x_2 = 2 * x"), Ok(Value::Number(44.)));
+    assert_eq!(eval("x_2 - x"), Ok(Value::Number(22.)));
+
+    let err = eval("xx + 1").unwrap_err();
+    assert_eq!((err.kind, err.message.as_str(), err.hint.as_deref()), (ErrorKind::UnknownVariable, "Unknown variable 'xx'", Some("did you mean 'x'?")));
+    assert_eq!(eval("pi = 3").unwrap_err().kind, ErrorKind::Assignment);
+    assert_eq!(eval("sqrt = 3").unwrap_err().labels[0].span, 0..4);
+}
+
+#[test]
+fn test_host_variables() {
+    let mut calc = Calculator::new();
+    calc.set_var("width", 3.).unwrap();
+    calc.set_var("height", 4.).unwrap();
+    calc.eval("area = width * height".to_string()).unwrap();
+
+    assert_eq!(calc.get_var("area"), Some(Value::Number(12.)));
+    assert_eq!(calc.vars().count(), 3);
+    assert!(calc.set_var("pi", 3.).is_err());
+    assert!(calc.set_var("2x", 3.).is_err());
+
+    calc.clear_vars();
+    assert_eq!(calc.get_var("width"), None);
+    assert_eq!(calc.eval("width".to_string()).unwrap_err().kind, ErrorKind::UnknownVariable);
+}
+
+#[test]
+fn test_resolver() {
+    let mut calc = Calculator::new();
+    let row = std::collections::HashMap::from([("price".to_string(), 12.5), ("pi".to_string(), 3.)]);
+    calc.set_resolver(row);
+    calc.set_var("qty", 4.).unwrap();
+
+    assert_eq!(calc.eval("price * qty".to_string()), Ok(Value::Number(50.)));
+    assert_eq!(calc.eval("pi".to_string()), Ok(Value::Number(std::f64::consts::PI)));
+    assert_eq!(calc.eval("cost".to_string()).unwrap_err().kind, ErrorKind::UnknownVariable);
+
+    calc.set_resolver(|name: &str| name.strip_prefix('r').and_then(|n| n.parse().ok()));
+    assert_eq!(calc.eval("r12 + 1".to_string()), Ok(Value::Number(13.)));
+    calc.clear_resolver();
+    assert!(calc.eval("r12".to_string()).is_err());
+}
+
+#[test]
+fn test_bindings() {
+    let mut calc = Calculator::new();
+    let record = |rate: f64, hours: f64| std::collections::HashMap::from([("rate".to_string(), rate), ("hours".to_string(), hours)]);
+    calc.set_var("bonus", 10.).unwrap();
+    let formula = calc.compile("rate * hours + bonus").unwrap();
+
+    assert_eq!(calc.evaluate_with(&formula, &record(40., 6.)), Ok(Value::Number(250.)));
+    assert_eq!(calc.evaluate_with(&formula, &record(25., 2.)), Ok(Value::Number(60.)));
+    assert_eq!(calc.eval_with("bonus + hours", &record(0., 1.)), Ok(Value::Number(11.)));
+    assert_eq!(calc.get_var("rate"), None);
+    assert!(calc.evaluate(&formula).is_err());
+}
+
+#[test]
+fn test_user_functions() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string());
+
+    assert!(eval("f(x) = x*x + 1").unwrap().number().unwrap().is_nan());
+    assert_eq!(eval("f(3)"), Ok(Value::Number(10.)));
+    eval("hyp(a, b) = sqrt(f(a) - 1 + f(b) - 1)").unwrap();
+    assert_eq!(eval("hyp(3, 4)"), Ok(Value::Number(5.)));
+    assert_eq!(eval("a = 10"), Ok(Value::Number(10.)));
+    assert_eq!(eval("f(a)"), Ok(Value::Number(101.)));
+
+    let err = eval("g(x) = x + y").and_then(|_| eval("1 + g(2)")).unwrap_err();
+    assert_eq!((err.message.as_str(), err.labels[0].span.clone()), ("In 'g': Unknown variable 'y'", 4..8));
+    assert_eq!(eval("f(1, 2)").unwrap_err().kind, ErrorKind::Arity { expected: 1, got: 2 });
+    assert_eq!(eval("sqrt(x) = x").unwrap_err().kind, ErrorKind::Assignment);
+    eval("loop(x) = loop(x)").unwrap();
+    assert_eq!(eval("loop(1)").unwrap_err().kind, ErrorKind::Limit);
+}
+
+#[test]
+fn test_compile() {
+    let mut calc = Calculator::new();
+    let expr = calc.compile("x^2 + 1").unwrap();
+    assert_eq!(expr.source(), "x^2 + 1");
+    assert_eq!(calc.evaluate(&expr).unwrap_err().kind, ErrorKind::UnknownVariable);
+
+    for x in 0..10 {
+        calc.eval(format!("x = {}", x)).unwrap();
+        assert_eq!(calc.evaluate(&expr), Ok(Value::Number((x * x + 1) as f64)));
+    }
+    assert!(calc.compile("f(x) = (x").is_err());
+}
+
+#[test]
+fn test_register_function() {
+    let mut calc = Calculator::new();
+    calc.register_function("mix(a, b, t)", Function::ThreeArg(|a, b, t| a + (b - a) * t)).unwrap();
+    calc.register_constant("answer", 42.).unwrap();
+    assert_eq!(calc.eval("mix(0, answer, 0.25)".to_string()), Ok(Value::Number(10.5)));
+    assert!(calc.signatures().any(|signature| signature == ("mix", "mix(a, b, t)")));
+
+    assert!(calc.register_function("mix(a, b)", Function::ThreeArg(|a, _, _| a)).is_err());
+    assert!(calc.register_function("mix", Function::OneArg(|a| a)).is_err());
+    assert!(calc.register_constant("2pi", 1.).is_err());
+
+    let counter = std::rc::Rc::new(std::cell::Cell::new(0.));
+    let calls = counter.clone();
+    calc.register_function("next(step)", Function::closure(1, move |args| {
+        calls.set(calls.get() + args[0]);
+        Ok(calls.get())
+    })).unwrap();
+    calc.eval("next(1) + next(2)".to_string()).unwrap();
+    assert_eq!(counter.get(), 3.);
+
+    let err = calc.register_function("sqrt(x)", Function::OneArg(|x| x)).unwrap_err();
+    assert_eq!(err, "'sqrt' is a builtin function, use override_function to replace it");
+    calc.override_function("sqrt(x)", Function::closure(1, |args| match args[0] {
+        x if x < 0. => Err(format!("Can't take the square root of {}", x)),
+        x => Ok(x.sqrt()),
+    })).unwrap();
+    assert_eq!(calc.eval("sqrt(-1)".to_string()).unwrap_err().message, "Can't take the square root of -1");
+}
+
+#[test]
+fn test_variadic() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("max(1, 7, 3, 9)"), Ok(Value::Number(9.)));
+    assert_eq!(eval("min(4)"), Ok(Value::Number(4.)));
+    assert_eq!(eval("avg(1, 2, 3, 4, 5) + sum(1, 2)"), Ok(Value::Number(6.)));
+    assert_eq!(eval("sqrt(1, 2)"), Err("Expected 1 argument for function 'sqrt'".to_string()));
+
+    calc.register_function("norm(x, y, ...)", Function::Variadic(2, |args| args.iter().map(|x| x * x).sum::<f64>().sqrt())).unwrap();
+    assert_eq!(calc.eval("norm(2, 3, 6)".to_string()), Ok(Value::Number(7.)));
+    assert_eq!(calc.eval("norm(2)".to_string()).unwrap_err().message, "Expected at least 2 arguments for function 'norm'");
+    assert!(calc.register_function("norm(x, y)", Function::Variadic(2, |_| 0.)).is_err());
+    assert!(calc.register_function("f(x, ...)", Function::OneArg(|x| x)).is_err());
+}
+
+#[test]
+fn test_register_operator() {
+    let mut calc = Calculator::new();
+    calc.register_operator("<>", 5, Operator::Infix(Assoc::Left, |a, b| ((a - b).abs() < 1e-9) as i32 as f64)).unwrap();
+    calc.register_operator("°", 40, Operator::Postfix(|a| a.to_radians())).unwrap();
+    calc.register_operator("^", 25, Operator::Infix(Assoc::Right, |a, b| a.powf(b))).unwrap();
+
+    assert_eq!(calc.eval("0.1 + 0.2 <> 0.3".to_string()), Ok(Value::Number(1.)));
+    assert_eq!(calc.eval("sin(90°)".to_string()), Ok(Value::Number(1.)));
+    assert_eq!(calc.eval("2*3^2".to_string()), Ok(Value::Number(18.)));
+    assert_eq!(calc.eval("2^3^2".to_string()), Ok(Value::Number(512.)));
+
+    calc.register_operator("mod", 20, Operator::Infix(Assoc::Left, |a, b| a.rem_euclid(b))).unwrap();
+    assert_eq!(calc.eval("-7 mod 3".to_string()), Ok(Value::Number(2.)));
+    assert!(calc.register_operator("m0d", 20, Operator::Infix(Assoc::Left, |a, b| a % b)).is_err());
+
+    calc.register_operator("//", 20, Operator::Infix(Assoc::Left, |a, b| (a / b).floor())).unwrap();
+    assert_eq!(calc.eval("7 // 2 + 7 / 2".to_string()), Ok(Value::Number(6.5)));
+    assert_eq!(calc.eval("-7 // 2".to_string()), Ok(Value::Number(-4.)));
+}
+
+#[test]
+fn test_zscore() {
+    let mut calc = Calculator::new();
+
+    assert_eq!(calc.eval("zscore(130, 100, 15)".to_string()), Ok(Value::Number(2.)));
+    assert!(calc.eval("zscore(1, 1, 0)".to_string()).is_err());
+}
+
+#[test]
+fn test_bits() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("popcount(255)"), Ok(Value::Number(8.)));
+    assert_eq!(eval("popcount(-1)"), Ok(Value::Number(64.)));
+    assert_eq!(eval("bitxor(12, 10)"), Ok(Value::Number(6.)));
+    assert_eq!(eval("setbit(0, 4)"), Ok(Value::Number(16.)));
+    assert_eq!(eval("getbit(5, 1)"), Ok(Value::Number(0.)));
+    assert_eq!(eval("rotl(129, 1, 8)"), Ok(Value::Number(3.)));
+    assert_eq!(eval("rotr(1, 1, 8)"), Ok(Value::Number(128.)));
+
+    assert_eq!(eval("bitand(1.5, 1)"), Err("Expected an integer but got 1.5".to_string()));
+    assert!(eval("rotl(256, 1, 8)").is_err());
+    assert!(eval("getbit(1, 64)").is_err());
+}
+
+#[test]
+fn test_modinv() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("modinv(3, 11)"), Ok(Value::Number(4.)));
+    assert_eq!(eval("modinv(-3, 11)"), Ok(Value::Number(7.)));
+    assert_eq!(eval("modinv(6, 9)"), Err("6 has no inverse modulo 9, gcd is 3".to_string()));
+    assert!(eval("modinv(3, 0)").is_err());
+
+    assert_eq!(eval("crt([2, 3, 2], [3, 5, 7])"), Ok(Value::Number(23.)));
+    assert_eq!(eval("crt([1, 3], [4, 6]) + crt([-1, 0], [5, 3])"), Ok(Value::Number(18.)));
+    assert_eq!(eval("crt([1, 2], [4, 6])"), Err("There is no number with remainder 1 modulo 4 and 2 modulo 6".to_string()));
+    assert_eq!(eval("crt([1, 2], [3])"), Err("Expected as many remainders as moduli, at least one, but got 2 and 1".to_string()));
+    assert!(eval("crt([1, 2], [2^40, 2^40 - 1])").is_err());
+}
+
+#[test]
+fn test_polyroots() {
+    let mut calc = Calculator::new();
+    let mut roots = |expr: &str| match calc.eval(expr.to_string()) {
+        Ok(Value::List(xs)) => Ok(xs),
+        Ok(value) => panic!("{}", value),
+        Err(err) => Err(err.message),
+    };
+
+    let cubic = roots("polyroots([1, 0, -2, -5])").unwrap();
+    assert_eq!(cubic.len(), 1);
+    assert!((cubic[0].powi(3) - 2. * cubic[0] - 5.).abs() < 1e-12);
+    assert_eq!(roots("polyroots([1, -6, 11, -6])"), Ok(vec![1., 2., 3.]));
+    assert_eq!(roots("polyroots([2, -6, 0, 0])"), Ok(vec![0., 0., 3.]));
+    assert_eq!(roots("polyroots([0, 1, -2, 1])"), Ok(vec![1., 1.]));
+    assert_eq!(roots("polyroots([1, -4, 6, -4, 1])"), Ok(vec![1.; 4]));
+    assert_eq!(roots("polyroots([1, 0, 1])"), Ok(vec![]));
+    assert_eq!(roots("polyroots([1, 0, 0, 0, 0, -1])"), Ok(vec![1.]));
+    assert_eq!(roots("polyroots([5])"), Ok(vec![]));
+
+    assert_eq!(roots("polyroots([0, 0])"), Err("Every number is a root of the zero polynomial".to_string()));
+    assert_eq!(roots("polyroots([1, 1/0])"), Err("Expected finite coefficients but got inf".to_string()));
+    assert_eq!(roots("polyroots(3)"), Err("Expected a list of coefficients but got 3".to_string()));
+}
+
+#[test]
+fn test_continued_fractions() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map(|value| value.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("cf(pi, 5)"), Ok("[3, 7, 15, 1, 292]".to_string()));
+    assert_eq!(eval("cf(0.75, 10)"), Ok("[0, 1, 3]".to_string()));
+    assert_eq!(eval("cf(-1.5, 10)"), Ok("[-2, 2]".to_string()));
+    assert_eq!(eval("cf(7/12, 10)"), Ok("[0, 1, 1, 2, 2]".to_string()));
+    assert_eq!(eval("cf(frac(0.1), 10)"), Ok("[0, 10]".to_string()));
+    assert_eq!(eval("cf(sqrt(2), 6)"), Ok("[1, 2, 2, 2, 2, 2]".to_string()));
+    assert_eq!(eval("fromcf([3, 7, 15, 1])"), Ok("355/113".to_string()));
+    assert_eq!(eval("fromcf(cf(-1.5, 10))"), Ok("-3/2".to_string()));
+    assert_eq!(eval("fromcf([5])"), Ok("5".to_string()));
+    assert_eq!(eval("fromcf([1, 2, 2, 2]) * 1"), Ok("1.4166666666666667".to_string()));
+
+    assert_eq!(eval("fromcf([1, 0, 2])"), Err("Expected positive terms after the first but got 0".to_string()));
+    assert_eq!(eval("fromcf([1.5])"), Err("Expected an integer but got 1.5".to_string()));
+    assert_eq!(eval("fromcf([])"), Err("Expected at least 1 term".to_string()));
+    assert_eq!(eval("cf(1e300, 3)"), Err("1e300 has too many digits for a continued fraction".to_string()));
+
+    assert_eq!(eval("ratapprox(pi, 1000)"), Ok("355/113".to_string()));
+    assert_eq!(eval("ratapprox(pi, 100)"), Ok("311/99".to_string()));
+    assert_eq!(eval("ratapprox(pi, 7)"), Ok("22/7".to_string()));
+    assert_eq!(eval("ratapprox(sqrt(2), 100)"), Ok("140/99".to_string()));
+    assert_eq!(eval("ratapprox(-2.75, 3)"), Ok("-8/3".to_string()));
+    assert_eq!(eval("ratapprox(0.3, 2)"), Ok("1/2".to_string()));
+    assert_eq!(eval("ratapprox(0.75, 10)"), Ok("3/4".to_string()));
+    assert_eq!(eval("ratapprox(7/12, 10)"), Ok("4/7".to_string()));
+    assert_eq!(eval("ratapprox(1e-300, 10)"), Ok("0".to_string()));
+    assert_eq!(eval("ratapprox(0.5, 0)"), Err("Expected a denominator of at least 1".to_string()));
+    assert_eq!(eval("ratapprox(1e300, 10)"), Err("1e300 has too many digits for a fraction".to_string()));
+}
+
+#[test]
+fn test_ieee() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).unwrap().number().unwrap();
+
+    assert_eq!(eval("exponent(1)"), 0.);
+    assert_eq!(eval("exponent(0.1)"), -4.);
+    assert_eq!(eval("mantissa(6)"), 1.5);
+    assert_eq!(eval("mantissa(-0.1) * (2 ^ exponent(-0.1))"), 0.1);
+
+    assert_eq!(eval("frexp(8)[0] + frexp(8)[1]"), 0.5 + 4.);
+    assert_eq!(eval("ldexp(frexp(-3)[0], frexp(-3)[1])"), -3.);
+    assert_eq!(eval("ldexp(3, 1022)"), 1.5 * 2f64.powi(1023));
+    assert_eq!(eval("ldexp(1, -1074)"), f64::from_bits(1));
+    assert_eq!(eval("ulp(1)"), f64::EPSILON);
+    assert_eq!(eval("nextafter(1, 2) - 1"), f64::EPSILON);
+    assert_eq!(eval("nextafter(0, -1)"), -f64::from_bits(1));
+
+    assert_eq!(calc.eval("frexp(-3)".to_string()), Ok(Value::List(vec![-0.75, 2.])));
+
+    // Patterns past 2^53 are exact integers.
+    let bits = |calc: &mut Calculator, expr: &str| calc.eval(expr.to_string()).unwrap().number().unwrap().to_bits();
+    assert_eq!(calc.eval("bits(1)".to_string()), Ok(Value::Integer(BigInt::from_u64(1f64.to_bits()))));
+    assert_eq!(calc.eval("bits(-0)".to_string()), Ok(Value::Integer(BigInt::from_u64(1 << 63))));
+    assert_eq!(bits(&mut calc, "frombits(bits(-0))"), (-0f64).to_bits());
+    for x in [0.1, -2.5, f64::MAX, f64::from_bits(1), f64::INFINITY] {
+        calc.set_var("x", x).unwrap();
+        assert_eq!(calc.eval("frombits(bits(x))".to_string()), Ok(Value::Number(x)));
+    }
+    // NaNs keep their payload both ways.
+    for pattern in [0x7ff8_0000_0000_0001u64, 0xfff0_0000_dead_beef, 0x7ff0_0000_0000_0001] {
+        calc.set_var("x", f64::from_bits(pattern)).unwrap();
+        assert_eq!(calc.eval("bits(x)".to_string()), Ok(Value::Integer(BigInt::from_u64(pattern))));
+        assert_eq!(bits(&mut calc, "frombits(bits(x))"), pattern);
+    }
+    assert_eq!(bits(&mut calc, "frombits(1)"), 1);
+    assert_eq!(calc.eval("bits(frombits(2^53))".to_string()), Ok(Value::Integer(BigInt::from_u64(1 << 53))));
+    assert!(calc.eval("frombits(2^64)".to_string()).is_err());
+    assert!(calc.eval("frombits(-1)".to_string()).is_err());
+}
+
+#[test]
+fn test_diagnostic() {
+    let mut calc = Calculator::new();
+
+    let err = calc.eval("(1 + 2".to_string()).unwrap_err();
+    assert_eq!(err.render("(1 + 2"), "Expected ')'\n  (1 + 2\n  ^ this '(' was never closed\n        ^ input ends here");
+
+    let err = calc.eval("sqr(4)".to_string()).unwrap_err();
+    assert_eq!(err.labels[0].span, 0..3);
+    assert_eq!(err.hint.as_deref(), Some("did you mean 'sqrt'?"));
+}
+
+#[test]
+fn test_warnings() {
+    let mut calc = Calculator::new();
+    calc.set_warnings(true);
+    calc.eval("2*3^2 + 2^(3^2) + (-2)^2 + (1/2)pi".to_string()).unwrap();
+    assert!(calc.warnings().is_empty());
+
+    // The default operators, where `^` binds tighter than `-` and groups to
+    // the right.
+    assert_eq!(calc.eval("2^3^2".to_string()), Ok(Value::Number(512.)));
+    assert_eq!(calc.warnings()[0].message, "'2^3^2' is evaluated as 2^(3^2)");
+    assert_eq!(calc.eval("-2^2".to_string()), Ok(Value::Number(-4.)));
+    assert_eq!(calc.warnings()[0].message, "'-2^2' is evaluated as -(2^2)");
+    assert_eq!(calc.eval("x=2; 1/2x".to_string()), Ok(Value::Number(1.)));
+    assert_eq!(calc.warnings()[0].message, "'1/2x' is evaluated as (1/2)x");
+    calc.eval("1 / 2 m".to_string()).unwrap();
+    assert!(calc.warnings().is_empty());
+
+    calc.set_operators(OperatorTable::standard());
+    assert_eq!(calc.eval("-2^2".to_string()), Ok(Value::Number(4.)));
+    assert_eq!(calc.warnings()[0].message, "'-2^2' is evaluated as (-2)^2");
+
+    calc.eval("1 + 2 * 3^2".to_string()).unwrap();
+    assert_eq!(calc.warnings()[0].message, "'2 * 3^2' is evaluated as (2 * 3)^2");
+
+    calc.eval("2^(3^2) + (-2)^2".to_string()).unwrap();
+    assert!(calc.warnings().is_empty());
+}
+
+#[test]
+fn test_stats() {
+    let mut calc = Calculator::new();
+    calc.eval("sin(1) + sin(2) * cos(3)".to_string()).unwrap();
+    calc.eval("foo(1)".to_string()).unwrap_err();
+    calc.eval("1 +".to_string()).unwrap_err();
+
+    let stats = calc.stats();
+    assert_eq!(stats.evaluations, 3);
+    assert_eq!(stats.errors[ErrorKind::UnknownFunction.name()], 1);
+    assert_eq!(stats.errors["incomplete input"], 1);
+    assert_eq!(stats.calls["sin"], 2);
+    assert_eq!(stats.calls["cos"], 1);
+}
+
+#[test]
+fn test_env() {
+    let mut calc = Calculator::new();
+    std::env::set_var("CALC_TEST_RATE", "1.5");
+
+    assert!(calc.eval("$CALC_TEST_RATE".to_string()).is_err());
+
+    calc.set_env_vars(true);
+    assert_eq!(calc.eval("$CALC_TEST_RATE * 1000".to_string()), Ok(Value::Number(1500.)));
+    assert!(calc.eval("$CALC_TEST_UNSET".to_string()).is_err());
+}
+
+#[test]
+fn test_formatter() {
+    let fmt = |notation: Notation, precision: Option<usize>, value: f64| Formatter { notation, precision, ..Formatter::default() }.format(value);
+
+    assert_eq!(fmt(Notation::Auto, None, 0.1 + 0.2), "0.30000000000000004");
+    assert_eq!(fmt(Notation::Auto, Some(4), 0.1 + 0.2), "0.3");
+    assert_eq!(fmt(Notation::Auto, Some(4), -1234567.), "-1.235e+06");
+    assert_eq!(fmt(Notation::Auto, None, 1.5e21), "1.5e+21");
+    assert_eq!(fmt(Notation::Auto, None, 123456.), "123456");
+    assert_eq!(fmt(Notation::Fixed, Some(2), 2. / 3.), "0.67");
+    assert_eq!(fmt(Notation::Scientific, None, 0.00015), "1.5e-04");
+    assert_eq!(fmt(Notation::Scientific, Some(2), -1500.), "-1.50e+03");
+    assert_eq!(fmt(Notation::Fixed, Some(2), f64::INFINITY), "inf");
+    assert_eq!(fmt(Notation::Engineering, None, 4700.), "4.7k");
+    assert_eq!(fmt(Notation::Engineering, None, 0.000012), "12µ");
+    assert_eq!(fmt(Notation::Engineering, None, -0.25), "-250m");
+    assert_eq!(fmt(Notation::Engineering, None, 42.), "42");
+    assert_eq!(fmt(Notation::Engineering, None, 1e-18), "1e-18");
+    assert_eq!(fmt(Notation::Engineering, None, 1.5e22), "15e+21");
+    assert_eq!(fmt(Notation::Engineering, Some(3), 999.96), "1.00k");
+    assert_eq!(fmt(Notation::Engineering, Some(4), 1. / 3.), "333.3m");
+    assert_eq!(fmt(Notation::Engineering, None, 0.), "0");
+}
+
+#[test]
+fn test_unicode_symbols() {
+    let mut calc = Calculator::new();
+    calc.set_var("r", 2.).unwrap();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("2πr²"), eval("2*pi*r^2"));
+    assert_eq!(eval("6 × 4 ÷ 3 − 1"), Ok(Value::Number(7.)));
+    assert_eq!(eval("√16 + √(3² + 4²)"), Ok(Value::Number(9.)));
+    assert_eq!(eval("-√4"), Ok(Value::Number(-2.)));
+    assert_eq!(eval("10⁻² + 2¹⁰"), Ok(Value::Number(1024.01)));
+    assert_eq!(eval("r³ + 1"), Ok(Value::Number(9.)));
+    assert_eq!(eval("2⁻"), Err("Expected a superscript digit after '⁻'".to_string()));
+    assert_eq!(calc.eval("π × r² + §".to_string()).unwrap_err().labels[0].span, 9..10);
+}
+
+#[test]
+fn test_math_functions() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).unwrap().number().unwrap();
+
+    assert_eq!(eval("sinh(0) + cosh(0) + tanh(0)"), 1.);
+    assert_eq!(eval("tanh(1000) + tanh(-1000)"), 0.);
+    assert_eq!(eval("asinh(-1)"), -(1f64).asinh());
+    assert!(eval("acosh(0.5)").is_nan());
+    assert_eq!(eval("atanh(1)"), f64::INFINITY);
+    assert_eq!(eval("atan2(1, -1)"), 3. * std::f64::consts::FRAC_PI_4);
+    assert_eq!(eval("atan2(0, -1)"), std::f64::consts::PI);
+    assert_eq!(eval("hypot(3, -4)"), 5.);
+    assert_eq!(eval("hypot(-1 / 0, 0 / 0)"), f64::INFINITY);
+    assert_eq!(eval("cbrt(-27)"), -3.);
+    assert_eq!(eval("exp(0) + exp2(10)"), 1025.);
+    assert_eq!(eval("exp(-1 / 0)"), 0.);
+    assert_eq!(eval("log2(1024) + log10(0.001)"), 7.);
+    assert_eq!(eval("log10(0)"), f64::NEG_INFINITY);
+    assert!(eval("log2(-1)").is_nan());
+    assert_eq!(eval("trunc(-2.7) + fract(-2.75)"), -2.75);
+    assert_eq!(eval("trunc(1 / 0)"), f64::INFINITY);
+}
+
+#[test]
+fn test_list() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("xs = [1, 2, 3]"), Ok(Value::List(vec![1., 2., 3.])));
+    assert_eq!(eval("[]"), Ok(Value::List(vec![])));
+    assert_eq!(eval("xs * 2 - 1"), Ok(Value::List(vec![1., 3., 5.])));
+    assert_eq!(eval("10 / [2, 5] + [1, 1]"), Ok(Value::List(vec![6., 3.])));
+    assert_eq!(eval("-xs^2"), Ok(Value::List(vec![-1., -4., -9.])));
+    assert_eq!(eval("xs[0] + xs[1 + 1] + [[4, 5][1], 6][0]"), Ok(Value::Number(9.)));
+    assert_eq!(eval("mean(xs) + sum(xs, 10) + stddev([1, 3])"), Ok(Value::Number(2. + 16. + 2f64.sqrt())));
+    eval("sq(x) = x * x").unwrap();
+    assert_eq!(eval("sq(xs)"), Ok(Value::List(vec![1., 4., 9.])));
+
+    assert_eq!(eval("xs[3]"), Err("Index 3 is out of range for a list of 3 numbers".to_string()));
+    assert_eq!(eval("xs[0.5]"), Err("Index 0.5 is out of range for a list of 3 numbers".to_string()));
+    assert_eq!(eval("xs + [1, 2]"), Err("Expected lists of the same length but got 3 and 2 numbers".to_string()));
+    assert_eq!(eval("sqrt(xs)"), Err("Expected a number but got [1, 2, 3]".to_string()));
+    assert_eq!(eval("2[0]"), Err("Expected a list but got 2".to_string()));
+    assert_eq!(eval("xs < 2"), Err("Expected a number but got [1, 2, 3]".to_string()));
+    assert_eq!(eval("stddev([1])"), Err("Expected at least 2 arguments for function 'stddev'".to_string()));
+    assert_eq!(eval("[1, 2"), Err("Expected ']'".to_string()));
+
+    calc.set_formatter(Formatter { precision: Some(2), ..Formatter::default() });
+    assert_eq!(calc.format(Value::List(vec![1. / 3., 2.])), "[0.33, 2]");
+}
+
+#[test]
+#[cfg(feature = "matrix")]
+fn test_matrix() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map(|value| value.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("a = [[1, 2], [3, 4]]"), Ok("[[1, 2], [3, 4]]".to_string()));
+    assert_eq!(eval("a * [[0, 1], [1, 0]]"), Ok("[[2, 1], [4, 3]]".to_string()));
+    assert_eq!(eval("a + a - 1"), Ok("[[1, 3], [5, 7]]".to_string()));
+    assert_eq!(eval("a * [1, 1]"), Ok("[3, 7]".to_string()));
+    assert_eq!(eval("[1, 1] * a"), Ok("[4, 6]".to_string()));
+    assert_eq!(eval("a^2"), Ok("[[7, 10], [15, 22]]".to_string()));
+    assert_eq!(eval("[[2, 1], [1, 1]]^-1"), Ok("[[1, -1], [-1, 2]]".to_string()));
+    assert_eq!(eval("a[1][0] + det(a)"), Ok("1".to_string()));
+    assert_eq!(eval("inv([[2, 0], [0, 4]])"), Ok("[[0.5, 0], [0, 0.25]]".to_string()));
+    assert_eq!(eval("transpose([[1, 2, 3], [4, 5, 6]])"), Ok("[[1, 4], [2, 5], [3, 6]]".to_string()));
+    assert_eq!(eval("transpose([1, 2])"), Ok("[[1], [2]]".to_string()));
+    assert_eq!(eval("det([[2, 0, 1], [1, 3, 2], [1, 1, 2]])"), Ok("6".to_string()));
+    assert_eq!(eval("dot([1, 2, 3], [4, 5, 6])"), Ok("32".to_string()));
+    assert_eq!(eval("cross([1, 0, 0], [0, 1, 0])"), Ok("[0, 0, 1]".to_string()));
+    assert_eq!(eval("linsolve([[2, 1], [1, 3]], [5, 10])"), Ok("[1, 3]".to_string()));
+    assert_eq!(eval("linsolve([[0, 1, 0], [2, 0, 0], [0, 0, 4]], [3, 4, 2])"), Ok("[2, 3, 0.5]".to_string()));
+
+    assert_eq!(eval("[[1, 2], [3]]"), Err("Expected rows of 2 numbers but got a row of 1".to_string()));
+    assert_eq!(eval("a * [[1, 2, 3]]"), Err("Can't multiply a 2x2 matrix by a 1x3 matrix".to_string()));
+    assert_eq!(eval("inv([[1, 2], [2, 4]])"), Err("The matrix is singular, it has no inverse".to_string()));
+    assert_eq!(eval("linsolve([[1, 2], [2, 4]], [1, 2])"), Err("The matrix is singular, the system has no unique solution".to_string()));
+    assert_eq!(eval("linsolve(a, [1, 2, 3])"), Err("Expected a vector of 2 numbers but got 3".to_string()));
+    assert_eq!(eval("det([[1, 2]])"), Err("Expected a square matrix but got a 1x2 one".to_string()));
+    assert_eq!(eval("det(1)"), Err("Expected a matrix but got 1".to_string()));
+    assert_eq!(eval("cross([1, 2], [3, 4])"), Err("Expected vectors of 3 numbers but got 2".to_string()));
+    assert_eq!(eval("a < 1"), Err("Expected a number but got [[1, 2], [3, 4]]".to_string()));
+}
+
+#[test]
+#[cfg(feature = "decimal")]
+fn test_decimal() {
+    let mut calc = Calculator::new();
+    calc.set_arithmetic(Arithmetic::Decimal { places: 28 });
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map(|value| value.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("0.1 + 0.2"), Ok("0.3".to_string()));
+    assert_eq!(eval("0.1 + 0.2 == 0.3"), Ok("true".to_string()));
+    assert_eq!(eval("price = 19.99"), Ok("19.99".to_string()));
+    assert_eq!(eval("price * 3 - 0.97"), Ok("59".to_string()));
+    assert_eq!(eval("1 / 3"), Ok("0.3333333333333333333333333333".to_string()));
+    assert_eq!(eval("2 / 3"), Ok("0.6666666666666666666666666667".to_string()));
+    assert_eq!(eval("1.1^2 - -0.5 ^ -1"), Ok("3.21".to_string()));
+    // Anything else is evaluated with floats.
+    assert_eq!(eval("sqrt(price) > 4"), Ok("true".to_string()));
+    assert_eq!(eval("2^0.5"), Ok(2f64.sqrt().to_string()));
+    assert_eq!(eval("1 / 0"), Ok("inf".to_string()));
+    assert_eq!(eval("10^40 + 1"), Ok(1e40.to_string()));
+}
+
+#[test]
+fn test_bigint() {
+    let mut calc = Calculator::new();
+    calc.set_arithmetic(Arithmetic::Integer);
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map(|value| value.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("2^100"), Ok("1267650600228229401496703205376".to_string()));
+    assert_eq!(eval("x = 2^64 - 1"), Ok("18446744073709551615".to_string()));
+    assert_eq!(eval("-x * x + 1"), Ok("-340282366920938463426481119284349108224".to_string()));
+    assert_eq!(eval("25! / 5"), Ok("3102242008666197196800000".to_string()));
+    assert_eq!(eval("100! / 98! == 9900"), Ok("true".to_string()));
+    assert_eq!(eval("-3 - -5 * 2"), Ok("7".to_string()));
+    assert_eq!(eval("(2^200)").map(|digits| digits.len()), Ok(61));
+    // Anything that isn't integer arithmetic is evaluated with floats.
+    assert_eq!(eval("7 / 2"), Ok("3.5".to_string()));
+    assert_eq!(eval("2^-1 + sqrt(x + 1)"), Ok("4294967296.5".to_string()));
+    assert_eq!(eval("x / 1.5"), Ok((18446744073709551615f64 / 1.5).to_string()));
+}
+
+#[test]
+fn test_numeric() {
+    use calculator::{BigInt, Rational};
+    let mut calc = Calculator::new();
+    calc.set_var("half", Rational::new(1, 2).unwrap()).unwrap();
+    calc.set_var("r", 0.1f32).unwrap();
+    assert_eq!(calc.eval_as::<Rational>("half + 1/3"), Ok(Rational::new(5, 6).unwrap()));
+    assert_eq!(calc.eval_as::<f32>("r * 3"), Ok(0.3));
+    assert_eq!(calc.eval_as::<f64>("half + r"), Ok(0.6));
+    assert_eq!(calc.eval_as::<BigInt>("2^70").map(|n| n.to_string()), Ok("1180591620717411303424".to_string()));
+    assert_eq!(calc.eval_as::<f64>("2^70"), Ok(2f64.powi(70)));
+    assert_eq!(calc.arithmetic(), Arithmetic::Float);
+    assert_eq!(calc.eval_as::<BigInt>("7 / 2").unwrap_err().message, "Expected an integer but got 3.5");
+    let err = calc.eval_as::<f64>("1 < 2").unwrap_err();
+    assert_eq!((err.kind, err.message), (ErrorKind::Type, "Expected a number but got true".to_string()));
+    assert_eq!(calc.eval_as::<f64>("nope").unwrap_err().kind, ErrorKind::UnknownVariable);
+
+    #[cfg(feature = "decimal")]
+    {
+        use calculator::Decimal;
+        calc.set_var("price", Decimal::parse("19.99").unwrap()).unwrap();
+        assert_eq!(calc.eval_as::<Decimal>("price * 3 + 0.1 + 0.2"), Ok(Decimal::parse("60.27").unwrap()));
+        assert_eq!(calc.eval_as::<f64>("price"), Ok(19.99));
+    }
+}
+
+#[test]
+fn test_interval() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map(|value| value.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("l = 5 ± 0.1"), Ok("5.00 ± 0.10".to_string()));
+    assert_eq!(eval("2 * l + 1"), Ok("11.00 ± 0.20".to_string()));
+    assert_eq!(eval("l - l"), Ok("0.00 ± 0.20".to_string()));
+    assert_eq!(eval("interval(2, 4) * interval(-1, 1)"), Ok("0.0 ± 4.0".to_string()));
+    assert_eq!(eval("interval(-2, 1)^2"), Ok("2.0 ± 2.0".to_string()));
+    assert_eq!(eval("sqrt(interval(4, 16))"), Ok("3.0 ± 1.0".to_string()));
+    assert_eq!(eval("-interval(1, 3)"), Ok("-2.0 ± 1.0".to_string()));
+    assert_eq!(eval("1 + 2 ± 0.5 * 2"), Ok("3.0 ± 1.0".to_string()));
+    assert_eq!(eval("(100 ± 3) / (20 ± 1)"), Ok("5.02 ± 0.40".to_string()));
+    assert_eq!(eval("(5 ± 0.1) ± 0.1"), Ok("5.00 ± 0.20".to_string()));
+
+    assert_eq!(eval("1 / interval(-1, 1)"), Err("Can't divide by 0.0 ± 1.0, it contains 0".to_string()));
+    assert_eq!(eval("sin(l)"), Err("Expected a number but got 5.00 ± 0.10".to_string()));
+    assert_eq!(eval("ln(interval(-1, 1))"), Err("'ln' is undefined for part of 0.0 ± 1.0".to_string()));
+    assert_eq!(eval("l < 6"), Err("Expected a number but got 5.00 ± 0.10".to_string()));
+}
+
+#[test]
+#[cfg(feature = "units")]
+fn test_units() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map(|value| value.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("5 km + 300 m"), Ok("5.3 km".to_string()));
+    assert_eq!(eval("2 h * 30 W"), Ok("60 h*W".to_string()));
+    assert_eq!(eval("2 h * 30 W in kWh"), Ok("0.06 kWh".to_string()));
+    assert_eq!(eval("10 m / 2 s"), Ok("5 m/s".to_string()));
+    assert_eq!(eval("100 km / 2 h in m/s"), Ok("13.88888888888889 m/s".to_string()));
+    assert_eq!(eval("9.81 kg*m/s^2 in N"), Ok("9.81 N".to_string()));
+    assert_eq!(eval("(3 m)^2"), Ok("9 m^2".to_string()));
+    assert_eq!(eval("1 / 4 s"), Ok("0.25 s^-1".to_string()));
+    assert_eq!(eval("3 km / 1 m"), Ok("3000".to_string()));
+    assert_eq!(eval("-(5km)"), Ok("-5 km".to_string()));
+    assert_eq!(eval("m = 2"), Ok("2".to_string()));
+    assert_eq!(eval("3 m"), Ok("6".to_string()));
+
+    assert_eq!(eval("5 km + 3 s"), Err("Can't add 5 km and 3 s, one is m and the other s".to_string()));
+    assert_eq!(eval("1 h - 1"), Err("Can't subtract 1 h and 1, one is s and the other a plain number".to_string()));
+    assert_eq!(eval("60 mph in s"), Err("Can't convert 60 mph to s, one is m/s and the other s".to_string()));
+    assert_eq!(eval("60 mph in 2 km"), Err("Expected a unit after 'in' but got 2 km".to_string()));
+    assert_eq!(eval("sqrt(4 h)"), Err("Expected a number but got 4 h".to_string()));
+}
+
+#[test]
+fn test_physical_constants() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("phys.c"), Ok(Value::Number(299792458.)));
+    assert_eq!(eval("phys.kB * phys.Na"), Ok(Value::Number(1.380649e-23 * 6.02214076e23)));
+    assert_eq!(eval("2phys.g"), Ok(Value::Number(19.6133)));
+    assert_eq!(eval("phys.q"), Err("Unknown variable 'phys.q'".to_string()));
+    assert_eq!(eval("phys.c = 3"), Err("Can't assign to 'phys.c', it is a constant".to_string()));
+    assert_eq!(calc.register_constant("my.answer", 42.), Ok(()));
+    assert!(calc.register_constant("my.", 1.).is_err());
+}
+
+#[test]
+fn test_dates() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map(|value| value.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("date(2024, 5, 1) + days(90)"), Ok("2024-07-30".to_string()));
+    assert_eq!(eval("date(2024, 5, 1) - date(2024, 1, 1)"), Ok("P121D".to_string()));
+    assert_eq!(eval("date(2024, 5, 1) + hours(36)"), Ok("2024-05-02T12:00:00Z".to_string()));
+    #[cfg(feature = "units")]
+    assert_eq!(eval("date(2024, 5, 1) - 90 min"), Ok("2024-04-30T22:30:00Z".to_string()));
+    assert_eq!(eval("date(1900, 3, 1) - days(1)"), Ok("1900-02-28".to_string()));
+    assert_eq!(eval("weekday(date(2024, 5, 1))"), Ok("3".to_string()));
+    assert_eq!(eval("days_between(date(2024, 1, 1), date(2024, 12, 25))"), Ok("359".to_string()));
+    assert_eq!(eval("days(1.5) + minutes(2)"), Ok("P1DT12H2M".to_string()));
+    assert_eq!(eval("-weeks(2) / 4"), Ok("-P3DT12H".to_string()));
+    assert_eq!(eval("days(3) / hours(1)"), Ok("72".to_string()));
+    assert_eq!(eval("now() - now()"), Ok("PT0S".to_string()));
+
+    assert_eq!(eval("date(2023, 2, 29)"), Err("There is no day 29 in month 2 of 2023".to_string()));
+    assert_eq!(eval("date(2024, 1, 1) * 2"), Err("Can't apply '*' to 2024-01-01 and 2".to_string()));
+    assert_eq!(eval("weekday(3)"), Err("Expected a date but got 3".to_string()));
+}
+
+#[test]
+fn test_percent() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("15%"), Ok(Value::Number(0.15)));
+    assert_eq!(eval("200 + 10%"), Ok(Value::Number(220.)));
+    assert_eq!(eval("200 - 10%"), Ok(Value::Number(180.)));
+    assert_eq!(eval("50 * 20%"), Ok(Value::Number(10.)));
+    assert_eq!(eval("50% + 1"), Ok(Value::Number(1.5)));
+    assert_eq!(eval("7 % 4"), Ok(Value::Number(3.)));
+    assert_eq!(eval("7%(4)"), Ok(Value::Number(3.)));
+}
+
+#[test]
+fn test_si_prefixes() {
+    let mut calc = Calculator::new();
+    assert_eq!(calc.eval("n = 3".to_string()), Ok(Value::Number(3.)));
+    assert_eq!(calc.eval("2n".to_string()), Ok(Value::Number(6.)));
+
+    calc.set_si_prefixes(true);
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map(|value| value.to_string()).map_err(|err| err.message);
+    assert_eq!(eval("4.7k"), Ok("4700".to_string()));
+    assert_eq!(eval("2.2M + 100m"), Ok("2200000.1".to_string()));
+    assert_eq!(eval("47u * 1k"), Ok("0.047".to_string()));
+    assert_eq!(eval("2n"), Ok("0.000000002".to_string()));
+    assert_eq!(eval("2*n"), Ok("6".to_string()));
+    #[cfg(feature = "units")]
+    assert_eq!(eval("3km"), Ok("3 km".to_string()));
+    #[cfg(feature = "units")]
+    assert_eq!(eval("100 m"), Ok("100 m".to_string()));
+}
+
+#[test]
+fn test_derivatives() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map(|value| value.to_string()).map_err(|err| err.message);
+    assert_eq!(eval("diff(x^2 + sin(x), x)"), Ok("2*x + cos(x)".to_string()));
+    assert_eq!(eval("diff(3x^2 - 5x + 1, x)"), Ok("6*x - 5".to_string()));
+    assert_eq!(eval("diff(cos(2x), x)"), Ok("-2*sin(2*x)".to_string()));
+    assert_eq!(eval("diff(1/x, x)"), Ok("-1/x^2".to_string()));
+    assert_eq!(eval("diff(sqrt(x), x)"), Ok("1/(2*sqrt(x))".to_string()));
+    assert_eq!(eval("diff(exp(-x^2), x)"), Ok("-2*exp(-x^2)*x".to_string()));
+    assert_eq!(eval("diff(a*x + b, x)"), Ok("a".to_string()));
+    assert_eq!(eval("diff(x^2, x, 3)"), Ok("6".to_string()));
+    assert_eq!(eval("diff(x^2, x, [1, 2])"), Ok("[2, 4]".to_string()));
+    assert_eq!(eval("f(t) = t^3"), Ok("NaN".to_string()));
+    assert_eq!(eval("d = diff(f(x), x)"), Ok("3*x^2".to_string()));
+    assert_eq!(eval("diff(d, x, 1)"), Ok("6".to_string()));
+
+    assert_eq!(eval("diff(x!, x)"), Err("Can't differentiate 'x!'".to_string()));
+    assert_eq!(eval("diff(x, 2)"), Err("Expected a variable to differentiate by".to_string()));
+    assert_eq!(eval("d + 1"), Err("Expected a number but got 3*x^2".to_string()));
+    assert_eq!(eval("diff = 1"), Err("Can't assign to 'diff', it is a function".to_string()));
+
+    calc.set_operators(OperatorTable::standard());
+    calc.set_angle_unit(AngleUnit::Degrees);
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map(|value| value.to_string()).map_err(|err| err.message);
+    // `-x^2` is `(-x)^2` here.
+    assert_eq!(eval("diff(-x^2, x)"), Ok("2*x".to_string()));
+    assert_eq!(eval("diff(sin(x), x)"), Ok("cos(x)*pi/180".to_string()));
+}
+
+#[test]
+fn test_integrals() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map_err(|err| err.message);
+    let close = |value: Result<Value, String>, expected: f64| (value.unwrap().number().unwrap() - expected).abs() < 1e-9;
+    assert!(close(eval("integrate(sin(x), x, 0, pi)"), 2.));
+    assert!(close(eval("integrate(x^2, x, 0, 3)"), 9.));
+    assert!(close(eval("integrate(exp(-x^2), x, -10, 10)"), std::f64::consts::PI.sqrt()));
+    assert!(close(eval("integrate(abs(x), x, 1, -1)"), -1.));
+    assert_eq!(eval("k = 3"), Ok(Value::Number(3.)));
+    assert!(close(eval("integrate(k*t, t, 0, 2)"), 6.));
+    assert!((eval("integrate(sqrt(x), x, 0, 1, 0.01)").unwrap().number().unwrap() - 2. / 3.).abs() < 0.01);
+
+    assert_eq!(eval("integrate(1/x, x, -1, 1)"), Err("The function is inf at 0".to_string()));
+    assert_eq!(eval("integrate(x, x, 0, 1, 0)"), Err("Expected a positive tolerance but got 0".to_string()));
+    assert_eq!(eval("integrate(x, 2, 0, 1)"), Err("Expected a variable to integrate over".to_string()));
+    assert_eq!(eval("integrate(x > 1, x, 0, 2)"), Err("Expected a number but got false".to_string()));
+}
+
+#[test]
+fn test_solve() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map(|value| value.to_string()).map_err(|err| err.message);
+    assert_eq!(eval("solve(x^2 - 2, x, 1)"), Ok("1.414213562373095".to_string()));
+    assert_eq!(eval("solve(x^2 = 2, x, -1)"), Ok("-1.414213562373095".to_string()));
+    assert_eq!(eval("solve(cos(x) = x, x)"), Ok("0.7390851332151607".to_string()));
+    #[cfg(feature = "special-functions")]
+    assert_eq!(eval("solve(gamma(x) = 24, x, 4.5)"), Ok("5".to_string()));
+    assert_eq!(eval("solve(x^3 - 2x - 5, x, 2, 3)"), Ok("2.094551481542327".to_string()));
+
+    assert_eq!(eval("solve(x^2 + 1, x)"), Err("The slope is 0 at 0".to_string()));
+    assert_eq!(eval("solve(x^2 + 1, x, 0, 2)"), Err("The function doesn't change sign between 0 and 2".to_string()));
+    assert_eq!(eval("solve(1/x, x, -1, 2)"), Err("The function has a pole rather than a root at -0".to_string()));
+    assert_eq!(eval("solve(x, 2)"), Err("Expected a variable to solve for".to_string()));
+    assert_eq!(eval("max(x = 2, 3)"), Err("Expected ')'".to_string()));
+}
+
+#[test]
+fn test_series() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map_err(|err| err.message);
+    assert_eq!(eval("sum(i, 1, 100, i^2)"), Ok(Value::Number(338350.)));
+    assert_eq!(eval("prod(i, 1, 10, i)"), Ok(Value::Number(3628800.)));
+    assert_eq!(eval("sum(j, 1, 3, sum(k, 1, j, k))"), Ok(Value::Number(10.)));
+    assert_eq!(eval("sum(i, 1, 0, i)"), Ok(Value::Number(0.)));
+    assert_eq!(eval("prod(i, 1, 0, i)"), Ok(Value::Number(1.)));
+    assert_eq!(eval("a = 1"), Ok(Value::Number(1.)));
+    assert_eq!(eval("sum(a, 2, 3, 4)"), Ok(Value::Number(10.)));
+
+    assert_eq!(eval("sum(i, 1, 2.5, i)"), Err("Expected whole bounds but got 1 and 2.5".to_string()));
+    assert_eq!(eval("sum(i, 1, 10^9, i)"), Err("Can't go through more than 100000000 terms".to_string()));
+    assert_eq!(eval("prod(i, 1, 10)"), Err("Expected 4 arguments for function 'prod'".to_string()));
+    assert_eq!(eval("sum(i, 1, 3, [i, 1])"), Err("Expected a number but got [1, 1]".to_string()));
+}
+
+#[test]
+fn test_lambdas() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map_err(|err| err.message);
+    assert_eq!(eval("f = (x) -> x^2").map(|f| f.to_string()), Ok("(x) -> x^2".to_string()));
+    assert_eq!(eval("f(3)"), Ok(Value::Number(9.)));
+    assert_eq!(eval("map(f, [1, 2, 3])"), Ok(Value::List(vec![1., 4., 9.])));
+    assert_eq!(eval("filter(x -> x > 1, [1, 2, 3])"), Ok(Value::List(vec![2., 3.])));
+    assert_eq!(eval("reduce((a, b) -> a + b, [1, 2, 3])"), Ok(Value::Number(6.)));
+    assert_eq!(eval("reduce((a, b) -> a * b, [1, 2, 3], 10)"), Ok(Value::Number(60.)));
+    assert_eq!(eval("integrate(f, 0, 3)"), Ok(Value::Number(9.)));
+    assert_eq!(eval("solve(x -> x^2 - 2, 1)"), eval("solve(x^2 - 2, x, 1)"));
+    eval("add(n) = x -> x + n").unwrap();
+    eval("inc = add(1)").unwrap();
+    assert_eq!(eval("inc(41)"), Ok(Value::Number(42.)));
+    eval("g(h, y) = h(y) + 1").unwrap();
+    assert_eq!(eval("g(x -> 2*x, 5)"), Ok(Value::Number(11.)));
+
+    assert_eq!(eval("f(1, 2)"), Err("Expected 1 argument for function '(x) -> x^2'".to_string()));
+    assert_eq!(eval("map(3, [1])"), Err("Expected a lambda but got 3".to_string()));
+    assert_eq!(eval("filter(x -> x, [1])"), Err("Expected a boolean but got 1".to_string()));
+    assert_eq!(eval("reduce((a, b) -> a, [])"), Err("Can't reduce an empty list without an initial value".to_string()));
+    assert_eq!(eval("integrate((x, y) -> x, 0, 1)"), Err("Expected a lambda of one parameter but got (x, y) -> x".to_string()));
+    assert_eq!(eval("f + 1"), Err("Expected a number but got (x) -> x^2".to_string()));
+}
+
+#[test]
+fn test_odesolve() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map_err(|err| err.message);
+    let Ok(Value::Number(e)) = eval("odesolve((t, y) -> y, 0, 1, 1, 100)") else { panic!() };
+    assert!((e - std::f64::consts::E).abs() < 1e-9);
+    let Ok(Value::Number(y)) = eval("odesolve((t, y) -> 2t, 0, 0, 3, 10)") else { panic!() };
+    assert!((y - 9.).abs() < 1e-12);
+    let Ok(Value::List(ys)) = eval("odesolve((t, y) -> -y, 0, 1, [1, 2, 3], 50)") else { panic!() };
+    assert_eq!(ys.len(), 3);
+    assert!(ys.iter().zip(1..).all(|(y, t)| (y - (-t as f64).exp()).abs() < 1e-8));
+
+    assert_eq!(eval("odesolve(t -> t, 0, 1, 1, 10)"), Err("Expected a lambda of t and y but got (t) -> t".to_string()));
+    assert_eq!(eval("odesolve((t, y) -> y, 0, 1, 1, 0.5)"), Err("Expected a whole number of steps but got 0.5".to_string()));
+    assert_eq!(eval("odesolve((t, y) -> y, 0, 1, 1, 1e8)"), Err("Can't take more than 10000000 steps".to_string()));
+    assert_eq!(eval("odesolve((t, y) -> y, 0, 1, 1)"), Err("Expected 5 arguments for function 'odesolve'".to_string()));
+}
+
+#[test]
+fn test_plot() {
+    let mut calc = Calculator::new();
+    let Ok(Value::Plot(plot)) = calc.eval("plot(sin(x), x, -pi, pi)".to_string()) else { panic!() };
+    assert_eq!(plot.points().len(), 400);
+    assert_eq!(plot.points()[0], (-std::f64::consts::PI, -std::f64::consts::PI.sin()));
+    let graph = plot.render(40, 10);
+    let lines: Vec<&str> = graph.lines().collect();
+    assert_eq!(lines.len(), 10);
+    assert!(lines[0].starts_with(" 1 │") && lines[8].starts_with("-1 │"));
+    assert!(lines[9].trim_start().starts_with("-3.142 ") && lines[9].ends_with(" 3.142"));
+    assert!(lines.iter().all(|line| line.chars().count() == 40));
+
+    let Ok(Value::Plot(plot)) = calc.eval("plot(x -> sqrt(x), -1, 1)".to_string()) else { panic!() };
+    assert!(plot.points()[0].1.is_nan() && plot.points()[399].1 == 1.);
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map_err(|err| err.message);
+    assert_eq!(eval("plot(x, x, 1, 0)"), Err("Expected finite bounds from low to high but got 1 and 0".to_string()));
+    assert_eq!(eval("plot(x, x, 0)"), Err("Expected 4 arguments for function 'plot'".to_string()));
+    assert_eq!(eval("plot(y, x, 0, 1)"), Err("Unknown variable 'y'".to_string()));
+}
+
+#[test]
+fn test_simplify() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map(|value| value.to_string()).map_err(|err| err.message);
+    assert_eq!(eval("simplify(2*x + 3*x - 0)"), Ok("5*x".to_string()));
+    assert_eq!(eval("simplify(a - (b - 2*a) + 3)"), Ok("3*a - b + 3".to_string()));
+    assert_eq!(eval("simplify(x*1 + 0*y + x^0)"), Ok("x + 1".to_string()));
+    assert_eq!(eval("simplify(sqrt(4)*x + sqrt(2))"), Ok("2*x + sqrt(2)".to_string()));
+    assert_eq!(eval("simplify(2x + x/2 - x)"), Ok("1.5*x".to_string()));
+    assert_eq!(eval("simplify(x - x)"), Ok("0".to_string()));
+    eval("f(x) = x + x").unwrap();
+    assert_eq!(eval("simplify(f(y))"), Ok("2*y".to_string()));
+    assert_eq!(eval("simplify(1, 2)"), Err("Expected 1 argument for function 'simplify'".to_string()));
+
+    assert_eq!(calc.compile("((1 + 2))*x").unwrap().to_string(), "(1 + 2)*x");
+    assert_eq!(calc.compile("(a-b)-(c-d)").unwrap().to_string(), "a - b - (c - d)");
+    assert_eq!(calc.compile("2*(x^2)").unwrap().to_string(), "2*x^2");
+}
+
+#[test]
+fn test_latex() {
+    let mut calc = Calculator::new();
+    let latex = |expr: &str| calc.compile(expr).unwrap().to_latex();
+    assert_eq!(latex("1/2*sin(x)"), "\\frac{1}{2}\\sin\\left(x\\right)");
+    assert_eq!(latex("2*pi*r"), "2\\pi \\cdot r");
+    assert_eq!(latex("(x + 1)^2 / sqrt(y)"), "\\frac{\\left(x + 1\\right)^{2}}{\\sqrt{y}}");
+    assert_eq!(latex("-(a - b)*3"), "-\\left(a - b\\right) \\cdot 3");
+    assert_eq!(latex("abs(x) <= 2^(n+1)"), "\\left|x\\right| \\leq 2^{n + 1}");
+    assert_eq!(latex("[[1, 2], [3, 4]]"), "\\begin{bmatrix} 1 & 2 \\\\ 3 & 4 \\end{bmatrix}");
+    assert_eq!(latex("f(x) = log(2, x) + speed"), "f\\left(x\\right) = \\log_{2}\\left(x\\right) + \\mathrm{speed}");
+    match calc.eval("diff(x^3, x)".to_string()) {
+        Ok(Value::Formula(formula)) => assert_eq!(formula.to_latex(), "3x^{2}"),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn test_semicolons() {
+    let mut calc = Calculator::new();
+    assert_eq!(calc.eval("a = 2; b = 3; a*b".to_string()), Ok(Value::Number(6.)));
+    assert_eq!(calc.eval("f(x) = x + a; f(b);".to_string()), Ok(Value::Number(5.)));
+    assert!(!calc.is_definition("f(x) = x + a; f(b)"));
+    assert!(calc.is_definition("a = 1; g(x) = x;"));
+    assert_eq!(calc.eval_each("c = 1; c + 1; d(x) = x".to_string()).map(|values| values.len()), Ok(3));
+    assert_eq!(calc.compile("a=1;(a+1)*2").unwrap().to_string(), "a = 1; (a + 1)*2");
+    let err = calc.eval("k = 4; k +; 7".to_string()).unwrap_err();
+    assert_eq!((err.message.as_str(), err.labels[0].span.clone()), ("Expected a number, a name or '(' but got ';'", 10..11));
+    assert_eq!(calc.eval("k".to_string()).unwrap_err().kind, ErrorKind::UnknownVariable);
+    assert!(calc.eval("; 1".to_string()).is_err());
+    assert_eq!(calc.preview("h(x) = 2x; h(3)"), Some(Value::Number(6.)));
+    assert!(calc.eval("h(1)".to_string()).is_err());
+}
+
+#[test]
+fn test_preview_limits() {
+    let mut calc = Calculator::new();
+    assert_eq!(calc.preview("sum(k, 1, 100, k)"), Some(Value::Number(5050.)));
+    assert_eq!(calc.preview("sum(k, 1, 1e5, k)"), None);
+    assert_eq!(calc.eval("sum(k, 1, 1e5, k)".to_string()), Ok(Value::Number(5000050000.)));
+    calc.set_limits(Limits { max_steps: Some(50), ..Limits::default() });
+    assert_eq!(calc.preview("sum(k, 1, 100, k)"), None);
+}
+
+#[test]
+fn test_incomplete() {
+    let mut calc = Calculator::new();
+    for expr in ["1 +", "(1 + 2", "max(1,", "[1, 2", "x = ", "2 * "] {
+        assert_eq!(calc.eval(expr.to_string()).unwrap_err().kind, ErrorKind::Incomplete, "{}", expr);
+    }
+    let operand = Some("a number, a name or '('");
+    for (expr, pos, expected) in [("1 + )", 4, operand), ("1 2", 2, Some("an operator or the end of the expression")), ("(1 + 2))", 7, Some("an operator or the end of the expression")), ("(1 + 2 3)", 7, Some("')'")), ("1 + #", 4, operand)] {
+        assert_eq!(calc.eval(expr.to_string()).unwrap_err().kind, ErrorKind::Syntax { pos, expected }, "{}", expr);
+    }
+    assert_eq!(calc.eval("(1 + 2) * 3".to_string()), Ok(Value::Number(9.)));
+}
+
+#[test]
+fn test_strict() {
+    let mut calc = Calculator::new();
+    assert_eq!(calc.eval("1/0".to_string()), Ok(Value::Number(f64::INFINITY)));
+    calc.set_strict(true);
+    let err = calc.eval("1 / (2 - 2)".to_string()).unwrap_err();
+    assert_eq!((err.kind, err.message.as_str(), err.labels[0].span.clone()), (ErrorKind::DivisionByZero, "Division by zero", 0..11));
+    for (expr, msg) in [("sqrt(-1)", "'sqrt(-1)' is undefined"), ("ln(0)", "'ln(0)' is -inf"), ("10^400", "'10^400' is inf"), ("200!", "'200!' is inf")] {
+        assert_eq!(calc.eval(expr.to_string()).unwrap_err().message, msg, "{}", expr);
+    }
+    calc.eval("f(x) = 1/x".to_string()).unwrap();
+    assert_eq!(calc.eval("f(0)".to_string()).unwrap_err().kind, ErrorKind::DivisionByZero);
+    assert_eq!(calc.eval("sqrt(-1)".to_string()).unwrap_err().kind, ErrorKind::Domain);
+    assert_eq!(calc.eval("sqrt(4) / 2".to_string()), Ok(Value::Number(1.)));
+}
+
+#[test]
+fn test_validate() {
+    let mut calc = Calculator::new();
+    calc.eval("sq(x) = x*x".to_string()).unwrap();
+    for expr in ["rate * sq(n) + pi", "f(x) = g(x) + 1; g(y) = y; f(2)", "h = x -> x + 1; h(2)", "map(x -> sq(x), [1, 2])", "sum(k, 1, 10, k)", "max(1, 2, 3)"] {
+        assert_eq!(calc.validate(expr), Ok(()), "{}", expr);
+    }
+    let err = calc.validate("1 + nope(2)").unwrap_err();
+    assert_eq!((err.kind, err.labels[0].span.clone()), (ErrorKind::UnknownFunction, 4..8));
+    assert_eq!(calc.validate("sq(1, 2)").unwrap_err().kind, ErrorKind::Arity { expected: 1, got: 2 });
+    assert_eq!(calc.validate("f(x) = x; f(1, 2)").unwrap_err().kind, ErrorKind::Arity { expected: 1, got: 2 });
+    assert_eq!(calc.validate("1 +").unwrap_err().kind, ErrorKind::Incomplete);
+    assert!(calc.eval("rate".to_string()).is_err());
+
+    let variables = |expr: &str| calc.compile(expr).unwrap().variables();
+    assert_eq!(variables("200000 * rate / (1 - (1 + rate)^-n)"), ["rate", "n"]);
+    assert_eq!(variables("f(x) = x*a; y = 2; f(y) + b + pi"), ["a", "b", "pi"]);
+    assert_eq!(variables("diff(x^2*k, x) + sum(i, 1, m, i) + map(t -> t + c, xs)"), ["k", "m", "c", "xs"]);
+    assert_eq!(variables("x = x + 1"), ["x"]);
+}
+
+#[test]
+fn test_ast() {
+    use calculator::ast::Expr;
+    let calc = Calculator::new();
+    let ast = calc.compile("2x + 1").unwrap().ast();
+    let number = |x| Box::new(Expr::Number(x));
+    let product = Expr::BinaryOp { symbol: "*".to_string(), lhs: number(2.), rhs: Box::new(Expr::Name("x".to_string())) };
+    assert_eq!(ast, Expr::BinaryOp { symbol: "+".to_string(), lhs: Box::new(product), rhs: number(1.) });
+    assert_eq!(ast.children().len(), 2);
+
+    for expr in ["-2^2", "(-2)^2", "3! + 1", "f(x, y) = x*y", "a = [1, 2][0]; map(x -> x^2, [a])", "1 < 2 && y > 0", "2^-3"] {
+        let ast = calc.compile(expr).unwrap().ast();
+        assert_eq!(calc.compile(&ast.to_string()).unwrap().ast(), ast, "{}", expr);
+    }
+    assert_eq!(calc.compile("(1 + 2)*x!").unwrap().ast().to_string(), "(1 + 2) * (x!)");
+
+    let tree = |expr: &str| calc.compile(expr).unwrap().ast().to_tree();
+    assert_eq!(tree("1 - 2 - 3"), "-\n├── -\n│   ├── 1\n│   └── 2\n└── 3");
+    assert_eq!(tree("-2^2"), "-x\n└── ^\n    ├── 2\n    └── 2");
+    assert_eq!(tree("f(x) = sin(x)!"), "f(x) =\n└── x!\n    └── sin()\n        └── x");
+    assert_eq!(tree("a = [1, 2][0]; a"), ";\n├── a =\n│   └── index\n│       ├── []\n│       │   ├── 1\n│       │   └── 2\n│       └── 0\n└── a");
+    assert_eq!(tree("42"), "42");
+
+    let dot = calc.compile("sin(x) + 2").unwrap().ast().to_dot();
+    assert_eq!(dot, "digraph {\n    node [shape=box];\n    n0 [label=\"+\"];\n    n1 [label=\"sin()\"];\n    n2 [label=\"x\"];\n    n1 -> n2;\n    n0 -> n1;\n    n3 [label=\"2\"];\n    n0 -> n3;\n}\n");
+}
+
+#[test]
+fn test_program() {
+    let setup = || {
+        let mut calc = Calculator::new();
+        calc.set_var("x", 3.).unwrap();
+        calc.set_var("y", -2.).unwrap();
+        calc.eval("xs = [1, 2]".to_string()).unwrap();
+        calc
+    };
+    let (mut tree, mut vm) = (setup(), setup());
+    let same = |tree: &mut Calculator, vm: &mut Calculator, expr: &str| {
+        let expression = tree.compile(expr).unwrap();
+        let program = expression.to_program().expect(expr);
+        assert_eq!(vm.run_program(&program), tree.evaluate(&expression), "{}", expr);
+    };
+    for expr in ["42", "x^2 + 2x*y - 1", "-x! * 10%", "sqrt(x^2 + y^2) * max(1, x, y) / hypot(x, y)", "x > 2 && (y > 0 || x != 3)", "not x > 2 or 0 <= y < 1",
+                 "x < 0 && 1/0 > 1", "if(x < y, 1, atan2(y, x))", "if(x > 0, x > 1, x < 0)", "(-1)!", "sum(1, 2, x)", "nope(x)", "x + z", "xs * 2"] {
+        same(&mut tree, &mut vm, expr);
+    }
+    assert_eq!(vm.stats().calls, tree.stats().calls);
+
+    for calc in [&mut tree, &mut vm] {
+        calc.set_limits(Limits { max_steps: Some(3), ..Limits::default() });
+    }
+    same(&mut tree, &mut vm, "x + 1 + 2 + 3");
+    for calc in [&mut tree, &mut vm] {
+        calc.set_limits(Limits::default());
+        calc.set_strict(true);
+    }
+    same(&mut tree, &mut vm, "1/0");
+
+    for expr in ["[1, 2]", "a = 1", "f(t) = t", "2 + 10%", "(1 < 2) + 1", "if(x, 1, 2)", "diff(x^2, x)", "sum(i, 1, 3, i)"] {
+        assert!(tree.compile(expr).unwrap().to_program().is_none(), "{}", expr);
+    }
+}
+
+#[test]
+fn test_to_bytes() {
+    let mut calc = Calculator::new();
+    calc.set_env_vars(true);
+    calc.eval("x = 3".to_string()).unwrap();
+    for expr in ["0.1 + 2x^-1 * 5!", "0 <= x < 10 && not x == 4", "[1, x][1] + $HOME_NOPE", "f(a, b) = a*b; f(x, 2)", "area := x^2; area",
+                 "map([1, 2], (t) -> t * x)", "sum(i, 1, 4, i^2) + diff(x^3, x)", "y = -x; y", "5 ± 1", "1e-3"] {
+        let expression = calc.compile(expr).unwrap();
+        let loaded = calc.load_expression(&expression.to_bytes()).unwrap();
+        assert_eq!((loaded.source(), loaded.to_string()), (expression.source(), expression.to_string()));
+        assert_eq!(calc.evaluate(&loaded).map(|value| value.to_string()), calc.evaluate(&expression).map(|value| value.to_string()), "{}", expr);
+    }
+    let expression = calc.compile("nope(x)").unwrap();
+    let err = calc.evaluate(&calc.load_expression(&expression.to_bytes()).unwrap()).unwrap_err();
+    assert_eq!(err.labels[0].span, 0..4);
+
+    // Corrupt, cut short or from somewhere else, never a panic.
+    let bytes = calc.compile("sin(x) + [1, 2][0] - (t) -> t").unwrap().to_bytes();
+    for end in 0..bytes.len() {
+        assert_eq!(calc.load_expression(&bytes[..end]).unwrap_err().kind, ErrorKind::Environment);
+    }
+    for i in 5..bytes.len() {
+        let mut bytes = bytes.clone();
+        bytes[i] ^= 0xff;
+        let _ = calc.load_expression(&bytes);
+    }
+    let mut later = bytes.clone();
+    later[4] = 2;
+    assert_eq!(calc.load_expression(&later).unwrap_err().message, "Expected an expression encoded by version 1 or earlier but got version 2");
+    assert_eq!(calc.load_expression(b"x + 1").unwrap_err().message, "Expected an expression from Expression::to_bytes");
+    assert_eq!(calc.load_expression(&[bytes.clone(), vec![0]].concat()).unwrap_err().message, "The encoded expression is corrupt, there are bytes after its end");
+
+    let mut other = Calculator::new();
+    other.set_operators(OperatorTable::preset("standard").unwrap());
+    let bytes = calc.compile("-2^2").unwrap().to_bytes();
+    assert_eq!(other.evaluate(&other.load_expression(&bytes).unwrap()), Ok(Value::Number(-4.)));
+    let bytes = calc.compile("5!").unwrap().to_bytes();
+    other.set_operators(OperatorTable::new());
+    assert_eq!(other.load_expression(&bytes).unwrap_err().message, "The expression uses the operator '!', which this calculator doesn't have");
+}
+
+#[test]
+fn test_cache() {
+    let mut calc = Calculator::new();
+    calc.set_cache_size(2);
+    for expr in ["1 + 2", "x = 4", "1 + 2", "x * 2", "1 +"] {
+        let _ = calc.eval(expr.to_string());
+    }
+    assert_eq!(calc.cached_expressions(), ["x * 2", "1 + 2"]);
+    assert_eq!(calc.stats().cache_hits, 1);
+    assert_eq!(calc.eval("x * 2".to_string()), Ok(Value::Number(8.)));
+    assert_eq!(calc.stats().cache_hits, 2);
+
+    calc.set_operators(OperatorTable::preset("standard").unwrap());
+    assert!(calc.cached_expressions().is_empty());
+    assert_eq!(calc.eval("-2^2".to_string()), Ok(Value::Number(4.)));
+    calc.set_operators(OperatorTable::preset("math").unwrap());
+    assert_eq!(calc.eval("-2^2".to_string()), Ok(Value::Number(-4.)));
+
+    calc.set_cache_size(0);
+    calc.eval("x * 2".to_string()).unwrap();
+    assert_eq!((calc.cache_size(), calc.cached_expressions().len()), (0, 0));
+}
+
+#[test]
+fn test_eval_many() {
+    let mut calc = Calculator::new();
+    let results = calc.eval_many(&["x = 2", "x^2", "1 +", "[x, 1]"]);
+    assert_eq!(results[..2], [Ok(Value::Number(2.)), Ok(Value::Number(4.))]);
+    assert_eq!(results[2].as_ref().unwrap_err().kind, ErrorKind::Incomplete);
+    assert_eq!(results[3], Ok(Value::List(vec![2., 1.])));
+
+    let row = |price: f64, qty: f64| Bindings::from([("price".to_string(), price), ("qty".to_string(), qty)]);
+    let rows = [row(2., 3.), row(1.5, 4.), Bindings::new()];
+    let total = calc.compile("price * qty + x").unwrap();
+    let results = total.eval_batch(&mut calc, &rows);
+    assert_eq!(results[..2], [Ok(Value::Number(8.)), Ok(Value::Number(8.))]);
+    assert_eq!(results[2].as_ref().unwrap_err().kind, ErrorKind::UnknownVariable);
+    let pair = calc.compile("[price, qty]").unwrap();
+    assert_eq!(pair.eval_batch(&mut calc, &rows[..1]), [Ok(Value::List(vec![2., 3.]))]);
+    assert!(calc.eval("price".to_string()).is_err());
+}
+
+#[test]
+fn test_overloads() {
+    let mut calc = Calculator::new();
+    assert_eq!(calc.eval("log(100)".to_string()), Ok(Value::Number(2.)));
+    assert_eq!(calc.eval("log(2, 8)".to_string()), Ok(Value::Number(3.)));
+    assert_eq!(calc.eval("round(2.5)".to_string()), Ok(Value::Number(3.)));
+    assert_eq!(calc.eval("round(1.23456, 2)".to_string()), Ok(Value::Number(1.23)));
+    let err = calc.eval("log(1, 2, 3)".to_string()).unwrap_err();
+    assert_eq!((err.kind, err.message.as_str()), (ErrorKind::Arity { expected: 2, got: 3 }, "Expected 1 or 2 arguments for function 'log'"));
+    assert!(calc.validate("f(x) = log(x, x, x)").is_err());
+
+    // Another overload is added next to the others, the same number of
+    // parameters replaces one.
+    calc.register_function("mix(a, b)", Function::TwoArg(|a, b| (a + b) / 2.)).unwrap();
+    calc.register_function("mix(a, b, t)", Function::ThreeArg(|a, b, t| a + (b - a) * t)).unwrap();
+    calc.register_function("mix(a, b, t)", Function::ThreeArg(|a, b, t| b + (a - b) * t)).unwrap();
+    assert_eq!(calc.eval("mix(2, 4)".to_string()), Ok(Value::Number(3.)));
+    assert_eq!(calc.eval("mix(2, 4, 0.25)".to_string()), Ok(Value::Number(3.5)));
+    assert_eq!(calc.signatures().filter(|&(name, _)| name == "mix").count(), 2);
+    calc.register_function("mix(xs, ...)", Function::Variadic(1, |xs| xs.iter().sum())).unwrap();
+    assert_eq!(calc.eval("mix(1, 2, 3, 4)".to_string()), Ok(Value::Number(10.)));
+    assert_eq!(calc.eval("mix(2, 4)".to_string()), Ok(Value::Number(3.)));
+    let program = calc.compile("log(10) + round(2.25, 1)").unwrap().to_program().unwrap();
+    assert_eq!(calc.run_program(&program), Ok(Value::Number(3.3)));
+}
+
+#[test]
+fn test_interpolation() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map(|value| value.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("lerp(10, 20, 0.25)"), Ok("12.5".to_string()));
+    assert_eq!(eval("invlerp(10, 20, 12.5)"), Ok("0.25".to_string()));
+    assert_eq!(eval("remap(5, 0, 10, 100, 200)"), Ok("150".to_string()));
+    assert_eq!(eval("remap(15, 0, 10, 200, 100)"), Ok("50".to_string()));
+    assert_eq!(eval("smoothstep(0, 1, 0.5)"), Ok("0.5".to_string()));
+    assert_eq!(eval("smoothstep(0, 2, 0.5)"), Ok("0.15625".to_string()));
+    assert_eq!(eval("smoothstep(0, 1, -3) + smoothstep(0, 1, 7)"), Ok("1".to_string()));
+    assert_eq!(eval("step(1, 0.5) + step(1, 1) * 10"), Ok("10".to_string()));
+
+    assert_eq!(eval("invlerp(2, 2, 3)"), Err("Expected a range with different ends but got 2 to 2".to_string()));
+    assert_eq!(eval("remap(1, 0, 0, 1, 2)"), Err("Expected a range with different ends but got 0 to 0".to_string()));
+}
+
+#[test]
+fn test_formulas() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map(|value| value.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("area := w * h"), Ok("NaN".to_string()));
+    assert_eq!(eval("area"), Err("In 'area': Unknown variable 'w'".to_string()));
+    assert_eq!(eval("w = 3; h = 4; area"), Ok("12".to_string()));
+    assert_eq!(eval("w = 5"), Ok("5".to_string()));
+    assert_eq!(eval("area + 1"), Ok("21".to_string()));
+    assert_eq!(eval("volume := area * depth; depth = 2; volume"), Ok("40".to_string()));
+    // Parameters shadow formulas, but not inside them.
+    assert_eq!(eval("f(w) = area + w"), Ok("NaN".to_string()));
+    assert_eq!(eval("f(100)"), Ok("120".to_string()));
+
+    assert_eq!(eval("w := volume / 8"), Err("Circular reference w -> volume -> area -> w".to_string()));
+    assert_eq!(eval("area := area + 1"), Err("Circular reference area -> area".to_string()));
+    assert_eq!(eval("pi := 3"), Err("Can't assign to 'pi', it is a constant".to_string()));
+    assert_eq!(eval("area"), Ok("20".to_string()));
+    assert_eq!(eval("area = 7; volume"), Ok("14".to_string()));
+
+    assert!(calc.is_definition("total := a + b"));
+    assert_eq!(calc.formulas().collect::<Vec<_>>(), [("volume", "area * depth".to_string())]);
+    assert_eq!(calc.compile("x := 2 * (y + 1)").unwrap().ast().to_string(), "x := 2 * (y + 1)");
+    calc.set_var("volume", 1.).unwrap();
+    assert_eq!(calc.formulas().count(), 0);
+    // An exact assignment replaces a formula too.
+    calc.eval("a := w * 3".to_string()).unwrap();
+    calc.set_arithmetic(Arithmetic::Rational);
+    assert_eq!(calc.eval("a = 1/3".to_string()).map(|value| value.to_string()), Ok("1/3".to_string()));
+    assert_eq!(calc.formulas().count(), 0);
+    calc.set_arithmetic(Arithmetic::Float);
+    assert_eq!(calc.eval("a * 3".to_string()).map(|value| value.to_string()), Ok("1".to_string()));
+}
+
+#[test]
+fn test_formula_sets() {
+    use std::collections::HashMap;
+    let set = |formulas: &[(&str, &str)]| formulas.iter().map(|&(name, expr)| (name.to_string(), expr.to_string())).collect::<HashMap<_, _>>();
+    let mut calc = Calculator::new();
+    calc.set_var("price", 20.).unwrap();
+    calc.set_var("qty", 3.).unwrap();
+
+    let report = set(&[("tax", "total * 0.19"), ("gross", "total + tax"), ("total", "price * qty"), ("label", "qty")]);
+    assert_eq!(calc.dependencies(&report).unwrap(), HashMap::from([
+        ("tax".to_string(), vec!["total".to_string()]),
+        ("gross".to_string(), vec!["total".to_string(), "tax".to_string()]),
+        ("total".to_string(), vec![]),
+        ("label".to_string(), vec![]),
+    ]));
+    assert_eq!(calc.formula_order(&report).unwrap(), ["total", "tax", "gross", "label"]);
+    let values = calc.eval_formulas(&report).unwrap();
+    assert_eq!(values["gross"], Value::Number(71.4));
+    assert_eq!(values.len(), 4);
+    // The names are gone afterwards.
+    assert_eq!(calc.eval("total".to_string()).unwrap_err().kind, ErrorKind::UnknownVariable);
+
+    let err = calc.formula_order(&set(&[("a", "b + 1"), ("b", "c * 2"), ("c", "a"), ("d", "1")])).unwrap_err();
+    assert_eq!(err.message, "Circular reference a -> b -> c -> a");
+    assert_eq!(calc.eval_formulas(&set(&[("x", "x + 1")])).unwrap_err().message, "Circular reference x -> x");
+    assert_eq!(calc.eval_formulas(&set(&[("a", "1"), ("b", "a / nope")])).unwrap_err().message, "In 'b': Unknown variable 'nope'");
+    assert_eq!(calc.eval("a".to_string()).unwrap_err().kind, ErrorKind::UnknownVariable);
+    assert_eq!(calc.dependencies(&set(&[("a", "(1 +")])).unwrap_err().kind, ErrorKind::Incomplete);
+    assert_eq!(calc.dependencies(&set(&[("pi", "3")])).unwrap_err().message, "Can't assign to 'pi', it is a constant");
+    // A chain too long to recurse through.
+    let chain: HashMap<String, String> = (1..20_000).map(|i| (format!("f{}", i), format!("f{} + 1", i - 1))).chain([("f0".to_string(), "0".to_string())]).collect();
+    assert_eq!(calc.formula_order(&chain).unwrap().len(), 20_000);
+}
+
+#[test]
+fn test_load_definitions() {
+    let mut calc = Calculator::new();
+    let names = calc.load_definitions("# tuning\nbase = 10\ndamage(level) = base * 1.5^level\n\ntotal := damage(2) + 1\n").unwrap();
+    assert_eq!(names, ["base", "damage", "total"]);
+    assert_eq!(calc.eval("total".to_string()), Ok(Value::Number(23.5)));
+
+    // All or nothing, the old versions stay.
+    let err = calc.load_definitions("base = 20\nrate = base / nope").unwrap_err();
+    assert_eq!(err.message, "Line 2: Unknown variable 'nope'");
+    assert_eq!(calc.eval("damage(0)".to_string()), Ok(Value::Number(10.)));
+    let err = calc.load_definitions("base = 20\ndamage(2)").unwrap_err();
+    assert_eq!(err.message, "Line 2: Expected a definition like 'f(x) = ...', 'x = ...' or 'x := ...'");
+    assert_eq!(calc.eval("base".to_string()), Ok(Value::Number(10.)));
+
+    assert!(calc.forget("damage") && !calc.forget("damage"));
+    assert_eq!(calc.eval("damage(1)".to_string()).unwrap_err().kind, ErrorKind::UnknownFunction);
+}
+
+#[test]
+fn test_watch() {
+    use std::time::SystemTime;
+    let path = std::env::temp_dir().join(format!("calculator-watch-{}.calc", std::process::id()));
+    let write = |src: &str, modified: SystemTime| {
+        std::fs::write(&path, src).unwrap();
+        std::fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+    };
+    let mut calc = Calculator::new();
+    let mut watch = Watch::new(&path);
+    assert_eq!(watch.poll(&mut calc).unwrap_err().kind, ErrorKind::Environment);
+
+    write("speed = 3\nmove(t) = speed * t\n", SystemTime::UNIX_EPOCH + Duration::from_secs(1));
+    assert_eq!(watch.poll(&mut calc), Ok(true));
+    assert_eq!(watch.poll(&mut calc), Ok(false));
+    assert_eq!(calc.eval("move(2)".to_string()), Ok(Value::Number(6.)));
+
+    write("speed = 4\nmove(t) = speed * t +\n", SystemTime::UNIX_EPOCH + Duration::from_secs(2));
+    let err = watch.poll(&mut calc).unwrap_err();
+    assert!(err.message.starts_with(&format!("{}: Line 2: ", path.display())));
+    assert_eq!(calc.eval("move(2)".to_string()), Ok(Value::Number(6.)));
+    assert_eq!(watch.poll(&mut calc), Ok(false));
+
+    write("speed = 5\n", SystemTime::UNIX_EPOCH + Duration::from_secs(3));
+    assert_eq!(watch.poll(&mut calc), Ok(true));
+    assert_eq!(watch.names(), ["speed"]);
+    assert_eq!(calc.eval("speed".to_string()), Ok(Value::Number(5.)));
+    assert_eq!(calc.eval("move(2)".to_string()).unwrap_err().kind, ErrorKind::UnknownFunction);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_import() {
+    let dir = std::env::temp_dir().join(format!("calculator-import-{}", std::process::id()));
+    let lib = dir.join("lib");
+    std::fs::create_dir_all(&lib).unwrap();
+    std::fs::write(lib.join("finance.calc"), "# finance\nimport \"tax.calc\"\nrate = 0.05\nfv(n, pmt) = pmt * ((1 + rate)^n - 1) / rate\nnet(x) = x - tax::vat(x)\ngrow(rate, n) = (1 + rate)^n\n").unwrap();
+    std::fs::write(lib.join("tax.calc"), "vat(x) = x * rate\nrate = 0.2\n").unwrap();
+    std::fs::write(dir.join("main.calc"), "").unwrap();
+    let mut calc = Calculator::new();
+    let importer = Importer::new(vec![lib.clone()]);
+
+    let names = importer.import(&mut calc, "finance.calc", Some(&dir.join("main.calc"))).unwrap();
+    assert_eq!(names, ["finance::rate", "finance::fv", "finance::net", "finance::grow"]);
+    assert_eq!(calc.eval("round(finance::fv(10, 100), 2)".to_string()), Ok(Value::Number(1257.79)));
+    assert_eq!(calc.eval("finance::net(100) + tax::rate".to_string()), Ok(Value::Number(80.2)));
+    assert_eq!(calc.eval("finance::grow(1, 3)".to_string()), Ok(Value::Number(8.)));
+    assert_eq!(calc.eval("fv(10, 100)".to_string()).unwrap_err().kind, ErrorKind::UnknownFunction);
+    assert_eq!(calc.definition("finance::net"), Some("finance::net(x) = x - tax::vat(x)".to_string()));
+
+    let err = importer.import(&mut calc, "nope.calc", None).unwrap_err();
+    assert_eq!((err.kind, err.message), (ErrorKind::Environment, format!("Can't find 'nope.calc' in ., {}", lib.display())));
+    std::fs::write(lib.join("tax.calc"), "import \"finance.calc\"\n").unwrap();
+    let err = importer.import(&mut calc, "finance.calc", None).unwrap_err();
+    assert_eq!(err.message, format!("{}: Line 1: Import cycle: finance.calc -> tax.calc -> finance.calc", lib.join("tax.calc").display()));
+    std::fs::write(lib.join("tax.calc"), "vat(x) = x * rate\n1 + 2\n").unwrap();
+    let err = importer.import(&mut calc, "tax.calc", None).unwrap_err();
+    assert_eq!(err.message, format!("{}: Line 2: Expected a definition like 'f(x) = ...', 'x = ...' or an import", lib.join("tax.calc").display()));
+    assert_eq!(calc.eval("tax::vat(10)".to_string()), Ok(Value::Number(2.)));
+
+    assert_eq!(Importer::statement(" import \"a b.calc\" "), Some("a b.calc"));
+    assert_eq!(Importer::statement("import \"\""), None);
+    assert_eq!(Importer::statement("imports \"a.calc\""), None);
+    assert_eq!(Importer::statement("import = 2"), None);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_check() {
+    let calc = Calculator::new();
+    let check = |expr: &str| calc.check(expr).into_iter().map(|err| (err.labels[0].span.start, err.message)).collect::<Vec<_>>();
+    let operand = |got: &str| format!("Expected a number, a name or '(' but got '{}'", got);
+    let operator = "Expected an operator or the end of the expression".to_string();
+
+    assert_eq!(check("1 + 2*x"), []);
+    assert_eq!(check("1 + * 2"), [(4, operand("*"))]);
+    assert_eq!(check("2 3 4 +* 5"), [(2, operator.clone()), (7, operand("*"))]);
+    assert_eq!(check("a = 1; b = * 2; c = 3 3"), [(11, operand("*")), (22, operator.clone())]);
+    assert_eq!(check("sin(1 2 ,)"), [(6, "Expected ')'".to_string()), (9, operand(")"))]);
+    assert_eq!(check("f(1,,2) + (3"), [(4, operand(",")), (12, "Expected ')'".to_string())]);
+    assert_eq!(check("max(1, 2))"), [(9, operator.clone())]);
+    assert_eq!(check("1 +"), [(3, "Unexpected end of input".to_string())]);
+    // Not syntax errors, which only evaluating finds.
+    assert_eq!(check("nope(1) + x"), []);
+
+    let mut calc = Calculator::new();
+    calc.set_warnings(true);
+    let check = |expr: &str| calc.check(expr).into_iter().map(|err| (err.kind, err.message)).collect::<Vec<_>>();
+    assert_eq!(check("2^3^2"), [(ErrorKind::Warning, "'2^3^2' is evaluated as 2^(3^2)".to_string())]);
+    assert_eq!(check("-2^2"), [(ErrorKind::Warning, "'-2^2' is evaluated as -(2^2)".to_string())]);
+    assert_eq!(check("x=2; 1/2x"), [(ErrorKind::Warning, "'1/2x' is evaluated as (1/2)x".to_string())]);
+    assert_eq!(check("2^3^2 +"), [(ErrorKind::Incomplete, "Unexpected end of input".to_string())]);
+}
+
+#[test]
+fn test_robustness() {
+    // Inputs found by fuzzing, each used to panic, overflow the stack or
+    // never finish.
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map(|value| value.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("clamp(2.5, 255, 10)"), Err("Expected min <= max but got 255 and 10".to_string()));
+    assert_eq!(eval("x = simplify(x); x = simplify(x)"), Err("Can't expand 'x', it refers to itself".to_string()));
+    assert_eq!(eval("sum(i, 10^300, 10^300, i) == 10^300"), Ok("true".to_string()));
+    assert_eq!(eval("ncr(10^9, 5*10^8)"), Ok("inf".to_string()));
+    assert_eq!(eval("npr(10^9, 10^9)"), Ok("inf".to_string()));
+}
+
+#[test]
+fn test_statistics() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("mean(2, 4, 4, 4, 5, 5, 7, 9)"), Ok(Value::Number(5.)));
+    assert_eq!(eval("median(5, 1, 3) + median(4, 1, 3, 2)"), Ok(Value::Number(3. + 2.5)));
+    assert_eq!(eval("variance(2, 4, 4, 4, 5, 5, 7, 9)"), Ok(Value::Number(32. / 7.)));
+    assert_eq!(eval("stddev(1, 3)"), Ok(Value::Number(2f64.sqrt())));
+    assert_eq!(eval("geomean(2, 8) + geomean(5)"), Ok(Value::Number(9.)));
+    assert_eq!(eval("stddev(4)"), Err("Expected at least 2 arguments for function 'stddev'".to_string()));
+    assert_eq!(eval("geomean(2, -8)"), Err("Expected positive numbers for function 'geomean' but got -8".to_string()));
+    assert_eq!(eval("standardize([1, 3, 5])"), Ok(Value::List(vec![-1., 0., 1.])));
+    assert_eq!(eval("standardize([2, 2])"), Err("Expected numbers that aren't all equal".to_string()));
+    assert_eq!(eval("standardize([2])"), Err("Expected at least 2 numbers but got 1".to_string()));
+    assert_eq!(eval("standardize(2)"), Err("Expected a list but got 2".to_string()));
+
+    assert_eq!(eval("cov([1, 2, 3], [1, 2, 3])"), eval("variance(1, 2, 3)"));
+    assert_eq!(eval("cov([1, 2, 3, 4], [8, 6, 4, 2])"), Ok(Value::Number(-10. / 3.)));
+    assert_eq!(eval("corr([1, 2, 3, 4], [8, 6, 4, 2])"), Ok(Value::Number(-1.)));
+    assert_eq!(eval("corr([1, 2, 3], [1, 3, 2])"), Ok(Value::Number(0.5)));
+    assert_eq!(eval("wmean([1, 2, 3], [3, 0, 1])"), Ok(Value::Number(1.5)));
+    assert_eq!(eval("corr([1, 2], [3, 3])"), Err("Expected lists whose numbers aren't all equal".to_string()));
+    assert_eq!(eval("cov([1, 2, 3], [1, 2])"), Err("Expected lists of the same length but got 3 and 2 numbers".to_string()));
+    assert_eq!(eval("wmean([1, 2], [1, -1])"), Err("Expected weights that aren't negative but got -1".to_string()));
+    assert_eq!(eval("wmean([1, 2], [0, 0])"), Err("Expected weights that aren't all 0".to_string()));
+}
+
+#[test]
+fn test_namespaces() {
+    let mut calc = Calculator::new();
+    assert_eq!(calc.eval("stats::median([3, 1, 2]) + stats::mean(1, 2)".to_string()), Ok(Value::Number(3.5)));
+    assert_eq!(calc.eval("stats::stddev(4)".to_string()).map_err(|err| err.message), Err("Expected at least 2 arguments for function 'stats::stddev'".to_string()));
+    assert_eq!(calc.eval("stats::sin(1)".to_string()).unwrap_err().kind, ErrorKind::UnknownFunction);
+
+    calc.eval("game::blend(a, b, t) = a + (b - a) * t".to_string()).unwrap();
+    calc.eval("game::speed = 4".to_string()).unwrap();
+    assert_eq!(calc.eval("game::blend(0, game::speed, 0.5)".to_string()), Ok(Value::Number(2.)));
+    assert_eq!(calc.eval("blend(0, 1, 0.5)".to_string()).unwrap_err().kind, ErrorKind::UnknownFunction);
+
+    let (imported, kept) = calc.use_namespace("stats").unwrap();
+    assert!(imported.contains(&"median".to_string()) && kept.is_empty());
+    assert_eq!(calc.use_namespace("game"), Ok((vec!["blend".to_string()], vec![])));
+    assert_eq!(calc.eval("blend(0, 10, 0.1)".to_string()), Ok(Value::Number(1.)));
+    calc.eval("scale::blend(a, b, t) = t".to_string()).unwrap();
+    assert_eq!(calc.use_namespace("scale"), Ok((vec![], vec!["blend".to_string()])));
+    assert_eq!(calc.eval("blend(0, 10, 0.1)".to_string()), Ok(Value::Number(1.)));
+    assert_eq!(calc.use_namespace("nope"), Err("There are no functions in 'nope'".to_string()));
+}
+
+#[test]
+fn test_hist() {
+    let mut calc = Calculator::new();
+    let Ok(Value::Histogram(hist)) = calc.eval("hist([1, 2, 2, 3, 3, 3, 4, 9], 4)".to_string()) else { panic!() };
+    assert_eq!(hist.edges(), [1., 3., 5., 7., 9.]);
+    assert_eq!(hist.counts(), [3, 4, 0, 1]);
+    assert_eq!(hist.to_string(), format!("[1, 3) 3 {}\n[3, 5) 4 {}\n[5, 7) 0\n[7, 9] 1 {}", "█".repeat(30), "█".repeat(40), "█".repeat(10)));
+
+    let Ok(Value::Histogram(hist)) = calc.eval("hist(range(1, 100))".to_string()) else { panic!() };
+    assert_eq!(hist.counts().len(), 8);
+    assert_eq!(hist.counts().iter().sum::<usize>(), 100);
+    let Ok(Value::Histogram(hist)) = calc.eval("hist([5, 5], 2)".to_string()) else { panic!() };
+    assert_eq!((hist.edges(), hist.counts()), (&[4.5, 5., 5.5][..], &[0, 2][..]));
+
+    assert_eq!(calc.eval("hist([1, 2], 0)".to_string()).unwrap_err().message, "Expected 1 to 100 bins but got 0");
+    assert_eq!(calc.eval("hist([])".to_string()).unwrap_err().message, "Expected at least 1 number but got an empty list");
+    assert_eq!(calc.eval("hist([1, 0/0])".to_string()).unwrap_err().message, "Expected finite numbers but got NaN");
+    assert!(calc.eval("hist([1, 2]) + 1".to_string()).is_err());
+}
+
+#[test]
+fn test_sequences() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("range(1, 5)"), Ok(Value::List(vec![1., 2., 3., 4., 5.])));
+    assert_eq!(eval("range(10, 1, -3)"), Ok(Value::List(vec![10., 7., 4., 1.])));
+    assert_eq!(eval("range(0, 1, 0.25)"), Ok(Value::List(vec![0., 0.25, 0.5, 0.75, 1.])));
+    assert_eq!(eval("range(0, 1, 0.1)[3]"), Ok(Value::Number(0.3)));
+    assert_eq!(eval("range(0, 1, 0.1)[10]"), Ok(Value::Number(1.)));
+    assert_eq!(eval("range(3, 3)"), Ok(Value::List(vec![3.])));
+    assert_eq!(eval("range(1, 2, 5)"), Ok(Value::List(vec![1.])));
+    assert_eq!(eval("mean(range(1, 100))"), Ok(Value::Number(50.5)));
+    assert_eq!(eval("linspace(0, 1, 5)"), Ok(Value::List(vec![0., 0.25, 0.5, 0.75, 1.])));
+    assert_eq!(eval("linspace(0, 0.3, 4)[3]"), Ok(Value::Number(0.3)));
+    assert_eq!(eval("linspace(2, 3, 1)"), Ok(Value::List(vec![2.])));
+
+    assert_eq!(eval("range(1, 5, -1)"), Err("Expected a step towards 5 from 1 but got -1".to_string()));
+    assert_eq!(eval("range(1, 5, 0)"), Err("Expected a step towards 5 from 1 but got 0".to_string()));
+    assert_eq!(eval("range(0, 1e9)"), Err("Expected at most 1000000 numbers but the range has 1000000001".to_string()));
+    assert_eq!(eval("linspace(0, 1, 2.5)"), Err("Expected a count from 1 to 1000000 but got 2.5".to_string()));
+}
+
+#[test]
+fn test_number_theory() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("gcd(12, -18) + gcd(0, 0) + gcd(7, 0)"), Ok(Value::Number(13.)));
+    assert_eq!(eval("lcm(4, 6) + lcm(0, 5)"), Ok(Value::Number(12.)));
+    assert_eq!(eval("lcm(2^40, 3^20)"), Ok(Value::Number(2f64.powi(40) * 3f64.powi(20))));
+    assert_eq!(eval("gcd(2.5, 5)"), Err("Expected an integer but got 2.5".to_string()));
+
+    assert_eq!(eval("isprime(2) && isprime(97) && isprime(2^31 - 1) && isprime(999999999989)"), Ok(Value::Bool(true)));
+    assert_eq!(eval("isprime(1) || isprime(-7) || isprime(91) || isprime(3215031751)"), Ok(Value::Bool(false)));
+    assert_eq!(eval("if(isprime(7), 1, 0)"), Ok(Value::Number(1.)));
+    assert_eq!(eval("nextprime(-5) + nextprime(7) + nextprime(1000)"), Ok(Value::Number(2. + 11. + 1009.)));
+    assert!(eval("isprime(7.5)").is_err());
+
+    assert_eq!(eval("mod(-7, 3)"), Ok(Value::Number(2.)));
+    assert_eq!(eval("mod(7, -3)"), Ok(Value::Number(-2.)));
+    assert_eq!(eval("-7 % 3"), Ok(Value::Number(-1.)));
+    assert_eq!(eval("div(-7, 3) * 3 + mod(-7, 3)"), Ok(Value::Number(-7.)));
+    assert_eq!(eval("div(7.5, 2)"), Ok(Value::Number(3.)));
+}
+
+#[test]
+fn test_random() {
+    let mut calc = Calculator::new();
+    let draw = |calc: &mut Calculator, expr: &str| calc.eval(expr.to_string()).unwrap().number().unwrap();
+
+    calc.seed(42);
+    let first: Vec<f64> = (0..5).map(|_| draw(&mut calc, "rand()")).collect();
+    calc.seed(42);
+    let again: Vec<f64> = (0..5).map(|_| draw(&mut calc, "rand()")).collect();
+    assert_eq!(first, again);
+    assert!(first.iter().all(|x| (0. ..1.).contains(x)));
+    assert_ne!(first[0], first[1]);
+
+    for _ in 0..200 {
+        let x = draw(&mut calc, "rand(-2, 3)");
+        assert!((-2. ..3.).contains(&x));
+        let n = draw(&mut calc, "randint(1, 6)");
+        assert!((1. ..=6.).contains(&n) && n.fract() == 0.);
+    }
+    assert_eq!(draw(&mut calc, "randint(4, 4)"), 4.);
+
+    let seed = calc.preview("rand()");
+    assert_eq!(calc.eval("rand()".to_string()).ok(), seed);
+
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map_err(|err| err.message);
+    assert_eq!(eval("randint(6, 1)"), Err("Expected a range from low to high but got 6 to 1".to_string()));
+    assert!(eval("randint(1.5, 3)").is_err());
+    assert!(eval("rand(1)").is_err());
+    assert!(eval("max()").is_err());
+}
+
+#[test]
+fn test_montecarlo() {
+    let mut calc = Calculator::new();
+    let draw = |calc: &mut Calculator, expr: &str| calc.eval(expr.to_string()).unwrap().number().unwrap();
+    calc.seed(7);
+    let mean = draw(&mut calc, "montecarlo(x -> x^2, 100000)");
+    assert!((mean - 1. / 3.).abs() < 0.01);
+    calc.seed(7);
+    assert_eq!(draw(&mut calc, "montecarlo(x -> x^2, 100000)"), mean);
+    let pi = 4. * draw(&mut calc, "montecarlo((x, y) -> x^2 + y^2 < 1, 100000, -1, 1)");
+    assert!((pi - std::f64::consts::PI).abs() < 0.05);
+    let mean = draw(&mut calc, "montecarlo(x -> x, 100000, 2, 4)");
+    assert!((mean - 3.).abs() < 0.01);
+
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map_err(|err| err.message);
+    assert_eq!(eval("montecarlo(3, 10)"), Err("Expected a lambda but got 3".to_string()));
+    assert_eq!(eval("montecarlo(x -> x, 0)"), Err("Expected a whole number of samples but got 0".to_string()));
+    assert_eq!(eval("montecarlo(x -> x, 10, 1, 0)"), Err("Expected finite bounds from low to high but got 1 and 0".to_string()));
+    assert_eq!(eval("montecarlo(x -> x, 1e9)"), Err("Can't take more than 10000000 samples".to_string()));
+    assert_eq!(eval("montecarlo(x -> x, 10, 1)"), Err("Expected 2 or 4 arguments for function 'montecarlo'".to_string()));
+}
+
+#[test]
+#[cfg(feature = "special-functions")]
+fn test_special_functions() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map(|value| value.number().unwrap()).map_err(|err| err.message);
+    let near = |a: Result<f64, String>, b: f64| (a.clone().unwrap() - b).abs() <= b.abs() * 1e-12 || panic!("{:?} != {}", a, b);
+
+    assert_eq!(eval("gamma(5)"), Ok(24.));
+    near(eval("gamma(0.5)^2"), std::f64::consts::PI);
+    near(eval("gamma(-1.5)"), 2.3632718012073544);
+    near(eval("gamma(171.5)"), 9.483367566824801e307);
+    assert_eq!(eval("gamma(200)"), Ok(f64::INFINITY));
+    assert_eq!(eval("gamma(-2)"), Err("The gamma function is undefined at the non-positive integer -2".to_string()));
+    near(eval("lgamma(200)"), 857.9336698258575);
+    near(eval("lgamma(-0.5)"), 1.265512123484645);
+    near(eval("beta(2, 3)"), 1. / 12.);
+    near(eval("beta(0.5, 0.5)"), std::f64::consts::PI);
+
+    near(eval("erf(0.5)"), 0.5204998778130465);
+    near(eval("erf(1)"), 0.8427007929497149);
+    near(eval("erf(-2.5)"), -0.999593047982555);
+    near(eval("erfc(3)"), 2.2090496998585438e-5);
+    near(eval("erfc(10)"), 2.088487583762545e-45);
+    near(eval("erfc(-1)"), 1.842700792949715);
+    assert_eq!(eval("erf(0) + erfc(1 / 0) + erf(1 / 0)"), Ok(1.));
+}
+
+#[test]
+fn test_digit_separators() {
+    let mut calc = Calculator::new();
+    let mut eval = |expr: &str| calc.eval(expr.to_string()).map_err(|err| err.message);
+
+    assert_eq!(eval("1_000_000 + 0.000_5"), Ok(Value::Number(1000000.0005)));
+    assert_eq!(eval("0xFF_FF"), Ok(Value::Number(65535.)));
+    assert_eq!(eval("0b1111_0000"), Ok(Value::Number(240.)));
+    assert!(eval("1__0").is_err());
+    assert!(eval("1_").is_err());
+
+    let group = |precision: Option<usize>, value: f64| Formatter { precision, grouping: true, ..Formatter::default() }.format(value);
+    assert_eq!(group(None, 1234567.5), "1,234,567.5");
+    assert_eq!(group(None, -123456.), "-123,456");
+    assert_eq!(group(None, 999.), "999");
+    assert_eq!(group(Some(3), 1234567.), "1.23e+06");
+}
+
+#[test]
+fn test_template() {
+    let fmt = |template: &str, value: f64| Template::parse(template).unwrap().format(value);
+
+    assert_eq!(fmt("%.3f kWh", 2. / 3.), "0.667 kWh");
+    assert_eq!(fmt("[%8.2f]", -12.345), "[  -12.35]");
+    assert_eq!(fmt("[%-6d]", 42.9), "[42    ]");
+    assert_eq!(fmt("%08.3f", -1.5), "-001.500");
+    assert_eq!(fmt("%+.1e", 1500.), "+1.5e+03");
+    assert_eq!(fmt("%g", 0.0001234), "0.0001234");
+    assert_eq!(fmt("%g", 123456789.), "1.23457e+08");
+    assert_eq!(fmt("%d%%", 15.), "15%");
+
+    assert!(Template::parse("no conversion").is_err());
+    assert!(Template::parse("%q").is_err());
+}
+
+#[test]
+fn test_to_source() {
+    let mut calc = Calculator::new();
+    assert_eq!(Value::Number(f64::INFINITY).to_source(), Some("1/0".to_string()));
+    assert_eq!(Value::Number(1e-7).to_source(), Some("1e-7".to_string()));
+    assert_eq!(calc.eval("date(2024, 5, 1) + hours(2)".to_string()).unwrap().to_source(), Some("date(2024, 5, 1) + seconds(7200.0)".to_string()));
+    calc.set_arithmetic(Arithmetic::Integer);
+    let n = calc.eval("2^100".to_string()).unwrap();
+    assert_eq!(n.to_source(), Some("(1*1e15 + 267650600228229)*1e15 + 401496703205376".to_string()));
+    assert_eq!(calc.eval(n.to_source().unwrap()), Ok(n));
+    calc.set_arithmetic(Arithmetic::Rational);
+    let r = calc.eval("-2^70/3".to_string()).unwrap();
+    assert_eq!(calc.eval(r.to_source().unwrap()), Ok(r));
+    assert_eq!(calc.eval("plot(x, x, 0, 1)".to_string()).unwrap().to_source(), None);
+}